@@ -1,12 +1,14 @@
 use super::apu2a03_registers::Register;
-use super::channels::{Dmc, NoiseWave, SquarePulse, TriangleWave};
+use super::audio_levels::{AudioLevelMeter, AudioLevels};
+use super::channels::{Dmc, DmcStatus, NoiseWave, SquarePulse, TriangleWave};
 use super::envelope::EnvelopedChannel;
 use super::length_counter::LengthCountedChannel;
+use super::mixer::{APUChannelKind, StereoMixer};
 use super::tone_source::{APUChannel, APUChannelPlayer, BufferedChannel, TimedAPUChannel};
 use crate::common::{
     interconnection::{APUCPUConnection, CPUIrqProvider},
     save_state::{Savable, SaveError},
-    CPU_FREQ,
+    TvRegion, CPU_FREQ,
 };
 use serde::{Deserialize, Serialize};
 use std::cell::Cell;
@@ -18,6 +20,9 @@ use rodio::DeviceTrait;
 // APU, is clocked on every CPU clock
 const SAMPLES_EVERY_N_APU_CLOCK: f64 = CPU_FREQ / (super::SAMPLE_RATE as f64);
 
+/// the default limit of the pull audio buffer
+const DEFAULT_MAX_AUDIO_BUFFER_MS: u32 = 300;
+
 mod buffered_channel_serde {
     use super::BufferedChannel;
     use serde::{ser::Error, Deserialize, Deserializer, Serialize, Serializer};
@@ -56,12 +61,16 @@ pub struct APU2A03 {
     #[serde(with = "buffered_channel_serde")]
     buffered_channel: Arc<Mutex<BufferedChannel>>,
 
+    /// the mode written to `$4017`, applied to `is_4_step_squence_mode`
+    /// when the frame counter is reset
     is_4_step_squence_mode_hold_value: bool,
     is_4_step_squence_mode: bool,
     interrupt_inhibit_flag: bool,
 
     cycle: u16,
 
+    /// number of clocks until a pending `$4017` write resets the frame
+    /// counter and applies the new mode, `-1` if there is no pending write
     wait_reset: i8,
 
     sample_counter: f64,
@@ -71,6 +80,25 @@ pub struct APU2A03 {
     interrupt_flag: Cell<bool>,
     request_interrupt_flag_change: Cell<bool>,
 
+    /// stereo interleaved samples at [`SAMPLE_RATE`](super::SAMPLE_RATE)
+    /// for pulling with [`APU2A03::take_audio_buffer`]
+    #[serde(skip)]
+    audio_buffer: Vec<f32>,
+    /// the maximum length of `audio_buffer`, the oldest samples are dropped
+    /// if it is not consumed fast enough
+    #[serde(skip)]
+    max_audio_buffer_len: usize,
+    /// number of samples (per channel) dropped from `audio_buffer`
+    #[serde(skip)]
+    dropped_audio_samples: u64,
+    /// number of samples (per channel) generated since the start
+    #[serde(skip)]
+    generated_audio_samples: u64,
+    #[serde(skip)]
+    audio_levels: AudioLevelMeter,
+    #[serde(skip)]
+    stereo_mixer: StereoMixer,
+
     #[serde(skip)]
     player: Option<rodio::Sink>,
 }
@@ -91,8 +119,11 @@ impl APU2A03 {
 
             buffered_channel: buffered_channel.clone(),
 
-            is_4_step_squence_mode_hold_value: false,
-            is_4_step_squence_mode: false,
+            // at power-on, it acts as if `$00` was written to `$4017`
+            // (4-step mode with IRQ enabled), and the frame counter is reset
+            // on the first clock
+            is_4_step_squence_mode_hold_value: true,
+            is_4_step_squence_mode: true,
             interrupt_inhibit_flag: false,
 
             cycle: 0,
@@ -106,6 +137,13 @@ impl APU2A03 {
             interrupt_flag: Cell::new(false),
             request_interrupt_flag_change: Cell::new(false),
 
+            audio_buffer: Vec::new(),
+            max_audio_buffer_len: Self::audio_buffer_len_for_ms(DEFAULT_MAX_AUDIO_BUFFER_MS),
+            dropped_audio_samples: 0,
+            generated_audio_samples: 0,
+            audio_levels: AudioLevelMeter::default(),
+            stereo_mixer: StereoMixer::new(),
+
             player: Self::get_player(buffered_channel),
         }
     }
@@ -136,30 +174,12 @@ impl APU2A03 {
     pub(crate) fn read_register(&self, register: Register) -> u8 {
         match register {
             Register::Status => {
-                let sqr1_length_counter =
-                    (self.square_pulse_1.length_counter().counter() != 0) as u8;
-
-                let sqr2_length_counter =
-                    (self.square_pulse_2.length_counter().counter() != 0) as u8;
-
-                let triangle_length_counter = (self.triangle.length_counter().counter() != 0) as u8;
-
-                let noise_length_counter = (self.noise.length_counter().counter() != 0) as u8;
+                let result = self.peek_status();
 
-                let dmc_active = self.dmc.sample_remaining_bytes_more_than_0() as u8;
-                let dmc_interrupt = self.dmc.get_irq_pin_state() as u8;
-
-                let frame_interrupt = self.interrupt_flag.get() as u8;
                 self.interrupt_flag.set(false);
                 self.request_interrupt_flag_change.set(true);
 
-                dmc_interrupt << 7
-                    | frame_interrupt << 6
-                    | dmc_active << 4
-                    | noise_length_counter << 3
-                    | triangle_length_counter << 2
-                    | sqr2_length_counter << 1
-                    | sqr1_length_counter
+                result
             }
             _ => {
                 // unreadable
@@ -168,6 +188,31 @@ impl APU2A03 {
         }
     }
 
+    /// the value of the status register (`$4015`), without clearing the
+    /// frame interrupt flag
+    pub(crate) fn peek_status(&self) -> u8 {
+        let sqr1_length_counter = (self.square_pulse_1.length_counter().counter() != 0) as u8;
+
+        let sqr2_length_counter = (self.square_pulse_2.length_counter().counter() != 0) as u8;
+
+        let triangle_length_counter = (self.triangle.length_counter().counter() != 0) as u8;
+
+        let noise_length_counter = (self.noise.length_counter().counter() != 0) as u8;
+
+        let dmc_active = self.dmc.sample_remaining_bytes_more_than_0() as u8;
+        let dmc_interrupt = self.dmc.get_irq_pin_state() as u8;
+
+        let frame_interrupt = self.interrupt_flag.get() as u8;
+
+        dmc_interrupt << 7
+            | frame_interrupt << 6
+            | dmc_active << 4
+            | noise_length_counter << 3
+            | triangle_length_counter << 2
+            | sqr2_length_counter << 1
+            | sqr1_length_counter
+    }
+
     #[allow(clippy::identity_op)]
     pub(crate) fn write_register(&mut self, register: Register, data: u8) {
         match register {
@@ -190,11 +235,6 @@ impl APU2A03 {
                     .channel_mut()
                     .envelope_generator_mut()
                     .set_loop_flag(halt);
-
-                self.square_pulse_1
-                    .channel_mut()
-                    .envelope_generator_mut()
-                    .set_start_flag(true);
             }
             Register::Pulse1_2 => {
                 // sweep
@@ -247,10 +287,6 @@ impl APU2A03 {
                     .channel_mut()
                     .envelope_generator_mut()
                     .set_loop_flag(halt);
-                self.square_pulse_2
-                    .channel_mut()
-                    .envelope_generator_mut()
-                    .set_start_flag(true);
             }
             Register::Pulse2_2 => {
                 // sweep
@@ -335,10 +371,6 @@ impl APU2A03 {
                     .channel_mut()
                     .envelope_generator_mut()
                     .set_loop_flag(halt);
-                self.noise
-                    .channel_mut()
-                    .envelope_generator_mut()
-                    .set_start_flag(true);
             }
             Register::Noise2 => {
                 // unused
@@ -349,6 +381,11 @@ impl APU2A03 {
             }
             Register::Noise4 => {
                 self.noise.length_counter_mut().reload_counter(data >> 3);
+
+                self.noise
+                    .channel_mut()
+                    .envelope_generator_mut()
+                    .set_start_flag(true);
             }
             Register::DMC1 => {
                 let rate_index = data & 0xF;
@@ -396,6 +433,8 @@ impl APU2A03 {
             }
             Register::FrameCounter => {
                 self.is_4_step_squence_mode_hold_value = data & 0x80 == 0;
+                // the interrupt inhibit takes effect immediately, only the
+                // mode and the reset of the sequence are delayed
                 self.interrupt_inhibit_flag = data & 0x40 != 0;
 
                 if self.interrupt_inhibit_flag {
@@ -403,6 +442,9 @@ impl APU2A03 {
                     self.request_interrupt_flag_change.set(true);
                 }
 
+                // the write takes effect 3 or 4 cycles later depending on
+                // whether it happened during an APU cycle or between them,
+                // so the frame counter stays aligned to the APU cycles
                 self.wait_reset = if self.cycle % 2 == 0 { 4 } else { 3 };
             }
         }
@@ -420,14 +462,40 @@ impl APU2A03 {
         }
     }
 
-    fn generate_quarter_frame_clock(&mut self) {
+    pub fn dmc_status(&self) -> DmcStatus {
+        self.dmc.status()
+    }
+
+    /// is the frame counter asserting the IRQ line
+    pub fn frame_irq_pin_state(&self) -> bool {
+        self.interrupt_flag.get()
+    }
+
+    /// is the DMC asserting the IRQ line
+    pub fn dmc_irq_pin_state(&self) -> bool {
+        self.dmc.get_irq_pin_state()
+    }
+
+    /// select the rate tables used by the channels
+    pub fn set_region(&mut self, region: TvRegion) {
+        self.dmc.set_region(region);
+    }
+
+    /// clock the envelopes and the triangle linear counter, as done by the
+    /// frame counter on every quarter frame, without clocking the rest of
+    /// the APU
+    pub fn clock_quarter_frame(&mut self) {
         self.square_pulse_1.clock_envlope();
         self.square_pulse_2.clock_envlope();
         self.noise.clock_envlope();
         self.triangle.channel_mut().clock_linear_counter();
     }
 
-    fn generate_half_frame_clock(&mut self) {
+    /// clock the length counters and the sweep units, as done by the frame
+    /// counter on every half frame, without clocking the rest of the APU.
+    /// The frame counter also clocks a quarter frame at the same time, which
+    /// is not done here
+    pub fn clock_half_frame(&mut self) {
         self.square_pulse_1.length_counter_mut().decrement();
         self.square_pulse_1.channel_mut().clock_sweeper();
         self.square_pulse_2.length_counter_mut().decrement();
@@ -443,26 +511,163 @@ impl APU2A03 {
         }
     }
 
-    fn get_mixer_output(&mut self) -> f32 {
-        let square_pulse_1 = self.square_pulse_1.get_output();
-        let square_pulse_2 = self.square_pulse_2.get_output();
-        let triangle = self.triangle.get_output();
-        let noise = self.noise.get_output();
-        let dmc = self.dmc.get_output();
+    /// the `(left, right)` output of the mixer
+    fn get_mixer_output(&mut self) -> (f32, f32) {
+        let outputs = [
+            self.square_pulse_1.get_output(),
+            self.square_pulse_2.get_output(),
+            self.triangle.get_output(),
+            self.noise.get_output(),
+            self.dmc.get_output(),
+        ];
+
+        self.stereo_mixer.mix(&outputs)
+    }
 
-        let pulse_out = if square_pulse_1 == 0. && square_pulse_2 == 0. {
-            0.
-        } else {
-            95.88 / ((8128. / (square_pulse_1 + square_pulse_2)) + 100.)
-        };
+    /// set the stereo position of `channel`, from `-1.0` (left) to `1.0`
+    /// (right), default is `0.0` (center)
+    pub fn set_channel_pan(&mut self, channel: APUChannelKind, pan: f32) {
+        self.stereo_mixer.set_pan(channel, pan);
+    }
 
-        let tnd_out = if triangle == 0. && noise == 0. && dmc == 0. {
-            0.
-        } else {
-            159.79 / ((1. / ((triangle / 8227.) + (noise / 12241.) + (dmc / 22638.))) + 100.)
-        };
+    /// set the gain of `channel` before mixing, default is `1.0`
+    pub fn set_channel_volume(&mut self, channel: APUChannelKind, gain: f32) {
+        self.stereo_mixer.set_gain(channel, gain);
+    }
+
+    /// take the settings of the frontend and the audio buffer counters from
+    /// `old`, they are not part of the console, so they are kept when the
+    /// APU is replaced on reset
+    pub(crate) fn keep_frontend_state(&mut self, old: &APU2A03) {
+        self.max_audio_buffer_len = old.max_audio_buffer_len;
+        self.dropped_audio_samples = old.dropped_audio_samples;
+        self.generated_audio_samples = old.generated_audio_samples;
+        self.stereo_mixer = old.stereo_mixer.clone();
+        self.audio_levels
+            .set_silence_threshold(old.audio_levels.silence_threshold());
+    }
 
-        pulse_out + tnd_out
+    /// the stereo interleaved samples generated since the last take
+    pub fn audio_buffer(&self) -> &[f32] {
+        &self.audio_buffer
+    }
+
+    /// take the stereo interleaved samples generated since the last call
+    pub fn take_audio_buffer(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.audio_buffer)
+    }
+
+    fn audio_buffer_len_for_ms(ms: u32) -> usize {
+        // stereo
+        (super::SAMPLE_RATE as u64 * ms as u64 / 1000) as usize * 2
+    }
+
+    /// limit the samples kept for [`APU2A03::take_audio_buffer`] to `ms`
+    /// milliseconds, the oldest ones are dropped if the buffer is full
+    pub fn set_max_audio_buffer_ms(&mut self, ms: u32) {
+        self.max_audio_buffer_len = Self::audio_buffer_len_for_ms(ms);
+    }
+
+    /// number of samples (per channel) dropped because the buffer was full
+    pub fn dropped_audio_samples(&self) -> u64 {
+        self.dropped_audio_samples
+    }
+
+    /// number of samples (per channel) generated, including the dropped ones
+    pub fn generated_audio_samples(&self) -> u64 {
+        self.generated_audio_samples
+    }
+
+    /// the number of CPU cycles between two samples, it is adjusted slightly
+    /// when the audio is played by the APU itself, to follow the rate of the
+    /// audio device
+    pub fn cycles_per_sample(&self) -> f64 {
+        SAMPLES_EVERY_N_APU_CLOCK + self.offset
+    }
+
+    /// the number of samples (per channel) that the next `cycles` calls to
+    /// [`APU2A03::clock`] would generate, if the rate does not change in
+    /// between, see [`APU2A03::cycles_per_sample`]
+    pub fn samples_for_cycles(&self, cycles: u64) -> u64 {
+        // the counter and the period are multiples of `2^-52` (the period is
+        // larger than `1`, and `clock` only adds `1` and subtracts the period,
+        // which is exact), so the count is computed in fixed point to match
+        // `clock` exactly, `f64` would round for large `cycles`
+        const ONE: f64 = (1u64 << 52) as f64;
+
+        let sample_counter = (self.sample_counter * ONE) as u128;
+        let samples_every_n_apu_clock = (self.cycles_per_sample() * ONE) as u128;
+
+        ((sample_counter + ((cycles as u128) << 52)) / samples_every_n_apu_clock) as u64
+    }
+
+    /// end the frame of the audio levels, should be called when the PPU
+    /// completes a frame
+    pub fn end_audio_levels_frame(&mut self) {
+        self.audio_levels.end_frame();
+    }
+
+    /// the levels of the samples generated in the last frame
+    pub fn audio_levels(&self) -> AudioLevels {
+        self.audio_levels.levels()
+    }
+
+    /// number of consecutive frames where the peak of the samples was below
+    /// the silence threshold
+    pub fn silent_frames(&self) -> u32 {
+        self.audio_levels.silent_frames()
+    }
+
+    pub fn set_silence_threshold(&mut self, threshold: f32) {
+        self.audio_levels.set_silence_threshold(threshold);
+    }
+
+    /// the envelope volumes of pulse 1, pulse 2 and noise
+    #[cfg(test)]
+    pub(crate) fn envelope_volumes(&mut self) -> [u8; 3] {
+        [
+            self.square_pulse_1
+                .channel_mut()
+                .envelope_generator_mut()
+                .get_current_volume() as u8,
+            self.square_pulse_2
+                .channel_mut()
+                .envelope_generator_mut()
+                .get_current_volume() as u8,
+            self.noise
+                .channel_mut()
+                .envelope_generator_mut()
+                .get_current_volume() as u8,
+        ]
+    }
+
+    fn push_audio_sample(&mut self, left: f32, right: f32) {
+        if self.max_audio_buffer_len < 2 {
+            self.dropped_audio_samples += 1;
+            return;
+        }
+
+        let len = self.audio_buffer.len();
+        if len + 2 > self.max_audio_buffer_len {
+            // drop the oldest half of the buffer at once, instead of one
+            // sample every time, so that the cost is amortized
+            let to_drop = (len + 2 - self.max_audio_buffer_len)
+                .max(self.max_audio_buffer_len / 2)
+                .min(len)
+                & !1;
+
+            self.audio_buffer.drain(..to_drop);
+            self.dropped_audio_samples += to_drop as u64 / 2;
+        }
+
+        self.audio_buffer.push(left);
+        self.audio_buffer.push(right);
+    }
+
+    /// drop the generated samples, used when the audio is played by the
+    /// APU itself
+    pub fn clear_audio_buffer(&mut self) {
+        self.audio_buffer.clear();
     }
 
     pub fn empty_queue(&mut self) {
@@ -483,8 +688,8 @@ impl APU2A03 {
                 self.is_4_step_squence_mode = self.is_4_step_squence_mode_hold_value;
 
                 if !self.is_4_step_squence_mode {
-                    self.generate_quarter_frame_clock();
-                    self.generate_half_frame_clock();
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
                 }
             }
             std::cmp::Ordering::Greater => self.wait_reset -= 1,
@@ -495,8 +700,17 @@ impl APU2A03 {
 
         self.sample_counter += 1.;
         if self.sample_counter >= samples_every_n_apu_clock {
-            let output = self.get_mixer_output();
+            let (left, right) = self.get_mixer_output();
+
+            self.push_audio_sample(left, right);
+            self.generated_audio_samples += 1;
+            self.audio_levels.record(left, right);
+
+            // the player is mono, with the default mixing both sides are the
+            // same, so this is exactly the output of the NES
+            let output = (left + right) / 2.;
 
+            // the player gets all the samples, only the pull buffer is bounded
             if let Ok(mut buffered_channel) = self.buffered_channel.lock() {
                 buffered_channel.recored_sample(output);
 
@@ -531,21 +745,21 @@ impl APU2A03 {
         // this is clocked in every CPU cycle, so the numbers are multiplied by 2
         match self.cycle {
             7455 => {
-                self.generate_quarter_frame_clock();
+                self.clock_quarter_frame();
             }
             14913 => {
-                self.generate_quarter_frame_clock();
-                self.generate_half_frame_clock();
+                self.clock_quarter_frame();
+                self.clock_half_frame();
             }
             22371 => {
-                self.generate_quarter_frame_clock();
+                self.clock_quarter_frame();
             }
             29828 if self.is_4_step_squence_mode => {
                 self.update_irq_pin();
             }
             29829 if self.is_4_step_squence_mode => {
-                self.generate_quarter_frame_clock();
-                self.generate_half_frame_clock();
+                self.clock_quarter_frame();
+                self.clock_half_frame();
 
                 self.update_irq_pin();
             }
@@ -555,8 +769,8 @@ impl APU2A03 {
                 self.cycle = 0;
             }
             37281 if !self.is_4_step_squence_mode => {
-                self.generate_quarter_frame_clock();
-                self.generate_half_frame_clock();
+                self.clock_quarter_frame();
+                self.clock_half_frame();
             }
             37282 if !self.is_4_step_squence_mode => {
                 self.cycle = 0;
@@ -620,8 +834,11 @@ impl Savable for APU2A03 {
             _ => SaveError::Others,
         })?;
 
-        let _ = std::mem::replace(self, state);
+        let old_state = std::mem::replace(self, state);
 
+        // these are not part of the emulation state
+        self.keep_frontend_state(&old_state);
+        self.audio_levels = old_state.audio_levels;
         self.player = Self::get_player(self.buffered_channel.clone());
 
         Ok(())
@@ -0,0 +1,89 @@
+/// The levels of the audio output over one frame, see
+/// [`NES::audio_levels`](crate::nes::NES::audio_levels)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AudioLevels {
+    pub peak_left: f32,
+    pub peak_right: f32,
+    pub rms_left: f32,
+    pub rms_right: f32,
+}
+
+/// default value of [`AudioLevelMeter::set_silence_threshold`]
+const DEFAULT_SILENCE_THRESHOLD: f32 = 0.001;
+
+/// Measures the peak and RMS of the generated samples, the values are
+/// collected on every sample and reset at the end of every frame
+pub(crate) struct AudioLevelMeter {
+    peak: [f32; 2],
+    sum_of_squares: [f32; 2],
+    samples: u32,
+
+    last: AudioLevels,
+
+    silence_threshold: f32,
+    silent_frames: u32,
+}
+
+impl AudioLevelMeter {
+    pub fn record(&mut self, left: f32, right: f32) {
+        self.peak[0] = self.peak[0].max(left.abs());
+        self.peak[1] = self.peak[1].max(right.abs());
+        self.sum_of_squares[0] += left * left;
+        self.sum_of_squares[1] += right * right;
+        self.samples += 1;
+    }
+
+    /// should be called when the PPU completes a frame, so the levels cover
+    /// the same frames as the picture
+    pub fn end_frame(&mut self) {
+        let samples = self.samples.max(1) as f32;
+
+        self.last = AudioLevels {
+            peak_left: self.peak[0],
+            peak_right: self.peak[1],
+            rms_left: (self.sum_of_squares[0] / samples).sqrt(),
+            rms_right: (self.sum_of_squares[1] / samples).sqrt(),
+        };
+
+        if self.peak[0].max(self.peak[1]) < self.silence_threshold {
+            self.silent_frames = self.silent_frames.saturating_add(1);
+        } else {
+            self.silent_frames = 0;
+        }
+
+        self.peak = [0.; 2];
+        self.sum_of_squares = [0.; 2];
+        self.samples = 0;
+    }
+
+    /// the levels of the last complete frame
+    pub fn levels(&self) -> AudioLevels {
+        self.last
+    }
+
+    /// number of consecutive frames with peak below the silence threshold
+    pub fn silent_frames(&self) -> u32 {
+        self.silent_frames
+    }
+
+    pub fn silence_threshold(&self) -> f32 {
+        self.silence_threshold
+    }
+
+    pub fn set_silence_threshold(&mut self, threshold: f32) {
+        self.silence_threshold = threshold;
+    }
+}
+
+impl Default for AudioLevelMeter {
+    fn default() -> Self {
+        Self {
+            peak: [0.; 2],
+            sum_of_squares: [0.; 2],
+            samples: 0,
+            last: AudioLevels::default(),
+            silence_threshold: DEFAULT_SILENCE_THRESHOLD,
+            silent_frames: 0,
+        }
+    }
+}
@@ -1,12 +1,32 @@
 use super::super::tone_source::{APUChannel, TimedAPUChannel};
+use crate::common::TvRegion;
 use serde::{Deserialize, Serialize};
 
 const DMC_PERIOD_RATES_NTSC: [u16; 0x10] = [
     428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
 ];
 
+const DMC_PERIOD_RATES_PAL: [u16; 0x10] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+/// A snapshot of the DMC sample playback state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmcStatus {
+    /// is there a sample being played (there are bytes remaining)
+    pub active: bool,
+    /// number of sample bytes remaining to be read
+    pub remaining_bytes: u16,
+    /// the address of the next sample byte to be read
+    pub sample_address: u16,
+    /// the current 7-bit output level
+    pub output_level: u8,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Dmc {
+    region: TvRegion,
+    rate_index: u8,
     period: u16,
     current_timer: u16,
 
@@ -34,12 +54,15 @@ pub struct Dmc {
 
 impl Dmc {
     pub fn new() -> Self {
+        // power-on state is the same as writing `0` to all registers
         Self {
-            period: 0,
+            region: TvRegion::Ntsc,
+            rate_index: 0,
+            period: DMC_PERIOD_RATES_NTSC[0] / 2,
             current_timer: 0,
 
-            samples_address: 0,
-            samples_length: 0,
+            samples_address: 0xC000,
+            samples_length: 1,
 
             samples_address_counter: 0,
             samples_remaining_bytes_counter: 0,
@@ -49,7 +72,7 @@ impl Dmc {
 
             output_shift_register: 0,
             shifter_remaining_bits_counter: 0,
-            output_silence_flag: false,
+            output_silence_flag: true,
             silence_on_next_empty: false,
             output_level: 0,
 
@@ -75,8 +98,21 @@ impl Dmc {
     }
 
     pub(crate) fn set_rate_index(&mut self, rate_index: u8) {
+        self.rate_index = rate_index & 0xF;
+
+        let table = match self.region {
+            TvRegion::Ntsc | TvRegion::Dendy => &DMC_PERIOD_RATES_NTSC,
+            TvRegion::Pal => &DMC_PERIOD_RATES_PAL,
+        };
+
         // since the table is in CPU clocks, /2 to make it in APU clocks periods
-        self.period = DMC_PERIOD_RATES_NTSC[rate_index as usize & 0xF] / 2;
+        self.period = table[self.rate_index as usize] / 2;
+    }
+
+    /// change the rate table, the current rate is updated to the new table
+    pub(crate) fn set_region(&mut self, region: TvRegion) {
+        self.region = region;
+        self.set_rate_index(self.rate_index);
     }
 
     pub(crate) fn set_direct_output_level_load(&mut self, output_level: u8) {
@@ -126,6 +162,15 @@ impl Dmc {
         }
     }
 
+    pub(crate) fn status(&self) -> DmcStatus {
+        DmcStatus {
+            active: self.sample_remaining_bytes_more_than_0(),
+            remaining_bytes: self.samples_remaining_bytes_counter,
+            sample_address: self.samples_address_counter,
+            output_level: self.output_level & 0x7F,
+        }
+    }
+
     pub(crate) fn sample_remaining_bytes_more_than_0(&self) -> bool {
         self.samples_remaining_bytes_counter > 0
     }
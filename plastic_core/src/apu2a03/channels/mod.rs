@@ -3,7 +3,7 @@ mod noise;
 mod square;
 mod triangle;
 
-pub use dmc::Dmc;
+pub use dmc::{Dmc, DmcStatus};
 pub use noise::NoiseWave;
 pub use square::SquarePulse;
 pub use triangle::TriangleWave;
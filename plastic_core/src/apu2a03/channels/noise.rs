@@ -22,7 +22,7 @@ pub struct NoiseWave {
 impl NoiseWave {
     pub fn new() -> Self {
         Self {
-            period: 0,
+            period: NOISE_PERIODS_TABLE[0],
             current_timer: 0,
 
             envelope_generator: EnvelopeGenerator::new(),
@@ -0,0 +1,97 @@
+/// The sound channels of the APU
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum APUChannelKind {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl APUChannelKind {
+    fn index(self) -> usize {
+        match self {
+            Self::Pulse1 => 0,
+            Self::Pulse2 => 1,
+            Self::Triangle => 2,
+            Self::Noise => 3,
+            Self::Dmc => 4,
+        }
+    }
+}
+
+/// The outputs of the channels before mixing, in the order of
+/// [`APUChannelKind`]
+pub(crate) type ChannelOutputs = [f32; 5];
+
+/// Per channel pan and gain, applied to the output of every channel before
+/// the nonlinear mixer, which is then run once for each side.
+///
+/// This is an approximation, as the real mixer combines the channels in one
+/// nonlinear stage, mixing a channel alone on one side makes it slightly
+/// louder relative to the others than when it is shared. With the default
+/// settings the factors are all `1.0`, so both sides are exactly the mono
+/// output of the NES.
+#[derive(Clone)]
+pub(crate) struct StereoMixer {
+    pan: [f32; 5],
+    gain: [f32; 5],
+}
+
+impl StereoMixer {
+    pub fn new() -> Self {
+        Self {
+            pan: [0.; 5],
+            gain: [1.; 5],
+        }
+    }
+
+    /// `-1.0` is full left, `0.0` is center and `1.0` is full right
+    pub fn set_pan(&mut self, channel: APUChannelKind, pan: f32) {
+        self.pan[channel.index()] = pan.clamp(-1., 1.);
+    }
+
+    pub fn set_gain(&mut self, channel: APUChannelKind, gain: f32) {
+        self.gain[channel.index()] = gain.max(0.);
+    }
+
+    /// mix the outputs of the channels into the `(left, right)` samples
+    pub fn mix(&self, outputs: &ChannelOutputs) -> (f32, f32) {
+        let mut left = [0.; 5];
+        let mut right = [0.; 5];
+
+        for (i, &output) in outputs.iter().enumerate() {
+            // the center keeps the full level on both sides, and panning
+            // fades out only the opposite side
+            let pan = self.pan[i];
+            left[i] = output * self.gain[i] * (1. - pan).min(1.);
+            right[i] = output * self.gain[i] * (1. + pan).min(1.);
+        }
+
+        (Self::nonlinear_mix(&left), Self::nonlinear_mix(&right))
+    }
+
+    fn nonlinear_mix(outputs: &ChannelOutputs) -> f32 {
+        let [square_pulse_1, square_pulse_2, triangle, noise, dmc] = *outputs;
+
+        let pulse_out = if square_pulse_1 == 0. && square_pulse_2 == 0. {
+            0.
+        } else {
+            95.88 / ((8128. / (square_pulse_1 + square_pulse_2)) + 100.)
+        };
+
+        let tnd_out = if triangle == 0. && noise == 0. && dmc == 0. {
+            0.
+        } else {
+            159.79 / ((1. / ((triangle / 8227.) + (noise / 12241.) + (dmc / 22638.))) + 100.)
+        };
+
+        pulse_out + tnd_out
+    }
+}
+
+impl Default for StereoMixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
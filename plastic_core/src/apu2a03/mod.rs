@@ -1,12 +1,21 @@
 mod apu2a03;
 mod apu2a03_registers;
+mod audio_levels;
 mod channels;
 mod envelope;
 mod length_counter;
+mod mixer;
+mod resampler;
 mod sequencer;
 mod tone_source;
 
+mod tests;
+
 pub use apu2a03::APU2A03;
+pub use audio_levels::AudioLevels;
+pub use channels::DmcStatus;
+pub use mixer::APUChannelKind;
+pub use resampler::AudioResampler;
 
-// for performance
+/// the rate of the samples generated by the APU, (for performance)
 pub const SAMPLE_RATE: u32 = 22050;
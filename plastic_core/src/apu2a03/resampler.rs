@@ -0,0 +1,68 @@
+/// Linear interpolation resampler for stereo interleaved samples, it keeps
+/// the position between calls so that consecutive buffers are continuous
+pub struct AudioResampler {
+    /// the position of the next output sample in input samples, `-1` is the
+    /// last sample of the previous buffer
+    position: f64,
+    last: [f32; 2],
+}
+
+impl AudioResampler {
+    pub fn new() -> Self {
+        Self {
+            position: 0.,
+            last: [0.; 2],
+        }
+    }
+
+    /// resample `input` from `from_hz` to `to_hz`, both are stereo interleaved
+    pub fn resample(&mut self, input: &[f32], from_hz: u32, to_hz: u32) -> Vec<f32> {
+        let frames = input.len() / 2;
+        let step = from_hz as f64 / to_hz as f64;
+
+        let last = self.last;
+        let frame = |index: isize| -> [f32; 2] {
+            if index < 0 {
+                last
+            } else {
+                let index = index as usize * 2;
+                [input[index], input[index + 1]]
+            }
+        };
+
+        let mut output = Vec::with_capacity(((frames as f64 / step) as usize + 1) * 2);
+
+        while self.position <= frames as f64 - 1. {
+            let index = self.position.floor();
+            let fraction = (self.position - index) as f32;
+            let index = index as isize;
+
+            let first = frame(index);
+            // `index + 1` is always available, as `position <= frames - 1`
+            let second = if fraction == 0. {
+                first
+            } else {
+                frame(index + 1)
+            };
+
+            for (first, second) in first.iter().zip(second.iter()) {
+                output.push(first + (second - first) * fraction);
+            }
+
+            self.position += step;
+        }
+
+        if frames > 0 {
+            self.last = frame(frames as isize - 1);
+        }
+        self.position -= frames as f64;
+
+        output
+    }
+}
+
+impl Default for AudioResampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,413 @@
+#[cfg(test)]
+mod apu2a03_tests {
+    use super::super::{
+        envelope::EnvelopeGenerator, APUChannelKind, AudioResampler, APU2A03, SAMPLE_RATE,
+    };
+    use crate::common::{
+        interconnection::{APUCPUConnection, CPUIrqProvider},
+        Bus, Device, TvRegion, CPU_FREQ,
+    };
+
+    #[test]
+    fn power_on_status() {
+        let apu = APU2A03::new();
+
+        // all channels are disabled and there are no interrupts
+        assert_eq!(apu.read(0x4015, Device::CPU), 0);
+        assert!(!apu.irq_pin_state());
+
+        let dmc = apu.dmc_status();
+        assert!(!dmc.active);
+        assert_eq!(dmc.output_level, 0);
+    }
+
+    #[test]
+    fn power_on_frame_counter_mode() {
+        let mut apu = APU2A03::new();
+
+        // 4-step mode with IRQ enabled, the frame IRQ is raised at the end
+        // of the first frame sequence
+        for _ in 0..29827 {
+            apu.clock();
+        }
+        assert!(!apu.irq_pin_state());
+
+        apu.clock();
+        assert!(apu.irq_pin_state());
+
+        assert_eq!(apu.read(0x4015, Device::CPU), 0x40);
+        // reading clears the frame interrupt flag
+        assert_eq!(apu.read(0x4015, Device::CPU), 0);
+    }
+
+    /// number of CPU cycles between two DMC sample byte reads
+    fn dmc_read_interval(apu: &mut APU2A03) -> u32 {
+        // fastest rate with loop
+        apu.write(0x4010, 0x4F, Device::CPU);
+        apu.write(0x4015, 0x10, Device::CPU);
+
+        let mut read_cycles = Vec::new();
+        let mut cycle = 0;
+        while read_cycles.len() < 3 {
+            if apu.request_dmc_reader_read().is_some() {
+                apu.submit_dmc_buffer_byte(0);
+                read_cycles.push(cycle);
+            }
+            apu.clock();
+            cycle += 1;
+        }
+
+        read_cycles[2] - read_cycles[1]
+    }
+
+    #[test]
+    fn dmc_region_rate_table() {
+        let mut apu = APU2A03::new();
+        let ntsc_interval = dmc_read_interval(&mut apu);
+
+        let mut apu = APU2A03::new();
+        apu.set_region(TvRegion::Pal);
+        let pal_interval = dmc_read_interval(&mut apu);
+
+        // PAL rate `$F` is 50 CPU cycles per bit, and NTSC is 54
+        assert_eq!(ntsc_interval - pal_interval, 8 * (54 - 50));
+
+        // switching the region updates the current rate
+        apu.set_region(TvRegion::Ntsc);
+        assert_eq!(dmc_read_interval(&mut apu), ntsc_interval);
+    }
+
+    #[test]
+    fn audio_buffer_one_frame() {
+        let mut apu = APU2A03::new();
+
+        for _ in 0..29780 {
+            apu.clock();
+        }
+
+        let peeked = apu.audio_buffer().to_vec();
+        let samples = apu.take_audio_buffer();
+        assert_eq!(peeked, samples);
+        // stereo, ~367 samples per frame at 22050Hz
+        assert_eq!(samples.len() % 2, 0);
+        assert!((366..=368).contains(&(samples.len() / 2)));
+        assert!(apu.take_audio_buffer().is_empty());
+
+        let mut resampler = AudioResampler::new();
+        let resampled = resampler.resample(&samples, SAMPLE_RATE, 44100);
+        // ~735 at 44100Hz, the interpolation needs the next input sample,
+        // so the last one or two output samples come with the next buffer
+        assert!((730..=736).contains(&(resampled.len() / 2)));
+    }
+
+    #[test]
+    fn resampler_linear_interpolation() {
+        let mut resampler = AudioResampler::new();
+
+        // same rate does not change anything
+        let input = [0., 0., 1., -1., 2., -2.];
+        assert_eq!(resampler.resample(&input, 100, 100), input.to_vec());
+
+        // double the rate, continuing from the last sample of the previous
+        // buffer
+        let mut resampler = AudioResampler::new();
+        let first = resampler.resample(&[0., 0., 1., 1.], 100, 200);
+        assert_eq!(first, vec![0., 0., 0.5, 0.5, 1., 1.]);
+        let second = resampler.resample(&[2., 2., 3., 3.], 100, 200);
+        assert_eq!(second, vec![1.5, 1.5, 2., 2., 2.5, 2.5, 3., 3.]);
+    }
+
+    #[test]
+    fn resampler_sample_count() {
+        let mut resampler = AudioResampler::new();
+        let input = vec![0.; 2 * 22050];
+
+        // split into chunks, the total should be exact
+        let mut total = 0;
+        for chunk in input.chunks(2 * 367) {
+            total += resampler.resample(chunk, 22050, 48000).len() / 2;
+        }
+
+        // the last input sample is only used with the next buffer
+        assert!((47997..=48000).contains(&total));
+    }
+
+    #[test]
+    fn audio_buffer_bounded() {
+        let mut apu = APU2A03::new();
+        apu.set_max_audio_buffer_ms(100);
+        let max_len = (SAMPLE_RATE / 10 * 2) as usize;
+
+        // 600 frames without taking the samples
+        for _ in 0..600 * 29780 {
+            apu.clock();
+            assert!(apu.audio_buffer().len() <= max_len);
+        }
+
+        // ~367 samples per frame
+        let dropped = apu.dropped_audio_samples();
+        let generated = dropped + apu.audio_buffer().len() as u64 / 2;
+        assert!(dropped > 0);
+        assert!((600 * 366..=600 * 368).contains(&generated));
+        // at least half of the buffer is kept
+        assert!(apu.audio_buffer().len() >= max_len / 2);
+    }
+
+    /// number of APU clocks from a `$4017` write until the frame IRQ, the
+    /// write is done after `clocks_before_write` clocks
+    fn frame_irq_delay_after_write(clocks_before_write: u32) -> u32 {
+        let mut apu = APU2A03::new();
+        for _ in 0..clocks_before_write {
+            apu.clock();
+        }
+        apu.write(0x4017, 0x00, Device::CPU);
+
+        let mut clocks = 0;
+        while !apu.irq_pin_state() {
+            apu.clock();
+            clocks += 1;
+        }
+        clocks
+    }
+
+    #[test]
+    fn frame_counter_write_delay() {
+        // the frame counter is reset 4 cycles after a write on an even
+        // cycle, and 3 cycles after a write on an odd cycle
+        assert_eq!(frame_irq_delay_after_write(10), 4 + 29828);
+        assert_eq!(frame_irq_delay_after_write(11), 3 + 29828);
+
+        // the mode change is delayed as well
+        let mut apu = APU2A03::new();
+        for _ in 0..29826 {
+            apu.clock();
+        }
+        apu.write(0x4017, 0x80, Device::CPU);
+        apu.clock();
+        apu.clock();
+        // still in 4-step mode
+        assert!(apu.irq_pin_state());
+
+        // but the IRQ inhibit flag clears the interrupt immediately
+        apu.write(0x4017, 0x40, Device::CPU);
+        assert!(!apu.irq_pin_state());
+    }
+
+    fn run_audio_levels_frame(apu: &mut APU2A03) {
+        for _ in 0..29780 {
+            apu.clock();
+        }
+        apu.end_audio_levels_frame();
+    }
+
+    #[test]
+    fn audio_levels_and_silence() {
+        let mut apu = APU2A03::new();
+
+        for frame in 1..=3 {
+            run_audio_levels_frame(&mut apu);
+
+            assert_eq!(apu.silent_frames(), frame);
+            assert!(apu.audio_levels().rms_left < 1e-6);
+        }
+
+        // pulse 1, 50% duty with constant volume 15 at ~440Hz
+        apu.write(0x4015, 0x01, Device::CPU);
+        apu.write(0x4000, 0xBF, Device::CPU);
+        apu.write(0x4002, 0xFD, Device::CPU);
+        apu.write(0x4003, 0x00, Device::CPU);
+
+        run_audio_levels_frame(&mut apu);
+
+        let levels = apu.audio_levels();
+        assert_eq!(apu.silent_frames(), 0);
+        assert!(levels.peak_left > 0.1);
+        assert!(levels.rms_left > 0.05 && levels.rms_left < levels.peak_left);
+        // the NES is mono
+        assert_eq!(levels.peak_left, levels.peak_right);
+        assert_eq!(levels.rms_left, levels.rms_right);
+
+        // the channel is still playing, but it is quieter than the threshold
+        apu.set_silence_threshold(1.);
+        run_audio_levels_frame(&mut apu);
+        assert_eq!(apu.silent_frames(), 1);
+    }
+
+    fn pulse_1_frame_samples(apu: &mut APU2A03) -> Vec<f32> {
+        // pulse 1, 50% duty with constant volume 15 at ~440Hz
+        apu.write(0x4015, 0x01, Device::CPU);
+        apu.write(0x4000, 0xBF, Device::CPU);
+        apu.write(0x4002, 0xFD, Device::CPU);
+        apu.write(0x4003, 0x00, Device::CPU);
+
+        run_audio_levels_frame(apu);
+        apu.take_audio_buffer()
+    }
+
+    #[test]
+    fn channel_pan_and_volume() {
+        let centered = pulse_1_frame_samples(&mut APU2A03::new());
+
+        let mut apu = APU2A03::new();
+        apu.set_channel_pan(APUChannelKind::Pulse1, -1.);
+        let panned = pulse_1_frame_samples(&mut apu);
+
+        assert_eq!(centered.len(), panned.len());
+        assert!(centered.iter().any(|&sample| sample > 0.1));
+        for (centered, panned) in centered.chunks(2).zip(panned.chunks(2)) {
+            // the defaults are the same as the mono output
+            assert_eq!(centered[0], centered[1]);
+            assert_eq!(panned[0], centered[0]);
+            assert!(panned[1].abs() < 1e-6);
+        }
+
+        // other channels are not affected
+        let mut apu = APU2A03::new();
+        apu.set_channel_pan(APUChannelKind::Triangle, -1.);
+        apu.set_channel_volume(APUChannelKind::Noise, 0.);
+        assert_eq!(pulse_1_frame_samples(&mut apu), centered);
+
+        let mut apu = APU2A03::new();
+        apu.set_channel_volume(APUChannelKind::Pulse1, 0.);
+        assert!(pulse_1_frame_samples(&mut apu)
+            .iter()
+            .all(|&sample| sample == 0.));
+    }
+
+    #[test]
+    fn samples_for_cycles() {
+        let mut apu = APU2A03::new();
+
+        let cycles_per_sample = apu.cycles_per_sample();
+        assert!((cycles_per_sample - CPU_FREQ / SAMPLE_RATE as f64).abs() < 1e-9);
+
+        // uneven steps, so the counter is left in the middle of a sample
+        for cycles in [1, 40, 81, 7, 29780, 0, 333, 1_000_003] {
+            let expected = apu.samples_for_cycles(cycles);
+            let start = apu.generated_audio_samples();
+            for _ in 0..cycles {
+                apu.clock();
+            }
+
+            assert_eq!(apu.generated_audio_samples() - start, expected);
+        }
+
+        // the average rate is the same as `cycles_per_sample`
+        let samples = apu.samples_for_cycles(1_000_000);
+        let expected = 1_000_000. / cycles_per_sample;
+        assert!((samples as f64 - expected).abs() <= 1.);
+    }
+
+    #[test]
+    fn frame_counter_clocks() {
+        let mut apu = APU2A03::new();
+
+        // pulse 1 and noise with decaying envelopes of period 0, and length
+        // index 0 (10 half frames)
+        apu.write(0x4015, 0x09, Device::CPU);
+        apu.write(0x4000, 0x00, Device::CPU);
+        apu.write(0x4003, 0x00, Device::CPU);
+        apu.write(0x400C, 0x01, Device::CPU);
+        apu.write(0x400F, 0x00, Device::CPU);
+
+        // the first clock handles the start flag, then the volume decays on
+        // every clock
+        let volumes = (0..4)
+            .map(|_| {
+                apu.clock_quarter_frame();
+                apu.envelope_volumes()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            volumes,
+            [[15, 0, 15], [14, 0, 15], [13, 0, 14], [12, 0, 14]]
+        );
+
+        // the length counters are not affected by quarter frames
+        assert_eq!(apu.read(0x4015, Device::CPU), 0x09);
+        for _ in 0..9 {
+            apu.clock_half_frame();
+        }
+        assert_eq!(apu.read(0x4015, Device::CPU), 0x09);
+        apu.clock_half_frame();
+        assert_eq!(apu.read(0x4015, Device::CPU), 0x00);
+
+        // and the envelopes are not affected by half frames
+        assert_eq!(apu.envelope_volumes(), [12, 0, 14]);
+    }
+
+    /// the volume after each of `clocks` envelope clocks
+    fn envelope_trace(envelope: &mut EnvelopeGenerator, clocks: usize) -> Vec<u8> {
+        (0..clocks)
+            .map(|_| {
+                envelope.clock();
+                envelope.get_current_volume() as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn envelope_decay() {
+        let mut envelope = EnvelopeGenerator::new();
+
+        // period 2, the decay level is decremented every 3 clocks
+        envelope.set_volume(2, false);
+        envelope.set_loop_flag(false);
+        envelope.set_start_flag(true);
+        // the start flag is handled on the next clock, not on the write
+        assert_eq!(envelope.get_current_volume(), 0.);
+        assert_eq!(
+            envelope_trace(&mut envelope, 10),
+            [15, 15, 15, 14, 14, 14, 13, 13, 13, 12]
+        );
+
+        // the constant volume does not stop the decay, the volume is also
+        // the divider period, the divider was reloaded with 2 on the last
+        // clock, so it is reloaded with 7 on the third clock
+        envelope.set_volume(7, true);
+        assert_eq!(envelope_trace(&mut envelope, 3), [7, 7, 7]);
+        envelope.set_volume(0, false);
+        assert_eq!(
+            envelope_trace(&mut envelope, 8),
+            [11, 11, 11, 11, 11, 11, 11, 10]
+        );
+
+        // the decay level stays at 0 without the loop flag
+        assert_eq!(envelope_trace(&mut envelope, 11)[8..], [1, 0, 0]);
+
+        // and is reloaded with 15 with it
+        envelope.set_loop_flag(true);
+        envelope.set_start_flag(true);
+        let trace = envelope_trace(&mut envelope, 17);
+        assert_eq!(trace[..16], (0..=15).rev().collect::<Vec<_>>()[..]);
+        assert_eq!(trace[16], 15);
+    }
+
+    #[test]
+    fn envelope_start_flag_registers() {
+        let mut apu = APU2A03::new();
+
+        // decaying envelopes with period 0
+        for register in [0x4000, 0x4004, 0x400C] {
+            apu.write(register, 0x00, Device::CPU);
+        }
+        for register in [0x4003, 0x4007, 0x400F] {
+            apu.write(register, 0x00, Device::CPU);
+        }
+
+        // 4 quarter frames
+        for _ in 0..30000 {
+            apu.clock();
+        }
+        assert_eq!(apu.envelope_volumes(), [12, 12, 12]);
+
+        // writing the volume registers does not restart the envelopes
+        for register in [0x4000, 0x4004, 0x400C] {
+            apu.write(register, 0x00, Device::CPU);
+        }
+        for _ in 0..30000 {
+            apu.clock();
+        }
+        assert_eq!(apu.envelope_volumes(), [8, 8, 8]);
+    }
+}
@@ -1,19 +1,96 @@
 use super::{
+    chr_switch_log::{ChrSwitchEvent, ChrSwitchLog},
     error::{CartridgeError, SramError},
     mapper::{Mapper, MappingResult},
     mappers::*,
+    rom_info::{crc32_update, mapper_name, HeaderFormat, RomInfo},
 };
 use crate::common::{
+    atomic_file,
     interconnection::CPUIrqProvider,
     save_state::{Savable, SaveError},
-    Bus, Device, MirroringMode, MirroringProvider,
+    Bus, Device, MirroringMode, MirroringProvider, NametableTarget, TvRegion,
 };
+use serde::{Deserialize, Serialize};
 use std::{
     fs::File,
-    io::{Read, Seek, SeekFrom, Write},
+    io::{self, Cursor, ErrorKind, Read, Write},
     path::Path,
 };
 
+/// the console the ROM is made for, from the header, PlayChoice-10 ROMs are
+/// run as normal NES ROMs, so the colors may be wrong
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsoleType {
+    /// NES/Famicom
+    Nes,
+    /// Nintendo VS. System, has a different palette for each PPU model and
+    /// may have the controllers swapped
+    VsSystem,
+    /// PlayChoice-10, uses an RGB PPU palette
+    PlayChoice10,
+    /// NES 2.0 extended console type (byte 13), for example Famiclones and
+    /// VT0x consoles
+    Extended(u8),
+}
+
+impl ConsoleType {
+    /// decode the console type from the flags in byte 7 of the header and
+    /// the extended console type in byte 13 (only used in NES 2.0)
+    fn from_header(console_type: u8, extended_type: u8, is_nes2: bool) -> Self {
+        match console_type & 0x3 {
+            0 => Self::Nes,
+            1 => Self::VsSystem,
+            2 => Self::PlayChoice10,
+            _ if is_nes2 => match extended_type & 0xF {
+                // these are the same as the values in byte 7
+                0 => Self::Nes,
+                1 => Self::VsSystem,
+                2 => Self::PlayChoice10,
+                extended => Self::Extended(extended),
+            },
+            // both flags are set in iNES 1.0, which is not valid
+            _ => Self::Nes,
+        }
+    }
+}
+
+/// the PPU of a VS. System board, from byte 13 of NES 2.0 headers, the RGB
+/// PPUs have a different palette from the NES PPU, and the RP2C04 models
+/// scramble the order of the colors as a copy protection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VsPpuType {
+    /// RP2C03 and RC2C03 variants, also used for iNES 1.0 headers
+    Rp2c03,
+    Rp2c04_0001,
+    Rp2c04_0002,
+    Rp2c04_0003,
+    Rp2c04_0004,
+    /// RC2C05 variants, same colors as the RP2C03
+    Rc2c05,
+}
+
+impl VsPpuType {
+    fn from_header(vs_type: u8) -> Self {
+        match vs_type & 0xF {
+            2 => Self::Rp2c04_0001,
+            3 => Self::Rp2c04_0002,
+            4 => Self::Rp2c04_0003,
+            5 => Self::Rp2c04_0004,
+            8..=0xC => Self::Rc2c05,
+            _ => Self::Rp2c03,
+        }
+    }
+}
+
+/// the data after the header of an iNES file
+struct RomSections {
+    trainer: Vec<u8>,
+    prg: Vec<u8>,
+    /// empty for CHR RAM
+    chr: Vec<u8>,
+}
+
 struct INesHeader {
     // in 16kb units
     prg_rom_size: u16,
@@ -26,6 +103,12 @@ struct INesHeader {
     use_hardwaired_4_screen_mirroring: bool,
     mapper_id: u16,
     submapper_id: u8,
+    is_nes2: bool,
+    console_type: ConsoleType,
+    /// only set for [`ConsoleType::VsSystem`]
+    vs_ppu_type: Option<VsPpuType>,
+    /// `None` for multiple regions
+    tv_system: Option<TvRegion>,
     prg_wram_size: u32,
     prg_sram_size: u32,
     chr_wram_size: u32,
@@ -66,10 +149,13 @@ impl INesHeader {
             }
 
             let prg_ram_size;
+            let mut tv_system = TvRegion::Ntsc;
 
             if !is_archaic_ines {
                 prg_ram_size = if header[8] == 0 { 1 } else { header[8] };
-                let ntcs_tv_system = header[9] & 1 == 0;
+                if header[9] & 1 != 0 {
+                    tv_system = TvRegion::Pal;
+                }
 
                 if header[9] >> 1 != 0 {
                     return Err(CartridgeError::HeaderError);
@@ -95,6 +181,10 @@ impl INesHeader {
                 use_hardwaired_4_screen_mirroring,
                 mapper_id: mapper_id_middle << 4 | mapper_id_low,
                 submapper_id: 0,
+                is_nes2: false,
+                console_type: ConsoleType::from_header(console_type, 0, false),
+                vs_ppu_type: (console_type == 1).then_some(VsPpuType::Rp2c03),
+                tv_system: Some(tv_system),
                 prg_wram_size: prg_ram_size as u32 * 0x2000,
                 prg_sram_size: prg_ram_size as u32 * 0x2000,
                 chr_wram_size: 0x2000, // can only use 8kb
@@ -120,18 +210,39 @@ impl INesHeader {
             let shift_size = (header[11] & 0xF) as u32;
             let chr_sram_size_bytes = if shift_size != 0 { 64 << shift_size } else { 0 };
 
+            let tv_system = match header[12] & 0x3 {
+                0 => Some(TvRegion::Ntsc),
+                1 => Some(TvRegion::Pal),
+                2 => None,
+                _ => Some(TvRegion::Dendy),
+            };
+
             // TODO: implement the rest
 
+            let decoded_console_type = ConsoleType::from_header(console_type, header[13], true);
+            // with the extended console type, byte 13 does not have the PPU
+            let vs_ppu_type = match decoded_console_type {
+                ConsoleType::VsSystem if console_type == 1 => {
+                    Some(VsPpuType::from_header(header[13]))
+                }
+                ConsoleType::VsSystem => Some(VsPpuType::Rp2c03),
+                _ => None,
+            };
+
             Ok(Self {
                 prg_rom_size: prg_size_high << 8 | prg_size_low,
                 chr_rom_size: chr_size_high << 8 | chr_size_low,
-                is_chr_ram,
+                is_chr_ram: is_chr_ram && chr_size_high == 0,
                 hardwired_mirroring_vertical,
                 has_prg_ram_battery,
                 contain_trainer_data,
                 use_hardwaired_4_screen_mirroring,
                 mapper_id: mapper_id_high << 8 | mapper_id_middle << 4 | mapper_id_low,
                 submapper_id,
+                is_nes2: true,
+                console_type: decoded_console_type,
+                vs_ppu_type,
+                tv_system,
                 prg_wram_size: prg_wram_size_bytes,
                 prg_sram_size: prg_sram_size_bytes,
                 chr_wram_size: chr_wram_size_bytes,
@@ -140,6 +251,104 @@ impl INesHeader {
         }
     }
 
+    /// the size of CHR RAM, at least 8KB even if the header says otherwise,
+    /// as all boards without CHR ROM have CHR RAM
+    fn chr_ram_size(&self) -> u32 {
+        self.chr_wram_size.max(0x2000)
+    }
+
+    /// the size of PRG RAM declared by the header, for boards where it is
+    /// optional, iNES 1.0 headers always report at least 8KB, so only the
+    /// battery flag can tell if there is PRG RAM
+    fn declared_prg_ram_size(&self) -> u32 {
+        if self.has_prg_ram_battery {
+            self.prg_sram_size
+        } else if self.is_nes2 {
+            self.prg_wram_size
+        } else {
+            0
+        }
+    }
+
+    /// `chr_rom` is empty for CHR RAM, `trailing_data_size` is the number of
+    /// bytes after the ROMs in the file
+    fn rom_info(&self, prg_rom: &[u8], chr_rom: &[u8], trailing_data_size: usize) -> RomInfo {
+        let (prg_ram_size, prg_nvram_size) = if self.has_prg_ram_battery {
+            let prg_ram_size = if self.is_nes2 { self.prg_wram_size } else { 0 };
+            (prg_ram_size, self.prg_sram_size)
+        } else {
+            (self.prg_wram_size, 0)
+        };
+
+        let mirroring = if self.use_hardwaired_4_screen_mirroring {
+            MirroringMode::FourScreen
+        } else if self.hardwired_mirroring_vertical {
+            MirroringMode::Vertical
+        } else {
+            MirroringMode::Horizontal
+        };
+
+        RomInfo {
+            header_format: if self.is_nes2 {
+                HeaderFormat::Nes2
+            } else {
+                HeaderFormat::INes
+            },
+            mapper: self.mapper_id,
+            submapper: self.submapper_id,
+            mapper_name: mapper_name(self.mapper_id).map(str::to_owned),
+            console_type: self.console_type,
+            prg_rom_size: prg_rom.len(),
+            chr_rom_size: chr_rom.len(),
+            chr_ram_size: if self.is_chr_ram {
+                self.chr_ram_size() as usize
+            } else {
+                0
+            },
+            prg_ram_size: prg_ram_size as usize,
+            prg_nvram_size: prg_nvram_size as usize,
+            has_battery: self.has_prg_ram_battery,
+            has_trainer: self.contain_trainer_data,
+            mirroring,
+            tv_system: self.tv_system,
+            prg_crc32: crc32_update(0, prg_rom),
+            chr_crc32: if chr_rom.is_empty() {
+                0
+            } else {
+                crc32_update(0, chr_rom)
+            },
+            crc32: crc32_update(crc32_update(0, prg_rom), chr_rom),
+            trailing_data_size,
+        }
+    }
+
+    /// read the trainer, PRG ROM and CHR ROM (in this order) after the
+    /// header
+    fn read_sections<R: Read>(&self, reader: &mut R) -> Result<RomSections, CartridgeError> {
+        let trainer_data = if self.contain_trainer_data {
+            read_trainer(reader)?
+        } else {
+            Vec::new()
+        };
+
+        let chr_rom_size = if self.is_chr_ram {
+            0
+        } else {
+            (self.chr_rom_size as usize) * 8 * 1024
+        };
+        let (prg_data, chr_data) = read_roms(
+            reader,
+            (self.prg_rom_size as usize) * 16 * 1024,
+            chr_rom_size,
+        )?;
+
+        Ok(RomSections {
+            trainer: trainer_data,
+            prg: prg_data,
+            chr: chr_data,
+        })
+    }
+
     fn empty() -> Self {
         Self::from_bytes([0x4E, 0x45, 0x53, 0x1A, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap()
     }
@@ -150,9 +359,83 @@ impl INesHeader {
         if header == real {
             Ok(())
         } else {
-            Err(CartridgeError::HeaderError)
+            Err(CartridgeError::BadHeaderMagic)
         }
     }
+
+    /// read and decode the 16 bytes header
+    fn read<R: Read>(reader: &mut R) -> Result<Self, CartridgeError> {
+        let mut header = [0; 16];
+        reader.read_exact(&mut header).map_err(|err| {
+            if err.kind() == ErrorKind::UnexpectedEof {
+                CartridgeError::TooSmall
+            } else {
+                err.into()
+            }
+        })?;
+
+        Self::from_bytes(header)
+    }
+}
+
+/// decode the header and read the ROMs from `reader` for [`RomInfo`], using
+/// the same parsing as loading the cartridge, but without creating the
+/// mapper, so unsupported mappers are not an error
+pub(super) fn read_rom_info<R: Read>(mut reader: R) -> Result<RomInfo, CartridgeError> {
+    let header = INesHeader::read(&mut reader)?;
+    let sections = header.read_sections(&mut reader)?;
+    let trailing_data_size = read_trailing_data_size(&mut reader)?;
+
+    Ok(header.rom_info(&sections.prg, &sections.chr, trailing_data_size))
+}
+
+/// skip the rest of the file and return its size, some dumps have extra data
+/// at the end (title, or garbage), it is not used by the cartridge
+fn read_trailing_data_size<R: Read>(reader: &mut R) -> Result<usize, CartridgeError> {
+    Ok(io::copy(reader, &mut io::sink())? as usize)
+}
+
+/// read up to `size` bytes, the result is shorter if the file ends before
+fn read_rom_section<R: Read>(reader: &mut R, size: usize) -> Result<Vec<u8>, CartridgeError> {
+    let mut data = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+/// read the 512 bytes trainer, which comes after the header
+fn read_trainer<R: Read>(reader: &mut R) -> Result<Vec<u8>, CartridgeError> {
+    let data = read_rom_section(reader, 512)?;
+    if data.len() != 512 {
+        return Err(CartridgeError::TooSmall);
+    }
+
+    Ok(data)
+}
+
+/// read the PRG and CHR ROMs (in this order), `chr_size` is `0` for CHR RAM
+fn read_roms<R: Read>(
+    reader: &mut R,
+    prg_size: usize,
+    chr_size: usize,
+) -> Result<(Vec<u8>, Vec<u8>), CartridgeError> {
+    let prg_data = read_rom_section(reader, prg_size)?;
+    if prg_data.len() != prg_size {
+        return Err(CartridgeError::PrgSizeMismatch {
+            expected: prg_size,
+            actual: prg_data.len(),
+        });
+    }
+
+    let chr_data = read_rom_section(reader, chr_size)?;
+    if chr_data.len() != chr_size {
+        return Err(CartridgeError::ChrSizeMismatch {
+            expected: chr_size,
+            actual: chr_data.len(),
+        });
+    }
+
+    Ok((prg_data, chr_data))
 }
 
 pub struct Cartridge {
@@ -164,8 +447,19 @@ pub struct Cartridge {
     pub(crate) chr_data: Vec<u8>,
     prg_ram_data: Vec<u8>,
 
+    /// 8K PRG RAM used when [`force_prg_ram`] is enabled and the mapper does not
+    /// map `0x6000-0x7FFF`, it is not part of the save state
+    forced_prg_ram_data: Vec<u8>,
+    force_prg_ram: bool,
+
     mapper: Box<dyn Mapper>,
 
+    /// the size of the extra data after the ROMs in the file, it is ignored
+    trailing_data_size: usize,
+
+    /// only collected when enabled, it is not part of the save state
+    chr_switch_log: Option<ChrSwitchLog>,
+
     is_empty: bool,
 }
 
@@ -174,82 +468,118 @@ impl Cartridge {
     pub fn from_file<P: AsRef<Path>>(file_path: P) -> Result<Self, CartridgeError> {
         if let Some(extension) = file_path.as_ref().extension() {
             if extension == "nes" {
-                let mut file = File::open(file_path.as_ref())?;
-
-                let mut header = [0; 16];
-                file.read_exact(&mut header)?;
+                let file = File::open(file_path.as_ref())?;
 
-                // decode header
-                let header = INesHeader::from_bytes(header)?;
+                Self::from_reader(file_path.as_ref(), file)
+            } else {
+                Err(CartridgeError::ExtensionError)
+            }
+        } else {
+            Err(CartridgeError::ExtensionError)
+        }
+    }
 
-                let sram_data = if header.has_prg_ram_battery {
-                    // try to load old save data
-                    if let Ok(data) =
-                        Self::load_sram_file(file_path.as_ref(), header.prg_sram_size as usize)
-                    {
-                        data
-                    } else {
-                        vec![0; header.prg_sram_size as usize]
-                    }
-                } else {
-                    vec![0; header.prg_wram_size as usize]
-                };
+    /// load the ROM `entry` from the zip archive at `zip_path`, if `entry` is
+    /// `None`, the archive must have only one `.nes` file. The SRAM save file
+    /// is kept next to the archive, with the name of the ROM
+    #[cfg(feature = "zip")]
+    pub fn from_zip<P: AsRef<Path>>(
+        zip_path: P,
+        entry: Option<&str>,
+    ) -> Result<Self, CartridgeError> {
+        let (file_path, data) = super::zip_archive::read_zip_rom(zip_path.as_ref(), entry)?;
+
+        Self::from_reader(&file_path, Cursor::new(data))
+    }
 
-                println!("mapper {}", header.mapper_id);
+    /// load the cartridge data from `reader`, `file_path` is only used for
+    /// the SRAM save file
+    pub(crate) fn from_reader<R: Read>(
+        file_path: &Path,
+        mut reader: R,
+    ) -> Result<Self, CartridgeError> {
+        let header = INesHeader::read(&mut reader)?;
+
+        let sram_data = if header.has_prg_ram_battery {
+            // try to load old save data
+            if let Ok(data) = Self::load_sram_file(file_path, header.prg_sram_size as usize) {
+                data
+            } else {
+                vec![0; header.prg_sram_size as usize]
+            }
+        } else if header.console_type == ConsoleType::VsSystem {
+            // the VS. System has 2KB of work RAM at `0x6000-0x7FFF` on the
+            // main board, even if the header does not declare it
+            vec![0; header.prg_wram_size.max(0x800) as usize]
+        } else {
+            vec![0; header.prg_wram_size as usize]
+        };
 
-                // initialize the mapper first, so that if it is not supported yet,
-                // panic
-                let mapper = Self::get_mapper(&header)?;
+        println!("mapper {}", header.mapper_id);
 
-                let mut trainer_data = Vec::new();
+        // initialize the mapper first, so that if it is not supported yet,
+        // or the header is not valid for it, return an error
+        let mapper = Self::get_mapper(&header)?;
 
-                // read training data if present
-                if header.contain_trainer_data {
-                    trainer_data.resize(512, 0);
-                    file.read_exact(&mut trainer_data)?;
-                }
+        let RomSections {
+            trainer: trainer_data,
+            prg: prg_data,
+            chr: chr_data,
+        } = header.read_sections(&mut reader)?;
 
-                // read PRG data
-                let mut prg_data = vec![0; (header.prg_rom_size as usize) * 16 * 1024];
-                file.read_exact(&mut prg_data)?;
-
-                // read CHR data
-                let chr_data = if !header.is_chr_ram {
-                    let mut data = vec![0; (header.chr_rom_size as usize) * 8 * 1024];
-                    file.read_exact(&mut data)?;
-
-                    data
-                } else {
-                    // TODO: there is no way of knowing if we are using CHR WRAM or SRAM
-                    let ram_size = header.chr_wram_size;
-
-                    vec![0; ram_size as usize]
-                };
-
-                // there are missing parts
-                let current = file.seek(SeekFrom::Current(0))?;
-                let end = file.seek(SeekFrom::End(0))?;
-                if current != end {
-                    Err(CartridgeError::TooLargeFile(end - current))
-                } else {
-                    Ok(Self {
-                        file_path: file_path.as_ref().to_path_buf().into_boxed_path(),
-                        header,
-                        _trainer_data: trainer_data,
-                        prg_data,
-                        chr_data,
-                        prg_ram_data: sram_data,
-                        mapper,
-
-                        is_empty: false,
-                    })
-                }
-            } else {
-                Err(CartridgeError::ExtensionError)
-            }
+        let chr_data = if header.is_chr_ram {
+            // TODO: there is no way of knowing if we are using CHR WRAM or SRAM
+            vec![0; header.chr_ram_size() as usize]
         } else {
-            Err(CartridgeError::ExtensionError)
+            chr_data
+        };
+
+        let trailing_data_size = read_trailing_data_size(&mut reader)?;
+
+        Ok(Self {
+            file_path: file_path.to_path_buf().into_boxed_path(),
+            header,
+            _trainer_data: trainer_data,
+            prg_data,
+            chr_data,
+            prg_ram_data: sram_data,
+            forced_prg_ram_data: Vec::new(),
+            force_prg_ram: false,
+            mapper,
+            trailing_data_size,
+
+            chr_switch_log: None,
+
+            is_empty: false,
+        })
+    }
+
+    /// NROM cartridge with `prg` at `0x8000` (mirrored at `0xC000` if it
+    /// fits in 16KB), 8KB of PRG RAM and 8KB of CHR RAM, the rest of the PRG
+    /// is filled with zeros, if `prg` does not reach the vectors, RESET
+    /// points to `0x8000`, and NMI and IRQ point to an `RTI` before them
+    ///
+    /// panics if `prg` is larger than 32KB
+    pub(crate) fn from_prg(prg: &[u8]) -> Self {
+        assert!(prg.len() <= 0x8000, "NROM PRG ROM is at most 32KB");
+
+        let prg_size = if prg.len() <= 0x4000 { 0x4000 } else { 0x8000 };
+        let mut prg_data = vec![0; prg_size];
+        prg_data[..prg.len()].copy_from_slice(prg);
+        if prg.len() < prg_size - 6 {
+            // `RTI` at `0xFFF9`, then the NMI, RESET and IRQ vectors
+            prg_data[prg_size - 7..].copy_from_slice(&[0x40, 0xF9, 0xFF, 0x00, 0x80, 0xF9, 0xFF]);
         }
+
+        // NES 2.0 header, with 8KB of PRG RAM and CHR RAM
+        let prg_count = (prg_size / 0x4000) as u8;
+        let mut rom = vec![
+            b'N', b'E', b'S', 0x1A, prg_count, 0, 0, 0x08, 0, 0, 0x07, 0x07, 0, 0, 0, 0,
+        ];
+        rom.extend_from_slice(&prg_data);
+
+        Self::from_reader(Path::new(""), Cursor::new(rom))
+            .expect("the generated NROM cartridge is valid")
     }
 
     pub fn new_without_file() -> Self {
@@ -261,7 +591,12 @@ impl Cartridge {
             prg_data: Vec::new(),
             chr_data: Vec::new(),
             prg_ram_data: Vec::new(),
-            mapper: Box::new(Mapper0::new()),
+            forced_prg_ram_data: Vec::new(),
+            force_prg_ram: false,
+            mapper: Box::new(Mapper0::new(0)),
+            trailing_data_size: 0,
+
+            chr_switch_log: None,
 
             is_empty: true,
         }
@@ -269,7 +604,7 @@ impl Cartridge {
 
     fn get_mapper(header: &INesHeader) -> Result<Box<dyn Mapper>, CartridgeError> {
         let mut mapper: Box<dyn Mapper> = match header.mapper_id {
-            0 => Box::new(Mapper0::new()),
+            0 => Box::new(Mapper0::new(header.declared_prg_ram_size() as usize)),
             1 => Box::new(Mapper1::new()),
             2 => Box::new(Mapper2::new()),
             3 => Box::new(Mapper3::new()),
@@ -279,29 +614,57 @@ impl Cartridge {
             10 => Box::new(Mapper10::new()),
             11 => Box::new(Mapper11::new()),
             12 => Box::new(Mapper12::new()),
+            21 | 22 | 23 | 25 => Box::new(Mapper21::new(KonamiVrcBoard::from_mapper(
+                header.mapper_id,
+                header.submapper_id,
+            ))),
+            31 => Box::new(Mapper31::new()),
+            64 => Box::new(Mapper64::new()),
             66 => Box::new(Mapper66::new()),
+            79 | 146 => Box::new(Mapper79::new()),
+            88 => Box::new(Mapper206::new(Namco108Board::Mapper88)),
+            99 => Box::new(Mapper99::new()),
+            113 => Box::new(Mapper113::new()),
+            154 => Box::new(Mapper206::new(Namco108Board::Mapper154)),
+            185 => Box::new(Mapper185::new(header.submapper_id)),
+            206 => Box::new(Mapper206::new(Namco108Board::Mapper206)),
             _ => {
                 return Err(CartridgeError::MapperNotImplemented(header.mapper_id));
             }
         };
 
+        let chr_count = if !header.is_chr_ram {
+            header.chr_rom_size
+        } else {
+            (header.chr_ram_size() / 0x2000) as u16
+        };
+        let sram_count = if header.has_prg_ram_battery {
+            header.prg_sram_size / 0x2000
+        } else {
+            header.prg_wram_size / 0x2000
+        };
+
+        // the mappers use `u8` for the number of banks, and some of them
+        // convert the count into smaller banks (`* 8` at most for 1KB CHR
+        // banks, but `u16` is used there), so keep it in `1..=127`
+        if header.prg_rom_size == 0
+            || header.prg_rom_size > 127
+            || chr_count == 0
+            || chr_count > 127
+            || sram_count > 255
+        {
+            return Err(CartridgeError::InvalidBankCount);
+        }
+
         // FIXME: fix parameters types to support INES2.0
         // should always call init in a new mapper, as it is the only way
         // they share a constructor
         mapper.init(
             header.prg_rom_size as u8,
             header.is_chr_ram,
-            if !header.is_chr_ram {
-                header.chr_rom_size as u8
-            } else {
-                (header.chr_wram_size / 0x2000) as u8
-            },
-            if header.has_prg_ram_battery {
-                header.prg_sram_size / 0x2000
-            } else {
-                header.prg_wram_size / 0x2000
-            } as u8,
-        );
+            chr_count as u8,
+            sram_count as u8,
+        )?;
 
         Ok(mapper)
     }
@@ -323,17 +686,198 @@ impl Cartridge {
         let path = self.file_path.with_extension("nes.sav");
         println!("Writing SRAM file data to {:?}", path);
 
-        let mut file = File::create(&path)?;
+        // the old save file is kept if writing fails
+        atomic_file::write_atomic(&path, &self.prg_ram_data)?;
 
-        let size = file.write(&self.prg_ram_data)?;
+        Ok(())
+    }
+
+    /// some ROMs use `0x6000-0x7FFF` as RAM even though the header and the mapper
+    /// say there is no PRG RAM, enabling this will provide 8K of RAM in that
+    /// region whenever the mapper denies the access
+    pub fn set_force_prg_ram(&mut self, force: bool) {
+        self.force_prg_ram = force;
+
+        if force && self.forced_prg_ram_data.is_empty() {
+            self.forced_prg_ram_data = vec![0; 0x2000];
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_mapper(&mut self, mapper: Box<dyn Mapper>) {
+        self.mapper = mapper;
+    }
 
-        if size != self.header.prg_sram_size as usize {
-            file.sync_all()?;
-            // remove the file so it will not be loaded next time the game is run
-            std::fs::remove_file(path).expect("Could not remove `nes.sav` file");
-            Err(SramError::FailedToSaveSramFile)
+    /// the saved state of the mapper
+    pub fn mapper_state(&self) -> Vec<u8> {
+        if self.is_empty {
+            Vec::new()
         } else {
-            Ok(())
+            let mut state = Vec::with_capacity(self.mapper.save_state_size());
+            self.mapper.save_state(&mut state);
+            state
+        }
+    }
+
+    /// read the cartridge part of a saved state without loading it, and
+    /// return the mapper state in it like [`Cartridge::mapper_state`]
+    pub(crate) fn mapper_state_in_state<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Vec<u8>, SaveError> {
+        let mut mapper_state = vec![0; self.mapper.save_state_size()];
+        reader.read_exact(&mut mapper_state)?;
+
+        let mut prg_ram = vec![0; self.prg_ram_data.len()];
+        reader.read_exact(&mut prg_ram)?;
+
+        let mut is_chr_ram = [0u8; 1];
+        reader.read_exact(&mut is_chr_ram)?;
+        if is_chr_ram[0] != 0 {
+            let mut chr_ram = vec![0; self.chr_data.len()];
+            reader.read_exact(&mut chr_ram)?;
+        }
+
+        if self.is_empty {
+            mapper_state.clear();
+        }
+
+        Ok(mapper_state)
+    }
+
+    /// the registers of the mapper formatted for debugging, `None` if there
+    /// is no cartridge
+    pub fn mapper_debug_state(&self) -> Option<String> {
+        if self.is_empty {
+            None
+        } else {
+            Some(self.mapper.debug_state())
+        }
+    }
+
+    /// read from the 4KB CHR `bank` without going through the mapper, banks
+    /// past the end of CHR are mirrored
+    pub fn read_chr_bank(&self, bank: u8, offset: usize) -> u8 {
+        if self.chr_data.is_empty() {
+            return 0;
+        }
+
+        self.chr_data[(bank as usize * 0x1000 + offset) % self.chr_data.len()]
+    }
+
+    /// the 1KB CHR bank that the PPU `address` (`0x0000-0x1FFF`) is mapped
+    /// from, this goes through the mapper again, so it is only meant for
+    /// debugging
+    pub fn chr_bank(&self, address: u16) -> Option<u16> {
+        if self.is_empty || self.chr_data.is_empty() {
+            return None;
+        }
+
+        match self.mapper.map_read(address, Device::PPU) {
+            MappingResult::Allowed(offset) => Some(((offset % self.chr_data.len()) / 0x400) as u16),
+            MappingResult::Denied => None,
+        }
+    }
+
+    /// signal the mapper that the PPU address bus bit 12 went from 0 to 1
+    pub fn notify_ppu_a12_rising_edge(&mut self) {
+        if !self.is_empty {
+            self.mapper.ppu_a12_rising_edge();
+        }
+    }
+
+    /// write to CHR memory through the mapper like a PPU write, returns
+    /// `false` if the mapper does not allow it (CHR ROM)
+    pub fn write_chr(&mut self, address: u16, data: u8) -> bool {
+        if self.is_empty {
+            return false;
+        }
+
+        match self.mapper.map_write(address & 0x1FFF, data, Device::PPU) {
+            MappingResult::Allowed(new_address) => {
+                let len = self.chr_data.len();
+                self.chr_data[new_address % len] = data;
+                true
+            }
+            MappingResult::Denied => false,
+        }
+    }
+
+    /// restore the state of the mapper saved with [`Cartridge::mapper_state`]
+    pub(crate) fn restore_mapper_state(&mut self, data: &[u8]) {
+        if !self.is_empty {
+            self.mapper
+                .load_state(data)
+                .expect("the state was saved by the same mapper");
+        }
+    }
+
+    /// return the mapper to its power-on state, and clear the RAM that is not
+    /// battery backed (PRG RAM and CHR RAM)
+    pub fn power_cycle(&mut self) {
+        if self.is_empty {
+            return;
+        }
+
+        // the header was already accepted by the mapper on load
+        if let Ok(mapper) = Self::get_mapper(&self.header) {
+            self.mapper = mapper;
+        }
+
+        if !self.header.has_prg_ram_battery {
+            self.prg_ram_data.fill(0);
+        }
+        if self.header.is_chr_ram {
+            self.chr_data.fill(0);
+        }
+        self.forced_prg_ram_data.fill(0);
+    }
+
+    /// replace the PRG ROM and CHR ROM with the data from `reader`, which
+    /// must have the same mapper and sizes, everything else (RAMs, CHR RAM
+    /// and the mapper registers) is kept
+    pub(crate) fn reload_rom_data<R: Read>(&mut self, mut reader: R) -> Result<(), CartridgeError> {
+        let header = INesHeader::read(&mut reader)?;
+
+        if self.is_empty
+            || header.mapper_id != self.header.mapper_id
+            || header.submapper_id != self.header.submapper_id
+            || header.prg_rom_size != self.header.prg_rom_size
+            || header.chr_rom_size != self.header.chr_rom_size
+            || header.is_chr_ram != self.header.is_chr_ram
+        {
+            return Err(CartridgeError::ReloadMismatch);
+        }
+
+        if header.contain_trainer_data {
+            read_trainer(&mut reader)?;
+        }
+
+        // read everything first, so that nothing is changed on error
+        let chr_rom_size = if header.is_chr_ram {
+            0
+        } else {
+            self.chr_data.len()
+        };
+        let (prg_data, chr_data) = read_roms(&mut reader, self.prg_data.len(), chr_rom_size)?;
+
+        if !header.is_chr_ram {
+            self.chr_data = chr_data;
+        }
+        self.prg_data = prg_data;
+
+        Ok(())
+    }
+
+    /// does the mapper handle writes to `0x4020-0x5FFF`
+    pub fn uses_expansion_area(&self) -> bool {
+        !self.is_empty && self.mapper.uses_expansion_area()
+    }
+
+    /// should be called on every CPU cycle
+    pub fn cpu_clock(&mut self) {
+        if !self.is_empty {
+            self.mapper.cpu_clock();
         }
     }
 
@@ -344,6 +888,119 @@ impl Cartridge {
     pub fn cartridge_path(&self) -> &Path {
         &self.file_path
     }
+
+    /// the header information and CRCs of the loaded ROM, `None` if there
+    /// is no cartridge
+    pub fn rom_info(&self) -> Option<RomInfo> {
+        if self.is_empty {
+            return None;
+        }
+
+        let chr_rom: &[u8] = if self.header.is_chr_ram {
+            &[]
+        } else {
+            &self.chr_data
+        };
+
+        Some(
+            self.header
+                .rom_info(&self.prg_data, chr_rom, self.trailing_data_size),
+        )
+    }
+
+    pub fn console_type(&self) -> ConsoleType {
+        self.header.console_type
+    }
+
+    /// the PPU of the VS. System board, `None` for other consoles
+    pub fn vs_ppu_type(&self) -> Option<VsPpuType> {
+        self.header.vs_ppu_type
+    }
+
+    /// called on CPU writes to `$4016`, the VS. System boards use the
+    /// OUT2 pin (bit 2) for bank switching
+    pub fn write_controller_port(&mut self, data: u8) {
+        if !self.is_empty {
+            let banks_before = if self.chr_switch_log.is_some() {
+                Some(self.chr_banks())
+            } else {
+                None
+            };
+
+            self.mapper.write_controller_port(data);
+
+            if let Some(banks_before) = banks_before {
+                self.record_chr_switches(0x4016, banks_before);
+            }
+        }
+    }
+
+    /// collect the changes of the CHR banks done by CPU writes to the mapper,
+    /// disabling clears the collected events
+    pub fn set_chr_switch_logging(&mut self, enabled: bool) {
+        self.chr_switch_log = if enabled {
+            Some(ChrSwitchLog::new())
+        } else {
+            None
+        };
+    }
+
+    /// set the CPU cycle to use for the next CHR switch events
+    pub fn set_chr_switch_cycle(&mut self, cpu_cycle: u64) {
+        if let Some(log) = &mut self.chr_switch_log {
+            log.set_cpu_cycle(cpu_cycle);
+        }
+    }
+
+    /// take the CHR switch events collected since the last call
+    pub fn take_chr_switch_events(&mut self) -> Vec<ChrSwitchEvent> {
+        self.chr_switch_log
+            .as_mut()
+            .map(|log| log.take())
+            .unwrap_or_default()
+    }
+
+    /// the 1KB CHR bank of every slot of the pattern tables, mapping PPU
+    /// addresses can change the mapper state (MMC2 latches or MMC3 IRQ
+    /// counter), so it is restored after
+    fn chr_banks(&mut self) -> [Option<u16>; 8] {
+        let mut mapper_state = Vec::with_capacity(self.mapper.save_state_size());
+        self.mapper.save_state(&mut mapper_state);
+        let banks = std::array::from_fn(|slot| self.chr_bank(slot as u16 * 0x400));
+        self.mapper
+            .load_state(&mapper_state)
+            .expect("the state was saved by the same mapper");
+
+        banks
+    }
+
+    fn record_chr_switches(&mut self, mapper_register: u16, banks_before: [Option<u16>; 8]) {
+        let banks_after = self.chr_banks();
+        if let Some(log) = &mut self.chr_switch_log {
+            log.record(mapper_register, banks_before, banks_after);
+        }
+    }
+
+    /// headers can declare less PRG RAM than the mapper addresses, in that
+    /// case the missing part is treated as unmapped, for both reads and
+    /// writes, so it goes to the forced PRG RAM if enabled
+    fn deny_missing_prg_ram(
+        &self,
+        address: u16,
+        device: Device,
+        result: MappingResult,
+    ) -> MappingResult {
+        match result {
+            MappingResult::Allowed(new_address)
+                if device == Device::CPU
+                    && (0x6000..=0x7FFF).contains(&address)
+                    && new_address >= self.prg_ram_data.len() =>
+            {
+                MappingResult::Denied
+            }
+            _ => result,
+        }
+    }
 }
 
 impl Bus for Cartridge {
@@ -355,28 +1012,39 @@ impl Bus for Cartridge {
             };
         }
 
-        let result = self.mapper.map_read(address, device);
+        let result =
+            self.deny_missing_prg_ram(address, device, self.mapper.map_read(address, device));
 
         if let MappingResult::Allowed(new_address) = result {
+            // ROMs smaller than what the mapper expects are mirrored
             match device {
                 Device::CPU => match address {
-                    0x6000..=0x7FFF => *self
-                        .prg_ram_data
-                        .get(new_address)
-                        .expect("SRAM out of bounds"),
-                    0x8000..=0xFFFF => *self.prg_data.get(new_address).expect("PRG out of bounds"),
+                    0x6000..=0x7FFF => self.prg_ram_data[new_address],
+                    0x8000..=0xFFFF => self.prg_data[new_address % self.prg_data.len()],
                     _ => {
                         unreachable!();
                     }
                 },
                 Device::PPU => {
                     if address <= 0x1FFF {
-                        *self.chr_data.get(new_address).expect("CHR out of bounds")
+                        self.chr_data[new_address % self.chr_data.len()]
                     } else {
                         unreachable!();
                     }
                 }
             }
+        } else if self.force_prg_ram
+            && device == Device::CPU
+            && (0x6000..=0x7FFF).contains(&address)
+        {
+            self.forced_prg_ram_data[address as usize & 0x1FFF]
+        } else if device == Device::CPU && (0x6000..=0x7FFF).contains(&address) {
+            // there is no open bus emulation, but the last value on the bus
+            // is usually the high byte of the address (the last operand byte
+            // of absolute instructions), so games probing for PRG RAM will
+            // not read back what they wrote
+            self.mapper
+                .read_unmapped_prg_ram(address, (address >> 8) as u8)
         } else {
             0
         }
@@ -386,23 +1054,28 @@ impl Bus for Cartridge {
             return;
         }
 
+        let banks_before = if self.chr_switch_log.is_some() && device == Device::CPU {
+            Some(self.chr_banks())
+        } else {
+            None
+        };
+
         // send the write signal, this might trigger bank change
         let result = self.mapper.map_write(address, data, device);
 
+        if let Some(banks_before) = banks_before {
+            self.record_chr_switches(address, banks_before);
+        }
+
+        let result = self.deny_missing_prg_ram(address, device, result);
+
         if let MappingResult::Allowed(new_address) = result {
             match device {
                 Device::CPU => match address {
-                    0x6000..=0x7FFF => {
-                        *self
-                            .prg_ram_data
-                            .get_mut(new_address)
-                            .expect("SRAM out of bounds") = data;
-                    }
+                    0x6000..=0x7FFF => self.prg_ram_data[new_address] = data,
                     0x8000..=0xFFFF => {
-                        *self
-                            .prg_data
-                            .get_mut(new_address)
-                            .expect("PRG out of bounds") = data;
+                        let len = self.prg_data.len();
+                        self.prg_data[new_address % len] = data;
                     }
                     _ => {
                         unreachable!();
@@ -410,15 +1083,18 @@ impl Bus for Cartridge {
                 },
                 Device::PPU => {
                     if address <= 0x1FFF {
-                        *self
-                            .chr_data
-                            .get_mut(new_address)
-                            .expect("CHR out of bounds") = data;
+                        let len = self.chr_data.len();
+                        self.chr_data[new_address % len] = data;
                     } else {
                         unreachable!();
                     }
                 }
             }
+        } else if self.force_prg_ram
+            && device == Device::CPU
+            && (0x6000..=0x7FFF).contains(&address)
+        {
+            self.forced_prg_ram_data[address as usize & 0x1FFF] = data;
         }
     }
 }
@@ -442,6 +1118,25 @@ impl MirroringProvider for Cartridge {
             self.mapper.nametable_mirroring()
         }
     }
+
+    fn map_nametable(&self, address: u16) -> NametableTarget {
+        if self.is_empty {
+            NametableTarget::Default
+        } else {
+            self.mapper.map_nametable(address)
+        }
+    }
+
+    fn read_nametable_memory(&self, offset: usize) -> u8 {
+        *self.chr_data.get(offset).expect("CHR out of bounds")
+    }
+
+    fn write_nametable_memory(&mut self, offset: usize, data: u8) {
+        // CHR ROM cannot be written to
+        if self.header.is_chr_ram {
+            *self.chr_data.get_mut(offset).expect("CHR out of bounds") = data;
+        }
+    }
 }
 
 impl Drop for Cartridge {
@@ -472,7 +1167,8 @@ impl CPUIrqProvider for Cartridge {
 
 impl Savable for Cartridge {
     fn save<W: Write>(&self, writer: &mut W) -> Result<(), SaveError> {
-        let mapper_saved_state = self.mapper.save_state();
+        let mut mapper_saved_state = Vec::with_capacity(self.mapper.save_state_size());
+        self.mapper.save_state(&mut mapper_saved_state);
         writer.write_all(&mapper_saved_state)?;
 
         writer.write_all(&self.prg_ram_data)?;
@@ -488,7 +1184,7 @@ impl Savable for Cartridge {
     fn load<R: Read>(&mut self, reader: &mut R) -> Result<(), SaveError> {
         let mut mapper_load_data = vec![0; self.mapper.save_state_size()];
         reader.read_exact(&mut mapper_load_data)?;
-        self.mapper.load_state(mapper_load_data);
+        self.mapper.load_state(&mapper_load_data)?;
 
         reader.read_exact(&mut self.prg_ram_data)?;
 
@@ -0,0 +1,62 @@
+/// A change of the CHR bank mapped to a 1KB slot of the pattern tables,
+/// caused by a CPU write to the mapper, see
+/// [`NES::enable_chr_switch_logging`](crate::nes::NES::enable_chr_switch_logging)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChrSwitchEvent {
+    /// the CPU cycle of the write, same as
+    /// [`NES::cpu_cycle_count`](crate::nes::NES::cpu_cycle_count)
+    pub cycle: u64,
+    /// the address of the written register, `0x4016` for the VS. System
+    /// which switches banks with the controller port
+    pub mapper_register: u16,
+    /// the 1KB slot of the pattern tables (PPU address / `0x400`, 0-7), as
+    /// the mappers differ in how their registers are mapped to the slots
+    pub slot: u8,
+    /// the new 1KB bank in CHR memory mapped to `slot`
+    pub new_bank: u16,
+}
+
+/// Collects [`ChrSwitchEvent`]s by comparing the CHR banks of every slot
+/// before and after mapper writes
+pub(super) struct ChrSwitchLog {
+    cpu_cycle: u64,
+    events: Vec<ChrSwitchEvent>,
+}
+
+impl ChrSwitchLog {
+    pub fn new() -> Self {
+        Self {
+            cpu_cycle: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// set the CPU cycle to use for the next recorded events
+    pub fn set_cpu_cycle(&mut self, cpu_cycle: u64) {
+        self.cpu_cycle = cpu_cycle;
+    }
+
+    /// record an event for every slot with a different bank in `before` and
+    /// `after`, the banks are `None` when the slot is not mapped
+    pub fn record(
+        &mut self,
+        mapper_register: u16,
+        before: [Option<u16>; 8],
+        after: [Option<u16>; 8],
+    ) {
+        for (slot, (before, after)) in before.iter().zip(after.iter()).enumerate() {
+            if let (true, Some(new_bank)) = (before != after, after) {
+                self.events.push(ChrSwitchEvent {
+                    cycle: self.cpu_cycle,
+                    mapper_register,
+                    slot: slot as u8,
+                    new_bank: *new_bank,
+                });
+            }
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<ChrSwitchEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
@@ -8,10 +8,40 @@ use std::{
 
 pub enum CartridgeError {
     FileError(ioError),
+    /// the file is smaller than the 16 bytes header (and the trainer if
+    /// present)
+    TooSmall,
+    /// the file does not start with `NES\x1A`
+    BadHeaderMagic,
     HeaderError,
-    TooLargeFile(u64),
+    /// the file ends before the PRG ROM size in the header, `actual` is the
+    /// number of bytes available
+    PrgSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// the file ends before the CHR ROM size in the header, `actual` is the
+    /// number of bytes available
+    ChrSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// the number of PRG or CHR banks in the header is not supported by
+    /// the mapper, or is out of range (corrupted header)
+    InvalidBankCount,
     ExtensionError,
     MapperNotImplemented(u16),
+    /// the reloaded ROM has a different mapper or PRG/CHR sizes from the
+    /// loaded one
+    ReloadMismatch,
+    /// the zip archive could not be read
+    ZipError(String),
+    /// the zip archive does not have the requested entry, or does not have
+    /// any `.nes` file if no entry was requested
+    ZipEntryNotFound(Option<String>),
+    /// the zip archive has more than one `.nes` file and no entry was
+    /// requested, contains the names of the `.nes` files
+    ZipMultipleRoms(Vec<String>),
     Others,
 }
 
@@ -19,17 +49,42 @@ impl CartridgeError {
     fn get_message(&self) -> String {
         match self {
             Self::FileError(err) => format!("FileError: {}", err),
+            Self::TooSmall => "The file is too small to be an iNES file".to_owned(),
+            Self::BadHeaderMagic => "This is not an iNES file, it does not start with \
+                `NES\\x1A`"
+                .to_owned(),
             Self::HeaderError => "This is not a valid iNES file".to_owned(),
+            Self::PrgSizeMismatch { expected, actual } => format!(
+                "The file has {} bytes of PRG ROM, but the header specifies {} bytes",
+                actual, expected
+            ),
+            Self::ChrSizeMismatch { expected, actual } => format!(
+                "The file has {} bytes of CHR ROM, but the header specifies {} bytes",
+                actual, expected
+            ),
             Self::Others => {
                 "An unknown error occurred while decoding/reading the cartridge".to_owned()
             }
-            Self::TooLargeFile(size) => format!(
-                "The cartridge reader read all the data needed, but the file \
-                still has some data at the end with size {}-bytes",
-                size
-            ),
+            Self::InvalidBankCount => "The number of PRG/CHR banks in the header is not \
+                supported by the mapper, the header might be corrupted"
+                .to_owned(),
             Self::MapperNotImplemented(id) => format!("Mapper {} is not yet implemented", id),
             Self::ExtensionError => "The cartridge file must end with `.nes` extension".to_owned(),
+            Self::ReloadMismatch => "The reloaded cartridge must have the same mapper and \
+                PRG/CHR sizes as the loaded one"
+                .to_owned(),
+            Self::ZipError(err) => format!("ZipError: {}", err),
+            Self::ZipEntryNotFound(Some(entry)) => {
+                format!("The zip archive does not contain `{}`", entry)
+            }
+            Self::ZipEntryNotFound(None) => {
+                "The zip archive does not contain any `.nes` file".to_owned()
+            }
+            Self::ZipMultipleRoms(entries) => format!(
+                "The zip archive contains more than one `.nes` file, the entry name must be \
+                specified, one of: {}",
+                entries.join(", ")
+            ),
         }
     }
 }
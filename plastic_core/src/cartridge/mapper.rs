@@ -1,4 +1,5 @@
-use crate::common::{Device, MirroringMode};
+use super::error::CartridgeError;
+use crate::common::{save_state::SaveError, Device, MirroringMode, NametableTarget};
 
 pub enum MappingResult {
     Allowed(usize),
@@ -6,7 +7,15 @@ pub enum MappingResult {
 }
 
 pub trait Mapper {
-    fn init(&mut self, pgr_count: u8, is_chr_ram: bool, chr_count: u8, sram_count: u8);
+    /// returns [`CartridgeError::InvalidBankCount`] if the mapper does not
+    /// support the number of banks
+    fn init(
+        &mut self,
+        pgr_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        sram_count: u8,
+    ) -> Result<(), CartridgeError>;
 
     /// takes `address` to map from and `device`, then return `result`
     /// if `result` is `MappingResult::Allowed`, then the `real_address` is
@@ -20,6 +29,31 @@ pub trait Mapper {
     /// is no address to write to
     fn map_write(&mut self, address: u16, data: u8, device: Device) -> MappingResult;
 
+    /// does this mapper have registers in `0x4020-0x5FFF`, used to know if
+    /// writes to that area are handled or not
+    fn uses_expansion_area(&self) -> bool {
+        false
+    }
+
+    /// called for CPU reads of `0x6000-0x7FFF` that `map_read` denied, used by
+    /// boards that have a latch there instead of PRG RAM, `open_bus` is the
+    /// value left on the data bus, which is the result if nothing drives it
+    fn read_unmapped_prg_ram(&self, _address: u16, open_bus: u8) -> u8 {
+        open_bus
+    }
+
+    /// called on CPU writes to `$4016` (controller strobe and the OUT pins),
+    /// used by the VS. System mapper
+    fn write_controller_port(&mut self, _data: u8) {}
+
+    /// called on every CPU cycle, used by mappers that count CPU cycles
+    /// (for IRQ timers for example)
+    fn cpu_clock(&mut self) {}
+
+    /// called by the PPU when bit 12 of its address bus goes from 0 to 1,
+    /// used by mappers that count scanlines from the pattern table fetches
+    fn ppu_a12_rising_edge(&mut self) {}
+
     fn is_hardwired_mirrored(&self) -> bool {
         true
     }
@@ -28,6 +62,12 @@ pub trait Mapper {
         unreachable!()
     }
 
+    /// used by mappers that replace the console nametables, the
+    /// `NametableTarget::CartridgeMemory` offset is into CHR memory
+    fn map_nametable(&self, _address: u16) -> NametableTarget {
+        NametableTarget::Default
+    }
+
     fn is_irq_pin_state_changed_requested(&self) -> bool {
         false
     }
@@ -38,9 +78,30 @@ pub trait Mapper {
 
     fn clear_irq_request_pin(&mut self) {}
 
+    /// the registers of the mapper formatted in one line, for logging and
+    /// debugging tools
+    fn debug_state(&self) -> String {
+        "(no debug info)".to_owned()
+    }
+
     fn save_state_size(&self) -> usize;
 
-    fn save_state(&self) -> Vec<u8>;
+    /// append the state of the mapper to `out`, this must be exactly
+    /// [`Mapper::save_state_size`] bytes
+    fn save_state(&self, out: &mut Vec<u8>);
 
-    fn load_state(&mut self, data: Vec<u8>);
+    /// returns [`SaveError::CorruptedData`] if `data` is not a valid state of
+    /// this mapper, the mapper may be left in a partially loaded state
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError>;
+}
+
+/// a state of a different size than [`Mapper::save_state_size`] comes from
+/// a corrupted file or a different version of the mapper, so it is rejected
+/// before indexing into it
+pub(crate) fn check_state_size(data: &[u8], size: usize) -> Result<(), SaveError> {
+    if data.len() != size {
+        Err(SaveError::CorruptedData)
+    } else {
+        Ok(())
+    }
 }
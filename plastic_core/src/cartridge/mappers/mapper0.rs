@@ -1,34 +1,60 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::Device;
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device};
 
 pub struct Mapper0 {
     has_32kb_prg_rom: bool,
     is_chr_ram: bool,
+
+    /// in bytes, some boards (like Family BASIC) have 2-8KB of PRG RAM,
+    /// which is mirrored in `0x6000-0x7FFF`, `0` if there is no PRG RAM
+    prg_ram_size: usize,
 }
 
 impl Mapper0 {
-    pub fn new() -> Self {
+    pub fn new(prg_ram_size: usize) -> Self {
         Self {
             has_32kb_prg_rom: false,
             is_chr_ram: false,
+            prg_ram_size: prg_ram_size.min(0x2000),
+        }
+    }
+
+    fn map_prg_ram(&self, address: u16) -> MappingResult {
+        if self.prg_ram_size == 0 {
+            MappingResult::Denied
+        } else {
+            MappingResult::Allowed((address & 0x1FFF) as usize % self.prg_ram_size)
         }
     }
 }
 
 impl Mapper for Mapper0 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, _chr_count: u8, _sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        _chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         // the only allowed options
-        assert!(prg_count == 1 || prg_count == 2);
+        if prg_count != 1 && prg_count != 2 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
 
         self.has_32kb_prg_rom = prg_count == 2;
         self.is_chr_ram = is_chr_ram;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
         match device {
             Device::CPU => {
                 match address {
-                    0x6000..=0x7FFF => MappingResult::Denied,
+                    0x6000..=0x7FFF => self.map_prg_ram(address),
                     0x8000..=0xFFFF => {
                         // 0x7FFF is for mapping 0x8000-0xFFFF to 0x0000-0x7FFF
                         // which is the range of the array
@@ -64,7 +90,10 @@ impl Mapper for Mapper0 {
         // only for RAMs
 
         match device {
-            Device::CPU => MappingResult::Denied,
+            Device::CPU => match address {
+                0x6000..=0x7FFF => self.map_prg_ram(address),
+                _ => MappingResult::Denied,
+            },
             Device::PPU => {
                 if self.is_chr_ram && address <= 0x1FFF {
                     MappingResult::Allowed(address as usize)
@@ -79,14 +108,18 @@ impl Mapper for Mapper0 {
         1
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        vec![(self.is_chr_ram as u8) << 1 | self.has_32kb_prg_rom as u8]
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[(self.is_chr_ram as u8) << 1 | self.has_32kb_prg_rom as u8]);
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
         let state = data[0];
 
         self.is_chr_ram = state & 0b10 != 0;
         self.has_32kb_prg_rom = state & 1 != 0;
+
+        Ok(())
     }
 }
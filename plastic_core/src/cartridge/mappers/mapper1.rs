@@ -1,5 +1,8 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::{Device, MirroringMode};
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
 
 pub struct Mapper1 {
     writing_shift_register: u8,
@@ -93,6 +96,13 @@ pub struct Mapper1 {
 
     /// in 8kb units
     prg_ram_count: u8,
+
+    /// the serial port was written in the current CPU cycle
+    wrote_this_cycle: bool,
+    /// the serial port was written in the previous CPU cycle, writes on
+    /// consecutive cycles (like the two writes of read-modify-write
+    /// instructions) are ignored except the first
+    wrote_last_cycle: bool,
 }
 
 impl Mapper1 {
@@ -112,6 +122,9 @@ impl Mapper1 {
             prg_count: 0,
 
             prg_ram_count: 0,
+
+            wrote_this_cycle: false,
+            wrote_last_cycle: false,
         }
     }
 
@@ -148,17 +161,16 @@ impl Mapper1 {
     }
 
     fn is_prg_ram_enabled(&self) -> bool {
-        // 8KB (SNROM) and not in 512KB PRG mode
-        let snrom_prg_ram_enabled = if self.chr_count == 2 && self.prg_count <= 16 {
-            if self.is_chr_8kb_mode() {
-                self.chr_0_bank & 0x10 == 0
+        // 8KB PRG RAM and 8KB CHR RAM (SNROM), bit 4 is the PRG ROM outer
+        // bank in 512KB mode, and not connected on the boards with more
+        // PRG RAM (SOROM, SXROM)
+        let snrom_prg_ram_enabled =
+            if self.chr_count == 2 && !self.is_prg_512kb_mode() && self.prg_ram_count <= 1 {
+                self.prg_control_chr_bank() & 0x10 == 0
             } else {
-                self.chr_1_bank & 0x10 == 0
-            }
-        } else {
-            // only depend on `self.prg_ram_enable`
-            true
-        };
+                // only depend on `self.prg_ram_enable`
+                true
+            };
 
         self.prg_ram_enable && snrom_prg_ram_enabled
     }
@@ -188,17 +200,63 @@ impl Mapper1 {
         MappingResult::Allowed(start_of_bank + (address & mask) as usize)
     }
 
+    /// 512KB PRG ROM boards (SUROM, SXROM) use bit 4 of the CHR bank
+    /// register to select the 256KB outer bank, which all the 16KB banks
+    /// (including the fixed ones) are selected from
+    fn is_prg_512kb_mode(&self) -> bool {
+        self.prg_count > 16 && self.chr_count == 2
+    }
+
+    /// the CHR bank register used for the extra PRG bits (PRG ROM outer bank,
+    /// PRG RAM bank and disable) on boards with CHR RAM
+    fn prg_control_chr_bank(&self) -> u8 {
+        if self.is_chr_8kb_mode() {
+            self.chr_0_bank
+        } else {
+            self.chr_1_bank
+        }
+    }
+
+    fn map_prg_rom(&self, address: u16) -> MappingResult {
+        // the last 16KB bank in the 256KB outer bank
+        let last_bank = self.prg_count.min(16) - 1;
+
+        let bank = if self.is_prg_32kb_mode() {
+            // ignore last bit
+            (self.get_prg_bank() & 0b11110) | ((address >> 14) & 1) as u8
+        } else if address <= 0xBFFF {
+            if self.is_first_prg_chunk_fixed() {
+                0
+            } else {
+                self.get_prg_bank()
+            }
+        } else if self.is_first_prg_chunk_fixed() {
+            self.get_prg_bank()
+        } else {
+            last_bank
+        };
+
+        let outer_bank = if self.is_prg_512kb_mode() {
+            self.prg_control_chr_bank() & 0x10
+        } else {
+            0
+        };
+
+        let bank = (outer_bank | bank) as usize % self.prg_count as usize;
+
+        MappingResult::Allowed(0x4000 * bank + (address & 0x3FFF) as usize)
+    }
+
     fn map_prg_ram(&self, address: u16) -> MappingResult {
         if self.is_prg_ram_enabled() && self.prg_ram_count > 0 {
-            let bank = if self.prg_ram_count > 1 {
-                if self.is_chr_8kb_mode() {
-                    (self.chr_0_bank >> 2) & 0x3
-                } else {
-                    (self.chr_1_bank >> 2) & 0x3
-                }
-            } else {
-                0
-            } as usize;
+            let bank = match self.prg_ram_count {
+                1 => 0,
+                // 16KB (SOROM)
+                2 => (self.prg_control_chr_bank() >> 3) & 0x1,
+                // 32KB (SXROM)
+                _ => (self.prg_control_chr_bank() >> 2) & 0x3,
+            } as usize
+                % self.prg_ram_count as usize;
             MappingResult::Allowed(bank * 0x2000 + (address & 0x1FFF) as usize)
         } else {
             MappingResult::Denied
@@ -207,7 +265,13 @@ impl Mapper1 {
 }
 
 impl Mapper for Mapper1 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, chr_count: u8, sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         self.prg_count = prg_count;
         self.chr_count = chr_count * 2; // since this passed as the number of 8kb banks
         self.is_chr_ram = is_chr_ram;
@@ -218,67 +282,18 @@ impl Mapper for Mapper1 {
         self.prg_ram_count = sram_count;
 
         self.reset_shift_register();
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
         match device {
-            Device::CPU => {
-                match address {
-                    0x6000..=0x7FFF => self.map_prg_ram(address),
-                    0x8000..=0xFFFF => {
-                        let mut bank = if self.is_prg_32kb_mode() {
-                            // ignore last bit
-                            self.get_prg_bank() & 0b11110
-                        } else if address >= 0x8000 && address <= 0xBFFF {
-                            if self.is_first_prg_chunk_fixed() {
-                                0
-                            } else {
-                                self.get_prg_bank()
-                            }
-                        } else if address >= 0xC000 {
-                            if self.is_first_prg_chunk_fixed() {
-                                self.get_prg_bank()
-                            } else {
-                                // last bank
-                                self.prg_count - 1
-                            }
-                        } else {
-                            unreachable!();
-                        } as usize;
-
-                        if self.prg_count > 16 && self.chr_count == 2 {
-                            let prg_high_bit_512_mode = if self.is_chr_8kb_mode() {
-                                self.chr_0_bank & 0x10
-                            } else {
-                                self.chr_1_bank & 0x10
-                            } as usize;
-
-                            bank |= prg_high_bit_512_mode;
-                        }
-
-                        bank %= self.prg_count as usize;
-
-                        let start_of_bank = 0x4000 * bank;
-
-                        let last_bank = 0x4000 * (self.prg_count - 1) as usize;
-
-                        // since banks can be odd in number, we don't want to go out
-                        // of bounds, but this solution does mirroring, in case of
-                        // a possible out of bounds, but not sure what is the correct
-                        // solution
-                        let mask = if self.is_prg_32kb_mode() && start_of_bank != last_bank {
-                            0x7FFF
-                        } else {
-                            0x3FFF
-                        };
-
-                        // add the offset
-                        MappingResult::Allowed(start_of_bank + (address & mask) as usize)
-                    }
-                    0x4020..=0x5FFF => MappingResult::Denied,
-                    _ => unreachable!(),
-                }
-            }
+            Device::CPU => match address {
+                0x6000..=0x7FFF => self.map_prg_ram(address),
+                0x8000..=0xFFFF => self.map_prg_rom(address),
+                0x4020..=0x5FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
             Device::PPU => {
                 if address < 0x2000 {
                     self.map_ppu(address)
@@ -294,7 +309,13 @@ impl Mapper for Mapper1 {
             Device::CPU => {
                 match address {
                     0x6000..=0x7FFF => self.map_prg_ram(address),
+                    0x8000..=0xFFFF if self.wrote_last_cycle => {
+                        self.wrote_this_cycle = true;
+                        MappingResult::Denied
+                    }
                     0x8000..=0xFFFF => {
+                        self.wrote_this_cycle = true;
+
                         if data & 0x80 != 0 {
                             self.reset_shift_register();
                         } else {
@@ -352,12 +373,33 @@ impl Mapper for Mapper1 {
         ][self.get_mirroring() as usize]
     }
 
+    fn cpu_clock(&mut self) {
+        self.wrote_last_cycle = self.wrote_this_cycle;
+        self.wrote_this_cycle = false;
+    }
+
+    fn debug_state(&self) -> String {
+        format!(
+            "shift_reg={:05b} ctrl={:02X} chr0={:02X} chr1={:02X} prg={:02X} prg_ram={}",
+            self.writing_shift_register,
+            self.control_register,
+            self.chr_0_bank,
+            self.chr_1_bank,
+            self.prg_bank,
+            if self.prg_ram_enable {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        )
+    }
+
     fn save_state_size(&self) -> usize {
         10
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        vec![
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[
             self.writing_shift_register,
             self.control_register,
             self.chr_0_bank,
@@ -368,10 +410,12 @@ impl Mapper for Mapper1 {
             self.prg_ram_count,
             self.prg_ram_enable as u8,
             self.is_chr_ram as u8,
-        ]
+        ]);
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
         self.writing_shift_register = data[0];
         self.control_register = data[1];
         self.chr_0_bank = data[2];
@@ -382,5 +426,7 @@ impl Mapper for Mapper1 {
         self.prg_ram_count = data[7];
         self.prg_ram_enable = data[8] != 0;
         self.is_chr_ram = data[9] != 0;
+
+        Ok(())
     }
 }
@@ -1,5 +1,8 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::{Device, MirroringMode};
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
 use serde::{Deserialize, Serialize};
 use std::cell::Cell;
 
@@ -135,15 +138,25 @@ impl Mapper10 {
 }
 
 impl Mapper for Mapper10 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, chr_count: u8, _sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         self.prg_count = prg_count;
 
         // because 0xC000-0xFFFF holds the last 2 banks (fixed)
-        assert!(self.prg_count > 2);
+        if self.prg_count <= 2 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
 
         self.chr_count = chr_count * 2;
 
         self.is_chr_ram = is_chr_ram;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
@@ -223,13 +236,17 @@ impl Mapper for Mapper10 {
         bincode::serialized_size(self).unwrap() as usize
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        bincode::serialize(self).unwrap()
+    fn save_state(&self, out: &mut Vec<u8>) {
+        bincode::serialize_into(out, self).unwrap();
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
-        let state = bincode::deserialize(&data).unwrap();
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        let state = bincode::deserialize(data).map_err(|_| SaveError::CorruptedData)?;
 
         let _ = std::mem::replace(self, state);
+
+        Ok(())
     }
 }
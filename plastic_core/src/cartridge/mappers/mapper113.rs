@@ -0,0 +1,152 @@
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
+
+/// multicart variant of [`Mapper79`](super::Mapper79) with more PRG/CHR bits
+/// and mirroring control
+pub struct Mapper113 {
+    /// in 8kb units
+    chr_count: u8,
+
+    /// in 16kb units
+    prg_count: u8,
+
+    /// ($4100-$5FFF, mask $4100)
+    /// 7  bit  0
+    /// ---- ----
+    /// MCPP PCCC
+    /// |||| ||||
+    /// ||++-+|||- Select 32 KB PRG ROM bank for CPU $8000-$FFFF
+    /// |+----+++- Select 8 KB CHR ROM bank for PPU $0000-$1FFF (C bit 6 is
+    /// |          the high bit)
+    /// +--------- Mirroring (0: horizontal; 1: vertical)
+    register: u8,
+
+    /// using CHR RAM
+    is_chr_ram: bool,
+}
+
+impl Mapper113 {
+    pub fn new() -> Self {
+        Self {
+            chr_count: 0,
+            prg_count: 0,
+            register: 0,
+            is_chr_ram: false,
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> MappingResult {
+        let bank = ((self.register & 0x7) | ((self.register >> 3) & 0x8)) % self.chr_count;
+
+        let start_of_bank = 0x2000 * bank as usize;
+
+        MappingResult::Allowed(start_of_bank + (address & 0x1FFF) as usize)
+    }
+}
+
+impl Mapper for Mapper113 {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
+        if prg_count == 0 || chr_count == 0 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
+
+        self.prg_count = prg_count;
+        self.chr_count = chr_count;
+        self.is_chr_ram = is_chr_ram;
+
+        Ok(())
+    }
+
+    fn map_read(&self, address: u16, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x8000..=0xFFFF => {
+                    let bank = ((self.register >> 3) & 0x7) as usize;
+
+                    // 16KB PRG is mirrored
+                    let prg_size = self.prg_count as usize * 0x4000;
+
+                    MappingResult::Allowed((bank * 0x8000 + (address & 0x7FFF) as usize) % prg_size)
+                }
+                0x4020..=0x7FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                if address < 0x2000 {
+                    self.map_ppu(address)
+                } else {
+                    unreachable!()
+                }
+            }
+        }
+    }
+
+    fn map_write(&mut self, address: u16, data: u8, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => {
+                // only A8 is decoded in the expansion area
+                if (0x4020..=0x5FFF).contains(&address) && address & 0x4100 == 0x4100 {
+                    self.register = data;
+                }
+
+                MappingResult::Denied
+            }
+            Device::PPU => {
+                if self.is_chr_ram && address <= 0x1FFF {
+                    self.map_ppu(address)
+                } else {
+                    MappingResult::Denied
+                }
+            }
+        }
+    }
+
+    fn uses_expansion_area(&self) -> bool {
+        true
+    }
+
+    fn is_hardwired_mirrored(&self) -> bool {
+        false
+    }
+
+    fn nametable_mirroring(&self) -> MirroringMode {
+        if self.register & 0x80 != 0 {
+            MirroringMode::Vertical
+        } else {
+            MirroringMode::Horizontal
+        }
+    }
+
+    fn save_state_size(&self) -> usize {
+        4
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[
+            self.chr_count,
+            self.prg_count,
+            self.register,
+            self.is_chr_ram as u8,
+        ]);
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        self.chr_count = data[0];
+        self.prg_count = data[1];
+        self.register = data[2];
+        self.is_chr_ram = data[3] != 0;
+
+        Ok(())
+    }
+}
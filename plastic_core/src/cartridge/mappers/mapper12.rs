@@ -1,5 +1,8 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::{Device, MirroringMode};
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
 use serde::{Deserialize, Serialize};
 use std::cell::Cell;
 
@@ -135,10 +138,6 @@ pub struct Mapper12 {
     /// in 8kb units
     prg_count: u8,
 
-    /// false if the last accessed pattern table address is $0000
-    /// true  if the last accessed pattern table address is $1000
-    last_pattern_table: Cell<bool>,
-
     /// is PRG ram present?
     has_prg_ram: bool,
 }
@@ -171,37 +170,11 @@ impl Mapper12 {
             is_chr_ram: false,
             chr_count: 0,
             prg_count: 0,
-            last_pattern_table: Cell::new(false),
             has_prg_ram: false,
         }
     }
 
-    fn handle_irq_counter(&self, address: u16) {
-        let current_pattern_table = address & (1 << 12) != 0;
-
-        // transition from 0 to 1
-        if !self.last_pattern_table.get() && current_pattern_table {
-            if self.reload_irq_counter_flag.get() || self.irq_counter.get() == 0 {
-                self.reload_irq_counter_flag.set(false);
-                self.irq_counter.set(self.irq_latch);
-            } else {
-                self.irq_counter
-                    .set(self.irq_counter.get().saturating_sub(1));
-            }
-
-            if self.irq_counter.get() == 0 && self.irq_enabled {
-                // trigger IRQ
-                self.irq_pin.set(true);
-                self.is_irq_pin_changed.set(true);
-            }
-        }
-
-        self.last_pattern_table.set(current_pattern_table);
-    }
-
     fn map_ppu(&self, address: u16) -> MappingResult {
-        self.handle_irq_counter(address);
-
         let is_2k = (address & 0x1000 == 0) ^ self.chr_bank_2k_1000;
 
         let mut bank = if is_2k {
@@ -239,13 +212,21 @@ impl Mapper12 {
 }
 
 impl Mapper for Mapper12 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, chr_count: u8, sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         self.prg_count = prg_count * 2;
         self.chr_count = chr_count as u16 * 8;
 
         self.is_chr_ram = is_chr_ram;
 
         self.has_prg_ram = sram_count != 0;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
@@ -395,6 +376,10 @@ impl Mapper for Mapper12 {
         }
     }
 
+    fn uses_expansion_area(&self) -> bool {
+        true
+    }
+
     fn is_hardwired_mirrored(&self) -> bool {
         false
     }
@@ -407,6 +392,22 @@ impl Mapper for Mapper12 {
         }
     }
 
+    fn ppu_a12_rising_edge(&mut self) {
+        if self.reload_irq_counter_flag.get() || self.irq_counter.get() == 0 {
+            self.reload_irq_counter_flag.set(false);
+            self.irq_counter.set(self.irq_latch);
+        } else {
+            self.irq_counter
+                .set(self.irq_counter.get().saturating_sub(1));
+        }
+
+        if self.irq_counter.get() == 0 && self.irq_enabled {
+            // trigger IRQ
+            self.irq_pin.set(true);
+            self.is_irq_pin_changed.set(true);
+        }
+    }
+
     fn is_irq_pin_state_changed_requested(&self) -> bool {
         self.is_irq_pin_changed.get()
     }
@@ -424,13 +425,17 @@ impl Mapper for Mapper12 {
         bincode::serialized_size(self).unwrap() as usize
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        bincode::serialize(self).unwrap()
+    fn save_state(&self, out: &mut Vec<u8>) {
+        bincode::serialize_into(out, self).unwrap();
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
-        let state = bincode::deserialize(&data).unwrap();
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        let state = bincode::deserialize(data).map_err(|_| SaveError::CorruptedData)?;
 
         let _ = std::mem::replace(self, state);
+
+        Ok(())
     }
 }
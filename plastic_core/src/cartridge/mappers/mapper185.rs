@@ -0,0 +1,136 @@
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device};
+
+/// CNROM with copy protection, the value written to the register does not
+/// switch banks, instead it enables or disables the CHR ROM, and the game
+/// checks that reading CHR gives garbage for some values
+pub struct Mapper185 {
+    has_32kb_prg_rom: bool,
+
+    /// the last value written to `$8000-$FFFF`
+    chr_register: u8,
+
+    /// from the NES2.0 header, `0` means to use heuristics (old iNES dumps),
+    /// `4-7` means CHR is enabled only if the low 2 bits of the register
+    /// equal `submapper - 4`
+    submapper_id: u8,
+}
+
+impl Mapper185 {
+    pub fn new(submapper_id: u8) -> Self {
+        Self {
+            has_32kb_prg_rom: false,
+            // CHR should be enabled at power-on, as some games read it
+            // before writing to the register
+            chr_register: Self::initial_chr_register(submapper_id),
+            submapper_id,
+        }
+    }
+
+    /// a value which enables the CHR ROM for `submapper_id`
+    fn initial_chr_register(submapper_id: u8) -> u8 {
+        match submapper_id {
+            4..=7 => submapper_id - 4,
+            _ => 0x03,
+        }
+    }
+
+    fn is_chr_enabled(&self) -> bool {
+        match self.submapper_id {
+            4..=7 => self.chr_register & 0b11 == self.submapper_id - 4,
+            // heuristic used for old dumps, most games disable CHR with values
+            // with the low nibble clear, and "Seicross" uses `$13`
+            _ => self.chr_register & 0x0F != 0 && self.chr_register != 0x13,
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> MappingResult {
+        if self.is_chr_enabled() {
+            MappingResult::Allowed((address & 0x1FFF) as usize)
+        } else {
+            MappingResult::Denied
+        }
+    }
+}
+
+impl Mapper for Mapper185 {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        _is_chr_ram: bool,
+        _chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
+        if prg_count != 1 && prg_count != 2 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
+
+        self.has_32kb_prg_rom = prg_count == 2;
+
+        Ok(())
+    }
+
+    fn map_read(&self, address: u16, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x8000..=0xFFFF => MappingResult::Allowed(
+                    (if self.has_32kb_prg_rom {
+                        address & 0x7FFF
+                    } else {
+                        address & 0x3FFF
+                    }) as usize,
+                ),
+                0x4020..=0x7FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                if address < 0x2000 {
+                    self.map_ppu(address)
+                } else {
+                    unreachable!()
+                }
+            }
+        }
+    }
+
+    fn map_write(&mut self, address: u16, data: u8, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x8000..=0xFFFF => {
+                    self.chr_register = data;
+
+                    MappingResult::Denied
+                }
+                0x4020..=0x7FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            // CHR ROM only
+            Device::PPU => MappingResult::Denied,
+        }
+    }
+
+    fn save_state_size(&self) -> usize {
+        3
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[
+            self.chr_register,
+            self.submapper_id,
+            self.has_32kb_prg_rom as u8,
+        ]);
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        self.chr_register = data[0];
+        self.submapper_id = data[1];
+        self.has_32kb_prg_rom = data[2] != 0;
+
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::Device;
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device};
 
 pub struct Mapper2 {
     prg_top_bank: u8,
@@ -21,9 +24,17 @@ impl Mapper2 {
 }
 
 impl Mapper for Mapper2 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, _chr_count: u8, _sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        _chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         self.prg_count = prg_count;
         self.is_chr_ram = is_chr_ram;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
@@ -85,17 +96,25 @@ impl Mapper for Mapper2 {
         }
     }
 
+    fn debug_state(&self) -> String {
+        format!("prg={:02X}", self.prg_top_bank)
+    }
+
     fn save_state_size(&self) -> usize {
         3
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        vec![self.prg_top_bank, self.prg_count, self.is_chr_ram as u8]
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.prg_top_bank, self.prg_count, self.is_chr_ram as u8]);
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
         self.prg_top_bank = data[0];
         self.prg_count = data[1];
         self.is_chr_ram = data[2] != 0;
+
+        Ok(())
     }
 }
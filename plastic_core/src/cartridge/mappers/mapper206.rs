@@ -0,0 +1,215 @@
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
+use serde::{Deserialize, Serialize};
+
+/// the boards using the Namco 108 chip, they share the same banking but
+/// differ in how the CHR lines and mirroring are wired
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Namco108Board {
+    /// plain Namco 108/206, hardwired mirroring
+    Mapper206,
+    /// CHR A16 is connected to PPU A12, so the left pattern table uses the
+    /// first 64KB of CHR and the right one uses the second 64KB
+    Mapper88,
+    /// same as [`Namco108Board::Mapper88`] with single screen mirroring
+    /// controlled by bit 6 of writes to `$8000-$FFFF`
+    Mapper154,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Mapper206 {
+    board: Namco108Board,
+
+    /// ($8000-$9FFE, even)
+    /// 7  bit  0
+    /// ---- ----
+    /// xxxx xRRR
+    ///       |||
+    ///       +++- Specify which bank register to update on next write to Bank Data register
+    ///              000: R0: Select 2 KB CHR bank at PPU $0000-$07FF
+    ///              001: R1: Select 2 KB CHR bank at PPU $0800-$0FFF
+    ///              010: R2: Select 1 KB CHR bank at PPU $1000-$13FF
+    ///              011: R3: Select 1 KB CHR bank at PPU $1400-$17FF
+    ///              100: R4: Select 1 KB CHR bank at PPU $1800-$1BFF
+    ///              101: R5: Select 1 KB CHR bank at PPU $1C00-$1FFF
+    ///              110: R6: Select 8 KB PRG ROM bank at $8000-$9FFF
+    ///              111: R7: Select 8 KB PRG ROM bank at $A000-$BFFF
+    bank_select: u8,
+
+    /// R0-R7, CHR banks are in 1kb units, and PRG banks in 8kb units
+    bank_registers: [u8; 8],
+
+    /// mapper 154 only, (0: single screen low bank; 1: single screen high bank)
+    single_screen_high: bool,
+
+    /// is using CHR RAM?
+    is_chr_ram: bool,
+
+    /// in 1kb units
+    chr_count: u16,
+
+    /// in 8kb units
+    prg_count: u8,
+}
+
+impl Mapper206 {
+    pub fn new(board: Namco108Board) -> Self {
+        Self {
+            board,
+            bank_select: 0,
+            bank_registers: [0; 8],
+            single_screen_high: false,
+            is_chr_ram: false,
+            chr_count: 0,
+            prg_count: 0,
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> MappingResult {
+        let is_2k = address & 0x1000 == 0;
+
+        let mut bank = if is_2k {
+            self.bank_registers[((address >> 11) & 1) as usize]
+        } else {
+            self.bank_registers[2 + ((address >> 10) & 0b11) as usize]
+        } as usize;
+
+        if self.board != Namco108Board::Mapper206 {
+            bank = (bank & 0x3F) | ((address as usize & 0x1000) >> 6);
+        }
+
+        bank %= self.chr_count as usize;
+
+        let mask = if is_2k { 0x7FF } else { 0x3FF };
+
+        let start_of_bank = bank * 0x400;
+
+        MappingResult::Allowed(start_of_bank + (address & mask) as usize)
+    }
+}
+
+impl Mapper for Mapper206 {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
+        self.prg_count = prg_count * 2;
+        self.chr_count = chr_count as u16 * 8;
+
+        self.is_chr_ram = is_chr_ram;
+
+        Ok(())
+    }
+
+    fn map_read(&self, address: u16, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x6000..=0x7FFF => MappingResult::Denied,
+                0x8000..=0xFFFF => {
+                    let mut bank = match address {
+                        0x8000..=0x9FFF => self.bank_registers[6],
+                        0xA000..=0xBFFF => self.bank_registers[7],
+                        // fixed to the last two banks
+                        0xC000..=0xDFFF => self.prg_count - 2,
+                        0xE000..=0xFFFF => self.prg_count - 1,
+                        _ => unreachable!(),
+                    } as usize;
+
+                    bank %= self.prg_count as usize;
+
+                    let start_of_bank = bank * 0x2000;
+
+                    MappingResult::Allowed(start_of_bank + (address & 0x1FFF) as usize)
+                }
+                0x4020..=0x5FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                if address < 0x2000 {
+                    self.map_ppu(address)
+                } else {
+                    unreachable!();
+                }
+            }
+        }
+    }
+
+    fn map_write(&mut self, address: u16, data: u8, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x6000..=0x7FFF => MappingResult::Denied,
+                0x8000..=0xFFFF => {
+                    if self.board == Namco108Board::Mapper154 {
+                        self.single_screen_high = data & 0x40 != 0;
+                    }
+
+                    // the chip only decodes `$8000-$9FFF`
+                    if address <= 0x9FFF {
+                        if address & 1 == 0 {
+                            // even
+                            self.bank_select = data & 0b111;
+                        } else {
+                            // odd
+                            let value = match self.bank_select {
+                                0 | 1 => data & 0x3E, // store as even number
+                                2..=5 => data & 0x3F,
+                                6 | 7 => data & 0x0F,
+                                _ => unreachable!(),
+                            };
+
+                            self.bank_registers[self.bank_select as usize] = value;
+                        }
+                    }
+
+                    MappingResult::Denied
+                }
+                0x4020..=0x5FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                // CHR RAM
+                if self.is_chr_ram && address <= 0x1FFF {
+                    self.map_ppu(address)
+                } else {
+                    MappingResult::Denied
+                }
+            }
+        }
+    }
+
+    fn is_hardwired_mirrored(&self) -> bool {
+        self.board != Namco108Board::Mapper154
+    }
+
+    fn nametable_mirroring(&self) -> MirroringMode {
+        if self.single_screen_high {
+            MirroringMode::SingleScreenHighBank
+        } else {
+            MirroringMode::SingleScreenLowBank
+        }
+    }
+
+    fn save_state_size(&self) -> usize {
+        bincode::serialized_size(self).unwrap() as usize
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        bincode::serialize_into(out, self).unwrap();
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        let state = bincode::deserialize(data).map_err(|_| SaveError::CorruptedData)?;
+
+        let _ = std::mem::replace(self, state);
+
+        Ok(())
+    }
+}
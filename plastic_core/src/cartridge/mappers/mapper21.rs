@@ -0,0 +1,490 @@
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
+use serde::{Deserialize, Serialize};
+
+/// number of PPU dots in a scanline, the scanline IRQ mode divides the CPU
+/// clock by 113.667 (341 / 3)
+const IRQ_PRESCALER_PERIOD: i16 = 341;
+
+/// the boards using the Konami VRC2 and VRC4 chips, they share the same
+/// registers but connect different CPU address lines to the two register
+/// select pins of the chip (`A0` and `A1` of the chip)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KonamiVrcBoard {
+    /// mapper 22, A0=A1, A1=A0, the lowest bit of the CHR banks is ignored
+    Vrc2a,
+    /// mapper 23 submapper 3, A0=A0, A1=A1
+    Vrc2b,
+    /// mapper 25 submapper 3, A0=A1, A1=A0
+    Vrc2c,
+    /// mapper 21 submapper 1, A0=A1, A1=A2
+    Vrc4a,
+    /// mapper 25 submapper 1, A0=A1, A1=A0
+    Vrc4b,
+    /// mapper 21 submapper 2, A0=A6, A1=A7
+    Vrc4c,
+    /// mapper 25 submapper 2, A0=A3, A1=A2
+    Vrc4d,
+    /// mapper 23 submapper 2, A0=A2, A1=A3
+    Vrc4e,
+    /// mapper 23 submapper 1, A0=A0, A1=A1
+    Vrc4f,
+    /// mapper 21 without a submapper, both VRC4a and VRC4c lines are used
+    Vrc4ac,
+    /// mapper 25 without a submapper, both VRC4b and VRC4d lines are used,
+    /// which also covers VRC2c
+    Vrc4bd,
+    /// mapper 23 without a submapper, both VRC4e and VRC4f lines are used,
+    /// which also covers VRC2b
+    Vrc4ef,
+}
+
+impl KonamiVrcBoard {
+    /// the board for the iNES `mapper` (21, 22, 23 or 25) and NES 2.0
+    /// `submapper`, unknown submappers use both wirings of the mapper
+    pub fn from_mapper(mapper: u16, submapper: u8) -> Self {
+        match (mapper, submapper) {
+            (21, 1) => Self::Vrc4a,
+            (21, 2) => Self::Vrc4c,
+            (21, _) => Self::Vrc4ac,
+            (22, _) => Self::Vrc2a,
+            (23, 1) => Self::Vrc4f,
+            (23, 2) => Self::Vrc4e,
+            (23, 3) => Self::Vrc2b,
+            (23, _) => Self::Vrc4ef,
+            (25, 1) => Self::Vrc4b,
+            (25, 2) => Self::Vrc4d,
+            (25, 3) => Self::Vrc2c,
+            (25, _) => Self::Vrc4bd,
+            _ => unreachable!(),
+        }
+    }
+
+    fn is_vrc2(self) -> bool {
+        matches!(self, Self::Vrc2a | Self::Vrc2b | Self::Vrc2c)
+    }
+
+    /// VRC2 boards have the `$6000` latch, the combined boards of mappers 23
+    /// and 25 may be VRC2 as well, so they keep it
+    fn has_latch(self) -> bool {
+        self.is_vrc2() || matches!(self, Self::Vrc4bd | Self::Vrc4ef)
+    }
+
+    /// the masks of the CPU address lines connected to the chip `A0` and
+    /// `A1` pins
+    fn register_select_lines(self) -> (u16, u16) {
+        match self {
+            Self::Vrc2b | Self::Vrc4f => (0x01, 0x02),
+            Self::Vrc2a | Self::Vrc2c | Self::Vrc4b => (0x02, 0x01),
+            Self::Vrc4a => (0x02, 0x04),
+            Self::Vrc4c => (0x40, 0x80),
+            Self::Vrc4d => (0x08, 0x04),
+            Self::Vrc4e => (0x04, 0x08),
+            Self::Vrc4ac => (0x02 | 0x40, 0x04 | 0x80),
+            Self::Vrc4bd => (0x02 | 0x08, 0x01 | 0x04),
+            Self::Vrc4ef => (0x01 | 0x04, 0x02 | 0x08),
+        }
+    }
+
+    /// the register (`0-3`) selected by the CPU `address`
+    fn register(self, address: u16) -> u8 {
+        let (a0, a1) = self.register_select_lines();
+
+        ((address & a1 != 0) as u8) << 1 | (address & a0 != 0) as u8
+    }
+}
+
+/// Konami VRC2 and VRC4 (mappers 21, 22, 23 and 25)
+#[derive(Serialize, Deserialize)]
+pub struct Mapper21 {
+    board: KonamiVrcBoard,
+
+    /// ($8000-$8003) and ($A000-$A003)
+    /// 8KB PRG ROM banks, at `$8000` (or `$C000`) and `$A000`
+    prg_banks: [u8; 2],
+
+    /// ($9002, VRC4 only)
+    /// 7  bit  0
+    /// ---- ----
+    /// xxxx xxMx
+    ///        +-- PRG swap mode (0: `$C000-$DFFF` fixed to the second last bank;
+    ///                           1: `$8000-$9FFF` fixed to the second last bank)
+    prg_swap_mode: bool,
+
+    /// ($B000-$E003)
+    /// 1KB CHR banks, every bank is written in two parts, the low 4 bits on
+    /// even registers and the high 5 bits on odd registers
+    chr_banks: [u16; 8],
+
+    /// ($9000)
+    /// 7  bit  0
+    /// ---- ----
+    /// xxxx xxMM
+    ///        ++- Mirroring (0: vertical; 1: horizontal;
+    ///                       2: one-screen low bank; 3: one-screen high bank)
+    ///            VRC2 only has the low bit
+    mirroring: u8,
+
+    /// VRC2 boards (and the combined boards of mappers 23 and 25) without
+    /// PRG RAM have a one bit latch at `$6000-$6FFF`
+    /// (the microwire interface of the EEPROM on some boards), which some
+    /// games use to detect the board
+    latch: u8,
+
+    /// ($F000-$F001)
+    /// the value to reload `irq_counter` with, written 4 bits at a time
+    irq_latch: u8,
+
+    /// counts up and when it overflows, it is reloaded from `irq_latch` and
+    /// an **IRQ** interrupt is triggered
+    irq_counter: u8,
+
+    /// divides the CPU clock into scanlines in scanline mode
+    irq_prescaler: i16,
+
+    /// ($F002)
+    /// 7  bit  0
+    /// ---- ----
+    /// xxxx xMEA
+    ///      |||
+    ///      ||+- IRQ Enable after acknowledgement (see `$F003`)
+    ///      |+-- IRQ Enable (1 = enabled)
+    ///      +--- IRQ Mode (1 = cycle mode, 0 = scanline mode)
+    irq_enable_after_ack: bool,
+    irq_enabled: bool,
+    irq_cycle_mode: bool,
+
+    /// the status of the IRQ pin, should be used with `is_irq_pin_changed`
+    irq_pin: bool,
+
+    /// indicate whether there is a change that the CPU should be notified of
+    /// in the IRQ line
+    is_irq_pin_changed: bool,
+
+    /// is using CHR RAM?
+    is_chr_ram: bool,
+
+    /// is PRG ram present?
+    has_prg_ram: bool,
+
+    /// in 1kb units
+    chr_count: u16,
+
+    /// in 8kb units
+    prg_count: u8,
+}
+
+impl Mapper21 {
+    pub fn new(board: KonamiVrcBoard) -> Self {
+        Self {
+            board,
+            prg_banks: [0; 2],
+            prg_swap_mode: false,
+            chr_banks: [0; 8],
+            mirroring: 0,
+            latch: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_prescaler: IRQ_PRESCALER_PERIOD,
+            irq_enable_after_ack: false,
+            irq_enabled: false,
+            irq_cycle_mode: false,
+            irq_pin: false,
+            is_irq_pin_changed: false,
+            is_chr_ram: false,
+            has_prg_ram: false,
+            chr_count: 0,
+            prg_count: 0,
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> MappingResult {
+        let mut bank = self.chr_banks[(address >> 10) as usize & 7] as usize;
+
+        // VRC2a does not connect the lowest bit of the CHR banks
+        if self.board == KonamiVrcBoard::Vrc2a {
+            bank >>= 1;
+        }
+
+        bank %= self.chr_count as usize;
+
+        let start_of_bank = bank * 0x400;
+
+        MappingResult::Allowed(start_of_bank + (address & 0x3FF) as usize)
+    }
+
+    fn acknowledge_irq(&mut self) {
+        self.irq_pin = false;
+        self.is_irq_pin_changed = true;
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        let register = self.board.register(address);
+        let is_vrc2 = self.board.is_vrc2();
+
+        match address {
+            0x8000..=0x8FFF => self.prg_banks[0] = data & 0x1F,
+            0x9000..=0x9FFF => {
+                if is_vrc2 {
+                    self.mirroring = data & 1;
+                } else if register & 2 == 0 {
+                    self.mirroring = data & 3;
+                } else {
+                    self.prg_swap_mode = data & 2 != 0;
+                }
+            }
+            0xA000..=0xAFFF => self.prg_banks[1] = data & 0x1F,
+            0xB000..=0xEFFF => {
+                let bank = ((address - 0xB000) >> 12) as usize * 2 + (register >> 1) as usize;
+                let old = self.chr_banks[bank];
+
+                self.chr_banks[bank] = if register & 1 == 0 {
+                    (old & 0x1F0) | (data & 0xF) as u16
+                } else {
+                    (old & 0xF) | ((data & 0x1F) as u16) << 4
+                };
+            }
+            // VRC2 has no IRQ
+            0xF000..=0xFFFF if !is_vrc2 => match register {
+                0 => self.irq_latch = (self.irq_latch & 0xF0) | (data & 0xF),
+                1 => self.irq_latch = (self.irq_latch & 0xF) | (data & 0xF) << 4,
+                2 => {
+                    self.irq_enable_after_ack = data & 1 != 0;
+                    self.irq_enabled = data & 2 != 0;
+                    self.irq_cycle_mode = data & 4 != 0;
+
+                    if self.irq_enabled {
+                        self.irq_counter = self.irq_latch;
+                        self.irq_prescaler = IRQ_PRESCALER_PERIOD;
+                    }
+
+                    self.acknowledge_irq();
+                }
+                3 => {
+                    self.irq_enabled = self.irq_enable_after_ack;
+
+                    self.acknowledge_irq();
+                }
+                _ => unreachable!(),
+            },
+            _ => {}
+        }
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0xFF {
+            self.irq_counter = self.irq_latch;
+
+            // trigger IRQ
+            self.irq_pin = true;
+            self.is_irq_pin_changed = true;
+        } else {
+            self.irq_counter += 1;
+        }
+    }
+}
+
+impl Mapper for Mapper21 {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        sram_count: u8,
+    ) -> Result<(), CartridgeError> {
+        self.prg_count = prg_count * 2;
+
+        // because 0xC000-0xFFFF holds the last 2 banks (fixed)
+        if self.prg_count < 2 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
+
+        self.chr_count = chr_count as u16 * 8;
+
+        self.is_chr_ram = is_chr_ram;
+
+        self.has_prg_ram = sram_count != 0;
+
+        Ok(())
+    }
+
+    fn map_read(&self, address: u16, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x6000..=0x7FFF => {
+                    if self.has_prg_ram {
+                        MappingResult::Allowed(address as usize & 0x1FFF)
+                    } else {
+                        MappingResult::Denied
+                    }
+                }
+                0x8000..=0xFFFF => {
+                    let mut bank = match address {
+                        0x8000..=0x9FFF => {
+                            if self.prg_swap_mode {
+                                // second to last
+                                self.prg_count - 2
+                            } else {
+                                self.prg_banks[0]
+                            }
+                        }
+                        0xA000..=0xBFFF => self.prg_banks[1],
+                        0xC000..=0xDFFF => {
+                            if self.prg_swap_mode {
+                                self.prg_banks[0]
+                            } else {
+                                // second to last
+                                self.prg_count - 2
+                            }
+                        }
+                        0xE000..=0xFFFF => self.prg_count - 1,
+                        _ => unreachable!(),
+                    } as usize;
+
+                    bank %= self.prg_count as usize;
+
+                    let start_of_bank = bank * 0x2000;
+
+                    MappingResult::Allowed(start_of_bank + (address & 0x1FFF) as usize)
+                }
+                0x4020..=0x5FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                if address < 0x2000 {
+                    self.map_ppu(address)
+                } else {
+                    unreachable!();
+                }
+            }
+        }
+    }
+
+    fn map_write(&mut self, address: u16, data: u8, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x6000..=0x7FFF => {
+                    if self.has_prg_ram {
+                        MappingResult::Allowed(address as usize & 0x1FFF)
+                    } else {
+                        if self.board.has_latch() && address <= 0x6FFF {
+                            self.latch = data & 1;
+                        }
+
+                        MappingResult::Denied
+                    }
+                }
+                0x8000..=0xFFFF => {
+                    self.write_register(address, data);
+
+                    MappingResult::Denied
+                }
+                0x4020..=0x5FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                // CHR RAM
+                if self.is_chr_ram && address <= 0x1FFF {
+                    self.map_ppu(address)
+                } else {
+                    MappingResult::Denied
+                }
+            }
+        }
+    }
+
+    fn read_unmapped_prg_ram(&self, address: u16, open_bus: u8) -> u8 {
+        if self.board.has_latch() && address <= 0x6FFF {
+            open_bus & 0xFE | self.latch
+        } else {
+            open_bus
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+
+        if self.irq_cycle_mode {
+            self.clock_irq_counter();
+        } else {
+            self.irq_prescaler -= 3;
+
+            if self.irq_prescaler <= 0 {
+                self.irq_prescaler += IRQ_PRESCALER_PERIOD;
+                self.clock_irq_counter();
+            }
+        }
+    }
+
+    fn is_hardwired_mirrored(&self) -> bool {
+        false
+    }
+
+    fn nametable_mirroring(&self) -> MirroringMode {
+        match self.mirroring {
+            0 => MirroringMode::Vertical,
+            1 => MirroringMode::Horizontal,
+            2 => MirroringMode::SingleScreenLowBank,
+            3 => MirroringMode::SingleScreenHighBank,
+            _ => unreachable!(),
+        }
+    }
+
+    fn is_irq_pin_state_changed_requested(&self) -> bool {
+        self.is_irq_pin_changed
+    }
+
+    fn irq_pin_state(&self) -> bool {
+        self.irq_pin
+    }
+
+    fn clear_irq_request_pin(&mut self) {
+        self.irq_pin = false;
+        self.is_irq_pin_changed = false;
+    }
+
+    fn debug_state(&self) -> String {
+        format!(
+            "board={:?} prg0={:02X} prg1={:02X} prg_swap={} chr={:03X?} mirroring={} \
+             irq_latch={:02X} irq_counter={:02X} irq_enabled={} irq_cycle_mode={}",
+            self.board,
+            self.prg_banks[0],
+            self.prg_banks[1],
+            self.prg_swap_mode,
+            self.chr_banks,
+            self.mirroring,
+            self.irq_latch,
+            self.irq_counter,
+            self.irq_enabled,
+            self.irq_cycle_mode
+        )
+    }
+
+    fn save_state_size(&self) -> usize {
+        bincode::serialized_size(self).unwrap() as usize
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        bincode::serialize_into(out, self).unwrap();
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        let state: Self = bincode::deserialize(data).map_err(|_| SaveError::CorruptedData)?;
+
+        // invalid mirroring values would panic in `nametable_mirroring`
+        if state.mirroring > 3 {
+            return Err(SaveError::CorruptedData);
+        }
+
+        let _ = std::mem::replace(self, state);
+
+        Ok(())
+    }
+}
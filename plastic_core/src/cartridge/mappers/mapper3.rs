@@ -1,5 +1,8 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::Device;
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device};
 
 pub struct Mapper3 {
     has_32kb_prg_rom: bool,
@@ -31,12 +34,22 @@ impl Mapper3 {
 }
 
 impl Mapper for Mapper3 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, chr_count: u8, _sram_count: u8) {
-        assert!(prg_count == 1 || prg_count == 2);
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
+        if prg_count != 1 && prg_count != 2 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
 
         self.has_32kb_prg_rom = prg_count == 2;
         self.chr_count = chr_count;
         self.is_chr_ram = is_chr_ram;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
@@ -103,23 +116,31 @@ impl Mapper for Mapper3 {
         }
     }
 
+    fn debug_state(&self) -> String {
+        format!("chr={:02X}", self.chr_bank)
+    }
+
     fn save_state_size(&self) -> usize {
         4
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        vec![
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[
             self.chr_bank,
             self.chr_count,
             self.has_32kb_prg_rom as u8,
             self.is_chr_ram as u8,
-        ]
+        ]);
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
         self.chr_bank = data[0];
         self.chr_count = data[1];
         self.has_32kb_prg_rom = data[2] != 0;
         self.is_chr_ram = data[3] != 0;
+
+        Ok(())
     }
 }
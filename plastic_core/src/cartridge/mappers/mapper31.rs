@@ -0,0 +1,117 @@
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device};
+
+/// Board used by NSF music compilations, the PRG banking is the same as the
+/// NSF bankswitching, with CHR RAM and hardwired mirroring
+pub struct Mapper31 {
+    /// ($5000-$5FFF), the low 3 bits of the address select the register
+    /// 7  bit  0
+    /// ---- ----
+    /// PPPP PPPP
+    /// |||| ||||
+    /// ++++-++++- Select 4 KB PRG ROM bank at $8000 + register * $1000
+    prg_banks: [u8; 8],
+
+    /// in 4kb units
+    prg_count: u16,
+
+    /// using CHR RAM
+    is_chr_ram: bool,
+}
+
+impl Mapper31 {
+    pub fn new() -> Self {
+        Self {
+            // the last bank is mapped at power-on so the reset vector is
+            // available before any writes
+            prg_banks: [0, 0, 0, 0, 0, 0, 0, 0xFF],
+            prg_count: 0,
+            is_chr_ram: false,
+        }
+    }
+}
+
+impl Mapper for Mapper31 {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        _chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
+        self.prg_count = prg_count as u16 * 4;
+        self.is_chr_ram = is_chr_ram;
+
+        Ok(())
+    }
+
+    fn map_read(&self, address: u16, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x8000..=0xFFFF => {
+                    let register = (address >> 12) & 0x7;
+                    let bank = self.prg_banks[register as usize] as usize % self.prg_count as usize;
+
+                    let start_of_bank = bank * 0x1000;
+
+                    MappingResult::Allowed(start_of_bank + (address & 0xFFF) as usize)
+                }
+                0x4020..=0x7FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                if address < 0x2000 {
+                    MappingResult::Allowed(address as usize)
+                } else {
+                    unreachable!()
+                }
+            }
+        }
+    }
+
+    fn map_write(&mut self, address: u16, data: u8, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => {
+                if (0x5000..=0x5FFF).contains(&address) {
+                    self.prg_banks[(address & 0x7) as usize] = data;
+                }
+
+                MappingResult::Denied
+            }
+            Device::PPU => {
+                if self.is_chr_ram && address <= 0x1FFF {
+                    MappingResult::Allowed(address as usize)
+                } else {
+                    MappingResult::Denied
+                }
+            }
+        }
+    }
+
+    fn uses_expansion_area(&self) -> bool {
+        true
+    }
+
+    fn save_state_size(&self) -> usize {
+        11
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.prg_banks);
+        out.extend_from_slice(&self.prg_count.to_le_bytes());
+        out.push(self.is_chr_ram as u8);
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        self.prg_banks.copy_from_slice(&data[..8]);
+        self.prg_count = u16::from_le_bytes([data[8], data[9]]);
+        self.is_chr_ram = data[10] != 0;
+
+        Ok(())
+    }
+}
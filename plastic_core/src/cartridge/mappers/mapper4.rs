@@ -1,7 +1,10 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::{Device, MirroringMode};
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use super::mmc3::{self, Mmc3Irq};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
 use serde::{Deserialize, Serialize};
-use std::cell::Cell;
 
 #[derive(Serialize, Deserialize)]
 pub struct Mapper4 {
@@ -59,13 +62,8 @@ pub struct Mapper4 {
     /// false: use 2kb banks for 1000-17FF and 1800-1FFF
     chr_bank_2k_1000: bool,
 
-    // chr banks
-    chr_bank_r0: u8,
-    chr_bank_r1: u8,
-    chr_bank_r2: u8,
-    chr_bank_r3: u8,
-    chr_bank_r4: u8,
-    chr_bank_r5: u8,
+    /// chr banks `R0-R5`
+    chr_banks: [u8; 6],
 
     /// ($A000-$BFFE, even)
     /// 7  bit  0
@@ -89,33 +87,8 @@ pub struct Mapper4 {
     /// +--------- PRG RAM chip enable (0: disable; 1: enable)
     prg_ram_enabled: bool,
 
-    /// ($C000-$DFFE, even)
-    /// the value to reload `irq_counter` when it reaches zero or when asked
-    /// to be reloaded from `($C001-$DFFF, odd)`
-    irq_latch: u8,
-
-    /// counter will be decremented, and when reached zero and `irq_enabled`
-    /// `true` it should trigger an **IRQ** interrupt
-    irq_counter: Cell<u8>,
-
-    /// reload IRQ counter at the NEXT clocking of the IRQ
-    reload_irq_counter_flag: Cell<bool>,
-
-    /// denotes if an **IRQ** interrupt should occur on `irq_counter` reaching
-    /// zero or not
-    irq_enabled: bool,
-
-    /// the status of the IRQ pin, should be used with `is_irq_pin_changed`
-    irq_pin: Cell<bool>,
-
-    /// indicate whether there is a change that the CPU should be notified of
-    /// in the IRQ line, since the IRQ does not happen immediatly like NMI,
-    /// it can be disabled when the `irq_pin` is set and then it is cleared
-    ///
-    /// in the NES, this is a wire connection directly from `irq_pin` to the CPU,
-    /// but in this emulator, the CPU has its own copy of `irq_pin` so it should
-    /// be notified if any changes happened from this side
-    is_irq_pin_changed: Cell<bool>,
+    /// the IRQ registers in `$C000-$FFFF`
+    irq: Mmc3Irq,
 
     /// is using CHR RAM?
     is_chr_ram: bool,
@@ -126,10 +99,6 @@ pub struct Mapper4 {
     /// in 8kb units
     prg_count: u8,
 
-    /// false if the last accessed pattern table address is $0000
-    /// true  if the last accessed pattern table address is $1000
-    last_pattern_table: Cell<bool>,
-
     /// is PRG ram present?
     has_prg_ram: bool,
 }
@@ -142,76 +111,22 @@ impl Mapper4 {
             prg_bank_8000_c000: 0,
             prg_bank_a000: 0,
             chr_bank_2k_1000: false,
-            chr_bank_r0: 0,
-            chr_bank_r1: 0,
-            chr_bank_r2: 0,
-            chr_bank_r3: 0,
-            chr_bank_r4: 0,
-            chr_bank_r5: 0,
+            chr_banks: [0; 6],
             mirroring_vertical: false,
             prg_ram_allow_writes: true,
             prg_ram_enabled: true,
-            irq_latch: 0,
-            irq_counter: Cell::new(0),
-            reload_irq_counter_flag: Cell::new(false),
-            irq_enabled: false,
-            irq_pin: Cell::new(false),
-            is_irq_pin_changed: Cell::new(false),
+            irq: Mmc3Irq::new(),
             is_chr_ram: false,
             chr_count: 0,
             prg_count: 0,
-            last_pattern_table: Cell::new(false),
             has_prg_ram: false,
         }
     }
 
-    fn handle_irq_counter(&self, address: u16) {
-        let current_pattern_table = address & (1 << 12) != 0;
-
-        // transition from 0 to 1
-        if !self.last_pattern_table.get() && current_pattern_table {
-            if self.reload_irq_counter_flag.get() || self.irq_counter.get() == 0 {
-                self.reload_irq_counter_flag.set(false);
-                self.irq_counter.set(self.irq_latch);
-            } else {
-                self.irq_counter
-                    .set(self.irq_counter.get().saturating_sub(1));
-            }
-
-            if self.irq_counter.get() == 0 && self.irq_enabled {
-                // trigger IRQ
-                self.irq_pin.set(true);
-                self.is_irq_pin_changed.set(true);
-            }
-        }
-
-        self.last_pattern_table.set(current_pattern_table);
-    }
-
     fn map_ppu(&self, address: u16) -> MappingResult {
-        self.handle_irq_counter(address);
+        let (bank, mask) = mmc3::chr_bank(address, self.chr_bank_2k_1000, &self.chr_banks);
 
-        let is_2k = (address & 0x1000 == 0) ^ self.chr_bank_2k_1000;
-
-        let mut bank = if is_2k {
-            if address & 0x0800 == 0 {
-                self.chr_bank_r0
-            } else {
-                self.chr_bank_r1
-            }
-        } else {
-            match (address >> 10) & 0b11 {
-                0 => self.chr_bank_r2,
-                1 => self.chr_bank_r3,
-                2 => self.chr_bank_r4,
-                3 => self.chr_bank_r5,
-                _ => unreachable!(),
-            }
-        } as usize;
-
-        bank %= self.chr_count as usize;
-
-        let mask = if is_2k { 0x7FF } else { 0x3FF };
+        let bank = bank as usize % self.chr_count as usize;
 
         let start_of_bank = bank * 0x400;
 
@@ -220,13 +135,21 @@ impl Mapper4 {
 }
 
 impl Mapper for Mapper4 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, chr_count: u8, sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         self.prg_count = prg_count * 2;
         self.chr_count = chr_count as u16 * 8;
 
         self.is_chr_ram = is_chr_ram;
 
         self.has_prg_ram = sram_count != 0;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
@@ -305,12 +228,7 @@ impl Mapper for Mapper4 {
                                 } else {
                                     // odd
                                     match self.bank_select {
-                                        0 => self.chr_bank_r0 = data & !(1), // store as even number
-                                        1 => self.chr_bank_r1 = data & !(1), // store as even number
-                                        2 => self.chr_bank_r2 = data,
-                                        3 => self.chr_bank_r3 = data,
-                                        4 => self.chr_bank_r4 = data,
-                                        5 => self.chr_bank_r5 = data,
+                                        0..=5 => self.chr_banks[self.bank_select as usize] = data,
                                         6 => self.prg_bank_8000_c000 = data,
                                         7 => self.prg_bank_a000 = data,
                                         _ => unreachable!(),
@@ -328,26 +246,7 @@ impl Mapper for Mapper4 {
                                     self.prg_ram_enabled = data & 0x80 != 0;
                                 }
                             }
-                            0xC000..=0xDFFF => {
-                                if address & 1 == 0 {
-                                    // even
-                                    self.irq_latch = data;
-                                } else {
-                                    // odd
-                                    self.reload_irq_counter_flag.set(true);
-                                }
-                            }
-                            0xE000..=0xFFFF => {
-                                // enable on odd addresses, disable on even addresses
-                                self.irq_enabled = address & 1 != 0;
-
-                                // if cleared, then clear the pin as well if it is set
-                                // and notify the CPU
-                                if !self.irq_enabled {
-                                    self.irq_pin.set(false);
-                                    self.is_irq_pin_changed.set(true);
-                                }
-                            }
+                            0xC000..=0xFFFF => self.irq.write(address, data),
                             _ => unreachable!(),
                         }
 
@@ -380,30 +279,64 @@ impl Mapper for Mapper4 {
         }
     }
 
+    fn ppu_a12_rising_edge(&mut self) {
+        if self.irq.clock_counter(self.irq.latch()) {
+            // trigger IRQ
+            self.irq.assert_pin();
+        }
+    }
+
     fn is_irq_pin_state_changed_requested(&self) -> bool {
-        self.is_irq_pin_changed.get()
+        self.irq.is_pin_changed()
     }
 
     fn irq_pin_state(&self) -> bool {
-        self.irq_pin.get()
+        self.irq.pin()
     }
 
     fn clear_irq_request_pin(&mut self) {
-        self.irq_pin.set(false);
-        self.is_irq_pin_changed.set(false);
+        self.irq.clear_pin();
+    }
+
+    fn debug_state(&self) -> String {
+        format!(
+            "bank_select={:02X} prg_fix_8000={} chr_inversion={} r0={:02X} r1={:02X} \
+             r2={:02X} r3={:02X} r4={:02X} r5={:02X} r6={:02X} r7={:02X} mirroring={} {}",
+            self.bank_select,
+            self.prg_rom_bank_fix_8000,
+            self.chr_bank_2k_1000,
+            self.chr_banks[0],
+            self.chr_banks[1],
+            self.chr_banks[2],
+            self.chr_banks[3],
+            self.chr_banks[4],
+            self.chr_banks[5],
+            self.prg_bank_8000_c000,
+            self.prg_bank_a000,
+            if self.mirroring_vertical {
+                "vertical"
+            } else {
+                "horizontal"
+            },
+            self.irq.debug_state()
+        )
     }
 
     fn save_state_size(&self) -> usize {
         bincode::serialized_size(self).unwrap() as usize
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        bincode::serialize(self).unwrap()
+    fn save_state(&self, out: &mut Vec<u8>) {
+        bincode::serialize_into(out, self).unwrap();
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
-        let state = bincode::deserialize(&data).unwrap();
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        let state = bincode::deserialize(data).map_err(|_| SaveError::CorruptedData)?;
 
         let _ = std::mem::replace(self, state);
+
+        Ok(())
     }
 }
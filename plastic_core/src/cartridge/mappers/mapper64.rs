@@ -0,0 +1,359 @@
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use super::mmc3::{self, Mmc3Irq};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+// The documented delay of RAMBO-1 IRQs (one scanline, or four CPU cycles) is
+// the counter being reloaded with `latch + 1` instead of `latch`, which is
+// done in `clock_irq_counter`. On top of that, the IRQ pin is asserted a few
+// CPU cycles after the counter reaches zero. The wiki does not give these
+// values, they are the ones used by Mesen's RAMBO-1 implementation
+// (`Rambo1.h`, `CpuIrqDelay` and `PpuIrqDelay`).
+
+/// number of CPU cycles between the IRQ counter reaching zero and the IRQ
+/// pin being asserted in CPU cycle mode
+const CPU_MODE_IRQ_DELAY: u8 = 1;
+/// number of CPU cycles between the IRQ counter reaching zero and the IRQ
+/// pin being asserted in scanline (A12) mode
+const SCANLINE_MODE_IRQ_DELAY: u8 = 2;
+
+/// Tengen RAMBO-1
+#[derive(Serialize, Deserialize)]
+pub struct Mapper64 {
+    /// ($8000-$9FFE, even)
+    /// 7  bit  0
+    /// ---- ----
+    /// xxxx RRRR
+    ///      ||||
+    ///      ++++- Specify which bank register to update on next write to Bank Data register
+    ///              0000: R0: Select 2 (K=0) or 1 (K=1) KB CHR bank at PPU $0000 (or $1000)
+    ///              0001: R1: Select 2 (K=0) or 1 (K=1) KB CHR bank at PPU $0800 (or $1800)
+    ///              0010: R2: Select 1 KB CHR bank at PPU $1000-$13FF (or $0000-$03FF)
+    ///              0011: R3: Select 1 KB CHR bank at PPU $1400-$17FF (or $0400-$07FF)
+    ///              0100: R4: Select 1 KB CHR bank at PPU $1800-$1BFF (or $0800-$0BFF)
+    ///              0101: R5: Select 1 KB CHR bank at PPU $1C00-$1FFF (or $0C00-$0FFF)
+    ///              0110: R6: Select 8 KB PRG ROM bank at $8000-$9FFF (or $A000-$BFFF)
+    ///              0111: R7: Select 8 KB PRG ROM bank at $A000-$BFFF (or $C000-$DFFF)
+    ///              1000: R8: If K=1, Select 1 KB CHR bank at PPU $0400 (or $1400)
+    ///              1001: R9: If K=1, Select 1 KB CHR bank at PPU $0C00 (or $1C00)
+    ///              1111: RF: Select 8 KB PRG ROM bank at $C000-$DFFF (or $8000-$9FFF)
+    bank_select: u8,
+
+    /// ($8000-$9FFE, even)
+    /// 7  bit  0
+    /// ---- ----
+    /// xPxx xxxx
+    ///  +-------- PRG ROM bank mode (0: $8000-$9FFF=R6, $A000-$BFFF=R7, $C000-$DFFF=RF;
+    ///                               1: $8000-$9FFF=RF, $A000-$BFFF=R6, $C000-$DFFF=R7)
+    prg_rom_bank_mode: bool,
+
+    /// ($8000-$9FFE, even)
+    /// 7  bit  0
+    /// ---- ----
+    /// xxKx xxxx
+    ///   +------- CHR 1KB mode (0: R0 and R1 are 2KB banks;
+    ///                          1: R0, R8, R1 and R9 are 1KB banks)
+    chr_full_1k_mode: bool,
+
+    /// ($8000-$9FFE, even)
+    /// 7  bit  0
+    /// ---- ----
+    /// Cxxx xxxx
+    /// +--------- CHR A12 inversion (0: R0, R1 (and R8, R9) at $0000-$0FFF,
+    ///                                  R2-R5 at $1000-$1FFF;
+    ///                               1: the other way around)
+    chr_a12_inversion: bool,
+
+    /// bank registers `R0-RF`, only `R0-R9` and `RF` are used
+    bank_registers: [u8; 16],
+
+    /// ($A000-$BFFE, even)
+    /// 7  bit  0
+    /// ---- ----
+    /// xxxx xxxM
+    ///         |
+    ///         +- Nametable mirroring (0: vertical; 1: horizontal)
+    mirroring_vertical: bool,
+
+    /// the IRQ registers in `$C000-$FFFF`, the latch, reload and enable
+    /// registers are the same as MMC3
+    irq: Mmc3Irq,
+
+    /// ($C001-$DFFF, odd)
+    /// 7  bit  0
+    /// ---- ----
+    /// xxxx xxxM
+    ///         |
+    ///         +- IRQ counter clock source (0: PPU A12 (scanline); 1: CPU cycles / 4)
+    irq_cpu_cycle_mode: bool,
+
+    /// divides the CPU clock by 4 in CPU cycle mode
+    irq_prescaler: u8,
+
+    /// number of CPU cycles left until the IRQ pin is asserted, `0` means
+    /// there is no pending IRQ
+    irq_delay: Cell<u8>,
+
+    /// is using CHR RAM?
+    is_chr_ram: bool,
+
+    /// in 1kb units
+    chr_count: u16,
+
+    /// in 8kb units
+    prg_count: u8,
+}
+
+impl Mapper64 {
+    pub fn new() -> Self {
+        Self {
+            bank_select: 0,
+            prg_rom_bank_mode: false,
+            chr_full_1k_mode: false,
+            chr_a12_inversion: false,
+            bank_registers: [0; 16],
+            mirroring_vertical: false,
+            irq: Mmc3Irq::new(),
+            irq_cpu_cycle_mode: false,
+            irq_prescaler: 0,
+            irq_delay: Cell::new(0),
+            is_chr_ram: false,
+            chr_count: 0,
+            prg_count: 0,
+        }
+    }
+
+    /// clock the IRQ counter, `delay` is the number of CPU cycles until the
+    /// IRQ is asserted if the counter reached zero
+    fn clock_irq_counter(&self, delay: u8) {
+        let latch = self.irq.latch();
+
+        // after a reload, the counter is one more than the latch value
+        // (unlike MMC3), so the IRQ occures one scanline (or 4 cycles) late
+        let reload_value = if latch <= 1 {
+            latch
+        } else {
+            latch.wrapping_add(1)
+        };
+
+        if self.irq.clock_counter(reload_value) {
+            self.irq_delay.set(delay);
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> MappingResult {
+        let r = &self.bank_registers;
+
+        let is_2k = (address & 0x1000 == 0) ^ self.chr_a12_inversion;
+
+        let (bank, mask) = if self.chr_full_1k_mode && is_2k {
+            // R0, R8, R1 and R9 as 1kb banks instead of R0 and R1 as 2kb
+            (
+                [r[0], r[8], r[1], r[9]][(address >> 10) as usize & 0b11],
+                0x3FF,
+            )
+        } else {
+            mmc3::chr_bank(address, self.chr_a12_inversion, &r[..6])
+        };
+
+        let bank = bank as usize % self.chr_count as usize;
+
+        let start_of_bank = bank * 0x400;
+
+        MappingResult::Allowed(start_of_bank + (address & mask) as usize)
+    }
+}
+
+impl Mapper for Mapper64 {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
+        self.prg_count = prg_count * 2;
+        self.chr_count = chr_count as u16 * 8;
+
+        self.is_chr_ram = is_chr_ram;
+
+        Ok(())
+    }
+
+    fn map_read(&self, address: u16, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x8000..=0xFFFF => {
+                    let r = &self.bank_registers;
+
+                    let mut bank = match address {
+                        0x8000..=0x9FFF => {
+                            if self.prg_rom_bank_mode {
+                                r[15]
+                            } else {
+                                r[6]
+                            }
+                        }
+                        0xA000..=0xBFFF => {
+                            if self.prg_rom_bank_mode {
+                                r[6]
+                            } else {
+                                r[7]
+                            }
+                        }
+                        0xC000..=0xDFFF => {
+                            if self.prg_rom_bank_mode {
+                                r[7]
+                            } else {
+                                r[15]
+                            }
+                        }
+                        0xE000..=0xFFFF => self.prg_count - 1,
+                        _ => unreachable!(),
+                    } as usize;
+
+                    bank %= self.prg_count as usize;
+
+                    let start_of_bank = bank * 0x2000;
+
+                    MappingResult::Allowed(start_of_bank + (address & 0x1FFF) as usize)
+                }
+                0x4020..=0x7FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                if address < 0x2000 {
+                    self.map_ppu(address)
+                } else {
+                    unreachable!();
+                }
+            }
+        }
+    }
+
+    fn map_write(&mut self, address: u16, data: u8, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => {
+                match address {
+                    0x8000..=0x9FFF => {
+                        if address & 1 == 0 {
+                            // even
+                            self.bank_select = data & 0xF;
+                            self.chr_full_1k_mode = data & 0x20 != 0;
+                            self.prg_rom_bank_mode = data & 0x40 != 0;
+                            self.chr_a12_inversion = data & 0x80 != 0;
+                        } else {
+                            // odd
+                            self.bank_registers[self.bank_select as usize] = data;
+                        }
+                    }
+                    0xA000..=0xBFFF => {
+                        if address & 1 == 0 {
+                            // even
+                            self.mirroring_vertical = data & 1 == 0;
+                        }
+                        // odd: nothing, there is no PRG RAM
+                    }
+                    0xC000..=0xFFFF => {
+                        self.irq.write(address, data);
+
+                        match address & 0xE001 {
+                            0xC001 => {
+                                self.irq_cpu_cycle_mode = data & 1 != 0;
+                                if self.irq_cpu_cycle_mode {
+                                    self.irq_prescaler = 0;
+                                }
+                            }
+                            // a pending IRQ is dropped when disabled
+                            0xE000 => self.irq_delay.set(0),
+                            _ => {}
+                        }
+                    }
+                    0x4020..=0x7FFF => {}
+                    _ => unreachable!(),
+                }
+
+                MappingResult::Denied
+            }
+            Device::PPU => {
+                // CHR RAM
+                if self.is_chr_ram && address <= 0x1FFF {
+                    self.map_ppu(address)
+                } else {
+                    MappingResult::Denied
+                }
+            }
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_delay.get() > 0 {
+            self.irq_delay.set(self.irq_delay.get() - 1);
+
+            if self.irq_delay.get() == 0 {
+                // trigger IRQ
+                self.irq.assert_pin();
+            }
+        }
+
+        if self.irq_cpu_cycle_mode {
+            self.irq_prescaler = (self.irq_prescaler + 1) & 3;
+
+            if self.irq_prescaler == 0 {
+                self.clock_irq_counter(CPU_MODE_IRQ_DELAY);
+            }
+        }
+    }
+
+    fn is_hardwired_mirrored(&self) -> bool {
+        false
+    }
+
+    fn nametable_mirroring(&self) -> MirroringMode {
+        if self.mirroring_vertical {
+            MirroringMode::Vertical
+        } else {
+            MirroringMode::Horizontal
+        }
+    }
+
+    fn ppu_a12_rising_edge(&mut self) {
+        if !self.irq_cpu_cycle_mode {
+            self.clock_irq_counter(SCANLINE_MODE_IRQ_DELAY);
+        }
+    }
+
+    fn is_irq_pin_state_changed_requested(&self) -> bool {
+        self.irq.is_pin_changed()
+    }
+
+    fn irq_pin_state(&self) -> bool {
+        self.irq.pin()
+    }
+
+    fn clear_irq_request_pin(&mut self) {
+        self.irq.clear_pin();
+    }
+
+    fn save_state_size(&self) -> usize {
+        bincode::serialized_size(self).unwrap() as usize
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        bincode::serialize_into(out, self).unwrap();
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        let state = bincode::deserialize(data).map_err(|_| SaveError::CorruptedData)?;
+
+        let _ = std::mem::replace(self, state);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::Device;
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device};
 
 pub struct Mapper66 {
     /// in 8kb units
@@ -50,13 +53,23 @@ impl Mapper66 {
 }
 
 impl Mapper for Mapper66 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, chr_count: u8, _sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         // even and more than 0
-        assert!(prg_count % 2 == 0 && prg_count > 0);
+        if prg_count == 0 || prg_count % 2 == 1 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
 
         self.prg_count = prg_count / 2;
         self.chr_count = chr_count;
         self.is_chr_ram = is_chr_ram;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
@@ -106,25 +119,33 @@ impl Mapper for Mapper66 {
         }
     }
 
+    fn debug_state(&self) -> String {
+        format!("prg={:02X} chr={:02X}", self.prg_bank, self.chr_bank)
+    }
+
     fn save_state_size(&self) -> usize {
         5
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        vec![
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[
             self.chr_count,
             self.chr_bank,
             self.prg_count,
             self.prg_bank,
             self.is_chr_ram as u8,
-        ]
+        ]);
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
         self.chr_count = data[0];
         self.chr_bank = data[1];
         self.prg_count = data[2];
         self.prg_bank = data[3];
         self.is_chr_ram = data[4] != 0;
+
+        Ok(())
     }
 }
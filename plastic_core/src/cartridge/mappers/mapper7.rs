@@ -1,5 +1,8 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::{Device, MirroringMode};
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
 
 pub struct Mapper7 {
     /// select the 32KB bank
@@ -29,12 +32,22 @@ impl Mapper7 {
 }
 
 impl Mapper for Mapper7 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, _chr_count: u8, _sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        _chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         // even and positive
-        assert!(prg_count % 2 == 0 && prg_count > 0);
+        if prg_count == 0 || prg_count % 2 == 1 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
 
         self.prg_count = prg_count / 2;
         self.is_chr_ram = is_chr_ram;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
@@ -102,23 +115,39 @@ impl Mapper for Mapper7 {
         }
     }
 
+    fn debug_state(&self) -> String {
+        format!(
+            "prg={:02X} nametable={}",
+            self.prg_bank,
+            if self.is_mirroring_screen_high_bank {
+                "high"
+            } else {
+                "low"
+            }
+        )
+    }
+
     fn save_state_size(&self) -> usize {
         4
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        vec![
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[
             self.prg_bank,
             self.prg_count,
             self.is_mirroring_screen_high_bank as u8,
             self.is_chr_ram as u8,
-        ]
+        ]);
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
         self.prg_bank = data[0];
         self.prg_count = data[1];
         self.is_mirroring_screen_high_bank = data[2] != 0;
         self.is_chr_ram = data[3] != 0;
+
+        Ok(())
     }
 }
@@ -1,5 +1,8 @@
-use super::super::mapper::{Mapper, MappingResult};
-use crate::common::{Device, MirroringMode};
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device, MirroringMode};
 use serde::{Deserialize, Serialize};
 use std::cell::Cell;
 
@@ -139,16 +142,26 @@ impl Mapper9 {
 }
 
 impl Mapper for Mapper9 {
-    fn init(&mut self, prg_count: u8, is_chr_ram: bool, chr_count: u8, sram_count: u8) {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        sram_count: u8,
+    ) -> Result<(), CartridgeError> {
         self.prg_count = prg_count * 2;
 
         // because 0xA000-0xFFFF holds the last 3 banks (fixed)
-        assert!(self.prg_count > 3);
+        if self.prg_count <= 3 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
 
         self.chr_count = chr_count * 2;
 
         self.is_chr_ram = is_chr_ram;
         self.has_prg_ram = sram_count != 0;
+
+        Ok(())
     }
 
     fn map_read(&self, address: u16, device: Device) -> MappingResult {
@@ -246,13 +259,17 @@ impl Mapper for Mapper9 {
         bincode::serialized_size(self).unwrap() as usize
     }
 
-    fn save_state(&self) -> Vec<u8> {
-        bincode::serialize(self).unwrap()
+    fn save_state(&self, out: &mut Vec<u8>) {
+        bincode::serialize_into(out, self).unwrap();
     }
 
-    fn load_state(&mut self, data: Vec<u8>) {
-        let state = bincode::deserialize(&data).unwrap();
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        let state = bincode::deserialize(data).map_err(|_| SaveError::CorruptedData)?;
 
         let _ = std::mem::replace(self, state);
+
+        Ok(())
     }
 }
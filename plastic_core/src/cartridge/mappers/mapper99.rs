@@ -0,0 +1,141 @@
+use super::super::{
+    error::CartridgeError,
+    mapper::{check_state_size, Mapper, MappingResult},
+};
+use crate::common::{save_state::SaveError, Device};
+
+/// VS. System boards, the banks are selected by the OUT2 pin of the
+/// controller port (bit 2 of `$4016` writes) instead of a register in the
+/// cartridge, the 2KB of work RAM on the main board is mirrored in
+/// `0x6000-0x7FFF`
+pub struct Mapper99 {
+    /// in 16kb units
+    prg_count: u8,
+
+    /// in 8kb units
+    chr_count: u8,
+
+    /// bit 2 of the last write to `$4016`, selects the 8KB CHR bank, and
+    /// for PRG ROMs larger than 32KB, the 8KB PRG bank at `0x8000-0x9FFF`
+    bank_select: bool,
+
+    is_chr_ram: bool,
+}
+
+impl Mapper99 {
+    pub fn new() -> Self {
+        Self {
+            prg_count: 0,
+            chr_count: 0,
+            bank_select: false,
+            is_chr_ram: false,
+        }
+    }
+
+    fn map_ppu(&self, address: u16) -> MappingResult {
+        let bank = self.bank_select as u8 % self.chr_count;
+
+        let start_of_bank = 0x2000 * bank as usize;
+
+        MappingResult::Allowed(start_of_bank + (address & 0x1FFF) as usize)
+    }
+
+    fn map_prg_rom(&self, address: u16) -> MappingResult {
+        let address = (address & 0x7FFF) as usize;
+
+        // Vs. Gumshoe has 40KB of PRG ROM, the extra 8KB is the second bank
+        // of `0x8000-0x9FFF`, after the 32KB
+        if self.prg_count > 2 && self.bank_select && address < 0x2000 {
+            MappingResult::Allowed(0x8000 + address)
+        } else {
+            MappingResult::Allowed(address)
+        }
+    }
+}
+
+impl Mapper for Mapper99 {
+    fn init(
+        &mut self,
+        prg_count: u8,
+        is_chr_ram: bool,
+        chr_count: u8,
+        _sram_count: u8,
+    ) -> Result<(), CartridgeError> {
+        if prg_count == 0 || prg_count > 3 || chr_count == 0 || chr_count > 2 {
+            return Err(CartridgeError::InvalidBankCount);
+        }
+
+        self.prg_count = prg_count;
+        self.chr_count = chr_count;
+        self.is_chr_ram = is_chr_ram;
+
+        Ok(())
+    }
+
+    fn map_read(&self, address: u16, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x6000..=0x7FFF => MappingResult::Allowed((address & 0x7FF) as usize),
+                0x8000..=0xFFFF => self.map_prg_rom(address),
+                0x4020..=0x5FFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                if address < 0x2000 {
+                    self.map_ppu(address)
+                } else {
+                    unreachable!()
+                }
+            }
+        }
+    }
+
+    fn map_write(&mut self, address: u16, _data: u8, device: Device) -> MappingResult {
+        match device {
+            Device::CPU => match address {
+                0x6000..=0x7FFF => MappingResult::Allowed((address & 0x7FF) as usize),
+                0x4020..=0x5FFF | 0x8000..=0xFFFF => MappingResult::Denied,
+                _ => unreachable!(),
+            },
+            Device::PPU => {
+                if self.is_chr_ram && address <= 0x1FFF {
+                    self.map_ppu(address)
+                } else {
+                    MappingResult::Denied
+                }
+            }
+        }
+    }
+
+    fn write_controller_port(&mut self, data: u8) {
+        self.bank_select = data & 0x4 != 0;
+    }
+
+    fn debug_state(&self) -> String {
+        format!("bank={}", self.bank_select as u8)
+    }
+
+    fn save_state_size(&self) -> usize {
+        4
+    }
+
+    fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[
+            self.prg_count,
+            self.chr_count,
+            self.bank_select as u8,
+            self.is_chr_ram as u8,
+        ]);
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        check_state_size(data, self.save_state_size())?;
+
+        self.prg_count = data[0];
+        self.chr_count = data[1];
+        self.bank_select = data[2] != 0;
+        self.is_chr_ram = data[3] != 0;
+
+        Ok(())
+    }
+}
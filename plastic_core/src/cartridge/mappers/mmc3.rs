@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+/// map a pattern table address with the MMC3 CHR layout, returns the bank
+/// in 1kb units and the mask of the address inside it
+///
+/// `chr_banks` are the bank registers `R0-R5`, `R0` and `R1` select 2kb banks
+/// at $0000-$0FFF and `R2-R5` select 1kb banks at $1000-$1FFF, the two halves
+/// are swapped if `chr_a12_inversion` is set
+pub(super) fn chr_bank(address: u16, chr_a12_inversion: bool, chr_banks: &[u8]) -> (u8, u16) {
+    let is_2k = (address & 0x1000 == 0) ^ chr_a12_inversion;
+
+    if is_2k {
+        // the lowest bit is ignored for 2kb banks
+        (chr_banks[(address >> 11) as usize & 1] & !1, 0x7FF)
+    } else {
+        (chr_banks[2 + ((address >> 10) as usize & 0b11)], 0x3FF)
+    }
+}
+
+/// the scanline IRQ counter of MMC3 and the boards based on it
+#[derive(Serialize, Deserialize)]
+pub(super) struct Mmc3Irq {
+    /// ($C000-$DFFE, even)
+    /// the value to reload `counter` when it reaches zero or when asked
+    /// to be reloaded from `($C001-$DFFF, odd)`
+    latch: u8,
+
+    /// counter will be decremented, and when reached zero and `enabled`
+    /// `true` it should trigger an **IRQ** interrupt
+    counter: Cell<u8>,
+
+    /// reload IRQ counter at the NEXT clocking of the IRQ
+    reload_counter_flag: Cell<bool>,
+
+    /// denotes if an **IRQ** interrupt should occur on `counter` reaching
+    /// zero or not
+    enabled: bool,
+
+    /// the status of the IRQ pin, should be used with `is_pin_changed`
+    pin: Cell<bool>,
+
+    /// indicate whether there is a change that the CPU should be notified of
+    /// in the IRQ line, since the IRQ does not happen immediatly like NMI,
+    /// it can be disabled when the `pin` is set and then it is cleared
+    ///
+    /// in the NES, this is a wire connection directly from `pin` to the CPU,
+    /// but in this emulator, the CPU has its own copy of `pin` so it should
+    /// be notified if any changes happened from this side
+    is_pin_changed: Cell<bool>,
+}
+
+impl Mmc3Irq {
+    pub fn new() -> Self {
+        Self {
+            latch: 0,
+            counter: Cell::new(0),
+            reload_counter_flag: Cell::new(false),
+            enabled: false,
+            pin: Cell::new(false),
+            is_pin_changed: Cell::new(false),
+        }
+    }
+
+    /// handle a write to the IRQ registers in `$C000-$FFFF`
+    pub fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0xC000..=0xDFFF => {
+                if address & 1 == 0 {
+                    // even
+                    self.latch = data;
+                } else {
+                    // odd
+                    self.reload_counter_flag.set(true);
+                }
+            }
+            0xE000..=0xFFFF => {
+                // enable on odd addresses, disable on even addresses
+                self.enabled = address & 1 != 0;
+
+                // if cleared, then clear the pin as well if it is set
+                // and notify the CPU
+                if !self.enabled {
+                    self.pin.set(false);
+                    self.is_pin_changed.set(true);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// clock the counter, `reload_value` is loaded into it if a reload was
+    /// requested, otherwise it is reloaded from the latch when it is zero or
+    /// decremented, returns `true` if the counter reached zero and the IRQ
+    /// is enabled
+    pub fn clock_counter(&self, reload_value: u8) -> bool {
+        if self.reload_counter_flag.get() {
+            self.reload_counter_flag.set(false);
+            self.counter.set(reload_value);
+        } else if self.counter.get() == 0 {
+            self.counter.set(self.latch);
+        } else {
+            self.counter.set(self.counter.get() - 1);
+        }
+
+        self.counter.get() == 0 && self.enabled
+    }
+
+    pub fn latch(&self) -> u8 {
+        self.latch
+    }
+
+    /// assert the IRQ pin and notify the CPU
+    pub fn assert_pin(&self) {
+        self.pin.set(true);
+        self.is_pin_changed.set(true);
+    }
+
+    pub fn is_pin_changed(&self) -> bool {
+        self.is_pin_changed.get()
+    }
+
+    pub fn pin(&self) -> bool {
+        self.pin.get()
+    }
+
+    pub fn clear_pin(&mut self) {
+        self.pin.set(false);
+        self.is_pin_changed.set(false);
+    }
+
+    pub fn debug_state(&self) -> String {
+        format!(
+            "irq_latch={:02X} irq_counter={:02X} irq_enabled={}",
+            self.latch,
+            self.counter.get(),
+            self.enabled
+        )
+    }
+}
@@ -10,7 +10,20 @@ mod mapper10;
 mod mapper11;
 mod mapper12;
 
+mod mapper21;
+
+mod mapper31;
+
+mod mapper64;
 mod mapper66;
+mod mapper79;
+mod mapper99;
+
+mod mapper113;
+mod mapper185;
+mod mapper206;
+
+mod mmc3;
 
 mod tests;
 
@@ -26,4 +39,15 @@ pub use mapper10::Mapper10;
 pub use mapper11::Mapper11;
 pub use mapper12::Mapper12;
 
+pub use mapper21::{KonamiVrcBoard, Mapper21};
+
+pub use mapper31::Mapper31;
+
+pub use mapper64::Mapper64;
 pub use mapper66::Mapper66;
+pub use mapper79::Mapper79;
+pub use mapper99::Mapper99;
+
+pub use mapper113::Mapper113;
+pub use mapper185::Mapper185;
+pub use mapper206::{Mapper206, Namco108Board};
@@ -1,5 +1,12 @@
 #[cfg(test)]
 mod mappers_tests {
+    use super::super::{
+        KonamiVrcBoard, Mapper0, Mapper1, Mapper10, Mapper11, Mapper113, Mapper12, Mapper185,
+        Mapper2, Mapper206, Mapper21, Mapper3, Mapper31, Mapper4, Mapper64, Mapper66, Mapper7,
+        Mapper79, Mapper9, Mapper99, Namco108Board,
+    };
+    use crate::cartridge::mapper::{Mapper, MappingResult};
+    use crate::common::{save_state::SaveError, Device, MirroringMode};
     use crate::tests::{NesTester, TestError};
 
     /// the return code is the position within the 4 details result code
@@ -120,8 +127,7 @@ mod mappers_tests {
         )
     }
 
-    // FIXME: this test is still failing
-    // #[test]
+    #[test]
     fn holy_mapperel_m1_p512k_cr8k_s32k_test() -> Result<(), TestError> {
         run_holy_mapperel_test(
             "../test_roms/holy-mapperel-bin-0.02/testroms/M1_P512K_CR8K_S32K.nes",
@@ -129,8 +135,7 @@ mod mappers_tests {
         )
     }
 
-    // FIXME: this test is still failing
-    // #[test]
+    #[test]
     fn holy_mapperel_m1_p512k_cr8k_s8k_test() -> Result<(), TestError> {
         run_holy_mapperel_test(
             "../test_roms/holy-mapperel-bin-0.02/testroms/M1_P512K_CR8K_S8K.nes",
@@ -138,8 +143,7 @@ mod mappers_tests {
         )
     }
 
-    // FIXME: this test is still failing
-    // #[test]
+    #[test]
     fn holy_mapperel_m1_p512k_s32k_test() -> Result<(), TestError> {
         run_holy_mapperel_test(
             "../test_roms/holy-mapperel-bin-0.02/testroms/M1_P512K_S32K.nes",
@@ -147,8 +151,7 @@ mod mappers_tests {
         )
     }
 
-    // FIXME: this test is still failing
-    // #[test]
+    #[test]
     fn holy_mapperel_m1_p512k_s8k_test() -> Result<(), TestError> {
         run_holy_mapperel_test(
             "../test_roms/holy-mapperel-bin-0.02/testroms/M1_P512K_S8K.nes",
@@ -365,4 +368,751 @@ mod mappers_tests {
             180,
         )
     }
+
+    /// the CPU address of the VRC register `register` (`0-3`) at `base`,
+    /// using the address lines of `board`
+    fn vrc_address(board: KonamiVrcBoard, base: u16, register: u8) -> u16 {
+        let lines = match board {
+            KonamiVrcBoard::Vrc4c => [0x40, 0x80],
+            KonamiVrcBoard::Vrc4e => [0x04, 0x08],
+            KonamiVrcBoard::Vrc2a | KonamiVrcBoard::Vrc4b => [0x02, 0x01],
+            KonamiVrcBoard::Vrc4d => [0x08, 0x04],
+            KonamiVrcBoard::Vrc4a => [0x02, 0x04],
+            _ => [0x01, 0x02],
+        };
+
+        base | if register & 1 != 0 { lines[0] } else { 0 }
+            | if register & 2 != 0 { lines[1] } else { 0 }
+    }
+
+    #[test]
+    fn vrc_register_address_lines() {
+        let boards = [
+            KonamiVrcBoard::Vrc4a,
+            KonamiVrcBoard::Vrc4b,
+            KonamiVrcBoard::Vrc4c,
+            KonamiVrcBoard::Vrc4d,
+            KonamiVrcBoard::Vrc4e,
+            KonamiVrcBoard::Vrc4f,
+        ];
+
+        for board in boards {
+            let mut mapper = Mapper21::new(board);
+            // 256KB PRG, 256KB CHR
+            mapper.init(16, false, 32, 0).unwrap();
+
+            // CHR bank 5 (`$D002`, `$D003`) = 0xA3
+            mapper.map_write(vrc_address(board, 0xD000, 2), 0x3, Device::CPU);
+            mapper.map_write(vrc_address(board, 0xD000, 3), 0xA, Device::CPU);
+            assert_eq!(
+                map_address(&mapper, 0x1400, Device::PPU),
+                0xA3 * 0x400,
+                "{:?}",
+                board
+            );
+
+            // PRG swap mode (`$9002`) and mirroring (`$9000`)
+            mapper.map_write(0x8000, 3, Device::CPU);
+            mapper.map_write(vrc_address(board, 0x9000, 2), 2, Device::CPU);
+            mapper.map_write(vrc_address(board, 0x9000, 0), 3, Device::CPU);
+            assert_eq!(map_address(&mapper, 0x8000, Device::CPU), 30 * 0x2000);
+            assert_eq!(map_address(&mapper, 0xC000, Device::CPU), 3 * 0x2000);
+            assert!(matches!(
+                mapper.nametable_mirroring(),
+                MirroringMode::SingleScreenHighBank
+            ));
+        }
+
+        // the boards without a submapper use the lines of both boards
+        let mut mapper = Mapper21::new(KonamiVrcBoard::from_mapper(21, 0));
+        mapper.init(16, false, 32, 0).unwrap();
+        mapper.map_write(0xB000, 0x7, Device::CPU);
+        mapper.map_write(0xB002, 0x1, Device::CPU);
+        mapper.map_write(0xB080, 0x5, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x0000, Device::PPU), 0x17 * 0x400);
+        assert_eq!(map_address(&mapper, 0x0400, Device::PPU), 0x5 * 0x400);
+        mapper.map_write(0xB040, 0x2, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x0000, Device::PPU), 0x27 * 0x400);
+    }
+
+    #[test]
+    fn vrc2_banking_and_latch() {
+        let board = KonamiVrcBoard::from_mapper(22, 0);
+        let mut mapper = Mapper21::new(board);
+        // 128KB PRG, 128KB CHR, no PRG RAM
+        mapper.init(8, false, 16, 0).unwrap();
+
+        // the lowest bit of the CHR bank is not connected
+        mapper.map_write(vrc_address(board, 0xE000, 2), 0xB, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x1C00, Device::PPU), 0x5 * 0x400);
+
+        // there is no PRG swap mode and only one bit of mirroring
+        mapper.map_write(0x8000, 3, Device::CPU);
+        mapper.map_write(vrc_address(board, 0x9000, 2), 3, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x8000, Device::CPU), 3 * 0x2000);
+        assert_eq!(map_address(&mapper, 0xC000, Device::CPU), 14 * 0x2000);
+        assert_eq!(map_address(&mapper, 0xE000, Device::CPU), 15 * 0x2000);
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::Horizontal
+        ));
+
+        // one bit latch in `$6000-$6FFF`
+        mapper.map_write(0x6000, 0xFF, Device::CPU);
+        assert_eq!(mapper.read_unmapped_prg_ram(0x6123, 0x60), 0x61);
+        assert_eq!(mapper.read_unmapped_prg_ram(0x7123, 0x60), 0x60);
+
+        // and no IRQ
+        mapper.map_write(0xF002, 0x6, Device::CPU);
+        for _ in 0..0x200 {
+            mapper.cpu_clock();
+        }
+        assert!(!mapper.is_irq_pin_state_changed_requested());
+    }
+
+    #[test]
+    fn vrc_combined_boards_latch() {
+        // mappers 23 and 25 without a submapper may be VRC2b or VRC2c, they
+        // keep the latch if there is no PRG RAM
+        for mapper_id in [23, 25] {
+            let mut mapper = Mapper21::new(KonamiVrcBoard::from_mapper(mapper_id, 0));
+            mapper.init(8, false, 16, 0).unwrap();
+
+            mapper.map_write(0x6000, 0xFF, Device::CPU);
+            assert_eq!(mapper.read_unmapped_prg_ram(0x6123, 0x60), 0x61);
+        }
+
+        // mapper 21 is only VRC4
+        let mut mapper = Mapper21::new(KonamiVrcBoard::from_mapper(21, 0));
+        mapper.init(8, false, 16, 0).unwrap();
+        mapper.map_write(0x6000, 0xFF, Device::CPU);
+        assert_eq!(mapper.read_unmapped_prg_ram(0x6123, 0x60), 0x60);
+
+        // PRG RAM replaces the latch
+        let mut mapper = Mapper21::new(KonamiVrcBoard::from_mapper(23, 0));
+        mapper.init(8, false, 16, 1).unwrap();
+        assert!(matches!(
+            mapper.map_write(0x6000, 0xFF, Device::CPU),
+            MappingResult::Allowed(0)
+        ));
+    }
+
+    fn new_vrc4_with_irq(latch: u8, cycle_mode: bool) -> Mapper21 {
+        let mut mapper = Mapper21::new(KonamiVrcBoard::Vrc4f);
+        mapper.init(8, false, 16, 0).unwrap();
+
+        mapper.map_write(0xF000, latch & 0xF, Device::CPU);
+        mapper.map_write(0xF001, latch >> 4, Device::CPU);
+        mapper.map_write(0xF002, 0x3 | (cycle_mode as u8) << 2, Device::CPU);
+        mapper.clear_irq_request_pin();
+
+        mapper
+    }
+
+    #[test]
+    fn vrc4_cycle_irq() {
+        let mut mapper = new_vrc4_with_irq(0xFC, true);
+
+        // the counter counts up from the latch and triggers on overflow
+        for _ in 0..3 {
+            mapper.cpu_clock();
+            assert!(!mapper.is_irq_pin_state_changed_requested());
+        }
+        mapper.cpu_clock();
+        assert!(mapper.is_irq_pin_state_changed_requested());
+        assert!(mapper.irq_pin_state());
+        mapper.clear_irq_request_pin();
+
+        // reloaded from the latch
+        for _ in 0..3 {
+            mapper.cpu_clock();
+            assert!(!mapper.is_irq_pin_state_changed_requested());
+        }
+        mapper.cpu_clock();
+        assert!(mapper.irq_pin_state());
+
+        // acknowledging copies the "enable after acknowledgement" bit
+        mapper.map_write(0xF003, 0, Device::CPU);
+        assert!(mapper.is_irq_pin_state_changed_requested());
+        assert!(!mapper.irq_pin_state());
+
+        mapper.map_write(0xF002, 0x4, Device::CPU);
+        mapper.map_write(0xF003, 0, Device::CPU);
+        mapper.clear_irq_request_pin();
+        for _ in 0..0x200 {
+            mapper.cpu_clock();
+        }
+        assert!(!mapper.is_irq_pin_state_changed_requested());
+    }
+
+    #[test]
+    fn vrc4_scanline_irq() {
+        let mut mapper = new_vrc4_with_irq(0xFE, false);
+
+        // two scanlines of 341 / 3 CPU cycles
+        for _ in 0..227 {
+            mapper.cpu_clock();
+            assert!(!mapper.is_irq_pin_state_changed_requested());
+        }
+        mapper.cpu_clock();
+        assert!(mapper.irq_pin_state());
+    }
+
+    fn new_mapper64_with_irq(latch: u8, cpu_cycle_mode: bool) -> Mapper64 {
+        let mut mapper = Mapper64::new();
+        mapper.init(4, false, 2, 0).unwrap();
+
+        mapper.map_write(0xC000, latch, Device::CPU);
+        mapper.map_write(0xC001, cpu_cycle_mode as u8, Device::CPU);
+        mapper.map_write(0xE001, 0, Device::CPU);
+
+        mapper
+    }
+
+    #[test]
+    fn mapper64_scanline_irq_timing() {
+        let mut mapper = new_mapper64_with_irq(2, false);
+
+        // one A12 rising edge per scanline, after the reload the counter is
+        // `latch + 1`, so it needs 4 scanlines (one more than MMC3)
+        for _ in 0..4 {
+            mapper.ppu_a12_rising_edge();
+        }
+
+        assert!(!mapper.irq_pin_state());
+        mapper.cpu_clock();
+        assert!(!mapper.irq_pin_state());
+        mapper.cpu_clock();
+        assert!(mapper.irq_pin_state());
+        assert!(mapper.is_irq_pin_state_changed_requested());
+
+        // acknowledge
+        mapper.map_write(0xE000, 0, Device::CPU);
+        assert!(!mapper.irq_pin_state());
+    }
+
+    #[test]
+    fn mapper64_cpu_cycle_irq_timing() {
+        let mut mapper = new_mapper64_with_irq(2, true);
+
+        // A12 edges should not clock the counter in this mode
+        for _ in 0..10 {
+            mapper.ppu_a12_rising_edge();
+        }
+
+        // the counter is clocked every 4 CPU cycles, and it needs 4 clocks
+        // to reach zero after a reload
+        for _ in 0..16 {
+            mapper.cpu_clock();
+            assert!(!mapper.irq_pin_state());
+        }
+
+        mapper.cpu_clock();
+        assert!(mapper.irq_pin_state());
+    }
+
+    // NOTE: the frame-hash tests for Klax and Skull & Crossbones are missing,
+    //       the ROMs are not available in `test_roms`, so the RAMBO-1 banking
+    //       and IRQs are only tested here
+    #[test]
+    fn mapper64_banking() {
+        let mut mapper = Mapper64::new();
+        // 128KB PRG, 64KB CHR
+        mapper.init(8, false, 8, 0).unwrap();
+
+        for (register, data) in [
+            (0, 3),
+            (8, 5),
+            (1, 7),
+            (9, 9),
+            (2, 0x10),
+            (6, 1),
+            (7, 2),
+            (15, 3),
+        ] {
+            mapper.map_write(0x8000, register, Device::CPU);
+            mapper.map_write(0x8001, data, Device::CPU);
+        }
+
+        // 2KB banks like MMC3, ignoring the lowest bit
+        mapper.map_write(0x8000, 0x00, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x0123, Device::PPU), 2 * 0x400 + 0x123);
+        assert_eq!(map_address(&mapper, 0x0923, Device::PPU), 6 * 0x400 + 0x123);
+        assert_eq!(
+            map_address(&mapper, 0x1012, Device::PPU),
+            0x10 * 0x400 + 0x12
+        );
+        assert_eq!(map_address(&mapper, 0x8010, Device::CPU), 0x2000 + 0x10);
+        assert_eq!(map_address(&mapper, 0xA010, Device::CPU), 0x4000 + 0x10);
+        assert_eq!(map_address(&mapper, 0xC010, Device::CPU), 0x6000 + 0x10);
+        assert_eq!(map_address(&mapper, 0xFFFF, Device::CPU), 0x1FFFF);
+
+        // 1KB mode, R0, R8, R1 and R9
+        mapper.map_write(0x8000, 0x20, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x0012, Device::PPU), 3 * 0x400 + 0x12);
+        assert_eq!(map_address(&mapper, 0x0412, Device::PPU), 5 * 0x400 + 0x12);
+        assert_eq!(map_address(&mapper, 0x0812, Device::PPU), 7 * 0x400 + 0x12);
+        assert_eq!(map_address(&mapper, 0x0C12, Device::PPU), 9 * 0x400 + 0x12);
+        assert_eq!(
+            map_address(&mapper, 0x1012, Device::PPU),
+            0x10 * 0x400 + 0x12
+        );
+
+        // CHR A12 inversion and the other PRG mode (RF, R6, R7)
+        mapper.map_write(0x8000, 0xE0, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x1412, Device::PPU), 5 * 0x400 + 0x12);
+        assert_eq!(
+            map_address(&mapper, 0x0012, Device::PPU),
+            0x10 * 0x400 + 0x12
+        );
+        assert_eq!(map_address(&mapper, 0x8010, Device::CPU), 0x6000 + 0x10);
+        assert_eq!(map_address(&mapper, 0xA010, Device::CPU), 0x2000 + 0x10);
+        assert_eq!(map_address(&mapper, 0xC010, Device::CPU), 0x4000 + 0x10);
+    }
+
+    #[test]
+    fn mapper185_chr_protection() {
+        // heuristic for iNES 1.0
+        let mut mapper = Mapper185::new(0);
+        mapper.init(2, false, 1, 0).unwrap();
+
+        assert!(matches!(
+            mapper.map_read(0x0000, Device::PPU),
+            MappingResult::Allowed(0)
+        ));
+        mapper.map_write(0x8000, 0x00, Device::CPU);
+        assert!(matches!(
+            mapper.map_read(0x0000, Device::PPU),
+            MappingResult::Denied
+        ));
+        mapper.map_write(0x8000, 0x13, Device::CPU);
+        assert!(matches!(
+            mapper.map_read(0x0000, Device::PPU),
+            MappingResult::Denied
+        ));
+        mapper.map_write(0x8000, 0x21, Device::CPU);
+        assert!(matches!(
+            mapper.map_read(0x1FFF, Device::PPU),
+            MappingResult::Allowed(0x1FFF)
+        ));
+
+        // submapper 6, enabled only when the low 2 bits are `10`
+        let mut mapper = Mapper185::new(6);
+        mapper.init(2, false, 1, 0).unwrap();
+
+        for data in 0..4 {
+            mapper.map_write(0x8000, data, Device::CPU);
+            let is_enabled = matches!(
+                mapper.map_read(0x0000, Device::PPU),
+                MappingResult::Allowed(_)
+            );
+
+            assert_eq!(is_enabled, data == 2);
+        }
+    }
+
+    fn map_address(mapper: &dyn Mapper, address: u16, device: Device) -> usize {
+        match mapper.map_read(address, device) {
+            MappingResult::Allowed(address) => address,
+            MappingResult::Denied => panic!("address {:04X} is not mapped", address),
+        }
+    }
+
+    #[test]
+    fn mapper206_banking() {
+        let mut mapper = Mapper206::new(Namco108Board::Mapper206);
+        // 128KB PRG, 64KB CHR
+        mapper.init(8, false, 8, 0).unwrap();
+
+        // R0 = 3 (stored as 2), R2 = 0x45 (6 bits), R6 = 0x13 (4 bits)
+        for (register, data) in [(0, 3), (2, 0x45), (6, 0x13), (7, 5)] {
+            mapper.map_write(0x8000, register, Device::CPU);
+            mapper.map_write(0x8001, data, Device::CPU);
+        }
+
+        assert_eq!(map_address(&mapper, 0x0123, Device::PPU), 0x0800 + 0x123);
+        assert_eq!(map_address(&mapper, 0x1012, Device::PPU), 0x1400 + 0x12);
+        assert_eq!(map_address(&mapper, 0x8010, Device::CPU), 0x6000 + 0x10);
+        assert_eq!(map_address(&mapper, 0xA010, Device::CPU), 0xA000 + 0x10);
+        // fixed to the last two banks
+        assert_eq!(map_address(&mapper, 0xC000, Device::CPU), 0x1C000);
+        assert_eq!(map_address(&mapper, 0xFFFF, Device::CPU), 0x1FFFF);
+
+        // `$A000-$FFFF` is not decoded
+        mapper.map_write(0xA001, 0, Device::CPU);
+        assert_eq!(map_address(&mapper, 0xA010, Device::CPU), 0xA000 + 0x10);
+
+        assert!(mapper.is_hardwired_mirrored());
+    }
+
+    #[test]
+    fn mapper88_chr_a16_from_ppu_a12() {
+        let mut mapper = Mapper206::new(Namco108Board::Mapper88);
+        // 128KB CHR
+        mapper.init(8, false, 16, 0).unwrap();
+
+        // bit 6 is ignored on the left pattern table, and forced on the right
+        mapper.map_write(0x8000, 0, Device::CPU);
+        mapper.map_write(0x8001, 0x42, Device::CPU);
+        mapper.map_write(0x8000, 2, Device::CPU);
+        mapper.map_write(0x8001, 0x03, Device::CPU);
+
+        assert_eq!(map_address(&mapper, 0x0000, Device::PPU), 0x02 * 0x400);
+        assert_eq!(map_address(&mapper, 0x1000, Device::PPU), 0x43 * 0x400);
+        assert!(mapper.is_hardwired_mirrored());
+    }
+
+    #[test]
+    fn mapper154_single_screen_mirroring() {
+        let mut mapper = Mapper206::new(Namco108Board::Mapper154);
+        mapper.init(8, false, 16, 0).unwrap();
+
+        assert!(!mapper.is_hardwired_mirrored());
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::SingleScreenLowBank
+        ));
+
+        // any address in `$8000-$FFFF` sets the mirroring
+        mapper.map_write(0xE000, 0x40, Device::CPU);
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::SingleScreenHighBank
+        ));
+
+        // and bank select still works together with it
+        mapper.map_write(0x8000, 0x45, Device::CPU);
+        mapper.map_write(0x8001, 0x41, Device::CPU);
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::SingleScreenHighBank
+        ));
+        assert_eq!(map_address(&mapper, 0x1C00, Device::PPU), 0x41 * 0x400);
+
+        mapper.map_write(0x8001, 0x01, Device::CPU);
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::SingleScreenLowBank
+        ));
+    }
+
+    #[test]
+    fn mapper7_single_screen_mirroring() {
+        let mut mapper = Mapper7::new();
+        // 256KB PRG, CHR RAM
+        mapper.init(16, true, 1, 0).unwrap();
+
+        assert!(!mapper.is_hardwired_mirrored());
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::SingleScreenLowBank
+        ));
+
+        // bit 4 selects the nametable, independent of the PRG bank
+        mapper.map_write(0xFFFF, 0x13, Device::CPU);
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::SingleScreenHighBank
+        ));
+        assert_eq!(map_address(&mapper, 0x8000, Device::CPU), 3 * 0x8000);
+
+        mapper.map_write(0x8000, 0x03, Device::CPU);
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::SingleScreenLowBank
+        ));
+        assert_eq!(map_address(&mapper, 0x8000, Device::CPU), 3 * 0x8000);
+
+        // other bits do not select the high nametable
+        mapper.map_write(0x8000, 0xEF, Device::CPU);
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::SingleScreenLowBank
+        ));
+    }
+
+    #[test]
+    fn mapper79_register_address_decoding() {
+        let mut mapper = Mapper79::new();
+        // 64KB PRG, 64KB CHR
+        mapper.init(4, false, 8, 0).unwrap();
+
+        // A8 clear, or outside the expansion area
+        for address in [0x4020, 0x40FF, 0x4200, 0x5E00, 0x6100, 0x8100] {
+            mapper.map_write(address, 0x0F, Device::CPU);
+            assert_eq!(map_address(&mapper, 0x8000, Device::CPU), 0);
+            assert_eq!(map_address(&mapper, 0x0000, Device::PPU), 0);
+        }
+
+        mapper.map_write(0x4100, 0x0D, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x8010, Device::CPU), 0x8010);
+        assert_eq!(map_address(&mapper, 0x0010, Device::PPU), 5 * 0x2000 + 0x10);
+
+        mapper.map_write(0x5FFF, 0x02, Device::CPU);
+        assert_eq!(map_address(&mapper, 0xFFFF, Device::CPU), 0x7FFF);
+        assert_eq!(
+            map_address(&mapper, 0x1FFF, Device::PPU),
+            2 * 0x2000 + 0x1FFF
+        );
+
+        assert!(mapper.is_hardwired_mirrored());
+    }
+
+    #[test]
+    fn mapper113_banking_and_mirroring() {
+        let mut mapper = Mapper113::new();
+        // 256KB PRG, 128KB CHR
+        mapper.init(16, false, 16, 0).unwrap();
+
+        assert!(!mapper.is_hardwired_mirrored());
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::Horizontal
+        ));
+
+        // A8 clear
+        mapper.map_write(0x4000 | 0x0E00, 0xFF, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x8000, Device::CPU), 0);
+
+        // PRG bank 5, CHR bank 0b1011, vertical
+        mapper.map_write(0x4300, 0b1110_1011, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x8010, Device::CPU), 5 * 0x8000 + 0x10);
+        assert_eq!(
+            map_address(&mapper, 0x0010, Device::PPU),
+            11 * 0x2000 + 0x10
+        );
+        assert!(matches!(
+            mapper.nametable_mirroring(),
+            MirroringMode::Vertical
+        ));
+
+        // `$6000-$7FFF` is not decoded
+        mapper.map_write(0x7100, 0, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x8010, Device::CPU), 5 * 0x8000 + 0x10);
+    }
+
+    #[test]
+    fn mapper99_banks_from_controller_port() {
+        let mut mapper = Mapper99::new();
+        // 48KB PRG, 16KB CHR
+        mapper.init(3, false, 2, 0).unwrap();
+
+        assert_eq!(map_address(&mapper, 0x8010, Device::CPU), 0x10);
+        assert_eq!(map_address(&mapper, 0x0010, Device::PPU), 0x10);
+
+        // writes to the cartridge do not switch banks
+        mapper.map_write(0x8000, 0x04, Device::CPU);
+        assert_eq!(map_address(&mapper, 0x0010, Device::PPU), 0x10);
+
+        // the strobe bit is ignored
+        mapper.write_controller_port(0x05);
+        assert_eq!(map_address(&mapper, 0x0010, Device::PPU), 0x2010);
+        assert_eq!(map_address(&mapper, 0x8010, Device::CPU), 0x8010);
+        // only `0x8000-0x9FFF` is switched
+        assert_eq!(map_address(&mapper, 0xA010, Device::CPU), 0x2010);
+
+        mapper.write_controller_port(0x01);
+        assert_eq!(map_address(&mapper, 0x0010, Device::PPU), 0x10);
+
+        // 2KB of work RAM mirrored
+        assert_eq!(map_address(&mapper, 0x6801, Device::CPU), 0x1);
+    }
+
+    /// write the 5 bits of `value` to the MMC1 register at `address`, with
+    /// a free CPU cycle between the writes
+    fn mmc1_write(mapper: &mut Mapper1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.map_write(address, value >> bit & 1, Device::CPU);
+            mapper.cpu_clock();
+            mapper.cpu_clock();
+        }
+    }
+
+    fn is_prg_ram_mapped(mapper: &mut Mapper1) -> bool {
+        let read = matches!(
+            mapper.map_read(0x6000, Device::CPU),
+            MappingResult::Allowed(_)
+        );
+        let write = matches!(
+            mapper.map_write(0x6000, 0, Device::CPU),
+            MappingResult::Allowed(_)
+        );
+        assert_eq!(read, write);
+
+        read
+    }
+
+    #[test]
+    fn mapper1_prg_ram_disable() {
+        let mut mapper = Mapper1::new();
+        // 128KB PRG, 32KB CHR ROM, 8KB PRG RAM (SKROM)
+        mapper.init(8, false, 4, 1).unwrap();
+
+        mmc1_write(&mut mapper, 0xE000, 0x00);
+        assert!(is_prg_ram_mapped(&mut mapper));
+
+        mmc1_write(&mut mapper, 0xE000, 0x10);
+        assert!(!is_prg_ram_mapped(&mut mapper));
+
+        // the CHR banks do not control the PRG RAM here
+        mmc1_write(&mut mapper, 0xE000, 0x00);
+        mmc1_write(&mut mapper, 0xA000, 0x10);
+        assert!(is_prg_ram_mapped(&mut mapper));
+    }
+
+    #[test]
+    fn mapper1_snrom_prg_ram_disable() {
+        let mut mapper = Mapper1::new();
+        // 256KB PRG, 8KB CHR RAM, 8KB PRG RAM (SNROM)
+        mapper.init(16, true, 1, 1).unwrap();
+        // 8KB CHR mode, so the extra bits are from the first CHR bank
+        mmc1_write(&mut mapper, 0x8000, 0x0C);
+        mmc1_write(&mut mapper, 0xE000, 0x00);
+        assert!(is_prg_ram_mapped(&mut mapper));
+
+        // bit 4 of the CHR bank is the second PRG RAM disable
+        mmc1_write(&mut mapper, 0xA000, 0x10);
+        assert!(!is_prg_ram_mapped(&mut mapper));
+        mmc1_write(&mut mapper, 0xA000, 0x00);
+        assert!(is_prg_ram_mapped(&mut mapper));
+
+        // both must be clear
+        mmc1_write(&mut mapper, 0xE000, 0x10);
+        assert!(!is_prg_ram_mapped(&mut mapper));
+    }
+
+    #[test]
+    fn mapper1_surom_prg_ram_not_disabled_by_outer_bank() {
+        let mut mapper = Mapper1::new();
+        // 512KB PRG, 8KB CHR RAM, 8KB PRG RAM (SUROM)
+        mapper.init(32, true, 1, 1).unwrap();
+        mmc1_write(&mut mapper, 0x8000, 0x0C);
+        mmc1_write(&mut mapper, 0xE000, 0x00);
+
+        // bit 4 of the CHR bank selects the second 256KB
+        mmc1_write(&mut mapper, 0xA000, 0x10);
+        assert!(is_prg_ram_mapped(&mut mapper));
+        assert_eq!(map_address(&mapper, 0x8000, Device::CPU), 16 * 0x4000);
+
+        mmc1_write(&mut mapper, 0xE000, 0x10);
+        assert!(!is_prg_ram_mapped(&mut mapper));
+    }
+
+    #[test]
+    fn mapper1_ignores_consecutive_writes() {
+        let mut mapper = Mapper1::new();
+        // 256KB PRG, 128KB CHR
+        mapper.init(16, false, 32, 1).unwrap();
+
+        // like the two writes of `INC $8000`, only the first is used
+        mapper.map_write(0xE000, 1, Device::CPU);
+        mapper.cpu_clock();
+        mapper.map_write(0xE000, 0, Device::CPU);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        assert!(mapper.debug_state().starts_with("shift_reg=11000 "));
+
+        // the reset bit is ignored too
+        mapper.map_write(0x8000, 0x80, Device::CPU);
+        mapper.cpu_clock();
+        mapper.map_write(0x8000, 0x80, Device::CPU);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        assert!(mapper.debug_state().starts_with("shift_reg=10000 "));
+        mapper.map_write(0x8000, 0, Device::CPU);
+        mapper.cpu_clock();
+        mapper.map_write(0x8000, 0x80, Device::CPU);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        assert!(mapper.debug_state().starts_with("shift_reg=01000 "));
+
+        // a free cycle between the writes is enough
+        mapper.map_write(0x8000, 0x80, Device::CPU);
+        assert!(mapper.debug_state().starts_with("shift_reg=10000 "));
+    }
+
+    #[test]
+    fn mapper_debug_state() {
+        let mut mapper = Mapper0::new(0);
+        mapper.init(1, false, 1, 0).unwrap();
+        assert_eq!(mapper.debug_state(), "(no debug info)");
+
+        let mut mapper = Mapper1::new();
+        // 256KB PRG, 128KB CHR
+        mapper.init(16, false, 32, 1).unwrap();
+        // power-up state
+        assert_eq!(
+            mapper.debug_state(),
+            "shift_reg=10000 ctrl=1C chr0=00 chr1=00 prg=0F prg_ram=disabled"
+        );
+
+        // the shift register holds the written bits until the fifth write
+        mapper.map_write(0xE000, 1, Device::CPU);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        mapper.map_write(0xE000, 1, Device::CPU);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        assert_eq!(
+            mapper.debug_state(),
+            "shift_reg=11100 ctrl=1C chr0=00 chr1=00 prg=0F prg_ram=disabled"
+        );
+        for bit in [1, 0, 0] {
+            mapper.map_write(0xE000, bit, Device::CPU);
+            mapper.cpu_clock();
+            mapper.cpu_clock();
+        }
+        assert_eq!(
+            mapper.debug_state(),
+            "shift_reg=10000 ctrl=1C chr0=00 chr1=00 prg=07 prg_ram=enabled"
+        );
+    }
+
+    #[test]
+    fn mapper_state_size_mismatch() {
+        let mut mappers: Vec<Box<dyn Mapper>> = vec![
+            Box::new(Mapper0::new(0x2000)),
+            Box::new(Mapper1::new()),
+            Box::new(Mapper2::new()),
+            Box::new(Mapper3::new()),
+            Box::new(Mapper4::new()),
+            Box::new(Mapper7::new()),
+            Box::new(Mapper9::new()),
+            Box::new(Mapper10::new()),
+            Box::new(Mapper11::new()),
+            Box::new(Mapper12::new()),
+            Box::new(Mapper21::new(KonamiVrcBoard::Vrc4ac)),
+            Box::new(Mapper31::new()),
+            Box::new(Mapper64::new()),
+            Box::new(Mapper66::new()),
+            Box::new(Mapper79::new()),
+            Box::new(Mapper99::new()),
+            Box::new(Mapper113::new()),
+            Box::new(Mapper185::new(0)),
+            Box::new(Mapper206::new(Namco108Board::Mapper206)),
+        ];
+
+        for (i, mapper) in mappers.iter_mut().enumerate() {
+            let mut state = Vec::new();
+            mapper.save_state(&mut state);
+            assert_eq!(state.len(), mapper.save_state_size(), "mapper #{}", i);
+
+            let short = &state[..state.len() - 1];
+            assert!(
+                matches!(mapper.load_state(short), Err(SaveError::CorruptedData)),
+                "mapper #{} accepted a short state",
+                i
+            );
+
+            let mut long = state.clone();
+            long.push(0);
+            assert!(
+                matches!(mapper.load_state(&long), Err(SaveError::CorruptedData)),
+                "mapper #{} accepted a long state",
+                i
+            );
+
+            assert!(mapper.load_state(&state).is_ok(), "mapper #{}", i);
+        }
+    }
 }
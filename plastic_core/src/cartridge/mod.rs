@@ -1,9 +1,15 @@
 mod cartridge;
+mod chr_switch_log;
 mod error;
 mod mapper;
 mod mappers;
+mod rom_info;
+#[cfg(feature = "zip")]
+mod zip_archive;
 
 mod tests;
 
-pub use cartridge::Cartridge;
+pub use cartridge::{Cartridge, ConsoleType, VsPpuType};
+pub use chr_switch_log::ChrSwitchEvent;
 pub use error::CartridgeError;
+pub use rom_info::{rom_info, rom_info_from_bytes, HeaderFormat, RomInfo};
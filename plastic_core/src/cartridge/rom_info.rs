@@ -0,0 +1,119 @@
+use super::{cartridge::read_rom_info, CartridgeError, ConsoleType};
+use crate::common::{MirroringMode, TvRegion};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufReader, path::Path};
+
+/// The format of the iNES header
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HeaderFormat {
+    INes,
+    Nes2,
+}
+
+/// The information of a ROM file from its header, for showing in frontends,
+/// returned by [`rom_info`] without loading the cartridge, and by
+/// [`NES::rom_info`](crate::nes::NES::rom_info) for the loaded one
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RomInfo {
+    pub header_format: HeaderFormat,
+    pub mapper: u16,
+    /// always `0` for iNES headers
+    pub submapper: u8,
+    /// the name of the board or chip, `None` if the mapper is not supported
+    pub mapper_name: Option<String>,
+    pub console_type: ConsoleType,
+    /// in bytes
+    pub prg_rom_size: usize,
+    /// in bytes, `0` if the cartridge uses CHR RAM
+    pub chr_rom_size: usize,
+    /// in bytes, `0` if the cartridge uses CHR ROM
+    pub chr_ram_size: usize,
+    /// PRG RAM without battery in bytes, for iNES headers it is 8KB (or the
+    /// size in byte 8) if there is no battery, as the format can not tell
+    /// if there is PRG RAM
+    pub prg_ram_size: usize,
+    /// battery backed PRG RAM in bytes
+    pub prg_nvram_size: usize,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    /// the hardwired mirroring from the header, mappers with mirroring
+    /// control change it while running
+    pub mirroring: MirroringMode,
+    /// `None` if the ROM works on multiple regions
+    pub tv_system: Option<TvRegion>,
+    /// CRC32 of the PRG ROM
+    pub prg_crc32: u32,
+    /// CRC32 of the CHR ROM, `0` if there is no CHR ROM
+    pub chr_crc32: u32,
+    /// CRC32 of the PRG ROM followed by the CHR ROM, without the header and
+    /// trainer, this is what ROM databases use
+    pub crc32: u32,
+    /// the number of bytes after the PRG and CHR ROMs in the file, some dumps
+    /// have extra data at the end (title, or garbage), it is ignored
+    pub trailing_data_size: usize,
+}
+
+/// read the header of the ROM file at `path` and compute the CRCs, without
+/// loading the cartridge, so it also works for unsupported mappers
+pub fn rom_info<P: AsRef<Path>>(path: P) -> Result<RomInfo, CartridgeError> {
+    let file = File::open(path)?;
+
+    read_rom_info(BufReader::new(file))
+}
+
+/// same as [`rom_info`] for a ROM file in memory
+pub fn rom_info_from_bytes(data: &[u8]) -> Result<RomInfo, CartridgeError> {
+    read_rom_info(data)
+}
+
+/// the name of the board or chip of the supported mappers
+pub(super) fn mapper_name(mapper: u16) -> Option<&'static str> {
+    let name = match mapper {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        12 => "MMC3 clone (Gouder)",
+        21 => "VRC4a/VRC4c",
+        22 => "VRC2a",
+        23 => "VRC2b/VRC4e/VRC4f",
+        25 => "VRC4b/VRC4d/VRC2c",
+        31 => "NSF compilation (INL-NSF)",
+        64 => "RAMBO-1",
+        66 => "GxROM",
+        79 => "NINA-03/NINA-06",
+        88 => "Namco 108 (CHR A16 on PPU A12)",
+        99 => "VS. System",
+        113 => "NINA-03/NINA-06 multicart",
+        146 => "Sachen 3015",
+        154 => "NAMCOT-3453",
+        185 => "CNROM with copy protection",
+        206 => "Namco 108 (DxROM)",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// continue the CRC32 `crc` of the previous data with `data`, starting with
+/// `0` for the first part
+pub(super) fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
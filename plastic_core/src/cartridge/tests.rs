@@ -1,6 +1,58 @@
 #[cfg(test)]
 mod cartridge_tests {
-    use super::super::{Cartridge, CartridgeError};
+    use super::super::{
+        mapper::{check_state_size, Mapper, MappingResult},
+        rom_info,
+        rom_info::crc32_update,
+        rom_info_from_bytes, Cartridge, CartridgeError, ChrSwitchEvent, ConsoleType, HeaderFormat,
+        VsPpuType,
+    };
+    use crate::common::{save_state::SaveError, Bus, Device, MirroringMode, TvRegion};
+
+    /// NROM with a single bit latch in `0x6000-0x7FFF` like VRC2 boards
+    struct LatchMapper {
+        latch: u8,
+    }
+
+    impl Mapper for LatchMapper {
+        fn init(&mut self, _: u8, _: bool, _: u8, _: u8) -> Result<(), CartridgeError> {
+            Ok(())
+        }
+
+        fn map_read(&self, address: u16, _: Device) -> MappingResult {
+            match address {
+                0x8000..=0xFFFF => MappingResult::Allowed((address & 0x7FFF) as usize),
+                _ => MappingResult::Denied,
+            }
+        }
+
+        fn map_write(&mut self, address: u16, data: u8, _: Device) -> MappingResult {
+            if (0x6000..=0x7FFF).contains(&address) {
+                self.latch = data & 1;
+            }
+
+            MappingResult::Denied
+        }
+
+        fn read_unmapped_prg_ram(&self, _address: u16, open_bus: u8) -> u8 {
+            open_bus & 0xFE | self.latch
+        }
+
+        fn save_state_size(&self) -> usize {
+            1
+        }
+
+        fn save_state(&self, out: &mut Vec<u8>) {
+            out.push(self.latch);
+        }
+
+        fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+            check_state_size(data, self.save_state_size())?;
+            self.latch = data[0];
+
+            Ok(())
+        }
+    }
 
     #[test]
     fn cartridge_file_not_found() {
@@ -34,24 +86,22 @@ mod cartridge_tests {
             .err()
             .expect("Should get an error as the cartridge has wrong header");
 
-        if let CartridgeError::HeaderError = err {
+        if let CartridgeError::BadHeaderMagic = err {
             // passed
         } else {
-            panic!("Should get header error");
+            panic!("Should get header magic error");
         }
     }
 
     #[test]
-    fn cartridge_large_file() {
-        let err = Cartridge::from_file("../test_roms/cartridge_tests/test_large_file.nes")
-            .err()
-            .expect("Should get an error as the cartridge file is larger than expected");
+    fn cartridge_large_file() -> Result<(), CartridgeError> {
+        // the extra data at the end is ignored
+        let cartridge = Cartridge::from_file("../test_roms/cartridge_tests/test_large_file.nes")?;
 
-        if let CartridgeError::TooLargeFile(exceeded_size) = err {
-            assert_eq!(exceeded_size, 1);
-        } else {
-            panic!("Should get too large file error");
-        }
+        assert_eq!(cartridge.prg_data.len(), 0x8000);
+        assert_eq!(cartridge.chr_data.len(), 0x2000);
+
+        Ok(())
     }
 
     #[test]
@@ -71,4 +121,598 @@ mod cartridge_tests {
         // test passed
         Ok(())
     }
+
+    #[test]
+    fn force_prg_ram() -> Result<(), CartridgeError> {
+        // mapper 0, without PRG RAM
+        let mut cartridge = Cartridge::from_file("../test_roms/cartridge_tests/test_creation.nes")?;
+
+        cartridge.write(0x6000, 0x55, Device::CPU);
+        assert_ne!(cartridge.read(0x6000, Device::CPU), 0x55);
+
+        cartridge.set_force_prg_ram(true);
+
+        cartridge.write(0x6000, 0x55, Device::CPU);
+        cartridge.write(0x7FFF, 0xAA, Device::CPU);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x55);
+        assert_eq!(cartridge.read(0x7FFF, Device::CPU), 0xAA);
+
+        Ok(())
+    }
+
+    #[test]
+    fn force_prg_ram_missing_from_header() -> Result<(), CartridgeError> {
+        // MMC4 always maps `0x6000-0x7FFF`, but the NES 2.0 header declares
+        // no PRG RAM, so both reads and writes go to the forced PRG RAM
+        let mut data = nrom_16k(0xA0, 0x08, 0);
+        data.resize(16 + 0x20000, 0);
+        data[4] = 8;
+        let mut cartridge = load_nrom("plastic_mmc4_no_ram.nes", data)?;
+        cartridge.set_force_prg_ram(true);
+
+        cartridge.write(0x6000, 0x55, Device::CPU);
+        cartridge.write(0x7FFF, 0xAA, Device::CPU);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x55);
+        assert_eq!(cartridge.read(0x7FFF, Device::CPU), 0xAA);
+
+        Ok(())
+    }
+
+    #[test]
+    fn prg_ram_open_bus_without_wram() -> Result<(), CartridgeError> {
+        // mapper 0, without PRG RAM
+        let mut cartridge = Cartridge::from_file("../test_roms/cartridge_tests/test_creation.nes")?;
+
+        cartridge.write(0x6000, 0x55, Device::CPU);
+        cartridge.write(0x7123, 0x55, Device::CPU);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x60);
+        assert_eq!(cartridge.read(0x7123, Device::CPU), 0x71);
+
+        Ok(())
+    }
+
+    #[test]
+    fn prg_ram_mapper_latch() -> Result<(), CartridgeError> {
+        let mut cartridge = Cartridge::from_file("../test_roms/cartridge_tests/test_creation.nes")?;
+        cartridge.set_mapper(Box::new(LatchMapper { latch: 0 }));
+
+        cartridge.write(0x6000, 0xFF, Device::CPU);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x61);
+        assert_eq!(cartridge.read(0x7000, Device::CPU), 0x71);
+
+        cartridge.write(0x6000, 0xFE, Device::CPU);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x60);
+
+        Ok(())
+    }
+
+    #[test]
+    fn prg_ram_with_wram() -> Result<(), CartridgeError> {
+        let mut cartridge = Cartridge::from_file(
+            "../test_roms/holy-mapperel-bin-0.02/testroms/M1_P128K_C32K_W8K.nes",
+        )?;
+
+        // enable PRG RAM, by writing `0` to the PRG bank register, MMC1
+        // ignores writes on consecutive cycles
+        for _ in 0..5 {
+            cartridge.write(0xE000, 0, Device::CPU);
+            cartridge.cpu_clock();
+            cartridge.cpu_clock();
+        }
+
+        cartridge.write(0x6000, 0x55, Device::CPU);
+        cartridge.write(0x7FFF, 0xAA, Device::CPU);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x55);
+        assert_eq!(cartridge.read(0x7FFF, Device::CPU), 0xAA);
+
+        Ok(())
+    }
+
+    /// write the 5 bits of `value` to the MMC1 register at `address`, with
+    /// a free CPU cycle between the writes
+    fn write_mmc1_register(cartridge: &mut Cartridge, address: u16, value: u8) {
+        for bit in 0..5 {
+            cartridge.write(address, value >> bit & 1, Device::CPU);
+            cartridge.cpu_clock();
+            cartridge.cpu_clock();
+        }
+    }
+
+    #[test]
+    fn prg_ram_disabled_by_mmc1() -> Result<(), CartridgeError> {
+        let mut cartridge = Cartridge::from_file(
+            "../test_roms/holy-mapperel-bin-0.02/testroms/M1_P128K_C32K_W8K.nes",
+        )?;
+
+        write_mmc1_register(&mut cartridge, 0xE000, 0x00);
+        cartridge.write(0x6000, 0x55, Device::CPU);
+
+        // bit 4 disables the PRG RAM, reads are open bus and writes are lost
+        write_mmc1_register(&mut cartridge, 0xE000, 0x10);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x60);
+        cartridge.write(0x6000, 0xAA, Device::CPU);
+
+        write_mmc1_register(&mut cartridge, 0xE000, 0x00);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x55);
+
+        Ok(())
+    }
+
+    /// NROM cartridge with 16KB PRG filled with the low byte of the offset
+    /// and CHR RAM, `flags_6`, `flags_7` and `prg_ram_shift` are header bytes
+    /// 6, 7 and 10
+    fn nrom_16k(flags_6: u8, flags_7: u8, prg_ram_shift: u8) -> Vec<u8> {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 0, flags_6, flags_7];
+        data.extend_from_slice(&[0, 0, prg_ram_shift, 0, 0, 0, 0, 0]);
+        data.extend((0..0x4000).map(|i| (i * 7) as u8));
+        data
+    }
+
+    fn load_nrom(name: &str, data: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        let path = std::env::temp_dir().join(name);
+        Cartridge::from_reader(&path, std::io::Cursor::new(data))
+    }
+
+    #[test]
+    fn nrom_16k_prg_mirrored() -> Result<(), CartridgeError> {
+        let cartridge = load_nrom("plastic_nrom_16k.nes", nrom_16k(0, 0, 0))?;
+
+        assert_eq!(cartridge.read(0xFFFC, Device::CPU), (0x3FFC * 7) as u8);
+        assert_eq!(cartridge.read(0xFFFD, Device::CPU), (0x3FFD * 7) as u8);
+        assert_eq!(cartridge.read(0xBFFC, Device::CPU), (0x3FFC * 7) as u8);
+
+        // only 16KB and 32KB are valid for NROM
+        let mut data = nrom_16k(0, 0, 0);
+        data[4] = 3;
+        data.resize(16 + 3 * 0x4000, 0);
+        assert!(matches!(
+            load_nrom("plastic_nrom_48k.nes", data),
+            Err(CartridgeError::InvalidBankCount)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn nrom_prg_ram_from_header() -> Result<(), CartridgeError> {
+        // iNES 1.0 without battery, there is no PRG RAM
+        let mut cartridge = load_nrom("plastic_nrom_no_ram.nes", nrom_16k(0, 0, 0))?;
+        cartridge.write(0x6000, 0x55, Device::CPU);
+        // open bus, approximated by the high byte of the address
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x60);
+        assert_eq!(cartridge.read(0x7123, Device::CPU), 0x71);
+
+        // NES 2.0 with 2KB of PRG RAM (like Family BASIC), it is mirrored
+        let mut cartridge = load_nrom("plastic_nrom_2k_ram.nes", nrom_16k(0, 0x08, 5))?;
+        cartridge.write(0x6000, 0x55, Device::CPU);
+        cartridge.write(0x7FFF, 0xAA, Device::CPU);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x55);
+        assert_eq!(cartridge.read(0x6800, Device::CPU), 0x55);
+        assert_eq!(cartridge.read(0x67FF, Device::CPU), 0xAA);
+
+        // NES 2.0 with 8KB of PRG RAM, the whole range is used
+        let mut cartridge = load_nrom("plastic_nrom_8k_ram.nes", nrom_16k(0, 0x08, 7))?;
+        cartridge.write(0x6000, 0x55, Device::CPU);
+        cartridge.write(0x7FFF, 0xAA, Device::CPU);
+        assert_eq!(cartridge.read(0x6000, Device::CPU), 0x55);
+        assert_eq!(cartridge.read(0x7FFF, Device::CPU), 0xAA);
+        assert_eq!(cartridge.read(0x67FF, Device::CPU), 0x00);
+
+        // iNES 1.0 with battery, the RAM is saved in the `.sav` file
+        let save_path = std::env::temp_dir().join("plastic_nrom_battery.nes.sav");
+        let _ = std::fs::remove_file(&save_path);
+        {
+            let mut cartridge = load_nrom("plastic_nrom_battery.nes", nrom_16k(0x02, 0, 0))?;
+            cartridge.write(0x7123, 0x42, Device::CPU);
+            assert_eq!(cartridge.read(0x7123, Device::CPU), 0x42);
+        }
+        let saved = std::fs::read(&save_path)?;
+        assert_eq!(saved.len(), 0x2000);
+        assert_eq!(saved[0x1123], 0x42);
+
+        let cartridge = load_nrom("plastic_nrom_battery.nes", nrom_16k(0x02, 0, 0))?;
+        assert_eq!(cartridge.read(0x7123, Device::CPU), 0x42);
+        drop(cartridge);
+        let _ = std::fs::remove_file(&save_path);
+
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_roms() {
+        assert!(matches!(
+            load_nrom("plastic_malformed.nes", Vec::new()),
+            Err(CartridgeError::TooSmall)
+        ));
+        assert!(matches!(
+            load_nrom("plastic_malformed.nes", nrom_16k(0, 0, 0)[..10].to_vec()),
+            Err(CartridgeError::TooSmall)
+        ));
+
+        let mut data = nrom_16k(0, 0, 0);
+        data[3] = 0x1B;
+        assert!(matches!(
+            load_nrom("plastic_malformed.nes", data),
+            Err(CartridgeError::BadHeaderMagic)
+        ));
+
+        // the trainer is missing
+        let data = nrom_16k(0b100, 0, 0)[..16 + 100].to_vec();
+        assert!(matches!(
+            load_nrom("plastic_malformed.nes", data),
+            Err(CartridgeError::TooSmall)
+        ));
+
+        // 32KB PRG in the header
+        let mut data = nrom_16k(0, 0, 0);
+        data[4] = 2;
+        assert!(matches!(
+            load_nrom("plastic_malformed.nes", data),
+            Err(CartridgeError::PrgSizeMismatch {
+                expected: 0x8000,
+                actual: 0x4000
+            })
+        ));
+
+        // 8KB CHR ROM in the header, but only half of it in the file
+        let mut data = nrom_16k(0, 0, 0);
+        data[5] = 1;
+        data.extend_from_slice(&[0; 0x1000]);
+        assert!(matches!(
+            load_nrom("plastic_malformed.nes", data),
+            Err(CartridgeError::ChrSizeMismatch {
+                expected: 0x2000,
+                actual: 0x1000
+            })
+        ));
+    }
+
+    #[test]
+    fn console_type_from_header() -> Result<(), CartridgeError> {
+        let console_type = |flags_7: u8, extended_type: u8| {
+            let mut data = nrom_16k(0, flags_7, 0);
+            data[13] = extended_type;
+            load_nrom("plastic_console_type.nes", data).map(|c| c.console_type())
+        };
+
+        assert_eq!(console_type(0, 0)?, ConsoleType::Nes);
+        assert_eq!(console_type(1, 0)?, ConsoleType::VsSystem);
+        assert_eq!(console_type(2, 0)?, ConsoleType::PlayChoice10);
+        // NES 2.0
+        assert_eq!(console_type(0x09, 0)?, ConsoleType::VsSystem);
+        assert_eq!(console_type(0x0B, 0x03)?, ConsoleType::Extended(3));
+        assert_eq!(console_type(0x0B, 0x01)?, ConsoleType::VsSystem);
+
+        Ok(())
+    }
+
+    #[test]
+    fn vs_ppu_type_from_header() -> Result<(), CartridgeError> {
+        let vs_ppu_type = |flags_7: u8, byte_13: u8| {
+            let mut data = nrom_16k(0, flags_7, 0);
+            data[13] = byte_13;
+            load_nrom("plastic_vs_ppu_type.nes", data).map(|c| c.vs_ppu_type())
+        };
+
+        assert_eq!(vs_ppu_type(0, 0)?, None);
+        // iNES 1.0 does not have the PPU type
+        assert_eq!(vs_ppu_type(1, 0)?, Some(VsPpuType::Rp2c03));
+        // NES 2.0, the hardware type in the high nibble is ignored
+        assert_eq!(vs_ppu_type(0x09, 0x00)?, Some(VsPpuType::Rp2c03));
+        assert_eq!(vs_ppu_type(0x09, 0x12)?, Some(VsPpuType::Rp2c04_0001));
+        assert_eq!(vs_ppu_type(0x09, 0x05)?, Some(VsPpuType::Rp2c04_0004));
+        assert_eq!(vs_ppu_type(0x09, 0x0A)?, Some(VsPpuType::Rc2c05));
+        // extended console type, byte 13 is the console type
+        assert_eq!(vs_ppu_type(0x0B, 0x01)?, Some(VsPpuType::Rp2c03));
+
+        Ok(())
+    }
+
+    /// xorshift, so the mutations are the same on every run
+    fn next_random(state: &mut u32) -> u32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state
+    }
+
+    /// random accesses to all the cartridge ranges, including bank switching
+    fn exercise_cartridge(cartridge: &mut Cartridge, random: &mut u32) {
+        use crate::common::MirroringProvider;
+
+        for _ in 0..300 {
+            let address = 0x4020 + (next_random(random) % (0x10000 - 0x4020)) as u16;
+            cartridge.write(address, next_random(random) as u8, Device::CPU);
+
+            let address = 0x4020 + (next_random(random) % (0x10000 - 0x4020)) as u16;
+            cartridge.read(address, Device::CPU);
+
+            let address = (next_random(random) % 0x2000) as u16;
+            cartridge.write(address, next_random(random) as u8, Device::PPU);
+            cartridge.read(address, Device::PPU);
+
+            cartridge.mirroring_mode();
+            cartridge.map_nametable(0x2000 | (next_random(random) % 0x1000) as u16);
+            cartridge.cpu_clock();
+        }
+    }
+
+    fn load_mutated(data: Vec<u8>, random: &mut u32) {
+        use std::io::Cursor;
+
+        // some mutations set the battery flag, keep the save file out of the
+        // working directory
+        let path = std::env::temp_dir().join("plastic_fuzz.nes");
+
+        if let Ok(mut cartridge) = Cartridge::from_reader(&path, Cursor::new(data)) {
+            exercise_cartridge(&mut cartridge, random);
+        }
+    }
+
+    #[test]
+    fn corrupted_headers_do_not_panic() {
+        let original =
+            std::fs::read("../test_roms/holy-mapperel-bin-0.02/testroms/M4_P128K_CR8K.nes")
+                .unwrap();
+        let mut random = 0x1234_5678;
+
+        // random header bytes, and truncated files
+        for _ in 0..3000 {
+            let mut data = original.clone();
+
+            for _ in 0..1 + next_random(&mut random) % 3 {
+                let index = 4 + (next_random(&mut random) % 12) as usize;
+                data[index] = next_random(&mut random) as u8;
+            }
+
+            if next_random(&mut random) % 4 == 0 {
+                data.truncate(next_random(&mut random) as usize % data.len());
+            }
+
+            load_mutated(data, &mut random);
+        }
+
+        // all mappers with random sizes
+        for mapper_id in 0..=255u8 {
+            for _ in 0..8 {
+                let mut data = original.clone();
+                let size_byte = next_random(&mut random);
+                data[4] = size_byte as u8 % 9;
+                data[5] = (size_byte >> 8) as u8 % 5;
+                data[6] = (mapper_id & 0xF) << 4 | (size_byte >> 16) as u8 & 0xB;
+                data[7] = mapper_id & 0xF0;
+                data[8] = (size_byte >> 24) as u8 % 5;
+
+                load_mutated(data, &mut random);
+            }
+        }
+
+        let _ = std::fs::remove_file(std::env::temp_dir().join("plastic_fuzz.nes.sav"));
+    }
+
+    #[test]
+    fn rom_info_holy_mapperel() {
+        let directory = "../test_roms/holy-mapperel-bin-0.02/testroms";
+        let mut checked = 0;
+
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("nes") {
+                continue;
+            }
+
+            let info = rom_info(&path).unwrap();
+            let name = path.file_stem().unwrap().to_str().unwrap().to_owned();
+            // `M<mapper>[.<submapper>]_P<size>K[_C<size>K][_CR<size>K]...`
+            let mut parts = name.split('_');
+            let mapper = parts.next().unwrap().trim_start_matches('M');
+            let (mapper, submapper) = mapper.split_once('.').unwrap_or((mapper, "0"));
+            assert_eq!(info.mapper, mapper.parse::<u16>().unwrap(), "{}", name);
+            assert_eq!(info.submapper, submapper.parse::<u8>().unwrap(), "{}", name);
+
+            let size = |part: &str, prefix: &str| {
+                part[prefix.len()..part.len() - 1].parse::<usize>().unwrap() * 1024
+            };
+            let mut chr_rom_size = 0;
+            let mut chr_ram_size = 0;
+            for part in parts {
+                if part.starts_with("CR") {
+                    chr_ram_size = size(part, "CR");
+                } else if part.starts_with('C') {
+                    chr_rom_size = size(part, "C");
+                } else if part.starts_with('P') {
+                    assert_eq!(info.prg_rom_size, size(part, "P"), "{}", name);
+                } else if part.starts_with('S') {
+                    assert!(info.has_battery, "{}", name);
+                    assert_eq!(info.prg_nvram_size, size(part, "S"), "{}", name);
+                } else if part.starts_with('W') {
+                    assert!(!info.has_battery, "{}", name);
+                    assert_eq!(info.prg_ram_size, size(part, "W"), "{}", name);
+                } else if part == "V" {
+                    assert_eq!(info.mirroring, MirroringMode::Vertical, "{}", name);
+                } else if part == "H" {
+                    assert_eq!(info.mirroring, MirroringMode::Horizontal, "{}", name);
+                }
+            }
+            assert_eq!(info.chr_rom_size, chr_rom_size, "{}", name);
+            if chr_ram_size != 0 {
+                assert_eq!(info.chr_ram_size, chr_ram_size, "{}", name);
+            }
+
+            let data = std::fs::read(&path).unwrap();
+            assert_eq!(rom_info_from_bytes(&data).unwrap(), info);
+            checked += 1;
+        }
+
+        assert_eq!(checked, 40);
+    }
+
+    #[test]
+    fn rom_info_from_loaded_cartridge() -> Result<(), CartridgeError> {
+        let data = nrom_16k(0x01, 0x08, 7);
+        let info = rom_info_from_bytes(&data)?;
+
+        assert_eq!(info.header_format, HeaderFormat::Nes2);
+        assert_eq!(info.mapper_name.as_deref(), Some("NROM"));
+        assert_eq!(info.prg_ram_size, 0x2000);
+        assert_eq!(info.chr_ram_size, 0x2000);
+        assert_eq!(info.tv_system, Some(TvRegion::Ntsc));
+        assert_eq!(info.prg_crc32, crc32_update(0, &data[16..]));
+        assert_eq!(info.chr_crc32, 0);
+        assert_eq!(info.crc32, info.prg_crc32);
+
+        let cartridge = load_nrom("plastic_rom_info.nes", data)?;
+        assert_eq!(cartridge.rom_info(), Some(info));
+        assert_eq!(Cartridge::new_without_file().rom_info(), None);
+
+        // the CRC of separate parts is the same as the whole
+        assert_eq!(crc32_update(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            crc32_update(crc32_update(0, b"1234"), b"56789"),
+            0xCBF4_3926
+        );
+
+        // unsupported mappers still have the information
+        let mut data = nrom_16k(0, 0, 0);
+        data[6] = 0x50;
+        data[9] = 1;
+        let info = rom_info_from_bytes(&data)?;
+        assert_eq!(info.mapper, 5);
+        assert_eq!(info.mapper_name, None);
+        assert_eq!(info.header_format, HeaderFormat::INes);
+        assert_eq!(info.tv_system, Some(TvRegion::Pal));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rom_info_trailing_data() -> Result<(), CartridgeError> {
+        let mut data = nrom_16k(0, 0, 0);
+        assert_eq!(rom_info_from_bytes(&data)?.trailing_data_size, 0);
+
+        // a title at the end of the file is ignored, but reported
+        data.extend_from_slice(b"some title");
+        let info = rom_info_from_bytes(&data)?;
+        assert_eq!(info.trailing_data_size, 10);
+
+        let cartridge = load_nrom("plastic_rom_info_trailing.nes", data)?;
+        assert_eq!(cartridge.rom_info(), Some(info));
+
+        Ok(())
+    }
+
+    #[test]
+    fn chr_switch_events() -> Result<(), CartridgeError> {
+        // MMC3 with 128KB PRG and 64KB CHR ROM
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 8, 8, 0x40, 0];
+        data.extend_from_slice(&[0; 8]);
+        data.resize(16 + 0x20000 + 0x10000, 0);
+        let mut logged = load_nrom("plastic_chr_switch.nes", data.clone())?;
+        let mut plain = load_nrom("plastic_chr_switch.nes", data)?;
+        logged.set_chr_switch_logging(true);
+
+        let writes = [(0x8000, 2), (0x8001, 5), (0xA000, 1), (0x8000, 0x82)];
+        for (cycle, &(address, value)) in writes.iter().enumerate() {
+            logged.set_chr_switch_cycle(cycle as u64 * 10);
+            logged.write(address, value, Device::CPU);
+            plain.write(address, value, Device::CPU);
+        }
+
+        let event = |cycle, mapper_register, slot, new_bank| ChrSwitchEvent {
+            cycle,
+            mapper_register,
+            slot,
+            new_bank,
+        };
+        // R2 is at `0x1000`, then the 2KB and 1KB halves are swapped, only
+        // the slots with a different bank are reported
+        assert_eq!(
+            logged.take_chr_switch_events(),
+            vec![
+                event(10, 0x8001, 4, 5),
+                event(30, 0x8000, 0, 5),
+                event(30, 0x8000, 1, 0),
+                event(30, 0x8000, 3, 0),
+                event(30, 0x8000, 4, 0),
+                event(30, 0x8000, 5, 1),
+                event(30, 0x8000, 7, 1),
+            ]
+        );
+        assert!(logged.take_chr_switch_events().is_empty());
+
+        // checking the banks does not change the IRQ counter
+        assert_eq!(logged.mapper_state(), plain.mapper_state());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    fn write_zip(name: &str, entries: &[(&str, &[u8])]) -> std::path::PathBuf {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(name);
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (entry, data) in entries {
+            writer
+                .start_file(*entry, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+
+        path
+    }
+
+    #[test]
+    #[cfg(feature = "zip")]
+    fn load_from_zip() -> Result<(), CartridgeError> {
+        let rom_16k = nrom_16k(0, 0, 0);
+        let mut rom_32k = nrom_16k(0, 0, 0);
+        rom_32k[4] = 2;
+        rom_32k.resize(16 + 0x8000, 0x32);
+
+        // the only `.nes` file is picked, even inside a directory
+        let path = write_zip(
+            "plastic_single_rom.zip",
+            &[("readme.txt", b"readme"), ("roms/game.nes", &rom_16k)],
+        );
+        let cartridge = Cartridge::from_zip(&path, None)?;
+        assert_eq!(cartridge.read(0xFFFC, Device::CPU), (0x3FFC * 7) as u8);
+        assert!(matches!(
+            Cartridge::from_zip(&path, Some("game.nes")),
+            Err(CartridgeError::ZipEntryNotFound(Some(_)))
+        ));
+
+        // multiple ROMs need the entry name
+        let path = write_zip(
+            "plastic_multiple_roms.zip",
+            &[("b.nes", &rom_32k), ("a.NES", &rom_16k)],
+        );
+        match Cartridge::from_zip(&path, None) {
+            Err(CartridgeError::ZipMultipleRoms(entries)) => {
+                assert_eq!(entries, ["a.NES", "b.nes"])
+            }
+            _ => panic!("expected ZipMultipleRoms"),
+        }
+        let cartridge = Cartridge::from_zip(&path, Some("b.nes"))?;
+        assert_eq!(cartridge.read(0xFFFC, Device::CPU), 0x32);
+        let cartridge = Cartridge::from_zip(&path, Some("a.NES"))?;
+        assert_eq!(cartridge.read(0xFFFC, Device::CPU), (0x3FFC * 7) as u8);
+
+        let path = write_zip("plastic_no_rom.zip", &[("readme.txt", b"readme")]);
+        assert!(matches!(
+            Cartridge::from_zip(&path, None),
+            Err(CartridgeError::ZipEntryNotFound(None))
+        ));
+
+        // not a zip archive
+        let path = std::env::temp_dir().join("plastic_not_a_zip.zip");
+        std::fs::write(&path, &rom_16k)?;
+        assert!(matches!(
+            Cartridge::from_zip(&path, None),
+            Err(CartridgeError::ZipError(_))
+        ));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,69 @@
+use super::error::CartridgeError;
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use zip::{result::ZipError, ZipArchive};
+
+impl From<ZipError> for CartridgeError {
+    fn from(from: ZipError) -> Self {
+        match from {
+            ZipError::Io(err) => Self::FileError(err),
+            ZipError::FileNotFound => Self::ZipEntryNotFound(None),
+            err => Self::ZipError(err.to_string()),
+        }
+    }
+}
+
+fn is_rom_entry(name: &str) -> bool {
+    !name.ends_with('/') && name.to_ascii_lowercase().ends_with(".nes")
+}
+
+/// read the ROM `entry` from the zip archive at `path`, or the only `.nes`
+/// file in it if `entry` is `None`
+///
+/// returns the data and the path of the ROM as if it was extracted next to
+/// the archive (without the directories inside the archive), which is used
+/// for the SRAM save file
+pub(super) fn read_zip_rom(
+    path: &Path,
+    entry: Option<&str>,
+) -> Result<(PathBuf, Vec<u8>), CartridgeError> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+
+    let entry_name = match entry {
+        Some(entry) => {
+            if archive.file_names().all(|name| name != entry) {
+                return Err(CartridgeError::ZipEntryNotFound(Some(entry.to_owned())));
+            }
+            entry.to_owned()
+        }
+        None => {
+            let mut roms = archive
+                .file_names()
+                .filter(|name| is_rom_entry(name))
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+
+            match roms.len() {
+                0 => return Err(CartridgeError::ZipEntryNotFound(None)),
+                1 => roms.remove(0),
+                _ => {
+                    roms.sort();
+                    return Err(CartridgeError::ZipMultipleRoms(roms));
+                }
+            }
+        }
+    };
+
+    let mut file = archive.by_name(&entry_name)?;
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut data)?;
+
+    let rom_file_name = Path::new(&entry_name)
+        .file_name()
+        .ok_or(CartridgeError::ZipEntryNotFound(Some(entry_name.clone())))?;
+
+    Ok((path.with_file_name(rom_file_name), data))
+}
@@ -0,0 +1,71 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as fmtResult},
+};
+
+/// Returned by [`NES::write_chr`](crate::nes::NES::write_chr) and
+/// [`NES::encode_tile`](crate::nes::NES::encode_tile)
+pub enum ChrWriteError {
+    /// there is no cartridge loaded
+    NoCartridge,
+    /// the mapper does not allow writes to `address`, it is mapped to CHR ROM
+    ReadOnly { address: u16 },
+}
+
+impl ChrWriteError {
+    fn get_message(&self) -> String {
+        match self {
+            Self::NoCartridge => "there is no cartridge loaded".to_owned(),
+            Self::ReadOnly { address } => {
+                format!("CHR address {:04X} is mapped to CHR ROM", address)
+            }
+        }
+    }
+}
+
+impl Error for ChrWriteError {}
+
+impl Display for ChrWriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        write!(f, "{}", self.get_message())
+    }
+}
+
+impl Debug for ChrWriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        write!(f, "{}", self.get_message())
+    }
+}
+
+/// convert a tile in the NES format (8 bytes of the low bit plane, then 8
+/// bytes of the high bit plane) into rows of color indices (0-3)
+pub(crate) fn decode_tile(data: &[u8; 16]) -> [[u8; 8]; 8] {
+    let mut pixels = [[0; 8]; 8];
+
+    for (y, row) in pixels.iter_mut().enumerate() {
+        let low = data[y];
+        let high = data[y + 8];
+
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let bit = 7 - x;
+            *pixel = (high >> bit & 1) << 1 | (low >> bit & 1);
+        }
+    }
+
+    pixels
+}
+
+/// the opposite of [`decode_tile`], only the low 2 bits of the pixels are used
+pub(crate) fn encode_tile(pixels: &[[u8; 8]; 8]) -> [u8; 16] {
+    let mut data = [0; 16];
+
+    for (y, row) in pixels.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            let bit = 7 - x;
+            data[y] |= (pixel & 1) << bit;
+            data[y + 8] |= (pixel >> 1 & 1) << bit;
+        }
+    }
+
+    data
+}
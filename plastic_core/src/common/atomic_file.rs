@@ -0,0 +1,59 @@
+use std::fs::{self, File};
+use std::io::{Result as ioResult, Write};
+use std::path::{Path, PathBuf};
+
+/// the temporary file used while writing `path`, it is in the same directory
+/// so that renaming it does not move it across file systems
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+
+    path.with_file_name(name)
+}
+
+/// write the content of `path` with `write`, the data is written to a
+/// temporary file, synced, then renamed over `path`, so `path` has either
+/// the old or the new content even if the process dies in the middle.
+/// If `write` fails, the temporary file is removed and `path` is untouched
+///
+/// `fs::rename` replaces the target on all platforms (with
+/// `MOVEFILE_REPLACE_EXISTING` on Windows)
+pub fn write_atomic_with<F: FnOnce(&mut File) -> ioResult<()>>(
+    path: &Path,
+    write: F,
+) -> ioResult<()> {
+    let temp_path = temp_path(path);
+
+    let result = File::create(&temp_path).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+
+    fs::rename(&temp_path, path)?;
+
+    sync_parent_dir(path)
+}
+
+/// make the rename durable, directories cannot be opened on Windows
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> ioResult<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> ioResult<()> {
+    Ok(())
+}
+
+/// write `data` to `path` atomically, see [`write_atomic_with`]
+pub fn write_atomic(path: &Path, data: &[u8]) -> ioResult<()> {
+    write_atomic_with(path, |file| file.write_all(data))
+}
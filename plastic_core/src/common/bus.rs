@@ -7,6 +7,16 @@ pub enum Device {
 pub trait Bus {
     fn read(&self, address: u16, device: Device) -> u8;
     fn write(&mut self, address: u16, data: u8, device: Device);
+
+    /// the 1KB CHR bank that the pattern table `address` is mapped from, used
+    /// for debugging, `None` if it is not known
+    fn chr_bank(&self, _address: u16) -> Option<u16> {
+        None
+    }
+
+    /// called by the PPU when bit 12 of the address it puts on the bus goes
+    /// from 0 to 1, the mapper uses it to count scanlines
+    fn ppu_a12_rising_edge(&self) {}
 }
 
 /// macro used to generate binding for enum to convert it from u16
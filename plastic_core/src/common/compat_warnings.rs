@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CompatWarningCategory {
+    /// access to a cartridge register the current mapper does not implement
+    UnsupportedMapperFeature,
+    /// write to a known expansion audio register (FDS, MMC5, ...)
+    ExpansionAudioWrite,
+    /// access to a register (or bits in it) that is not emulated
+    UnemulatedRegister,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompatWarning {
+    pub category: CompatWarningCategory,
+    pub address: u16,
+    /// the value of the first access (`0` for reads)
+    pub value: u8,
+    /// the CPU cycle of the first access
+    pub cpu_cycle: u64,
+    /// number of times this register was accessed
+    pub count: u64,
+}
+
+/// Collects accesses to unimplemented features, accesses to the same
+/// register are grouped in one warning
+pub struct CompatWarnings {
+    enabled: bool,
+    cpu_cycle: u64,
+    warnings: Vec<CompatWarning>,
+    /// index into `warnings` for each `(category, address)`
+    indices: HashMap<(CompatWarningCategory, u16), usize>,
+}
+
+impl CompatWarnings {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            cpu_cycle: 0,
+            warnings: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// set the CPU cycle to use for the next recorded warnings
+    pub fn set_cpu_cycle(&mut self, cpu_cycle: u64) {
+        self.cpu_cycle = cpu_cycle;
+    }
+
+    pub fn record(&mut self, category: CompatWarningCategory, address: u16, value: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if let Some(&index) = self.indices.get(&(category, address)) {
+            self.warnings[index].count += 1;
+        } else {
+            self.indices
+                .insert((category, address), self.warnings.len());
+            self.warnings.push(CompatWarning {
+                category,
+                address,
+                value,
+                cpu_cycle: self.cpu_cycle,
+                count: 1,
+            });
+        }
+    }
+
+    /// take all recorded warnings, and start collecting new ones
+    pub fn take(&mut self) -> Vec<CompatWarning> {
+        self.indices.clear();
+        std::mem::take(&mut self.warnings)
+    }
+}
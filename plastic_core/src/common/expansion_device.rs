@@ -0,0 +1,27 @@
+/// A device connected to the CPU bus in `0x4020-0x5FFF`, it gets the first
+/// chance to handle accesses in that range before the cartridge
+pub trait ExpansionDevice {
+    /// read from `address`, `None` lets the cartridge handle the read
+    fn read(&mut self, address: u16) -> Option<u8>;
+
+    /// write to `address`, returning `false` lets the cartridge handle the
+    /// write
+    fn write(&mut self, address: u16, data: u8) -> bool;
+
+    /// called once every CPU cycle, `cpu_cycles` is the number of cycles
+    /// since power-on/reset
+    fn clock(&mut self, cpu_cycles: u64);
+
+    /// the state of the IRQ line of this device, it is OR'd with the APU and
+    /// cartridge IRQ lines
+    fn irq(&self) -> bool;
+
+    /// the state to be included in save states, devices are not saved by
+    /// default
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// load the state saved with [`ExpansionDevice::save_state`]
+    fn load_state(&mut self, _data: &[u8]) {}
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum MirroringMode {
     Vertical,
     Horizontal,
@@ -7,6 +7,36 @@ pub enum MirroringMode {
     FourScreen,
 }
 
+/// Where a nametable access (`0x2000-0x2FFF`) should be routed to
+// no mapper replaces the nametables yet
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NametableTarget {
+    /// use the console VRAM with the current [`MirroringMode`]
+    Default,
+    /// one of the two 1KB pages of the console VRAM (CIRAM)
+    InternalVramPage(u8),
+    /// an offset into the cartridge memory (CHR), this is used by boards that
+    /// replace some or all the nametables with their own memory
+    CartridgeMemory(usize),
+}
+
 pub trait MirroringProvider {
     fn mirroring_mode(&self) -> MirroringMode;
+
+    /// get the target for the nametable `address`, this is checked before
+    /// using [`MirroringProvider::mirroring_mode`]
+    fn map_nametable(&self, _address: u16) -> NametableTarget {
+        NametableTarget::Default
+    }
+
+    /// read from the cartridge memory at `offset` returned by
+    /// [`NametableTarget::CartridgeMemory`]
+    fn read_nametable_memory(&self, _offset: usize) -> u8 {
+        0
+    }
+
+    /// write to the cartridge memory at `offset` returned by
+    /// [`NametableTarget::CartridgeMemory`]
+    fn write_nametable_memory(&mut self, _offset: usize, _data: u8) {}
 }
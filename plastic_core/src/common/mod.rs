@@ -2,10 +2,40 @@
 mod bus;
 mod mirroring;
 
+pub mod atomic_file;
+pub mod compat_warnings;
+pub mod expansion_device;
 pub mod interconnection;
 pub mod save_state;
 
 pub use bus::{Bus, Device};
-pub use mirroring::{MirroringMode, MirroringProvider};
+pub use mirroring::{MirroringMode, MirroringProvider, NametableTarget};
+
+mod tests;
 
 pub const CPU_FREQ: f64 = 1.789773 * 1E6;
+
+/// The TV system of the console, this affects the timing of some components
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TvRegion {
+    Ntsc,
+    Pal,
+    /// PAL famiclone, uses the PAL frame rate with the NTSC APU tables
+    Dendy,
+}
+
+impl Default for TvRegion {
+    fn default() -> Self {
+        Self::Ntsc
+    }
+}
+
+/// How accurately the timing details of the console are emulated, see
+/// [`NES::enable_timing_mode`](crate::nes::NES::enable_timing_mode)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimingMode {
+    /// simplified timing, fast enough and runs most games fine
+    Approximate,
+    /// models the extra details that some games and test ROMs depend on
+    CycleAccurate,
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::convert::From;
 use std::error::Error;
 use std::fmt::Display;
@@ -11,6 +12,8 @@ pub trait Savable {
 #[derive(Debug)]
 pub enum SaveError {
     IoError(ioError),
+    /// the (compressed) data is not valid
+    CorruptedData,
     Others,
 }
 
@@ -26,7 +29,253 @@ impl Display for SaveError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             SaveError::IoError(err) => write!(f, "IO Error: {}", err),
+            SaveError::CorruptedData => write!(f, "Corrupted data"),
             SaveError::Others => write!(f, "Others"),
         }
     }
 }
+
+/// differences separated by less than this number of bytes are merged into
+/// one run, as a run header is 8 bytes
+const DELTA_MIN_GAP: usize = 8;
+
+fn read_u32(data: &mut &[u8]) -> Result<usize, SaveError> {
+    let mut bytes = [0; 4];
+    data.read_exact(&mut bytes)
+        .map_err(|_| SaveError::CorruptedData)?;
+
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+/// compute a byte-level delta to get `new` from `base`, the format is the
+/// length of `new` followed by runs of `(skip, length, bytes)`, all numbers
+/// are `u32` little-endian
+pub fn compute_delta(base: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    delta.extend_from_slice(&(new.len() as u32).to_le_bytes());
+
+    let is_different = |i: usize| base.get(i) != Some(&new[i]);
+
+    let mut last_end = 0;
+    let mut i = 0;
+    while i < new.len() {
+        if !is_different(i) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let mut end = i + 1;
+        let mut gap = 0;
+        i += 1;
+
+        // extend the run until there are `DELTA_MIN_GAP` equal bytes
+        while i < new.len() && gap < DELTA_MIN_GAP {
+            if is_different(i) {
+                end = i + 1;
+                gap = 0;
+            } else {
+                gap += 1;
+            }
+            i += 1;
+        }
+
+        delta.extend_from_slice(&((start - last_end) as u32).to_le_bytes());
+        delta.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        delta.extend_from_slice(&new[start..end]);
+
+        last_end = end;
+        i = end;
+    }
+
+    delta
+}
+
+/// apply a delta generated by [`compute_delta`] to `base`
+pub fn apply_delta(base: &[u8], mut delta: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let new_len = read_u32(&mut delta)?;
+
+    // the bytes after the end of `base` are all in the delta, so don't
+    // trust `new_len` more than that
+    if new_len > base.len() + delta.len() {
+        return Err(SaveError::CorruptedData);
+    }
+
+    let mut result = base.to_vec();
+    result.resize(new_len, 0);
+
+    let mut position = 0;
+    while !delta.is_empty() {
+        position += read_u32(&mut delta)?;
+        let length = read_u32(&mut delta)?;
+
+        if length > delta.len() || position + length > new_len {
+            return Err(SaveError::CorruptedData);
+        }
+
+        result[position..position + length].copy_from_slice(&delta[..length]);
+        delta = &delta[length..];
+        position += length;
+    }
+
+    Ok(result)
+}
+
+/// the start of a compressed state, followed by a flag byte, legacy states
+/// are raw and do not have this
+const STATE_MAGIC: &[u8; 4] = b"PLST";
+const STATE_FLAG_COMPRESSED: u8 = 1;
+
+const LZ_HASH_BITS: usize = 14;
+/// a match takes 3 bytes, so it must be longer to be worth it
+const LZ_MIN_MATCH: usize = 4;
+/// `0x80 | (length - LZ_MIN_MATCH)` must fit in a byte
+const LZ_MAX_MATCH: usize = 0x7F + LZ_MIN_MATCH;
+const LZ_MAX_LITERALS: usize = 0x80;
+const LZ_MAX_DISTANCE: usize = 0xFFFF;
+
+fn lz_hash(data: &[u8]) -> usize {
+    let value = (data[0] as u32) | (data[1] as u32) << 8 | (data[2] as u32) << 16;
+
+    (value.wrapping_mul(2_654_435_761) >> (32 - LZ_HASH_BITS)) as usize
+}
+
+fn lz_flush_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(LZ_MAX_LITERALS) {
+        output.push((chunk.len() - 1) as u8);
+        output.extend_from_slice(chunk);
+    }
+}
+
+/// compress `data` using an LZ77 scheme, runs of the same byte are matches
+/// with distance `1`.
+///
+/// The format is the uncompressed length (`u32` little-endian) followed by
+/// tokens, a token starts with a byte `c`:
+/// - `c < 0x80`: `c + 1` literal bytes follow.
+/// - `c >= 0x80`: copy `(c & 0x7F) + 4` bytes from `distance` bytes back in the
+///   output, `distance` is a `u16` little-endian that follows.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() / 4 + 16);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+    let mut table = vec![usize::MAX; 1 << LZ_HASH_BITS];
+    let mut literals_start = 0;
+    let mut i = 0;
+
+    while i + LZ_MIN_MATCH <= data.len() {
+        let hash = lz_hash(&data[i..]);
+        let candidate = table[hash];
+        table[hash] = i;
+
+        if candidate == usize::MAX
+            || i - candidate > LZ_MAX_DISTANCE
+            || data[candidate..candidate + LZ_MIN_MATCH] != data[i..i + LZ_MIN_MATCH]
+        {
+            i += 1;
+            continue;
+        }
+
+        let mut length = LZ_MIN_MATCH;
+        while i + length < data.len()
+            && length < LZ_MAX_MATCH
+            && data[candidate + length] == data[i + length]
+        {
+            length += 1;
+        }
+
+        lz_flush_literals(&mut output, &data[literals_start..i]);
+        output.push(0x80 | (length - LZ_MIN_MATCH) as u8);
+        output.extend_from_slice(&((i - candidate) as u16).to_le_bytes());
+
+        for j in i + 1..(i + length).min(data.len() - LZ_MIN_MATCH + 1) {
+            table[lz_hash(&data[j..])] = j;
+        }
+
+        i += length;
+        literals_start = i;
+    }
+
+    lz_flush_literals(&mut output, &data[literals_start..]);
+
+    output
+}
+
+/// decompress data produced by [`compress`], invalid data results in
+/// [`SaveError::CorruptedData`]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, SaveError> {
+    if data.len() < 4 {
+        return Err(SaveError::CorruptedData);
+    }
+    let length = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let mut input = &data[4..];
+
+    // a token can expand to at most `LZ_MAX_MATCH` bytes from 3 bytes, so
+    // don't trust `length` more than that
+    if length > input.len() / 3 * LZ_MAX_MATCH + LZ_MAX_MATCH {
+        return Err(SaveError::CorruptedData);
+    }
+
+    let mut output = Vec::with_capacity(length);
+
+    while let Some((&token, rest)) = input.split_first() {
+        input = rest;
+
+        if token < 0x80 {
+            let count = token as usize + 1;
+            if count > input.len() || output.len() + count > length {
+                return Err(SaveError::CorruptedData);
+            }
+
+            output.extend_from_slice(&input[..count]);
+            input = &input[count..];
+        } else {
+            let count = (token & 0x7F) as usize + LZ_MIN_MATCH;
+            if input.len() < 2 || output.len() + count > length {
+                return Err(SaveError::CorruptedData);
+            }
+
+            let distance = u16::from_le_bytes([input[0], input[1]]) as usize;
+            input = &input[2..];
+            if distance == 0 || distance > output.len() {
+                return Err(SaveError::CorruptedData);
+            }
+
+            // byte by byte, as the source can overlap with the output
+            let start = output.len() - distance;
+            for j in 0..count {
+                let byte = output[start + j];
+                output.push(byte);
+            }
+        }
+    }
+
+    if output.len() != length {
+        return Err(SaveError::CorruptedData);
+    }
+
+    Ok(output)
+}
+
+/// wrap a raw state into the compressed state container
+pub fn compress_state(state: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    output.extend_from_slice(STATE_MAGIC);
+    output.push(STATE_FLAG_COMPRESSED);
+    output.extend_from_slice(&compress(state));
+
+    output
+}
+
+/// get the raw state from `data`, which is either a compressed state or a
+/// legacy raw state
+pub fn decompress_state(data: &[u8]) -> Result<Cow<'_, [u8]>, SaveError> {
+    if data.len() > STATE_MAGIC.len() && &data[..STATE_MAGIC.len()] == STATE_MAGIC {
+        match data[STATE_MAGIC.len()] {
+            STATE_FLAG_COMPRESSED => Ok(Cow::Owned(decompress(&data[STATE_MAGIC.len() + 1..])?)),
+            _ => Err(SaveError::CorruptedData),
+        }
+    } else {
+        Ok(Cow::Borrowed(data))
+    }
+}
@@ -0,0 +1,295 @@
+#[cfg(test)]
+mod compat_warnings_tests {
+    use super::super::compat_warnings::{CompatWarningCategory, CompatWarnings};
+
+    #[test]
+    fn compat_warnings_disabled_by_default() {
+        let mut warnings = CompatWarnings::new();
+
+        warnings.record(CompatWarningCategory::UnemulatedRegister, 0x4018, 1);
+
+        assert!(warnings.take().is_empty());
+    }
+
+    #[test]
+    fn compat_warnings_deduplicate() {
+        let mut warnings = CompatWarnings::new();
+        warnings.set_enabled(true);
+
+        warnings.set_cpu_cycle(10);
+        warnings.record(CompatWarningCategory::UnemulatedRegister, 0x4018, 1);
+        warnings.set_cpu_cycle(20);
+        warnings.record(CompatWarningCategory::UnemulatedRegister, 0x4018, 2);
+        warnings.record(CompatWarningCategory::UnemulatedRegister, 0x4018, 3);
+        warnings.record(CompatWarningCategory::ExpansionAudioWrite, 0x4080, 3);
+
+        let result = warnings.take();
+        assert_eq!(result.len(), 2);
+
+        let entry = &result[0];
+        assert_eq!(entry.category, CompatWarningCategory::UnemulatedRegister);
+        assert_eq!(entry.address, 0x4018);
+        assert_eq!(entry.value, 1);
+        assert_eq!(entry.cpu_cycle, 10);
+        assert_eq!(entry.count, 3);
+
+        assert_eq!(result[1].count, 1);
+
+        // starts from the beginning after `take`
+        warnings.record(CompatWarningCategory::UnemulatedRegister, 0x4018, 1);
+        let result = warnings.take();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].count, 1);
+    }
+}
+
+#[cfg(test)]
+mod save_state_delta_tests {
+    use super::super::save_state::{apply_delta, compute_delta, SaveError};
+
+    fn check_round_trip(base: &[u8], new: &[u8]) -> Vec<u8> {
+        let delta = compute_delta(base, new);
+        assert_eq!(apply_delta(base, &delta).unwrap(), new);
+
+        delta
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let base = vec![0u8; 0x1000];
+
+        // no changes, only the length
+        assert_eq!(check_round_trip(&base, &base).len(), 4);
+
+        let mut new = base.clone();
+        new[0] = 1;
+        new[5] = 2;
+        new[0x800] = 3;
+        new[0xFFF] = 4;
+        let delta = check_round_trip(&base, &new);
+        // much smaller than a full state
+        assert!(delta.len() < 64);
+
+        // different lengths
+        check_round_trip(&base, &new[..0x900]);
+        let mut longer = new.clone();
+        longer.extend_from_slice(&[9; 20]);
+        check_round_trip(&base, &longer);
+        check_round_trip(&[], &new);
+    }
+
+    #[test]
+    fn delta_corrupted() {
+        let base = vec![0u8; 0x100];
+        let mut new = base.clone();
+        new[0x10] = 1;
+
+        let delta = compute_delta(&base, &new);
+
+        assert!(matches!(
+            apply_delta(&base, &delta[..2]),
+            Err(SaveError::CorruptedData)
+        ));
+        assert!(matches!(
+            apply_delta(&base, &delta[..delta.len() - 1]),
+            Err(SaveError::CorruptedData)
+        ));
+
+        // the length is checked before allocating
+        assert!(matches!(
+            apply_delta(&base, &[0xFF; 4]),
+            Err(SaveError::CorruptedData)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod save_state_compression_tests {
+    use super::super::save_state::{
+        compress, compress_state, decompress, decompress_state, SaveError,
+    };
+
+    /// simple xorshift generator, to not depend on `rand`
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn bytes(&mut self, length: usize) -> Vec<u8> {
+            (0..length).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    fn check_round_trip(data: &[u8]) -> usize {
+        let compressed = compress(data);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+
+        compressed.len()
+    }
+
+    #[test]
+    fn compression_round_trip_zeros() {
+        for &length in [0, 1, 2, 3, 4, 130, 131, 0x800, 0x10000, 0x20001].iter() {
+            let size = check_round_trip(&vec![0; length]);
+
+            if length >= 0x800 {
+                assert!(size * 20 < length);
+            }
+        }
+    }
+
+    #[test]
+    fn compression_round_trip_incompressible() {
+        let mut random = Random(0x1234_5678_9ABC_DEF1);
+
+        for length in (0..300).chain([0x800, 0x2000, 0x10001].iter().cloned()) {
+            let data = random.bytes(length);
+            let size = check_round_trip(&data);
+
+            // literals add one byte for every 128, plus the length header
+            assert!(size <= 4 + length + length / 128 + 1);
+        }
+    }
+
+    #[test]
+    fn compression_round_trip_random_patterns() {
+        let mut random = Random(0xDEAD_BEEF_CAFE_F00D);
+
+        for _ in 0..200 {
+            let length = (random.next() % 0x3000) as usize;
+            let mut data = Vec::with_capacity(length);
+
+            // mix of runs, repeated blocks and noise, like a real state
+            while data.len() < length {
+                let chunk = (random.next() % 300) as usize + 1;
+                match random.next() % 3 {
+                    0 => {
+                        let byte = random.next() as u8;
+                        data.resize(data.len() + chunk, byte);
+                    }
+                    1 if data.len() > chunk => {
+                        let start = (random.next() as usize) % (data.len() - chunk);
+                        for i in 0..chunk {
+                            data.push(data[start + i]);
+                        }
+                    }
+                    _ => data.extend(random.bytes(chunk)),
+                }
+            }
+
+            check_round_trip(&data);
+        }
+    }
+
+    #[test]
+    fn decompress_corrupted_data() {
+        let mut random = Random(0x0BAD_5EED_0BAD_5EED);
+
+        let mut data = vec![0; 0x800];
+        data.extend(random.bytes(0x100));
+        let compressed = compress(&data);
+
+        // truncated
+        for length in 0..compressed.len() {
+            assert!(matches!(
+                decompress(&compressed[..length]),
+                Err(SaveError::CorruptedData)
+            ));
+        }
+
+        // random bytes and random modifications must not panic
+        for _ in 0..2000 {
+            let length = (random.next() % 64) as usize;
+            let _ = decompress(&random.bytes(length));
+
+            let mut modified = compressed.clone();
+            let index = (random.next() as usize) % modified.len();
+            modified[index] = random.next() as u8;
+            if let Ok(result) = decompress(&modified) {
+                assert_eq!(result.len(), data.len());
+            }
+        }
+
+        // huge declared length is rejected without allocating
+        assert!(matches!(
+            decompress(&[0xFF, 0xFF, 0xFF, 0xFF, 0x80, 1, 0]),
+            Err(SaveError::CorruptedData)
+        ));
+    }
+
+    #[test]
+    fn state_container() {
+        let state = vec![5; 0x100];
+
+        let compressed = compress_state(&state);
+        assert_eq!(decompress_state(&compressed).unwrap().as_ref(), &state[..]);
+
+        // legacy raw states are returned as is
+        assert_eq!(decompress_state(&state).unwrap().as_ref(), &state[..]);
+
+        let mut wrong_flag = compressed;
+        wrong_flag[4] = 9;
+        assert!(matches!(
+            decompress_state(&wrong_flag),
+            Err(SaveError::CorruptedData)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod atomic_file_tests {
+    use super::super::atomic_file::{write_atomic, write_atomic_with};
+    use crate::tests::TempDir;
+    use std::io::{Error, ErrorKind, Result as ioResult, Write};
+
+    /// fails after writing `remaining` bytes
+    struct FailingWriter<W: Write> {
+        inner: W,
+        remaining: usize,
+    }
+
+    impl<W: Write> Write for FailingWriter<W> {
+        fn write(&mut self, buf: &[u8]) -> ioResult<usize> {
+            if self.remaining == 0 {
+                return Err(Error::new(ErrorKind::WriteZero, "disk full"));
+            }
+
+            let len = buf.len().min(self.remaining);
+            self.remaining -= len;
+            self.inner.write(&buf[..len])
+        }
+
+        fn flush(&mut self) -> ioResult<()> {
+            self.inner.flush()
+        }
+    }
+
+    #[test]
+    fn failed_write_keeps_old_file() {
+        let dir = TempDir::new("atomic_file");
+        let path = dir.path().join("state.pst");
+
+        write_atomic(&path, &[1; 100]).unwrap();
+
+        let result = write_atomic_with(&path, |file| {
+            let mut writer = FailingWriter {
+                inner: file,
+                remaining: 10,
+            };
+            writer.write_all(&[2; 100])
+        });
+        assert!(result.is_err());
+
+        assert_eq!(std::fs::read(&path).unwrap(), [1; 100]);
+        // the temporary file is removed
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        write_atomic(&path, &[3; 50]).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), [3; 50]);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::common::{Bus, Device};
+use crate::common::{
+    save_state::{Savable, SaveError},
+    Bus, Device,
+};
 use bitflags::bitflags;
 use std::cell::Cell;
 use std::sync::{Arc, Mutex};
@@ -36,6 +39,45 @@ impl StandardNESControllerState {
     pub fn release(&mut self, key: StandardNESKey) {
         self.remove(StandardNESControllerState::from_bits(key as u8).unwrap());
     }
+
+    /// press or release `key`
+    pub fn set_key(&mut self, key: StandardNESKey, pressed: bool) {
+        if pressed {
+            self.press(key);
+        } else {
+            self.release(key);
+        }
+    }
+
+    /// remove `Left+Right` and `Up+Down` if they are pressed together, which
+    /// is not possible on a real controller
+    fn without_opposing_directions(self) -> Self {
+        let mut result = self;
+
+        if result.contains(Self::LEFT | Self::RIGHT) {
+            result.remove(Self::LEFT | Self::RIGHT);
+        }
+        if result.contains(Self::UP | Self::DOWN) {
+            result.remove(Self::UP | Self::DOWN);
+        }
+
+        result
+    }
+}
+
+/// the byte is in the hardware order, which is the order the keys are read
+/// from `$4016/$4017` starting from bit 0 (A, B, Select, Start, Up, Down,
+/// Left, Right)
+impl From<u8> for StandardNESControllerState {
+    fn from(bits: u8) -> Self {
+        Self::from_bits_truncate(bits)
+    }
+}
+
+impl From<StandardNESControllerState> for u8 {
+    fn from(state: StandardNESControllerState) -> Self {
+        state.bits
+    }
 }
 
 pub struct Controller {
@@ -43,6 +85,8 @@ pub struct Controller {
     polled_state: Cell<u8>,
 
     polling: bool,
+
+    allow_opposing_directions: bool,
 }
 
 impl Controller {
@@ -52,21 +96,65 @@ impl Controller {
             polled_state: Cell::new(0),
 
             polling: false,
+
+            allow_opposing_directions: true,
         }
     }
 
     pub fn get_primary_controller_state(&self) -> Arc<Mutex<StandardNESControllerState>> {
         self.primary_state.clone()
     }
+
+    /// replace the state of all the keys
+    pub fn set_state(&self, state: StandardNESControllerState) {
+        if let Ok(mut primary_state) = self.primary_state.lock() {
+            *primary_state = state;
+        }
+    }
+
+    pub fn state(&self) -> StandardNESControllerState {
+        self.primary_state
+            .lock()
+            .map(|state| *state)
+            .unwrap_or_else(|_| StandardNESControllerState::empty())
+    }
+
+    /// if `false`, `Left+Right` and `Up+Down` are filtered when they are
+    /// pressed together, default is `true`
+    pub fn set_allow_opposing_directions(&mut self, allow: bool) {
+        self.allow_opposing_directions = allow;
+    }
+
+    /// load the current keys into the shift register
+    fn refresh_polled_state(&self) {
+        if let Ok(primary_state) = self.primary_state.lock() {
+            let state = if self.allow_opposing_directions {
+                *primary_state
+            } else {
+                primary_state.without_opposing_directions()
+            };
+
+            self.polled_state.set(state.bits);
+        }
+    }
+}
+
+impl Controller {
+    /// the bit the next read would return, without shifting the register
+    pub fn peek(&self) -> u8 {
+        // while strobing the register is reloaded on every read anyway
+        if self.polling {
+            self.refresh_polled_state();
+        }
+        self.polled_state.get() & 1
+    }
 }
 
 impl Bus for Controller {
     fn read(&self, _address: u16, _device: Device) -> u8 {
         // refresh polled here
         if self.polling {
-            if let Ok(primary_state) = self.primary_state.lock() {
-                self.polled_state.set(primary_state.bits);
-            }
+            self.refresh_polled_state();
         }
         let result = self.polled_state.get() & 1;
 
@@ -80,11 +168,36 @@ impl Bus for Controller {
 
         // if the state changed, then refresh
         if self.polling ^ new_polling {
-            if let Ok(primary_state) = self.primary_state.lock() {
-                self.polled_state.set(primary_state.bits);
-            }
+            self.refresh_polled_state();
         }
 
         self.polling = new_polling;
     }
 }
+
+impl Savable for Controller {
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), SaveError> {
+        let primary_state = self
+            .primary_state
+            .lock()
+            .map_err(|_| SaveError::Others)?
+            .bits;
+
+        writer.write_all(&[primary_state, self.polled_state.get(), self.polling as u8])?;
+
+        Ok(())
+    }
+
+    fn load<R: std::io::Read>(&mut self, reader: &mut R) -> Result<(), SaveError> {
+        let mut data = [0; 3];
+        reader.read_exact(&mut data)?;
+
+        // keep the same `Arc`, as it is shared with the UI
+        *self.primary_state.lock().map_err(|_| SaveError::Others)? =
+            StandardNESControllerState::from_bits_truncate(data[0]);
+        self.polled_state.set(data[1]);
+        self.polling = data[2] != 0;
+
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 mod controller;
 
+mod tests;
+
 pub use controller::Controller;
 pub use controller::StandardNESControllerState;
 pub use controller::StandardNESKey;
@@ -0,0 +1,104 @@
+#[cfg(test)]
+mod controller_tests {
+    use super::super::{Controller, StandardNESControllerState, StandardNESKey};
+    use crate::common::{save_state::Savable, Bus, Device};
+
+    #[test]
+    fn save_load_held_keys() {
+        let mut controller = Controller::new();
+        let state = controller.get_primary_controller_state();
+
+        state.lock().unwrap().press(StandardNESKey::A);
+        state.lock().unwrap().press(StandardNESKey::Start);
+        // strobe
+        controller.write(0x4016, 1, Device::CPU);
+        controller.write(0x4016, 0, Device::CPU);
+        // read `A`, the remaining bits are `Start` and after
+        assert_eq!(controller.read(0x4016, Device::CPU), 1);
+
+        let mut data = Vec::new();
+        controller.save(&mut data).unwrap();
+
+        let mut loaded = Controller::new();
+        let loaded_state = loaded.get_primary_controller_state();
+        loaded.load(&mut data.as_slice()).unwrap();
+
+        assert!(loaded_state
+            .lock()
+            .unwrap()
+            .contains(StandardNESControllerState::A | StandardNESControllerState::START));
+        // continues from where the save was made: B, Select, Start
+        assert_eq!(loaded.read(0x4016, Device::CPU), 0);
+        assert_eq!(loaded.read(0x4016, Device::CPU), 0);
+        assert_eq!(loaded.read(0x4016, Device::CPU), 1);
+    }
+
+    /// read all 8 keys after strobing
+    fn read_keys(controller: &mut Controller) -> Vec<u8> {
+        controller.write(0x4016, 1, Device::CPU);
+        controller.write(0x4016, 0, Device::CPU);
+
+        (0..8)
+            .map(|_| controller.read(0x4016, Device::CPU))
+            .collect()
+    }
+
+    #[test]
+    fn state_bit_order() {
+        let mut controller = Controller::new();
+
+        let keys = [
+            StandardNESKey::A,
+            StandardNESKey::B,
+            StandardNESKey::Select,
+            StandardNESKey::Start,
+            StandardNESKey::Up,
+            StandardNESKey::Down,
+            StandardNESKey::Left,
+            StandardNESKey::Right,
+        ];
+
+        for (i, key) in keys.iter().enumerate() {
+            let mut state = StandardNESControllerState::empty();
+            state.press(*key);
+
+            assert_eq!(u8::from(state), 1 << i);
+            assert_eq!(StandardNESControllerState::from(1 << i), state);
+
+            // the keys are read in the same order as the bits
+            controller.set_state(state);
+            let mut expected = vec![0; 8];
+            expected[i] = 1;
+            assert_eq!(read_keys(&mut controller), expected);
+        }
+
+        controller.set_state(StandardNESControllerState::from(0b1000_0001));
+        assert_eq!(
+            controller.state(),
+            StandardNESControllerState::A | StandardNESControllerState::RIGHT
+        );
+    }
+
+    #[test]
+    fn opposing_directions_filter() {
+        let mut controller = Controller::new();
+        let state = StandardNESControllerState::A
+            | StandardNESControllerState::LEFT
+            | StandardNESControllerState::RIGHT
+            | StandardNESControllerState::UP
+            | StandardNESControllerState::DOWN;
+        controller.set_state(state);
+
+        // allowed by default
+        assert_eq!(read_keys(&mut controller), vec![1, 0, 0, 0, 1, 1, 1, 1]);
+
+        controller.set_allow_opposing_directions(false);
+        assert_eq!(read_keys(&mut controller), vec![1, 0, 0, 0, 0, 0, 0, 0]);
+        // the stored state is not changed, only what the game sees
+        assert_eq!(controller.state(), state);
+
+        // a single direction is not filtered
+        controller.set_state(StandardNESControllerState::LEFT | StandardNESControllerState::UP);
+        assert_eq!(read_keys(&mut controller), vec![0, 0, 0, 0, 1, 0, 1, 0]);
+    }
+}
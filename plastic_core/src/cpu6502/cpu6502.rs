@@ -1,6 +1,9 @@
-use super::instruction::{AddressingMode, Instruction, Opcode};
+use super::instruction::{AddressingMode, Instruction, InstructionInfo, Opcode};
 use super::CPUBusTrait;
-use crate::common::save_state::{Savable, SaveError};
+use crate::common::{
+    save_state::{Savable, SaveError},
+    TimingMode,
+};
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
 
@@ -14,6 +17,8 @@ pub enum CPURunState {
     Waiting,
     InfiniteLoop(u16),
     StartingInterrupt,
+    /// a `KIL` (`JAM`) opcode was executed, the CPU does nothing until reset
+    Jammed,
     NormalInstructionExecution,
 }
 
@@ -44,15 +49,49 @@ pub struct CPU6502<T: CPUBusTrait> {
 
     nmi_pin_status: bool,
     irq_pin_status: bool,
+    /// the remaining cycles of a BRK or IRQ sequence before the vector is
+    /// fetched, an NMI in these cycles hijacks the sequence to the NMI
+    /// vector, only used in [`TimingMode::CycleAccurate`]
+    nmi_hijack_cycles: u8,
 
     cycles_to_wait: u8,
 
     dma_remaining: u16,
     dma_address: u8,
+    /// the halt (and alignment) cycles before the DMA transfer are done,
+    /// only used in [`TimingMode::CycleAccurate`]
+    dma_halted: bool,
+
+    timing_mode: TimingMode,
+    /// the parity of the current CPU cycle, DMA reads can only happen on
+    /// even cycles
+    odd_cycle: bool,
+    /// the value of the interrupt disable flag before `CLI`, `SEI` or `PLP`,
+    /// as the interrupt polling happens before they change it
+    delayed_interrupt_disable: Option<bool>,
 
     /// a buffer to hold the next_instruction before execution,
     /// check `run_next` for more info
     next_instruction: Option<(Instruction, u8)>,
+    /// the number of cycles before the execution of `next_instruction` on
+    /// which its last early bus access was done, so that accesses are not
+    /// repeated when a DMC DMA stalls the CPU in the middle of the instruction
+    last_early_access: u8,
+    /// the value read by a read-modify-write instruction before its dummy
+    /// write, used when the instruction is executed
+    rmw_operand: Option<u8>,
+
+    /// the address of the instruction fetched in the last `run_next` if any
+    fetched_instruction_pc: Option<u16>,
+
+    /// the last fetched instruction and its address, kept until the next
+    /// instruction is fetched
+    last_instruction: Option<(u16, Instruction)>,
+    /// number of instructions fetched since the last reset
+    executed_count: u64,
+
+    /// set by the `KIL` opcode, only cleared by reset
+    jammed: bool,
 
     bus: T,
 }
@@ -73,13 +112,27 @@ where
 
             nmi_pin_status: false,
             irq_pin_status: false,
+            nmi_hijack_cycles: 0,
 
             cycles_to_wait: 0,
 
             dma_remaining: 0,
             dma_address: 0,
+            dma_halted: false,
+
+            timing_mode: TimingMode::Approximate,
+            odd_cycle: false,
+            delayed_interrupt_disable: None,
 
             next_instruction: None,
+            last_early_access: u8::MAX,
+            rmw_operand: None,
+
+            fetched_instruction_pc: None,
+            last_instruction: None,
+            executed_count: 0,
+
+            jammed: false,
 
             bus,
         }
@@ -96,11 +149,18 @@ where
 
         self.nmi_pin_status = false;
         self.irq_pin_status = false;
+        self.nmi_hijack_cycles = 0;
 
         self.cycles_to_wait = 0;
 
         self.dma_remaining = 0;
         self.dma_address = 0;
+        self.dma_halted = false;
+
+        self.delayed_interrupt_disable = None;
+        self.last_instruction = None;
+        self.executed_count = 0;
+        self.jammed = false;
 
         self.set_flag(StatusFlag::InterruptDisable);
         self.reg_sp = 0xFD; //reset
@@ -118,22 +178,116 @@ where
         self.bus.reset()
     }
 
+    pub fn set_timing_mode(&mut self, timing_mode: TimingMode) {
+        self.timing_mode = timing_mode;
+    }
+
     pub fn bus(&self) -> &T {
         &self.bus
     }
 
+    pub fn bus_mut(&mut self) -> &mut T {
+        &mut self.bus
+    }
+
+    /// the IRQ line state latched by the CPU
+    pub fn irq_pin_status(&self) -> bool {
+        self.irq_pin_status
+    }
+
+    /// the address of the instruction that was fetched in the last call to
+    /// [`run_next`](Self::run_next), `None` if it was in the middle of an
+    /// instruction, an interrupt or a DMA
+    pub fn fetched_instruction_pc(&self) -> Option<u16> {
+        self.fetched_instruction_pc
+    }
+
+    /// the last fetched instruction, which is being executed or just
+    /// finished executing
+    pub fn last_instruction(&self) -> Option<InstructionInfo> {
+        self.last_instruction
+            .map(|(pc, instruction)| InstructionInfo {
+                pc,
+                opcode: instruction.opcode_byte,
+                mnemonic: instruction.opcode.mnemonic(),
+                is_official_opcode: instruction.is_official(),
+            })
+    }
+
+    /// the number of instructions executed since the last reset, an
+    /// instruction is counted when it is fetched, interrupts and DMA
+    /// transfers are not counted
+    pub fn executed_count(&self) -> u64 {
+        self.executed_count
+    }
+
+    pub fn reset_executed_count(&mut self) {
+        self.executed_count = 0;
+    }
+
+    /// the CPU executed a `KIL` opcode and is halted until the next reset
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+
+    /// the address of the instruction that will be fetched in the next call
+    /// to [`run_next`](Self::run_next), `None` if it is in the middle of an
+    /// instruction or a DMA, or is jammed, a pending interrupt can still run
+    /// before it
+    pub fn next_instruction_pc(&self) -> Option<u16> {
+        let between_instructions = !self.jammed
+            && self.cycles_to_wait == 0
+            && self.next_instruction.is_none()
+            && self.dma_remaining == 0;
+
+        between_instructions.then_some(self.reg_pc)
+    }
+
+    /// the stack is at `$0100 | SP`, and grows down
+    pub fn stack_pointer(&self) -> u8 {
+        self.reg_sp
+    }
+
+    /// read the CPU part of a saved state without loading it, and return
+    /// the values of its registers with their names, used for debugging, the
+    /// state of the bus follows it in `reader`
+    pub fn registers_in_state<R: Read>(
+        reader: &mut R,
+    ) -> Result<[(&'static str, u16); 6], SaveError> {
+        Ok(SavableCPUState::read(reader)?.registers())
+    }
+
     pub fn run_next(&mut self) -> CPURunState {
+        self.fetched_instruction_pc = None;
+        if self.jammed {
+            // the bus is stuck, no DMA or interrupts are serviced
+            return CPURunState::Jammed;
+        }
+        self.odd_cycle = !self.odd_cycle;
         self.check_and_run_dmc_transfer();
 
         if self.cycles_to_wait == 0 && self.next_instruction.is_none() {
             // are we still executing the DMA transfer instruction?
-            if self.dma_remaining > 0 {
+            if self.dma_remaining > 0
+                && self.timing_mode == TimingMode::CycleAccurate
+                && !self.dma_halted
+            {
+                // one cycle to halt the CPU, and one more if the transfer
+                // would start on an odd cycle
+                self.dma_halted = true;
+                self.cycles_to_wait = self.odd_cycle as u8;
+                CPURunState::Waiting
+            } else if self.dma_remaining > 0 {
                 self.dma_remaining -= 1;
                 {
                     // send one byte at a time
                     let oma_address = (255 - self.dma_remaining) & 0xFF;
                     let cpu_address = (self.dma_address as u16) << 8 | oma_address;
 
+                    // this is a normal CPU read, so DMA from register pages
+                    // (like `$20xx` or `$40xx`) has the same side effects of
+                    // reading them (incrementing `v` on `$2007`, shifting the
+                    // controllers on `$4016`, ...)
                     let data = self.read_bus(cpu_address);
 
                     self.bus.send_oam_data(oma_address as u8, data);
@@ -142,10 +296,7 @@ where
                 // since it should read in one cycle and write in the other cycle
                 self.cycles_to_wait = 1;
                 CPURunState::DmaTransfere
-            } else if self.nmi_pin_status
-                || (self.irq_pin_status
-                    && self.reg_status & StatusFlag::InterruptDisable as u8 == 0)
-            {
+            } else if self.poll_interrupts() {
                 // execute interrupt
                 // hardware side interrupt
                 self.execute_interrupt(false, self.nmi_pin_status);
@@ -161,8 +312,23 @@ where
                 }
 
                 // reload the next instruction in `the next_instruction` buffer
+                let pc = self.reg_pc;
+                self.fetched_instruction_pc = Some(pc);
                 let instruction = self.fetch_next_instruction();
-                let (_, mut cycle_time, _) = self.decode_operand(&instruction);
+                self.last_instruction = Some((pc, instruction));
+                self.executed_count += 1;
+                if self.timing_mode == TimingMode::CycleAccurate
+                    && matches!(instruction.opcode, Opcode::Cli | Opcode::Sei | Opcode::Plp)
+                {
+                    self.delayed_interrupt_disable =
+                        Some(self.reg_status & StatusFlag::InterruptDisable as u8 != 0);
+                }
+                let (_, cycle_time, did_page_cross) = self.decode_operand(&instruction);
+                // the instruction is executed on the cycle of its last bus
+                // access, so that writes to the PPU (and other devices) are
+                // seen at the correct dot
+                let mut cycle_time =
+                    Self::last_bus_access_cycle(&instruction, cycle_time, did_page_cross);
 
                 // only the JMP instruction has lesser time than the base time
                 if instruction.opcode == Opcode::Jmp {
@@ -178,6 +344,8 @@ where
                 self.cycles_to_wait += cycle_time - 1;
 
                 self.next_instruction = Some((instruction, cycle_time));
+                self.last_early_access = u8::MAX;
+                self.rmw_operand = None;
 
                 CPURunState::Waiting
             }
@@ -201,6 +369,8 @@ where
             return_state
         } else {
             self.cycles_to_wait -= 1;
+            self.run_early_bus_access();
+            self.check_nmi_hijack();
             CPURunState::Waiting
         }
     }
@@ -235,6 +405,120 @@ where
         self.bus.write(address, data);
     }
 
+    /// run the bus access of the buffered instruction that happens on this
+    /// cycle if any, instructions are executed on their last cycle, but
+    /// indexed and read-modify-write instructions access the bus on the
+    /// cycles before:
+    /// - indexed instructions read from the address before the carry of the
+    ///   index is added to the high byte, this read is repeated with the
+    ///   fixed address when the page is crossed, stores and read-modify-write
+    ///   instructions always do this read, which is visible on registers with
+    ///   read side effects (like `$2002`, `$2007` or `$4016`)
+    /// - read-modify-write instructions read the value, and write it back
+    ///   unmodified on the cycle before the result is written, MMC1 ignores
+    ///   the second of the two consecutive writes, and registers like `$2007`
+    ///   see both
+    fn run_early_bus_access(&mut self) {
+        let instruction = match self.next_instruction {
+            Some((instruction, _)) => instruction,
+            None => return,
+        };
+        let cycles_before = self.cycles_to_wait;
+        if cycles_before >= self.last_early_access || !instruction.is_operand_address() {
+            return;
+        }
+
+        let is_rmw = matches!(
+            instruction.opcode,
+            Opcode::Asl
+                | Opcode::Lsr
+                | Opcode::Rol
+                | Opcode::Ror
+                | Opcode::Inc
+                | Opcode::Dec
+                | Opcode::Slo
+                | Opcode::Rla
+                | Opcode::Sre
+                | Opcode::Rra
+                | Opcode::Dcp
+                | Opcode::Isc
+        );
+        let is_store = matches!(
+            instruction.opcode,
+            Opcode::Sta | Opcode::Ahx | Opcode::Shx | Opcode::Shy | Opcode::Tas
+        );
+        let is_indexed = instruction.addressing_mode.can_cross_page();
+        if cycles_before > 3 || !(is_rmw || is_indexed) {
+            return;
+        }
+
+        let (address, _, did_page_cross) = self.decode_operand(&instruction);
+        // the index is less than a page, so the carry adds one page
+        let uncarried_address = if did_page_cross {
+            address.wrapping_sub(0x100)
+        } else {
+            address
+        };
+
+        match cycles_before {
+            3 if is_rmw && is_indexed => {
+                self.read_bus(uncarried_address);
+            }
+            2 if is_rmw => self.rmw_operand = Some(self.read_bus(address)),
+            1 if is_rmw => {
+                let original = self.rmw_operand.unwrap_or_else(|| self.read_bus(address));
+                self.write_bus(address, original);
+            }
+            1 if is_store || did_page_cross => {
+                self.read_bus(uncarried_address);
+            }
+            _ => return,
+        }
+
+        self.last_early_access = cycles_before;
+    }
+
+    /// the value of a read-modify-write instruction, which was read two
+    /// cycles before the instruction is executed
+    fn read_rmw_operand(&mut self, address: u16) -> u8 {
+        self.rmw_operand
+            .take()
+            .unwrap_or_else(|| self.read_bus(address))
+    }
+
+    /// the cycle of `instruction` on which its last bus access happens, which
+    /// is the last cycle for all instructions that write to memory, this must
+    /// not exceed the final cycle time computed by `run_instruction`
+    fn last_bus_access_cycle(
+        instruction: &Instruction,
+        cycle_time: u8,
+        did_page_cross: bool,
+    ) -> u8 {
+        let is_indexed_no_page_cross =
+            instruction.addressing_mode.can_cross_page() && !did_page_cross;
+
+        match instruction.opcode {
+            // stores always take the page cross cycle
+            Opcode::Sta | Opcode::Ahx | Opcode::Shx | Opcode::Shy | Opcode::Tas => {
+                cycle_time + is_indexed_no_page_cross as u8
+            }
+            // read-modify-write, the write is on the last cycle
+            Opcode::Asl | Opcode::Lsr | Opcode::Rol | Opcode::Ror | Opcode::Inc | Opcode::Dec
+                if instruction.is_operand_address() =>
+            {
+                if instruction.addressing_mode == AddressingMode::AbsoluteX {
+                    7
+                } else {
+                    cycle_time + 2
+                }
+            }
+            Opcode::Slo | Opcode::Rla | Opcode::Sre | Opcode::Rra | Opcode::Dcp | Opcode::Isc => {
+                cycle_time + 2 + is_indexed_no_page_cross as u8
+            }
+            _ => cycle_time,
+        }
+    }
+
     /// decods the operand of an instruction and returnrs
     /// (the decoded_operand, base cycle time for the instruction, has crossed page)
     fn decode_operand(&self, instruction: &Instruction) -> (u16, u8, bool) {
@@ -442,9 +726,13 @@ where
         self.push_stack(high);
         self.push_stack(low);
 
-        self.set_flag_status(StatusFlag::BreakCommand, is_soft);
-
-        self.push_stack(self.reg_status);
+        // the B flag does not exist in the register, it is only set in the
+        // pushed value for BRK (and PHP), and bit 5 is always pushed as 1
+        let mut status = (self.reg_status & !(StatusFlag::BreakCommand as u8)) | 0x20;
+        if is_soft {
+            status |= StatusFlag::BreakCommand as u8;
+        }
+        self.push_stack(status);
 
         let jump_vector_address = if is_nmi {
             NMI_VECTOR_ADDRESS
@@ -467,10 +755,55 @@ where
         let pc = high << 8 | low;
         self.reg_pc = pc;
 
+        if !is_nmi && self.timing_mode == TimingMode::CycleAccurate {
+            // the vector is fetched on the last 2 cycles, a hardware
+            // interrupt starts on its first cycle, and BRK is executed on
+            // its second cycle
+            self.nmi_hijack_cycles = if is_soft { 3 } else { 4 };
+        }
+
         // delay of interrupt
         self.cycles_to_wait += 7;
     }
 
+    /// an NMI detected before the vector fetch of a BRK or IRQ takes over
+    /// the sequence, the pushed status is kept (with B set for BRK), but the
+    /// execution continues from the NMI vector
+    fn check_nmi_hijack(&mut self) {
+        if self.nmi_hijack_cycles == 0 {
+            return;
+        }
+        self.nmi_hijack_cycles -= 1;
+
+        if self.bus.is_nmi_pin_set() {
+            self.bus.clear_nmi_pin();
+            self.nmi_pin_status = false;
+            self.nmi_hijack_cycles = 0;
+
+            let low = self.read_bus(NMI_VECTOR_ADDRESS) as u16;
+            let high = self.read_bus(NMI_VECTOR_ADDRESS + 1) as u16;
+            self.reg_pc = high << 8 | low;
+        }
+    }
+
+    /// check if an interrupt should be executed before the next instruction
+    fn poll_interrupts(&mut self) -> bool {
+        let interrupt_disabled = self
+            .delayed_interrupt_disable
+            .take()
+            .unwrap_or(self.reg_status & StatusFlag::InterruptDisable as u8 != 0);
+
+        // the IRQ line is sampled at the end of the instruction, otherwise
+        // it is only latched when fetching the next instruction, delaying
+        // the interrupt by one instruction
+        if self.timing_mode == TimingMode::CycleAccurate && self.bus.is_irq_change_requested() {
+            self.irq_pin_status = self.bus.irq_pin_state();
+            self.bus.clear_irq_request_pin()
+        }
+
+        self.nmi_pin_status || (self.irq_pin_status && !interrupt_disabled)
+    }
+
     fn check_for_nmi_dma(&mut self) {
         // check if the PPU is setting the NMI pin
         if self.bus.is_nmi_pin_set() {
@@ -481,6 +814,7 @@ where
         if self.bus.is_dma_request() {
             self.dma_address = self.bus.dma_address();
             self.dma_remaining = 256;
+            self.dma_halted = false;
             self.bus.clear_dma_request();
         }
     }
@@ -493,9 +827,15 @@ where
 
             self.bus.submit_dmc_buffer_byte(data);
 
-            // FIXME: respect different clock delay for respective positions to
-            //  steal the clock
-            self.cycles_to_wait += 3;
+            self.cycles_to_wait += match self.timing_mode {
+                // FIXME: respect different clock delay for respective positions to
+                //  steal the clock
+                TimingMode::Approximate => 3,
+                // halt, dummy, alignment and read cycles, but during OAM DMA
+                // the CPU is already halted so only 2 are needed
+                TimingMode::CycleAccurate if self.dma_remaining > 0 => 2,
+                TimingMode::CycleAccurate => 4,
+            };
         }
     }
 
@@ -565,7 +905,7 @@ where
             }
             Opcode::Asl => {
                 let mut operand = if is_operand_address {
-                    self.read_bus(decoded_operand)
+                    self.read_rmw_operand(decoded_operand)
                 } else {
                     // if its not address, then its Accumulator for this instruction
                     self.reg_a
@@ -594,7 +934,7 @@ where
             }
             Opcode::Lsr => {
                 let mut operand = if is_operand_address {
-                    self.read_bus(decoded_operand)
+                    self.read_rmw_operand(decoded_operand)
                 } else {
                     // if its not address, then its Accumulator for this instruction
                     self.reg_a
@@ -623,7 +963,7 @@ where
             }
             Opcode::Rol => {
                 let mut operand = if is_operand_address {
-                    self.read_bus(decoded_operand)
+                    self.read_rmw_operand(decoded_operand)
                 } else {
                     // if its not address, then its Accumulator for this instruction
                     self.reg_a
@@ -656,7 +996,7 @@ where
             }
             Opcode::Ror => {
                 let mut operand = if is_operand_address {
-                    self.read_bus(decoded_operand)
+                    self.read_rmw_operand(decoded_operand)
                 } else {
                     // if its not address, then its Accumulator for this instruction
                     self.reg_a
@@ -824,7 +1164,7 @@ where
             Opcode::Dec => {
                 assert!(is_operand_address);
 
-                let result = self.read_bus(decoded_operand).wrapping_sub(1);
+                let result = self.read_rmw_operand(decoded_operand).wrapping_sub(1);
 
                 self.update_zero_negative_flags(result);
 
@@ -840,7 +1180,7 @@ where
             Opcode::Inc => {
                 assert!(is_operand_address);
 
-                let result = self.read_bus(decoded_operand).wrapping_add(1);
+                let result = self.read_rmw_operand(decoded_operand).wrapping_add(1);
 
                 self.update_zero_negative_flags(result);
 
@@ -1350,8 +1690,8 @@ where
                 self.reg_sp = result;
             }
             Opcode::Kil => {
-                // TODO: implement halt
-                println!("KIL instruction executed, should halt....");
+                self.jammed = true;
+                state = CPURunState::Jammed;
             }
         };
 
@@ -1373,7 +1713,13 @@ where
         self.cycles_to_wait = state.cycles_to_wait;
         self.dma_remaining = state.dma_remaining;
         self.dma_address = state.dma_address;
+        self.dma_halted = self.dma_remaining < 256;
+        self.delayed_interrupt_disable = None;
+        self.nmi_hijack_cycles = 0;
         self.next_instruction = state.next_instruction;
+        self.last_early_access = state.last_early_access;
+        self.rmw_operand = state.rmw_operand;
+        self.jammed = false;
     }
 }
 
@@ -1395,6 +1741,8 @@ struct SavableCPUState {
     dma_address: u8,
 
     next_instruction: Option<(Instruction, u8)>,
+    last_early_access: u8,
+    rmw_operand: Option<u8>,
 }
 
 impl SavableCPUState {
@@ -1412,8 +1760,30 @@ impl SavableCPUState {
             dma_remaining: cpu.dma_remaining,
             dma_address: cpu.dma_address,
             next_instruction: cpu.next_instruction,
+            last_early_access: cpu.last_early_access,
+            rmw_operand: cpu.rmw_operand,
         }
     }
+
+    fn read<R: Read>(reader: &mut R) -> Result<Self, SaveError> {
+        let outer_reader = WrapperReader { inner: reader };
+
+        bincode::deserialize_from(outer_reader).map_err(|err| match *err {
+            bincode::ErrorKind::Io(err) => SaveError::IoError(err),
+            _ => SaveError::Others,
+        })
+    }
+
+    fn registers(&self) -> [(&'static str, u16); 6] {
+        [
+            ("PC", self.reg_pc),
+            ("SP", self.reg_sp as u16),
+            ("A", self.reg_a as u16),
+            ("X", self.reg_x as u16),
+            ("Y", self.reg_y as u16),
+            ("P", self.reg_status as u16),
+        ]
+    }
 }
 
 /// This is a solution to wrap a reference to reader
@@ -1443,17 +1813,8 @@ where
     }
 
     fn load<R: Read>(&mut self, reader: &mut R) -> Result<(), SaveError> {
-        let outer_reader = WrapperReader { inner: reader };
-
-        {
-            let state: SavableCPUState =
-                bincode::deserialize_from(outer_reader).map_err(|err| match *err {
-                    bincode::ErrorKind::Io(err) => SaveError::IoError(err),
-                    _ => SaveError::Others,
-                })?;
-
-            self.load_serialized_state(state);
-        }
+        let state = SavableCPUState::read(reader)?;
+        self.load_serialized_state(state);
 
         self.bus.load(reader)?;
 
@@ -1,6 +1,19 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
+/// the instruction fetched by the CPU, returned by
+/// [`NES::last_instruction`](crate::nes::NES::last_instruction)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstructionInfo {
+    /// the address of the opcode
+    pub pc: u16,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    /// `false` for the unofficial opcodes, including the unofficial `NOP`s
+    /// and `SBC #imm` (`$EB`)
+    pub is_official_opcode: bool,
+}
+
 #[derive(Serialize, Deserialize, Copy, Clone)]
 pub struct Instruction {
     pub opcode_byte: u8,
@@ -261,13 +274,24 @@ impl Instruction {
     pub fn is_operand_address(&self) -> bool {
         self.addressing_mode.is_operand_address()
     }
+
+    pub fn is_official(&self) -> bool {
+        use Opcode::*;
+        match self.opcode {
+            // the other opcode bytes of these are unofficial duplicates
+            Nop => self.opcode_byte == 0xEA,
+            Sbc => self.opcode_byte != 0xEB,
+            Slo | Sre | Rla | Rra | Isc | Dcp | Sax | Lax | Anc | Alr | Arr | Axs | Xaa | Ahx
+            | Shy | Shx | Tas | Las | Kil => false,
+            _ => true,
+        }
+    }
 }
 
-#[cfg(not(tarpaulin_include))]
-impl Display for Opcode {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl Opcode {
+    pub fn mnemonic(&self) -> &'static str {
         use Opcode::*;
-        let result = match *self {
+        match *self {
             Adc => "ADC",
             And => "AND",
             Asl => "ASL",
@@ -358,9 +382,14 @@ impl Display for Opcode {
             Las => "LAS",
 
             Kil => "KIL",
-        };
+        }
+    }
+}
 
-        write!(f, "{}", result)
+#[cfg(not(tarpaulin_include))]
+impl Display for Opcode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic())
     }
 }
 
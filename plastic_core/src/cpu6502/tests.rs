@@ -1,15 +1,25 @@
 #[cfg(test)]
 mod cpu_tests {
     use super::super::{CPUBusTrait, CPURunState, CPU6502};
-    use crate::common::{interconnection::*, save_state::Savable};
+    use crate::common::{interconnection::*, save_state::Savable, TimingMode};
+    use std::cell::RefCell;
 
     struct DummyBus {
         data: [u8; 0x10000],
+        dma_page: Option<u8>,
+        nmi_pin: bool,
+        /// all the accesses as (is write, address, data)
+        log: RefCell<Vec<(bool, u16, u8)>>,
     }
 
     impl DummyBus {
         pub fn new(data: [u8; 0x10000]) -> Self {
-            Self { data }
+            Self {
+                data,
+                dma_page: None,
+                nmi_pin: false,
+                log: RefCell::new(Vec::new()),
+            }
         }
     }
 
@@ -31,9 +41,12 @@ mod cpu_tests {
 
     impl CPUBusTrait for DummyBus {
         fn read(&self, address: u16) -> u8 {
-            self.data[address as usize]
+            let data = self.data[address as usize];
+            self.log.borrow_mut().push((false, address, data));
+            data
         }
         fn write(&mut self, address: u16, data: u8) {
+            self.log.get_mut().push((true, address, data));
             self.data[address as usize] = data;
         }
 
@@ -44,19 +57,21 @@ mod cpu_tests {
 
     impl PPUCPUConnection for DummyBus {
         fn is_nmi_pin_set(&self) -> bool {
-            false
+            self.nmi_pin
+        }
+        fn clear_nmi_pin(&mut self) {
+            self.nmi_pin = false;
         }
-        fn clear_nmi_pin(&mut self) {}
         fn is_dma_request(&self) -> bool {
-            false
+            self.dma_page.is_some()
         }
-        fn clear_dma_request(&mut self) {}
-        fn dma_address(&mut self) -> u8 {
-            unreachable!()
+        fn clear_dma_request(&mut self) {
+            self.dma_page = None;
         }
-        fn send_oam_data(&mut self, _address: u8, _data: u8) {
-            unreachable!();
+        fn dma_address(&mut self) -> u8 {
+            self.dma_page.unwrap()
         }
+        fn send_oam_data(&mut self, _address: u8, _data: u8) {}
     }
 
     impl APUCPUConnection for DummyBus {
@@ -114,4 +129,308 @@ mod cpu_tests {
             }
         }
     }
+
+    #[test]
+    fn writes_on_last_cycle() {
+        let mut data = [0; 0x10000];
+        data[0x8000..0x800A].copy_from_slice(&[
+            0xA2, 0x00, // LDX #$00       (2 cycles)
+            0xA9, 0x42, // LDA #$42       (2 cycles)
+            0x9D, 0x00, 0x03, // STA $0300,X    (5 cycles)
+            0xEE, 0x01, 0x03, // INC $0301      (6 cycles)
+        ]);
+        data[0xFFFC] = 0x00;
+        data[0xFFFD] = 0x80;
+
+        let mut cpu = CPU6502::new(DummyBus::new(data));
+        cpu.reset();
+
+        let mut sta_cycle = None;
+        let mut inc_cycle = None;
+
+        for cycle in 1..=30 {
+            cpu.run_next();
+
+            if sta_cycle.is_none() && cpu.bus().data[0x300] == 0x42 {
+                sta_cycle = Some(cycle);
+            }
+            if inc_cycle.is_none() && cpu.bus().data[0x301] == 1 {
+                inc_cycle = Some(cycle);
+            }
+        }
+
+        // 7 reset cycles, then 2 + 2 + 5 and 6 more
+        assert_eq!(sta_cycle, Some(16));
+        assert_eq!(inc_cycle, Some(22));
+    }
+
+    #[test]
+    fn fetched_instruction_pc() {
+        let mut data = [0; 0x10000];
+        data[0x8000..0x8008].copy_from_slice(&[
+            0xA2, 0x00, // LDX #$00       (2 cycles)
+            0xEE, 0x01, 0x03, // INC $0301      (6 cycles)
+            0x4C, 0x02, 0x80, // JMP $8002      (3 cycles)
+        ]);
+        data[0xFFFC] = 0x00;
+        data[0xFFFD] = 0x80;
+
+        let mut cpu = CPU6502::new(DummyBus::new(data));
+        cpu.reset();
+
+        let mut trace = Vec::new();
+        for cycle in 1..=7 + 2 + 6 + 3 + 6 {
+            cpu.run_next();
+
+            if let Some(pc) = cpu.fetched_instruction_pc() {
+                trace.push((cycle, pc));
+            }
+        }
+
+        assert_eq!(
+            trace,
+            vec![(8, 0x8000), (10, 0x8002), (16, 0x8005), (19, 0x8002)]
+        );
+    }
+
+    #[test]
+    fn last_instruction_official() {
+        let mut data = [0; 0x10000];
+        data[0x8000..0x8008].copy_from_slice(&[
+            0xEA, // NOP
+            0x1A, // NOP (unofficial)
+            0xA7, 0x10, // LAX $10
+            0xEB, 0x01, // SBC #$01 (unofficial)
+            0xE9, 0x01, // SBC #$01
+        ]);
+        data[0xFFFC] = 0x00;
+        data[0xFFFD] = 0x80;
+
+        let mut cpu = CPU6502::new(DummyBus::new(data));
+        cpu.reset();
+        assert!(cpu.last_instruction().is_none());
+
+        let mut trace = Vec::new();
+        for _ in 0..7 + 2 + 2 + 3 + 2 + 2 {
+            cpu.run_next();
+
+            if cpu.fetched_instruction_pc().is_some() {
+                let info = cpu.last_instruction().unwrap();
+                trace.push((info.pc, info.opcode, info.mnemonic, info.is_official_opcode));
+            }
+        }
+
+        assert_eq!(
+            trace,
+            vec![
+                (0x8000, 0xEA, "NOP", true),
+                (0x8001, 0x1A, "NOP", false),
+                (0x8002, 0xA7, "LAX", false),
+                (0x8004, 0xEB, "SBC", false),
+                (0x8006, 0xE9, "SBC", true),
+            ]
+        );
+        assert_eq!(cpu.executed_count(), 5);
+
+        cpu.reset_executed_count();
+        assert_eq!(cpu.executed_count(), 0);
+        cpu.reset();
+        for _ in 0..7 + 2 {
+            cpu.run_next();
+        }
+        assert_eq!(cpu.executed_count(), 1);
+    }
+
+    #[test]
+    fn kil_jams_until_reset() {
+        let mut data = [0; 0x10000];
+        data[0x8000..0x8005].copy_from_slice(&[
+            0xA9, 0x42, // LDA #$42
+            0x02, // KIL
+            0xA9, 0x00, // LDA #$00
+        ]);
+        data[0xFFFC] = 0x00;
+        data[0xFFFD] = 0x80;
+
+        let mut cpu = CPU6502::new(DummyBus::new(data));
+        cpu.reset();
+
+        let mut jammed_at = None;
+        for cycle in 0..100 {
+            if cpu.run_next() == CPURunState::Jammed && jammed_at.is_none() {
+                jammed_at = Some(cycle);
+            }
+        }
+
+        assert!(jammed_at.is_some());
+        assert!(cpu.is_jammed());
+        assert!(cpu.run_next() == CPURunState::Jammed);
+        // nothing is fetched after the `KIL`
+        assert_eq!(cpu.executed_count(), 2);
+        assert_eq!(cpu.last_instruction().unwrap().pc, 0x8002);
+
+        cpu.reset();
+        assert!(!cpu.is_jammed());
+        for _ in 0..7 + 2 {
+            cpu.run_next();
+        }
+        assert_eq!(cpu.executed_count(), 1);
+    }
+
+    /// the number of cycles between the first two instructions, where an OAM
+    /// DMA is requested during the first
+    fn oam_dma_gap(first_instruction: &[u8], timing_mode: TimingMode) -> u32 {
+        let mut data = [0xEA; 0x10000]; // NOP
+        data[0x8000..0x8000 + first_instruction.len()].copy_from_slice(first_instruction);
+        data[0xFFFC] = 0x00;
+        data[0xFFFD] = 0x80;
+
+        let mut cpu = CPU6502::new(DummyBus::new(data));
+        cpu.set_timing_mode(timing_mode);
+        cpu.reset();
+        cpu.bus_mut().dma_page = Some(0x02);
+
+        let mut fetches = Vec::new();
+        for cycle in 1..=600 {
+            cpu.run_next();
+
+            if cpu.fetched_instruction_pc().is_some() {
+                fetches.push(cycle);
+            }
+        }
+
+        fetches[1] - fetches[0]
+    }
+
+    #[test]
+    fn oam_dma_alignment() {
+        const NOP: &[u8] = &[0xEA];
+        // JMP $8003 (3 cycles)
+        const JMP: &[u8] = &[0x4C, 0x03, 0x80];
+
+        assert_eq!(oam_dma_gap(NOP, TimingMode::Approximate), 2 + 512);
+        assert_eq!(oam_dma_gap(JMP, TimingMode::Approximate), 3 + 512);
+
+        // halt cycle, and alignment cycle if the halt is on an odd cycle
+        assert_eq!(oam_dma_gap(NOP, TimingMode::CycleAccurate), 2 + 513);
+        assert_eq!(oam_dma_gap(JMP, TimingMode::CycleAccurate), 3 + 514);
+    }
+
+    /// run a `BRK` and assert the NMI line `nmi_delay` cycles after it is
+    /// fetched, returns the address of the first instruction of the handler
+    /// and the pushed status
+    fn brk_handler(nmi_delay: Option<u32>, timing_mode: TimingMode) -> (u16, u8) {
+        let mut data = [0xEA; 0x10000]; // NOP
+        data[0x8000] = 0x00; // BRK
+        data[0xFFFA..].copy_from_slice(&[
+            0x00, 0xA0, // NMI
+            0x00, 0x80, // reset
+            0x00, 0x90, // IRQ/BRK
+        ]);
+
+        let mut cpu = CPU6502::new(DummyBus::new(data));
+        cpu.set_timing_mode(timing_mode);
+        cpu.reset();
+
+        while cpu.fetched_instruction_pc() != Some(0x8000) {
+            cpu.run_next();
+        }
+        let mut cycle = 0;
+        loop {
+            if nmi_delay == Some(cycle) {
+                cpu.bus_mut().nmi_pin = true;
+            }
+            cpu.run_next();
+            cycle += 1;
+
+            if let Some(pc) = cpu.fetched_instruction_pc() {
+                // PC high, PC low, then the status
+                return (pc, cpu.bus().data[0x01FB]);
+            }
+        }
+    }
+
+    #[test]
+    fn brk_pushes_b_flag() {
+        for timing_mode in [TimingMode::Approximate, TimingMode::CycleAccurate] {
+            // B and bit 5 are set, with the `I` flag from the reset
+            assert_eq!(brk_handler(None, timing_mode), (0x9000, 0x34));
+        }
+    }
+
+    #[test]
+    fn nmi_hijacks_brk() {
+        // before the vector fetch, the handler is the NMI, but the pushed
+        // status still has B set
+        for delay in 1..=3 {
+            assert_eq!(
+                brk_handler(Some(delay), TimingMode::CycleAccurate),
+                (0xA000, 0x34)
+            );
+        }
+        // too late, the NMI runs after the first instruction of the handler
+        assert_eq!(
+            brk_handler(Some(5), TimingMode::CycleAccurate),
+            (0x9000, 0x34)
+        );
+    }
+
+    /// the data accesses (`0x0200-0x7FFF`) of indexed loads, stores and a
+    /// read-modify-write instruction, as (cycle, is write, address, data)
+    fn data_accesses(timing_mode: TimingMode) -> Vec<(u32, bool, u16, u8)> {
+        let mut data = [0; 0x10000];
+        data[0x8000..0x800C].copy_from_slice(&[
+            0xA2, 0x10, // LDX #$10
+            0xBD, 0xF8, 0x02, // LDA $02F8,X    (page cross)
+            0x9D, 0x00, 0x03, // STA $0300,X
+            0xEE, 0x00, 0x04, // INC $0400
+            0xEA, // NOP
+        ]);
+        data[0x0400] = 0x41;
+        data[0xFFFC] = 0x00;
+        data[0xFFFD] = 0x80;
+
+        let mut cpu = CPU6502::new(DummyBus::new(data));
+        cpu.set_timing_mode(timing_mode);
+        cpu.reset();
+
+        let mut accesses = Vec::new();
+        let mut cycle = 0;
+        while cpu.fetched_instruction_pc() != Some(0x800B) {
+            cpu.run_next();
+            cycle += 1;
+
+            accesses.extend(
+                cpu.bus()
+                    .log
+                    .borrow_mut()
+                    .drain(..)
+                    .filter(|(_, address, _)| (0x0200..0x8000).contains(address))
+                    .map(|(is_write, address, data)| (cycle, is_write, address, data)),
+            );
+        }
+
+        accesses
+    }
+
+    #[test]
+    fn dummy_reads_and_writes() {
+        for timing_mode in [TimingMode::Approximate, TimingMode::CycleAccurate] {
+            assert_eq!(
+                data_accesses(timing_mode),
+                [
+                    // read before fixing the high byte
+                    (13, false, 0x0208, 0),
+                    (14, false, 0x0308, 0),
+                    // stores always read first
+                    (18, false, 0x0310, 0),
+                    (19, true, 0x0310, 0),
+                    // the unmodified value is written back first
+                    (23, false, 0x0400, 0x41),
+                    (24, true, 0x0400, 0x41),
+                    (25, true, 0x0400, 0x42),
+                ]
+            );
+        }
+    }
 }
@@ -1,10 +1,26 @@
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
 
+impl Color {
+    pub const fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// `[r, g, b, 0xFF]`
+    pub const fn to_rgba(&self) -> [u8; 4] {
+        [self.r, self.g, self.b, 0xFF]
+    }
+
+    /// `0xAARRGGBB` with the alpha set to `0xFF`
+    pub const fn to_u32(&self) -> u32 {
+        0xFF00_0000 | (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
+}
+
 // for easier building
 macro_rules! color {
     ($r:expr, $g:expr, $b:expr) => {
@@ -0,0 +1,140 @@
+use super::color::Color;
+
+/// Post-processing filters applied to a complete frame before it is sent to
+/// the UI provider
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoFilter {
+    /// Raw PPU output
+    None,
+    /// Approximation of an NTSC composite signal, the chroma (IQ) components
+    /// are band-limited horizontally (color bleeding) and the luma (Y) is
+    /// slightly softened, followed by [`VideoFilter::Scanlines`]
+    ///
+    /// This is not a full composite simulation, the signal is not encoded
+    /// and decoded, so there are no dot crawl, fringing or artifact colors
+    Ntsc,
+    /// [`VideoFilter::Scanlines`] with a small horizontal gaussian blur
+    /// (sigma ~0.5 pixels) to simulate phosphor spread
+    Crt,
+    /// Darken every other row by 50%
+    Scanlines,
+}
+
+impl Default for VideoFilter {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+// gaussian kernel with sigma = 0.5, (1, e^-2, ...) normalized
+const CRT_KERNEL: [f32; 3] = [0.106_507, 0.786_986, 0.106_507];
+
+// chroma in NTSC has much lower bandwidth than luma, so it spreads
+// across more pixels
+const NTSC_LUMA_KERNEL: [f32; 3] = [0.15, 0.7, 0.15];
+const NTSC_CHROMA_KERNEL: [f32; 5] = [0.1, 0.2, 0.4, 0.2, 0.1];
+
+impl VideoFilter {
+    /// apply the filter in-place on `pixels` which is a `width * height` frame
+    pub(crate) fn apply(&self, pixels: &mut [Color], width: usize, height: usize) {
+        match self {
+            Self::None => {}
+            Self::Scanlines => Self::apply_scanlines(pixels, width, height),
+            Self::Crt => {
+                Self::apply_crt_blur(pixels, width, height);
+                Self::apply_scanlines(pixels, width, height);
+            }
+            Self::Ntsc => {
+                Self::apply_ntsc(pixels, width, height);
+                Self::apply_scanlines(pixels, width, height);
+            }
+        }
+    }
+
+    fn apply_scanlines(pixels: &mut [Color], width: usize, height: usize) {
+        for row in pixels
+            .chunks_exact_mut(width)
+            .take(height)
+            .skip(1)
+            .step_by(2)
+        {
+            for pixel in row {
+                pixel.r /= 2;
+                pixel.g /= 2;
+                pixel.b /= 2;
+            }
+        }
+    }
+
+    /// convolve a single row channel with `kernel`, edges are clamped
+    fn convolve_row(input: &[f32], output: &mut [f32], kernel: &[f32]) {
+        let half = (kernel.len() / 2) as isize;
+        let last = input.len() as isize - 1;
+
+        for (x, out) in output.iter_mut().enumerate() {
+            *out = kernel
+                .iter()
+                .enumerate()
+                .map(|(i, weight)| {
+                    let index = (x as isize + i as isize - half).clamp(0, last);
+                    input[index as usize] * weight
+                })
+                .sum();
+        }
+    }
+
+    fn apply_crt_blur(pixels: &mut [Color], width: usize, height: usize) {
+        let mut channels = [vec![0.; width], vec![0.; width], vec![0.; width]];
+        let mut blurred = vec![0.; width];
+
+        for row in pixels.chunks_exact_mut(width).take(height) {
+            for (x, pixel) in row.iter().enumerate() {
+                channels[0][x] = pixel.r as f32;
+                channels[1][x] = pixel.g as f32;
+                channels[2][x] = pixel.b as f32;
+            }
+
+            for (c, channel) in channels.iter().enumerate() {
+                Self::convolve_row(channel, &mut blurred, &CRT_KERNEL);
+
+                for (pixel, value) in row.iter_mut().zip(blurred.iter()) {
+                    let value = value.round().clamp(0., 255.) as u8;
+                    match c {
+                        0 => pixel.r = value,
+                        1 => pixel.g = value,
+                        _ => pixel.b = value,
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply_ntsc(pixels: &mut [Color], width: usize, height: usize) {
+        let mut yiq = [vec![0.; width], vec![0.; width], vec![0.; width]];
+        let mut filtered = [vec![0.; width], vec![0.; width], vec![0.; width]];
+
+        for row in pixels.chunks_exact_mut(width).take(height) {
+            for (x, pixel) in row.iter().enumerate() {
+                let (r, g, b) = (pixel.r as f32, pixel.g as f32, pixel.b as f32);
+
+                yiq[0][x] = 0.299 * r + 0.587 * g + 0.114 * b;
+                yiq[1][x] = 0.596 * r - 0.274 * g - 0.322 * b;
+                yiq[2][x] = 0.211 * r - 0.523 * g + 0.312 * b;
+            }
+
+            Self::convolve_row(&yiq[0], &mut filtered[0], &NTSC_LUMA_KERNEL);
+            Self::convolve_row(&yiq[1], &mut filtered[1], &NTSC_CHROMA_KERNEL);
+            Self::convolve_row(&yiq[2], &mut filtered[2], &NTSC_CHROMA_KERNEL);
+
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let (y, i, q) = (filtered[0][x], filtered[1][x], filtered[2][x]);
+
+                let to_u8 = |v: f32| v.round().clamp(0., 255.) as u8;
+
+                pixel.r = to_u8(y + 0.956 * i + 0.621 * q);
+                pixel.g = to_u8(y - 0.272 * i - 0.647 * q);
+                pixel.b = to_u8(y - 1.106 * i + 1.703 * q);
+            }
+        }
+    }
+}
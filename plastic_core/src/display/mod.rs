@@ -1,7 +1,15 @@
 #[macro_use]
 mod color;
+mod filter;
+mod palette_generator;
 mod tv;
+mod vs_palette;
+
+mod tests;
 
 pub use color::Color;
 pub use color::COLORS;
+pub use filter::VideoFilter;
+pub use palette_generator::{generate_palette, PaletteParams, GENERATED_PALETTE_SIZE};
 pub use tv::{TV, TV_BUFFER_SIZE, TV_HEIGHT, TV_WIDTH};
+pub(crate) use vs_palette::generate_vs_palette;
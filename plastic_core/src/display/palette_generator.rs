@@ -0,0 +1,131 @@
+use super::color::Color;
+use std::f32::consts::PI;
+
+/// number of entries in a generated palette, 64 colors for each of the 8
+/// emphasis combinations
+pub const GENERATED_PALETTE_SIZE: usize = 64 * 8;
+
+// NTSC signal voltage levels for the 4 luma levels, low is used for the
+// "off" half of the color wave, and high for the "on" half
+const SIGNAL_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f32 = SIGNAL_LOW[1];
+const WHITE: f32 = SIGNAL_HIGH[3];
+
+/// the amount of signal left on the emphasized phases
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/// Parameters for decoding the NTSC signal, the defaults produce colors close
+/// to [`COLORS`](super::COLORS), the average difference is ~18 (out of 255)
+/// per channel, the largest differences (up to ~60) are in the light cyan
+/// colors (`$3C` and `$3D`), and the grays of the first column are a bit
+/// darker
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaletteParams {
+    /// hue rotation in units of color phases (12 phases make a full circle)
+    pub hue: f32,
+    pub saturation: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+    /// the gamma of the display, `2.2` would result in no correction
+    pub gamma: f32,
+}
+
+impl Default for PaletteParams {
+    fn default() -> Self {
+        Self {
+            hue: 0.,
+            saturation: 2.,
+            brightness: 1.,
+            contrast: 1.,
+            gamma: 2.2,
+        }
+    }
+}
+
+/// is the color wave of `color` high at `phase` (0-11)
+fn in_color_phase(color: u16, phase: u16) -> bool {
+    (color + phase + 8) % 12 < 6
+}
+
+/// generate the color for `index` which is in the form `EEELLCCCC`
+/// (Emphasis, Luma and Chroma), the emphasis bits are in the same order as
+/// in the PPU mask register (BGR)
+fn generate_color(index: u16, params: &PaletteParams) -> Color {
+    let chroma = index & 0xF;
+    let emphasis = index >> 6;
+    // colors $xE and $xF are black (forced to luma 1 with no wave)
+    let luma = if chroma > 0xD { 1 } else { (index >> 4) & 3 } as usize;
+
+    let low = if chroma == 0 {
+        SIGNAL_HIGH[luma]
+    } else {
+        SIGNAL_LOW[luma]
+    };
+    let high = if chroma < 0xD {
+        SIGNAL_HIGH[luma]
+    } else {
+        SIGNAL_LOW[luma]
+    };
+
+    let mut y = 0.;
+    let mut i = 0.;
+    let mut q = 0.;
+
+    for phase in 0..12 {
+        let mut signal = if in_color_phase(chroma, phase) {
+            high
+        } else {
+            low
+        };
+
+        if (emphasis & 1 != 0 && in_color_phase(0xC, phase))
+            || (emphasis & 2 != 0 && in_color_phase(0x4, phase))
+            || (emphasis & 4 != 0 && in_color_phase(0x8, phase))
+        {
+            signal *= EMPHASIS_ATTENUATION;
+        }
+
+        let mut value = (signal - BLACK) / (WHITE - BLACK);
+        value = (value - 0.5) * params.contrast + 0.5;
+        value *= params.brightness / 12.;
+
+        let angle = (PI / 6.) * (phase as f32 + params.hue);
+
+        y += value;
+        i += value * angle.cos();
+        q += value * angle.sin();
+    }
+
+    i *= params.saturation;
+    q *= params.saturation;
+
+    let to_u8 = |value: f32| {
+        let corrected = if value <= 0. {
+            0.
+        } else {
+            value.powf(2.2 / params.gamma)
+        };
+
+        (corrected * 255.95).clamp(0., 255.) as u8
+    };
+
+    color!(
+        to_u8(y + 0.946_882 * i + 0.623_557 * q),
+        to_u8(y - 0.274_788 * i - 0.635_691 * q),
+        to_u8(y - 1.108_545 * i + 1.709_007 * q)
+    )
+}
+
+/// generate the full palette with all emphasis combinations by decoding the
+/// NTSC signal the PPU would generate, the index of a color is
+/// `emphasis << 6 | color`
+pub fn generate_palette(params: PaletteParams) -> [Color; GENERATED_PALETTE_SIZE] {
+    let mut palette = [color!(0, 0, 0); GENERATED_PALETTE_SIZE];
+
+    for (index, color) in palette.iter_mut().enumerate() {
+        *color = generate_color(index as u16, &params);
+    }
+
+    palette
+}
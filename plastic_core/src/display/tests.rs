@@ -0,0 +1,259 @@
+#[cfg(test)]
+mod palette_generator_tests {
+    use super::super::{generate_palette, Color, PaletteParams};
+
+    fn assert_color_near(color: &Color, r: u8, g: u8, b: u8) {
+        const TOLERANCE: i16 = 10;
+
+        assert!(
+            (color.r as i16 - r as i16).abs() <= TOLERANCE
+                && (color.g as i16 - g as i16).abs() <= TOLERANCE
+                && (color.b as i16 - b as i16).abs() <= TOLERANCE,
+            "color ({}, {}, {}) is not near ({}, {}, {})",
+            color.r,
+            color.g,
+            color.b,
+            r,
+            g,
+            b
+        );
+    }
+
+    fn luminance(color: &Color) -> u16 {
+        color.r as u16 + color.g as u16 + color.b as u16
+    }
+
+    #[test]
+    fn generated_palette_entries() {
+        let palette = generate_palette(PaletteParams::default());
+
+        // black
+        assert_color_near(&palette[0x0F], 0, 0, 0);
+        assert_color_near(&palette[0x1D], 0, 0, 0);
+        // near-white
+        assert_color_near(&palette[0x30], 250, 250, 250);
+        assert_color_near(&palette[0x20], 250, 250, 250);
+        // gray
+        assert_color_near(&palette[0x00], 102, 102, 102);
+        // red
+        let red = &palette[0x16];
+        assert!(red.r > 150 && red.g < 60 && red.b < 60);
+        // blue
+        let blue = &palette[0x12];
+        assert!(blue.b > 200 && blue.r < 100 && blue.g < 100);
+    }
+
+    #[test]
+    fn generated_palette_emphasis_is_darker() {
+        let palette = generate_palette(PaletteParams::default());
+
+        for emphasis in 1..8 {
+            for &color in &[0x00, 0x16, 0x21, 0x2A, 0x30] {
+                let base = &palette[color];
+                let emphasized = &palette[emphasis << 6 | color];
+
+                assert!(
+                    luminance(emphasized) < luminance(base),
+                    "emphasis {} on color {:02X} is not darker",
+                    emphasis,
+                    color
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod color_tests {
+    use super::super::{Color, COLORS};
+
+    #[test]
+    fn color_conversions() {
+        let color = Color::from_rgb(0x12, 0x34, 0x56);
+
+        assert_eq!(color.to_rgba(), [0x12, 0x34, 0x56, 0xFF]);
+        assert_eq!(color.to_u32(), 0xFF12_3456);
+        assert_eq!(COLORS[0x30], Color::from_rgb(0xFC, 0xFC, 0xFC));
+    }
+}
+
+#[cfg(test)]
+mod tv_tests {
+    use super::super::{Color, VideoFilter, TV, TV_HEIGHT, TV_WIDTH};
+
+    fn output_of(tv: &mut TV, color: Color) -> [u8; 4] {
+        tv.set_pixel(0, 0, &color);
+        tv.signal_end_of_frame();
+
+        let image = tv.get_image_clone();
+        let buffer = image.lock().unwrap();
+        [buffer[0], buffer[1], buffer[2], buffer[3]]
+    }
+
+    #[test]
+    fn gamma_correction() {
+        let mut tv = TV::new(Color::to_rgba);
+        let color = Color::from_rgb(0, 64, 255);
+
+        assert_eq!(output_of(&mut tv, color), [0, 64, 255, 0xFF]);
+
+        tv.set_gamma(2.0);
+        // sqrt(64 / 255) * 255 = 127.75
+        assert_eq!(output_of(&mut tv, color), [0, 128, 255, 0xFF]);
+
+        tv.set_gamma(0.5);
+        // (64 / 255)^2 * 255 = 16.06
+        assert_eq!(output_of(&mut tv, color), [0, 16, 255, 0xFF]);
+
+        tv.set_gamma(1.0);
+        assert_eq!(output_of(&mut tv, color), [0, 64, 255, 0xFF]);
+    }
+
+    #[test]
+    fn filtered_frame_changed() {
+        let mut tv = TV::new(Color::to_rgba);
+        tv.set_video_filter(VideoFilter::Scanlines);
+
+        let draw_frame = |tv: &mut TV| {
+            for y in 0..TV_HEIGHT as u32 {
+                for x in 0..TV_WIDTH as u32 {
+                    tv.set_pixel(x, y, &Color::from_rgb(200, 100, 50));
+                }
+            }
+            tv.signal_end_of_frame();
+        };
+
+        draw_frame(&mut tv);
+        assert!(tv.frame_changed());
+
+        // the filter is applied in-place, but the same frame is not a change
+        draw_frame(&mut tv);
+        assert!(!tv.frame_changed());
+
+        let image = tv.get_image_clone();
+        let buffer = image.lock().unwrap();
+        let second_row = TV_WIDTH * 4;
+        assert_eq!(buffer[..4], [200, 100, 50, 0xFF]);
+        assert_eq!(buffer[second_row..second_row + 4], [100, 50, 25, 0xFF]);
+    }
+}
+
+#[cfg(test)]
+mod video_filter_tests {
+    use super::super::{Color, VideoFilter};
+
+    const WIDTH: usize = 5;
+    const HEIGHT: usize = 4;
+
+    /// a frame with a white pixel in the middle of the first row on a gray
+    /// background
+    fn test_frame() -> Vec<Color> {
+        let mut pixels = vec![Color::from_rgb(100, 60, 20); WIDTH * HEIGHT];
+        pixels[2] = Color::from_rgb(255, 255, 255);
+        pixels
+    }
+
+    fn filtered(filter: VideoFilter) -> Vec<Color> {
+        let mut pixels = test_frame();
+        filter.apply(&mut pixels, WIDTH, HEIGHT);
+        pixels
+    }
+
+    #[test]
+    fn none_keeps_the_frame() {
+        assert_eq!(filtered(VideoFilter::None), test_frame());
+    }
+
+    #[test]
+    fn scanlines_halve_odd_rows() {
+        let original = test_frame();
+        let pixels = filtered(VideoFilter::Scanlines);
+
+        for (y, (row, original_row)) in pixels
+            .chunks_exact(WIDTH)
+            .zip(original.chunks_exact(WIDTH))
+            .enumerate()
+        {
+            for (pixel, original) in row.iter().zip(original_row) {
+                if y % 2 == 0 {
+                    assert_eq!(pixel, original);
+                } else {
+                    assert_eq!(
+                        *pixel,
+                        Color::from_rgb(original.r / 2, original.g / 2, original.b / 2)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crt_blurs_horizontally() {
+        let pixels = filtered(VideoFilter::Crt);
+
+        // the white pixel spreads to its left and right neighbours
+        // 0.106507 * 255 + 0.893493 * 100 = 116.5
+        assert_eq!(pixels[1].r, 117);
+        assert_eq!(pixels[3].r, 117);
+        // 0.786986 * 255 + 0.213014 * 100 = 222
+        assert_eq!(pixels[2].r, 222);
+        // but not further
+        assert_eq!(pixels[0], Color::from_rgb(100, 60, 20));
+        assert_eq!(pixels[4], Color::from_rgb(100, 60, 20));
+
+        // and not vertically, the second row is only darkened by the scanlines
+        for pixel in &pixels[WIDTH..WIDTH * 2] {
+            assert_eq!(*pixel, Color::from_rgb(50, 30, 10));
+        }
+        for pixel in &pixels[WIDTH * 2..WIDTH * 3] {
+            assert_eq!(*pixel, Color::from_rgb(100, 60, 20));
+        }
+    }
+}
+
+#[cfg(test)]
+mod vs_palette_tests {
+    use super::super::generate_vs_palette;
+    use crate::cartridge::VsPpuType;
+
+    #[test]
+    fn rp2c04_palettes_reorder_the_rgb_colors() {
+        let rp2c03 = generate_vs_palette(VsPpuType::Rp2c03);
+        let mut rp2c03_colors = rp2c03[..0x40]
+            .iter()
+            .map(|c| (c.r, c.g, c.b))
+            .collect::<Vec<_>>();
+        rp2c03_colors.sort_unstable();
+        rp2c03_colors.dedup();
+
+        for ppu_type in [
+            VsPpuType::Rp2c04_0001,
+            VsPpuType::Rp2c04_0002,
+            VsPpuType::Rp2c04_0003,
+            VsPpuType::Rp2c04_0004,
+        ] {
+            let palette = generate_vs_palette(ppu_type);
+            let mut colors = palette[..0x40]
+                .iter()
+                .map(|c| (c.r, c.g, c.b))
+                .collect::<Vec<_>>();
+            colors.sort_unstable();
+            colors.dedup();
+
+            // the same colors, in a different order
+            assert_eq!(colors, rp2c03_colors);
+            assert!(palette[..0x40]
+                .iter()
+                .zip(&rp2c03[..0x40])
+                .any(|(a, b)| (a.r, a.g, a.b) != (b.r, b.g, b.b)));
+        }
+
+        // RP2C03 color `$35` (`755`) is the first of the RP2C04-0001
+        let first = generate_vs_palette(VsPpuType::Rp2c04_0001)[0];
+        assert_eq!((first.r, first.g, first.b), (255, 182, 182));
+        assert_eq!(
+            (first.r, first.g, first.b),
+            (rp2c03[0x35].r, rp2c03[0x35].g, rp2c03[0x35].b)
+        );
+    }
+}
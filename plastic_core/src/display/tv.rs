@@ -1,4 +1,5 @@
 use super::color::Color;
+use super::filter::VideoFilter;
 use std::sync::{Arc, Mutex};
 
 pub const TV_WIDTH: usize = 256;
@@ -19,6 +20,31 @@ pub struct TV {
     /// A function to convert from [`Color`] to 4 byte value, which is used by
     /// the UI provider
     pixels_handler: fn(&Color) -> [u8; 4],
+
+    /// post-processing filter applied in-place on [`building_pixels`] (and
+    /// [`overlay_pixels`]) at the end of the frame before translating it
+    filter: VideoFilter,
+
+    /// lookup table for each color component, `None` if gamma is `1.0`
+    gamma_table: Option<[u8; 256]>,
+
+    /// the frame with the debug overlay of the PPU drawn on top, which is
+    /// displayed instead of [`building_pixels`], `None` if disabled
+    overlay_pixels: Option<Vec<Color>>,
+    /// the output of [`building_pixels`] when the overlay is enabled, so the
+    /// frame without the overlay is still available
+    plain_pixels_output: Vec<u8>,
+
+    /// hash of the last frame before it was filtered, used to check if a
+    /// frame changed since [`building_pixels`] holds the filtered frame
+    last_frame_hash: u64,
+    /// the next frame is reported as changed even if the pixels are the
+    /// same, used for the first frame and when the output settings change
+    force_frame_changed: bool,
+    /// is the last completed frame different from the one before it
+    frame_changed: bool,
+    /// incremented on every changed frame
+    frame_generation: u64,
 }
 
 impl TV {
@@ -27,6 +53,14 @@ impl TV {
             pixels_to_display: Arc::new(Mutex::new(vec![0; TV_BUFFER_SIZE])),
             building_pixels: [color!(0, 0, 0); TV_WIDTH * TV_HEIGHT],
             pixels_handler,
+            filter: VideoFilter::default(),
+            gamma_table: None,
+            overlay_pixels: None,
+            plain_pixels_output: Vec::new(),
+            last_frame_hash: 0,
+            force_frame_changed: true,
+            frame_changed: false,
+            frame_generation: 0,
         }
     }
 
@@ -35,26 +69,149 @@ impl TV {
         self.pixels_to_display.clone()
     }
 
+    pub fn set_video_filter(&mut self, filter: VideoFilter) {
+        self.filter = filter;
+        self.force_frame_changed = true;
+    }
+
+    /// apply `out = (in / 255) ^ (1 / gamma) * 255` on every color component
+    /// of the output, values above `1.0` brighten the image
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.force_frame_changed = true;
+
+        if gamma == 1.0 || !gamma.is_finite() || gamma <= 0. {
+            self.gamma_table = None;
+            return;
+        }
+
+        let mut table = [0; 256];
+        for (i, value) in table.iter_mut().enumerate() {
+            *value = ((i as f32 / 255.).powf(1. / gamma) * 255.).round() as u8;
+        }
+        self.gamma_table = Some(table);
+    }
+
     /// update the pixel of the temporary buffer [`building_pixels`]
     pub fn set_pixel(&mut self, x: u32, y: u32, color: &Color) {
-        let index = y as usize * TV_WIDTH + x as usize;
-        self.building_pixels[index] = *color;
+        self.building_pixels[y as usize * TV_WIDTH + x as usize] = *color;
+    }
+
+    /// enable or disable displaying the frame drawn by
+    /// [`TV::set_overlay_pixel`] instead of the normal frame
+    pub fn set_overlay_enabled(&mut self, enabled: bool) {
+        self.force_frame_changed = true;
+
+        if enabled {
+            self.overlay_pixels = Some(self.building_pixels.to_vec());
+            self.plain_pixels_output = vec![0; TV_BUFFER_SIZE];
+        } else {
+            self.overlay_pixels = None;
+            self.plain_pixels_output = Vec::new();
+        }
+    }
+
+    /// update the pixel of the overlay frame, does nothing if the overlay is
+    /// disabled
+    pub fn set_overlay_pixel(&mut self, x: u32, y: u32, color: &Color) {
+        if let Some(overlay_pixels) = &mut self.overlay_pixels {
+            overlay_pixels[y as usize * TV_WIDTH + x as usize] = *color;
+        }
+    }
+
+    /// the last frame without the overlay (in the same format as the shared
+    /// screen buffer), `None` if the overlay is disabled, in which case the
+    /// shared buffer does not have the overlay
+    pub fn plain_image(&self) -> Option<&[u8]> {
+        self.overlay_pixels
+            .as_ref()
+            .map(|_| &self.plain_pixels_output[..])
+    }
+
+    /// FNV-1a hash of the frame, one step per pixel
+    fn hash_pixels(pixels: &[Color]) -> u64 {
+        pixels.iter().fold(0xCBF2_9CE4_8422_2325, |hash, color| {
+            let value = (color.r as u64) << 16 | (color.g as u64) << 8 | color.b as u64;
+            (hash ^ value).wrapping_mul(0x0100_0000_01B3)
+        })
+    }
+
+    /// translate the [`Color`] data into `buffer` using the gamma and the
+    /// pixels handler
+    fn translate_pixels(&self, pixels: &[Color], buffer: &mut [u8]) {
+        for (result, color) in buffer.chunks_exact_mut(COLOR_BYTES_LEN).zip(pixels.iter()) {
+            let color = match &self.gamma_table {
+                Some(table) => color!(
+                    table[color.r as usize],
+                    table[color.g as usize],
+                    table[color.b as usize]
+                ),
+                None => *color,
+            };
+            result[0..4].copy_from_slice(&(self.pixels_handler)(&color));
+        }
     }
 
     /// the PPU must call this at the end of the frame, maybe around `VBLANK`
     /// to tell the screen to copy and translate the [`Color`] data into the
     /// [`Arc`] shared screen buffer
     pub fn signal_end_of_frame(&mut self) {
+        let frame_hash = Self::hash_pixels(&self.building_pixels);
+        let pixels_changed = frame_hash != self.last_frame_hash;
+        self.last_frame_hash = frame_hash;
+
+        self.filter
+            .apply(&mut self.building_pixels, TV_WIDTH, TV_HEIGHT);
+
+        let mut plain_output = std::mem::take(&mut self.plain_pixels_output);
+
+        if let Some(overlay_pixels) = &mut self.overlay_pixels {
+            self.filter.apply(overlay_pixels, TV_WIDTH, TV_HEIGHT);
+        }
+
+        let pixels = if let Some(overlay_pixels) = &self.overlay_pixels {
+            // keep the frame without the overlay, and display the overlay
+            self.translate_pixels(&self.building_pixels, &mut plain_output);
+            &overlay_pixels[..]
+        } else {
+            &self.building_pixels[..]
+        };
+
         if let Ok(mut buffer) = self.pixels_to_display.lock() {
-            for (result, color) in buffer
-                .chunks_exact_mut(COLOR_BYTES_LEN)
-                .zip(self.building_pixels.iter())
-            {
-                result[0..4].copy_from_slice(&(self.pixels_handler)(color));
-            }
+            self.translate_pixels(pixels, &mut buffer);
+        }
+
+        self.plain_pixels_output = plain_output;
+
+        self.frame_changed = pixels_changed || self.force_frame_changed;
+        self.force_frame_changed = false;
+        if self.frame_changed {
+            self.frame_generation += 1;
         }
     }
 
+    /// the PPU must call this instead of [`TV::signal_end_of_frame`] for
+    /// frames that are not drawn, the displayed frame stays the same
+    pub fn signal_skipped_frame(&mut self) {
+        self.frame_changed = false;
+    }
+
+    /// report the next frame as changed, even if it has the same pixels as
+    /// the last one, for example after loading a state
+    pub fn force_frame_changed(&mut self) {
+        self.force_frame_changed = true;
+    }
+
+    /// is the last completed frame different from the one before it, the
+    /// first frame is always different
+    pub fn frame_changed(&self) -> bool {
+        self.frame_changed
+    }
+
+    /// the number of changed frames
+    pub fn frame_generation(&self) -> u64 {
+        self.frame_generation
+    }
+
     /// resets and zero all buffers
     pub fn reset(&mut self) {
         if let Ok(mut buffer) = self.pixels_to_display.lock() {
@@ -66,5 +223,12 @@ impl TV {
         for i in &mut self.building_pixels {
             *i = color!(0, 0, 0);
         }
+
+        if let Some(overlay_pixels) = &mut self.overlay_pixels {
+            overlay_pixels.fill(color!(0, 0, 0));
+        }
+        self.plain_pixels_output.fill(0);
+
+        self.force_frame_changed = true;
     }
 }
@@ -0,0 +1,113 @@
+use super::color::Color;
+use super::palette_generator::GENERATED_PALETTE_SIZE;
+use crate::cartridge::VsPpuType;
+
+/// the colors of the RGB PPUs (RP2C03/RC2C05), each digit is the level
+/// (0-7) of the red, green and blue channels
+const RGB_PPU_COLORS: [u16; 0x40] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, //
+    0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000, //
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, //
+    0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000, //
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, //
+    0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000, //
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, //
+    0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000, //
+];
+
+/// the order of the RGB PPU colors in the RP2C04-0001 palette
+const RP2C04_0001_ORDER: [u8; 0x40] = [
+    0x35, 0x23, 0x16, 0x22, 0x1C, 0x09, 0x1D, 0x15, 0x20, 0x00, 0x27, 0x05, 0x04, 0x28, 0x08,
+    0x20, //
+    0x21, 0x3E, 0x1F, 0x29, 0x3C, 0x32, 0x36, 0x12, 0x3F, 0x2B, 0x2E, 0x1E, 0x3D, 0x2D, 0x24,
+    0x01, //
+    0x0E, 0x31, 0x33, 0x2A, 0x2C, 0x0C, 0x1B, 0x14, 0x2E, 0x07, 0x34, 0x06, 0x13, 0x02, 0x26,
+    0x2E, //
+    0x2E, 0x19, 0x10, 0x0A, 0x39, 0x03, 0x37, 0x17, 0x0F, 0x11, 0x0B, 0x0D, 0x38, 0x25, 0x18,
+    0x3A, //
+];
+
+/// the order of the RGB PPU colors in the RP2C04-0002 palette
+const RP2C04_0002_ORDER: [u8; 0x40] = [
+    0x2E, 0x27, 0x18, 0x39, 0x3A, 0x25, 0x1C, 0x31, 0x16, 0x13, 0x38, 0x34, 0x20, 0x23, 0x3C,
+    0x0B, //
+    0x0F, 0x21, 0x06, 0x3D, 0x1B, 0x29, 0x1E, 0x22, 0x1D, 0x24, 0x0E, 0x2B, 0x32, 0x08, 0x2E,
+    0x03, //
+    0x04, 0x36, 0x26, 0x33, 0x11, 0x1F, 0x10, 0x02, 0x14, 0x3F, 0x00, 0x09, 0x12, 0x2E, 0x28,
+    0x20, //
+    0x3E, 0x0D, 0x2A, 0x17, 0x0C, 0x01, 0x15, 0x19, 0x2E, 0x2C, 0x07, 0x37, 0x35, 0x05, 0x0A,
+    0x2D, //
+];
+
+/// the order of the RGB PPU colors in the RP2C04-0003 palette
+const RP2C04_0003_ORDER: [u8; 0x40] = [
+    0x14, 0x25, 0x3A, 0x10, 0x0B, 0x20, 0x31, 0x09, 0x01, 0x2E, 0x36, 0x08, 0x15, 0x3D, 0x3E,
+    0x3C, //
+    0x22, 0x1C, 0x05, 0x12, 0x19, 0x18, 0x17, 0x1B, 0x00, 0x03, 0x2E, 0x02, 0x16, 0x06, 0x34,
+    0x35, //
+    0x23, 0x0F, 0x0E, 0x37, 0x0D, 0x27, 0x26, 0x20, 0x29, 0x04, 0x21, 0x24, 0x11, 0x2D, 0x2E,
+    0x1F, //
+    0x2C, 0x1E, 0x39, 0x33, 0x07, 0x2A, 0x28, 0x1D, 0x0A, 0x2E, 0x32, 0x38, 0x13, 0x2B, 0x3F,
+    0x0C, //
+];
+
+/// the order of the RGB PPU colors in the RP2C04-0004 palette
+const RP2C04_0004_ORDER: [u8; 0x40] = [
+    0x18, 0x03, 0x1C, 0x28, 0x2E, 0x35, 0x01, 0x17, 0x10, 0x1F, 0x2A, 0x0E, 0x36, 0x37, 0x0B,
+    0x39, //
+    0x25, 0x1E, 0x12, 0x34, 0x2E, 0x1D, 0x06, 0x26, 0x3E, 0x1B, 0x22, 0x19, 0x04, 0x2E, 0x3A,
+    0x21, //
+    0x05, 0x0A, 0x07, 0x02, 0x13, 0x14, 0x00, 0x15, 0x0C, 0x3D, 0x11, 0x0F, 0x0D, 0x38, 0x2D,
+    0x24, //
+    0x33, 0x20, 0x08, 0x16, 0x3F, 0x2B, 0x20, 0x3C, 0x2E, 0x27, 0x23, 0x31, 0x29, 0x32, 0x2C,
+    0x09, //
+];
+
+/// the index into [`RGB_PPU_COLORS`] for each palette entry, the RP2C04
+/// models have the same colors in a different order
+fn color_order(ppu_type: VsPpuType) -> Option<&'static [u8; 0x40]> {
+    match ppu_type {
+        VsPpuType::Rp2c03 | VsPpuType::Rc2c05 => None,
+        VsPpuType::Rp2c04_0001 => Some(&RP2C04_0001_ORDER),
+        VsPpuType::Rp2c04_0002 => Some(&RP2C04_0002_ORDER),
+        VsPpuType::Rp2c04_0003 => Some(&RP2C04_0003_ORDER),
+        VsPpuType::Rp2c04_0004 => Some(&RP2C04_0004_ORDER),
+    }
+}
+
+fn rgb_ppu_color(index: usize) -> Color {
+    // 3 bits to 8 bits
+    let level = |shift: u16| ((RGB_PPU_COLORS[index] >> shift & 7) * 255 / 7) as u8;
+
+    color!(level(6), level(3), level(0))
+}
+
+/// the palette of the VS. System `ppu_type` with all emphasis combinations,
+/// in the same layout as [`generate_palette`](super::generate_palette), the
+/// RGB PPUs do not dim the other channels on emphasis, instead the
+/// emphasized channels are set to full brightness
+pub(crate) fn generate_vs_palette(ppu_type: VsPpuType) -> [Color; GENERATED_PALETTE_SIZE] {
+    let order = color_order(ppu_type);
+    let mut palette = [color!(0, 0, 0); GENERATED_PALETTE_SIZE];
+
+    for (index, color) in palette.iter_mut().enumerate() {
+        let entry = index & 0x3F;
+        // emphasis bits in the order of the PPU mask register (BGR)
+        let emphasis = index >> 6;
+
+        let mut rgb = rgb_ppu_color(order.map_or(entry, |order| order[entry] as usize));
+        if emphasis & 1 != 0 {
+            rgb.r = 0xFF;
+        }
+        if emphasis & 2 != 0 {
+            rgb.g = 0xFF;
+        }
+        if emphasis & 4 != 0 {
+            rgb.b = 0xFF;
+        }
+
+        *color = rgb;
+    }
+
+    palette
+}
@@ -1,128 +1,97 @@
+use crate::common::TvRegion;
 use std::thread;
-use std::time;
-
-fn get_time() -> u64 {
-    time::SystemTime::now()
-        .duration_since(time::UNIX_EPOCH)
-        .unwrap_or_else(|e| e.duration())
-        .as_micros() as u64
-}
-
-fn delay_1_ms() {
-    thread::sleep(time::Duration::from_millis(1));
-}
-
-fn add_percent(value: u64, percent: u64) -> u64 {
-    (value * 100 + value * percent) / 100
-}
-
+use std::time::{Duration, Instant};
+
+/// NTSC frame, 341 * 262 - 0.5 PPU dots (the odd frames skip one dot) of 4
+/// master clock cycles each, at 236.25 / 11 MHz, around 60.0988 Hz
+const NTSC_FRAME_PERIOD: Duration = Duration::from_nanos(16_639_263);
+/// PAL and Dendy frame, 341 * 312 PPU dots of 5 master clock cycles each, at
+/// 26.601712 MHz, around 50.0070 Hz
+const PAL_FRAME_PERIOD: Duration = Duration::from_nanos(19_997_209);
+
+/// `thread::sleep` can overshoot by around a millisecond, so it is only used
+/// until this time before the deadline, and the rest is a busy wait
+const SPIN_MARGIN: Duration = Duration::from_micros(1500);
+
+/// Paces frames to the exact frame rate of a TV region, for frontends without
+/// their own clock (like vsync or the audio device)
+///
+/// The deadlines are kept on a fixed grid from the first frame, so the errors
+/// of single waits do not accumulate, and if the caller is more than one
+/// frame late, the frames are counted in [`FrameLimiter::missed_frames`] and
+/// the grid restarts from the current time instead of running fast to catch
+/// up.
 pub struct FrameLimiter {
-    target_fps: u64,
-    time_per_frame: u64,
-    begin_timestamp: u64,
-    last_frame_timestamp: u64,
-    average_frametime: u64,
-    average_delaytime: u64,
-
-    frame_counter: u64,
-    frame_counter_timestamp: u64,
-    fps: u64,
-
-    tolerance_percentage: u64,
+    frame_period: Duration,
+    next_deadline: Option<Instant>,
+    missed_frames: u64,
 }
 
 impl FrameLimiter {
-    pub fn new(target_fps: u64) -> FrameLimiter {
-        let time_per_frame = 1000000 / target_fps;
-
-        FrameLimiter {
-            target_fps,
-            time_per_frame,
-            last_frame_timestamp: 0,
-            average_frametime: time_per_frame / 2,
-            average_delaytime: 1000,
-            frame_counter: 0,
-            frame_counter_timestamp: get_time(),
-            fps: 0,
-            begin_timestamp: 0,
-            tolerance_percentage: 5,
+    pub fn new(region: TvRegion) -> Self {
+        Self {
+            frame_period: Self::region_frame_period(region),
+            next_deadline: None,
+            missed_frames: 0,
         }
     }
 
-    fn time_left_until_deadline(&self, current: u64) -> u64 {
-        let target = self.last_frame_timestamp + self.time_per_frame;
-        if current > target {
-            0
-        } else {
-            target - current
+    /// the duration of one frame of `region` in real time
+    pub fn region_frame_period(region: TvRegion) -> Duration {
+        match region {
+            TvRegion::Ntsc => NTSC_FRAME_PERIOD,
+            TvRegion::Pal | TvRegion::Dendy => PAL_FRAME_PERIOD,
         }
     }
 
-    #[allow(dead_code)]
-    pub fn target_fps(&self) -> u64 {
-        self.target_fps
+    pub fn frame_period(&self) -> Duration {
+        self.frame_period
     }
 
-    #[allow(dead_code)]
-    pub fn fps(&self) -> u64 {
-        self.fps
+    pub fn target_fps(&self) -> f64 {
+        1. / self.frame_period.as_secs_f64()
     }
 
-    pub fn begin(&mut self) -> bool {
-        self.begin_timestamp = get_time();
-        let time_left = self.time_left_until_deadline(self.begin_timestamp);
-        if time_left > add_percent(self.average_frametime, self.tolerance_percentage) {
-            delay_1_ms();
-
-            let elapsed = get_time() - self.begin_timestamp;
-            self.average_delaytime = (self.average_delaytime + elapsed) / 2;
-
-            if self.average_frametime < self.average_delaytime {
-                self.average_frametime = self.average_delaytime;
-            }
+    /// the number of frames the caller fell behind since the creation of the
+    /// limiter
+    pub fn missed_frames(&self) -> u64 {
+        self.missed_frames
+    }
 
-            false
-        } else {
-            true
-        }
+    /// start a new grid from the next call to
+    /// [`FrameLimiter::wait_for_next_frame`], should be called after a pause
+    /// so the paused time is not counted as missed frames
+    pub fn reset(&mut self) {
+        self.next_deadline = None;
     }
 
-    pub fn end(&mut self) -> Option<u64> {
-        let current = get_time();
-        let elapsed = current - self.begin_timestamp;
-        let elapsed_since_last_fps_update = current - self.frame_counter_timestamp;
+    /// wait until the start of the next frame, the first call returns
+    /// immediately and starts the timing
+    pub fn wait_for_next_frame(&mut self) {
+        let now = Instant::now();
 
-        self.average_frametime = if elapsed < self.average_frametime {
-            (self.average_frametime * 2 + elapsed) / 3
-        } else {
-            (self.average_frametime + elapsed * 2) / 3
+        let deadline = match self.next_deadline {
+            Some(deadline) => deadline,
+            None => {
+                self.next_deadline = Some(now + self.frame_period);
+                return;
+            }
         };
 
-        if self.average_frametime < 1000 {
-            self.average_frametime = 1000;
+        if now > deadline + self.frame_period {
+            let late = now - deadline;
+            self.missed_frames += (late.as_nanos() / self.frame_period.as_nanos()) as u64;
+            self.next_deadline = Some(now + self.frame_period);
+            return;
         }
 
-        if elapsed_since_last_fps_update > add_percent(self.time_per_frame, 20) {
-            self.last_frame_timestamp = current;
-        } else {
-            self.last_frame_timestamp += self.time_per_frame;
+        if deadline > now + SPIN_MARGIN {
+            thread::sleep(deadline - now - SPIN_MARGIN);
         }
-
-        self.frame_counter += 1;
-        if elapsed_since_last_fps_update >= 1000 * 1000 {
-            self.fps = (self.frame_counter * 1000 * 1000) / elapsed_since_last_fps_update;
-            self.frame_counter_timestamp = current;
-            self.frame_counter = 0;
-
-            if self.fps.saturating_sub(self.target_fps) > 5 {
-                if self.tolerance_percentage > 2 {
-                    self.tolerance_percentage -= 1;
-                }
-            }
-
-            Some(self.fps)
-        } else {
-            None
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
         }
+
+        self.next_deadline = Some(deadline + self.frame_period);
     }
 }
@@ -0,0 +1,171 @@
+use std::time::{Duration, Instant};
+
+/// Returned by [`NES::clock_for_frame`] after every frame, unlike
+/// [`FrameStats`] it is always available
+///
+/// [`NES::clock_for_frame`]: crate::nes::NES::clock_for_frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameSummary {
+    /// CPU cycles run in this frame
+    pub cycles_elapsed: u64,
+    /// number of audio samples (per channel) generated by the APU in this
+    /// frame
+    pub audio_samples_produced: usize,
+    /// number of frames since the last reset, including this one, same as
+    /// [`NES::frame_count`](crate::nes::NES::frame_count)
+    pub total_frames: u64,
+}
+
+/// Statistics of the last frame run by [`NES::clock_for_frame`], collected
+/// only when enabled by [`NES::set_stats_enabled`]
+///
+/// [`NES::clock_for_frame`]: crate::nes::NES::clock_for_frame
+/// [`NES::set_stats_enabled`]: crate::nes::NES::set_stats_enabled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// number of instructions fetched by the CPU
+    pub cpu_instructions: u32,
+    pub cpu_cycles: u32,
+    /// number of times the NMI line was asserted
+    pub nmi_count: u32,
+    /// number of times an IRQ line was asserted, from any source
+    pub irq_count: u32,
+    /// CPU cycles taken by OAM DMA transfers
+    pub dma_cycles: u32,
+    /// number of audio samples (per channel) generated by the APU
+    pub audio_samples_generated: u32,
+    /// wall-clock time spent in the CPU (including the mapper)
+    pub cpu_micros: u64,
+    /// wall-clock time spent in the PPU
+    pub ppu_micros: u64,
+    /// wall-clock time spent in the APU
+    pub apu_micros: u64,
+}
+
+/// Collects [`FrameStats`] during a frame, all the functions do nothing if
+/// it is disabled, so that there is no timing calls in the normal path
+pub(crate) struct FrameStatsCollector {
+    enabled: bool,
+    current: FrameStats,
+    last: FrameStats,
+
+    /// start of the current measured part of the CPU cycle
+    lap_start: Option<Instant>,
+    cpu_time: Duration,
+    ppu_time: Duration,
+    apu_time: Duration,
+
+    /// the number of audio samples generated by the APU at the start of the
+    /// frame
+    audio_samples_at_start: u64,
+}
+
+impl FrameStatsCollector {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            current: FrameStats::default(),
+            last: FrameStats::default(),
+            lap_start: None,
+            cpu_time: Duration::ZERO,
+            ppu_time: Duration::ZERO,
+            apu_time: Duration::ZERO,
+            audio_samples_at_start: 0,
+        }
+    }
+
+    /// enable or disable the collection, and clear all the stats
+    pub fn set_enabled(&mut self, enabled: bool) {
+        *self = Self::new();
+        self.enabled = enabled;
+    }
+
+    /// the stats of the last complete frame
+    pub fn last(&self) -> FrameStats {
+        self.last
+    }
+
+    pub fn begin_frame(&mut self, audio_samples_generated: u64) {
+        if self.enabled {
+            self.audio_samples_at_start = audio_samples_generated;
+        }
+    }
+
+    pub fn end_frame(&mut self, audio_samples_generated: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        self.current.audio_samples_generated =
+            (audio_samples_generated - self.audio_samples_at_start) as u32;
+        self.current.cpu_micros = self.cpu_time.as_micros() as u64;
+        self.current.ppu_micros = self.ppu_time.as_micros() as u64;
+        self.current.apu_micros = self.apu_time.as_micros() as u64;
+
+        self.last = std::mem::take(&mut self.current);
+        self.cpu_time = Duration::ZERO;
+        self.ppu_time = Duration::ZERO;
+        self.apu_time = Duration::ZERO;
+    }
+
+    /// start measuring the time of a CPU cycle, the parts are measured in
+    /// order by the `lap_*` functions
+    pub fn start_lap(&mut self) {
+        if self.enabled {
+            self.lap_start = Some(Instant::now());
+        }
+    }
+
+    fn lap(&mut self) -> Duration {
+        // only set when enabled
+        if let Some(start) = self.lap_start {
+            let now = Instant::now();
+            self.lap_start = Some(now);
+            now - start
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    pub fn lap_cpu(&mut self) {
+        let time = self.lap();
+        self.cpu_time += time;
+    }
+
+    pub fn lap_ppu(&mut self) {
+        let time = self.lap();
+        self.ppu_time += time;
+    }
+
+    pub fn lap_apu(&mut self) {
+        let time = self.lap();
+        self.apu_time += time;
+    }
+
+    pub fn count_cpu_cycle(&mut self, fetched_instruction: bool, dma_transfer: bool) {
+        if !self.enabled {
+            return;
+        }
+
+        self.current.cpu_cycles += 1;
+        if fetched_instruction {
+            self.current.cpu_instructions += 1;
+        }
+        // one cycle to read and one to write
+        if dma_transfer {
+            self.current.dma_cycles += 2;
+        }
+    }
+
+    pub fn count_nmi(&mut self) {
+        if self.enabled {
+            self.current.nmi_count += 1;
+        }
+    }
+
+    pub fn count_irq(&mut self) {
+        if self.enabled {
+            self.current.irq_count += 1;
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::controller::StandardNESControllerState;
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as fmtResult},
+};
+
+/// hold the buttons in `hold` for `frames` frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputStep {
+    pub hold: StandardNESControllerState,
+    pub frames: u32,
+}
+
+/// the result of [`NES::run_input_script`](crate::nes::NES::run_input_script)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputScriptSummary {
+    /// the total number of frames run
+    pub frames: u32,
+    /// FNV-1a hash of the screen buffer (in the format of the UI provider)
+    /// after the last frame, it is stable between builds, so it can be stored
+    /// in tests
+    pub frame_hash: u64,
+}
+
+pub enum InputScriptError {
+    /// the token is not in the `BUTTONS:FRAMES` format
+    InvalidToken { index: usize, token: String },
+    /// the button name is not one of `A, B, SELECT, START, UP, DOWN, LEFT,
+    /// RIGHT` or `.` for no buttons
+    UnknownButton {
+        index: usize,
+        token: String,
+        button: String,
+    },
+    /// the number of frames is not a valid number
+    InvalidFrames { index: usize, token: String },
+}
+
+impl InputScriptError {
+    fn get_message(&self) -> String {
+        match self {
+            Self::InvalidToken { index, token } => format!(
+                "token {} `{}` is not in the `BUTTONS:FRAMES` format",
+                index, token
+            ),
+            Self::UnknownButton {
+                index,
+                token,
+                button,
+            } => format!("unknown button `{}` in token {} `{}`", button, index, token),
+            Self::InvalidFrames { index, token } => {
+                format!("invalid number of frames in token {} `{}`", index, token)
+            }
+        }
+    }
+}
+
+impl Error for InputScriptError {}
+
+impl Display for InputScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        write!(f, "{}", self.get_message())
+    }
+}
+
+impl Debug for InputScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        write!(f, "{}", self.get_message())
+    }
+}
+
+/// A sequence of [`InputStep`]s parsed from text
+pub struct InputScript {
+    steps: Vec<InputStep>,
+}
+
+impl InputScript {
+    /// parse a script of whitespace separated `BUTTONS:FRAMES` tokens, where
+    /// `BUTTONS` is `.` for no buttons, or button names separated by `+`
+    /// (case insensitive), for example `"A:2 .:58 START+A:2 .:120"`
+    pub fn parse(script: &str) -> Result<Self, InputScriptError> {
+        let steps = script
+            .split_whitespace()
+            .enumerate()
+            .map(|(index, token)| Self::parse_step(index, token))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { steps })
+    }
+
+    pub fn steps(&self) -> &[InputStep] {
+        &self.steps
+    }
+
+    fn parse_step(index: usize, token: &str) -> Result<InputStep, InputScriptError> {
+        let (buttons, frames) =
+            token
+                .split_once(':')
+                .ok_or_else(|| InputScriptError::InvalidToken {
+                    index,
+                    token: token.to_owned(),
+                })?;
+
+        let frames = frames
+            .parse()
+            .map_err(|_| InputScriptError::InvalidFrames {
+                index,
+                token: token.to_owned(),
+            })?;
+
+        let mut hold = StandardNESControllerState::empty();
+        if buttons != "." {
+            for button in buttons.split('+') {
+                hold |= match button.to_ascii_uppercase().as_str() {
+                    "A" => StandardNESControllerState::A,
+                    "B" => StandardNESControllerState::B,
+                    "SELECT" => StandardNESControllerState::SELECT,
+                    "START" => StandardNESControllerState::START,
+                    "UP" => StandardNESControllerState::UP,
+                    "DOWN" => StandardNESControllerState::DOWN,
+                    "LEFT" => StandardNESControllerState::LEFT,
+                    "RIGHT" => StandardNESControllerState::RIGHT,
+                    _ => {
+                        return Err(InputScriptError::UnknownButton {
+                            index,
+                            token: token.to_owned(),
+                            button: button.to_owned(),
+                        })
+                    }
+                };
+            }
+        }
+
+        Ok(InputStep { hold, frames })
+    }
+}
+
+/// FNV-1a hash of the screen buffer, it does not change between builds, so it
+/// can be stored in tests
+pub(crate) fn frame_hash(image: &[u8]) -> u64 {
+    image.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
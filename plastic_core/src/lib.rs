@@ -10,14 +10,39 @@ mod ppu2c02;
 #[cfg(test)]
 mod tests;
 
+mod chr_edit;
 mod frame_limiter;
+mod frame_stats;
+mod input_script;
 pub mod nes;
+mod state_diff;
+mod vs_system;
+
+pub use cartridge::{rom_info, rom_info_from_bytes};
 
 pub mod nes_controller {
     pub use super::controller::{StandardNESControllerState, StandardNESKey};
+    pub use super::input_script::{InputScript, InputScriptError, InputScriptSummary, InputStep};
+}
+pub mod nes_audio {
+    pub use super::apu2a03::{APUChannelKind, AudioLevels, DmcStatus, SAMPLE_RATE};
+}
+pub mod nes_debug {
+    pub use super::cartridge::ChrSwitchEvent;
+    pub use super::common::compat_warnings::{CompatWarning, CompatWarningCategory};
+    pub use super::cpu6502::instruction::InstructionInfo;
+    pub use super::frame_stats::FrameStats;
+    pub use super::ppu2c02::{DebugOverlay, PpuScroll};
+    pub use super::state_diff::{MemoryChange, RegisterChange, StateDiff};
+
+    #[cfg(feature = "debugger")]
+    pub use super::ppu2c02::PpuInternalState;
 }
 pub mod nes_display {
-    pub use super::display::{Color, TV_BUFFER_SIZE, TV_HEIGHT, TV_WIDTH};
+    pub use super::display::{
+        generate_palette, Color, PaletteParams, VideoFilter, GENERATED_PALETTE_SIZE,
+        TV_BUFFER_SIZE, TV_HEIGHT, TV_WIDTH,
+    };
 }
 
 use std::sync::{
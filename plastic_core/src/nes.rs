@@ -1,49 +1,94 @@
-use crate::apu2a03::APU2A03;
-use crate::cartridge::{Cartridge, CartridgeError};
+use crate::apu2a03::{
+    APUChannelKind, AudioLevels, AudioResampler, DmcStatus, APU2A03, SAMPLE_RATE,
+};
+use crate::cartridge::{Cartridge, CartridgeError, ChrSwitchEvent};
+pub use crate::cartridge::{ConsoleType, HeaderFormat, RomInfo, VsPpuType};
+use crate::chr_edit;
+pub use crate::chr_edit::ChrWriteError;
+pub use crate::common::expansion_device::ExpansionDevice;
 use crate::common::{
+    atomic_file,
+    compat_warnings::{CompatWarning, CompatWarningCategory, CompatWarnings},
     interconnection::*,
-    save_state::{Savable, SaveError},
-    Bus, Device, MirroringProvider,
+    save_state::{self, Savable, SaveError},
+    Bus, Device,
+};
+pub use crate::common::{MirroringMode, TimingMode, TvRegion};
+use crate::controller::{Controller, StandardNESControllerState, StandardNESKey};
+use crate::cpu6502::{instruction::InstructionInfo, CPUBusTrait, CPURunState, CPU6502};
+use crate::display::{
+    generate_palette, generate_vs_palette, PaletteParams, VideoFilter, TV, TV_HEIGHT, TV_WIDTH,
 };
-use crate::controller::{Controller, StandardNESControllerState};
-use crate::cpu6502::{CPUBusTrait, CPU6502};
-use crate::display::TV;
-use crate::ppu2c02::{Palette, VRam, PPU2C02};
+pub use crate::frame_limiter::FrameLimiter;
+pub use crate::frame_stats::FrameSummary;
+#[cfg(feature = "debugger")]
+use crate::ppu2c02::PpuInternalState;
+pub use crate::ppu2c02::VideoEmulation;
+use crate::ppu2c02::{DebugOverlay, Palette, PpuScroll, VRam, PPU2C02};
+use crate::state_diff::{StateDiff, StateSnapshot};
+use crate::vs_system::VsSystemInputs;
 use directories_next::ProjectDirs;
 use regex::{self, Regex};
 use std::cell::Cell;
 use std::cell::RefCell;
-use std::fs::{self, File};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{mpsc::channel, Arc, Mutex};
 
-use super::{frame_limiter::FrameLimiter, BackendEvent, UiEvent, UiProvider};
+use super::{
+    frame_stats::{FrameStats, FrameStatsCollector},
+    input_script::{frame_hash, InputScriptSummary, InputStep},
+    BackendEvent, UiEvent, UiProvider,
+};
 
-struct PPUBus {
-    cartridge: Rc<RefCell<dyn Bus>>,
+pub(crate) struct PPUBus {
+    cartridge: Rc<RefCell<Cartridge>>,
     vram: VRam,
     palettes: Palette,
+    /// 4KB CHR bank to use for each pattern table instead of the mapper
+    chr_bank_override: [Option<u8>; 2],
 }
 
 impl PPUBus {
-    pub fn new<S>(cartridge: Rc<RefCell<S>>) -> Self
-    where
-        S: Bus + MirroringProvider + 'static,
-    {
+    pub fn new(cartridge: Rc<RefCell<Cartridge>>) -> Self {
         PPUBus {
             cartridge: cartridge.clone(),
             vram: VRam::new(cartridge),
             palettes: Palette::new(),
+            chr_bank_override: [None; 2],
         }
     }
+
+    /// read the pattern table `half` (0 for `$0000-$0FFF` and 1 for
+    /// `$1000-$1FFF`) from the 4KB CHR `bank`, or from the mapper if `None`
+    pub fn set_chr_bank_override(&mut self, half: u8, bank: Option<u8>) {
+        self.chr_bank_override[(half & 1) as usize] = bank;
+    }
+
+    /// write to the pattern tables (`$0000-$1FFF`) through the mapper, like
+    /// a PPU write, but returns `false` if the mapper does not allow it
+    pub fn write_chr(&mut self, address: u16, data: u8) -> bool {
+        self.cartridge.borrow_mut().write_chr(address, data)
+    }
 }
 
 impl Bus for PPUBus {
     fn read(&self, address: u16, device: Device) -> u8 {
         match address {
-            0x0000..=0x1FFF => self.cartridge.borrow().read(address, device),
+            0x0000..=0x1FFF => {
+                if let Some(bank) = self.chr_bank_override[(address >> 12) as usize] {
+                    self.cartridge
+                        .borrow()
+                        .read_chr_bank(bank, address as usize & 0xFFF)
+                } else {
+                    self.cartridge.borrow().read(address, device)
+                }
+            }
             0x2000..=0x3EFF => self.vram.read(address & 0x2FFF, device),
             0x3F00..=0x3FFF => self.palettes.read(address, device),
             // mirror
@@ -59,6 +104,22 @@ impl Bus for PPUBus {
             0x4000..=0xFFFF => self.write(address & 0x3FFF, data, device),
         }
     }
+
+    fn chr_bank(&self, address: u16) -> Option<u16> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        if let Some(bank) = self.chr_bank_override[(address >> 12) as usize] {
+            Some(bank as u16 * 4 + ((address >> 10) & 3))
+        } else {
+            self.cartridge.borrow().chr_bank(address)
+        }
+    }
+
+    fn ppu_a12_rising_edge(&self) {
+        self.cartridge.borrow_mut().notify_ppu_a12_rising_edge();
+    }
 }
 
 impl Savable for PPUBus {
@@ -77,13 +138,36 @@ impl Savable for PPUBus {
     }
 }
 
-struct CPUBus {
+/// function called on CPU accesses to a specific address, the arguments are
+/// the address and the value read or written
+pub type CpuAccessHook = Rc<dyn Fn(u16, u8)>;
+
+pub(crate) struct CPUBus {
     ram: [u8; 0x800],
     cartridge: Rc<RefCell<Cartridge>>,
     ppu: Rc<RefCell<PPU2C02<PPUBus>>>,
     apu: Rc<RefCell<APU2A03>>,
     contoller: Controller,
+    contoller_2: Controller,
     irq_pin_change_requested: Cell<bool>,
+    compat_warnings: RefCell<CompatWarnings>,
+    read_hooks: HashMap<u16, Vec<CpuAccessHook>>,
+    write_hooks: HashMap<u16, Vec<CpuAccessHook>>,
+    expansion_device: RefCell<Option<Box<dyn ExpansionDevice>>>,
+    /// the last IRQ line state of the expansion device seen by the CPU
+    expansion_device_irq: Cell<bool>,
+    mapper_write_log: Option<Box<dyn FnMut(u16, u8)>>,
+    /// the device asserting the IRQ line the last time the CPU checked it
+    irq_source: Cell<Option<IrqSource>>,
+    /// debug overrides to hide the APU IRQs from the CPU
+    apu_dmc_irq_enabled: bool,
+    apu_frame_irq_enabled: bool,
+    /// the overrides changed, so the CPU should check the IRQ line again
+    apu_irq_gate_changed: Cell<bool>,
+    /// the coins and DIP switches, only for VS. System cartridges
+    vs_system: Option<VsSystemInputs>,
+    /// NMI asserted from outside the console, cleared when the CPU takes it
+    external_nmi: bool,
 }
 
 impl CPUBus {
@@ -92,6 +176,7 @@ impl CPUBus {
         ppu: Rc<RefCell<PPU2C02<PPUBus>>>,
         apu: Rc<RefCell<APU2A03>>,
         contoller: Controller,
+        contoller_2: Controller,
     ) -> Self {
         CPUBus {
             cartridge,
@@ -99,14 +184,170 @@ impl CPUBus {
             ppu,
             apu,
             contoller,
+            contoller_2,
             irq_pin_change_requested: Cell::new(false),
+            compat_warnings: RefCell::new(CompatWarnings::new()),
+            read_hooks: HashMap::new(),
+            write_hooks: HashMap::new(),
+            expansion_device: RefCell::new(None),
+            expansion_device_irq: Cell::new(false),
+            mapper_write_log: None,
+            irq_source: Cell::new(None),
+            apu_dmc_irq_enabled: true,
+            apu_frame_irq_enabled: true,
+            apu_irq_gate_changed: Cell::new(false),
+            vs_system: None,
+            external_nmi: false,
+        }
+    }
+
+    /// the device asserting the IRQ line the last time the CPU checked it
+    pub fn irq_source(&self) -> Option<IrqSource> {
+        self.irq_source.get()
+    }
+
+    pub fn set_expansion_device(&mut self, device: Option<Box<dyn ExpansionDevice>>) {
+        *self.expansion_device.get_mut() = device;
+        self.expansion_device_irq.set(false);
+    }
+
+    pub fn set_apu_irq_enabled(&mut self, dmc: bool, frame: bool) {
+        self.apu_dmc_irq_enabled = dmc;
+        self.apu_frame_irq_enabled = frame;
+        self.apu_irq_gate_changed.set(true);
+    }
+
+    #[cfg(feature = "debugger")]
+    pub fn generate_nmi(&mut self) {
+        self.external_nmi = true;
+    }
+
+    pub fn set_mapper_write_log(&mut self, mapper_write_log: Option<Box<dyn FnMut(u16, u8)>>) {
+        self.mapper_write_log = mapper_write_log;
+    }
+
+    /// set the work RAM to its power-on state, it is not cleared on reset
+    pub fn clear_ram(&mut self) {
+        self.ram = [0; 0x800];
+    }
+
+    /// fill the work RAM with a pseudo-random pattern generated from `seed`
+    /// (SplitMix64), the same seed always gives the same pattern
+    pub fn randomize_ram(&mut self, seed: u64) {
+        let mut state = seed;
+        for chunk in self.ram.chunks_mut(8) {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+
+            chunk.copy_from_slice(&z.to_le_bytes());
+        }
+    }
+
+    pub fn clock_expansion_device(&mut self, cpu_cycles: u64) {
+        if let Some(device) = self.expansion_device.get_mut() {
+            device.clock(cpu_cycles);
+        }
+        if let Some(vs_system) = &mut self.vs_system {
+            vs_system.clock();
+        }
+    }
+
+    /// add the VS. System inputs to `$4016` and `$4017`, they are kept if
+    /// already enabled
+    pub fn set_vs_system(&mut self, enabled: bool) {
+        if !enabled {
+            self.vs_system = None;
+        } else if self.vs_system.is_none() {
+            self.vs_system = Some(VsSystemInputs::new());
+        }
+    }
+
+    pub fn vs_system_mut(&mut self) -> Option<&mut VsSystemInputs> {
+        self.vs_system.as_mut()
+    }
+
+    /// the controllers of `$4016` and `$4017` in this order
+    fn controller_ports(&self) -> (&Controller, &Controller) {
+        match &self.vs_system {
+            Some(vs_system) if vs_system.swap_controllers() => (&self.contoller_2, &self.contoller),
+            _ => (&self.contoller, &self.contoller_2),
+        }
+    }
+
+    /// read a controller port (`$4016` or `$4017`) with the VS. System
+    /// inputs, the controller is not shifted if `peek` is set
+    fn read_controller_port(&self, address: u16, peek: bool) -> u8 {
+        let (port_1, port_2) = self.controller_ports();
+        let controller = if address == 0x4016 { port_1 } else { port_2 };
+
+        let data = if peek {
+            controller.peek()
+        } else {
+            controller.read(address, Device::CPU)
+        };
+
+        match &self.vs_system {
+            Some(vs_system) if address == 0x4016 => data | vs_system.bits_4016(),
+            Some(vs_system) => data | vs_system.bits_4017(),
+            None => data,
+        }
+    }
+
+    fn expansion_device_irq(&self) -> bool {
+        self.expansion_device
+            .borrow()
+            .as_ref()
+            .map(|device| device.irq())
+            .unwrap_or(false)
+    }
+
+    fn controller(&self, player: u8) -> &Controller {
+        match player {
+            0 => &self.contoller,
+            1 => &self.contoller_2,
+            _ => panic!("only 2 controllers are supported"),
+        }
+    }
+
+    /// read `address` without the side effects of a CPU read, the registers
+    /// return the value the next read would return, and the read hooks and
+    /// the expansion device are not called
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram[(address & 0x7FF) as usize],
+            0x2000..=0x3FFF => self.ppu.borrow().peek(0x2000 | (address & 0x7)),
+            0x4014 => self.ppu.borrow().peek(address),
+            0x4015 => self.apu.borrow().peek_status(),
+            0x4016 | 0x4017 => self.read_controller_port(address, true),
+            // write only APU registers and unused test registers
+            0x4000..=0x401F => 0,
+            0x4020..=0xFFFF => self.cartridge.borrow().read(address, Device::CPU),
+        }
+    }
+
+    fn call_hooks(hooks: &HashMap<u16, Vec<CpuAccessHook>>, address: u16, data: u8) {
+        if let Some(hooks) = hooks.get(&address) {
+            for hook in hooks {
+                hook(address, data);
+            }
+        }
+    }
+
+    fn report_compat_warning(&self, category: CompatWarningCategory, address: u16, value: u8) {
+        let mut compat_warnings = self.compat_warnings.borrow_mut();
+
+        if compat_warnings.is_enabled() {
+            compat_warnings.record(category, address, value);
         }
     }
 }
 
 impl CPUBusTrait for CPUBus {
     fn read(&self, address: u16) -> u8 {
-        match address {
+        let data = match address {
             0x0000..=0x1FFF => self.ram[(address & 0x7FF) as usize],
             0x2000..=0x3FFF => self
                 .ppu
@@ -115,20 +356,46 @@ impl CPUBusTrait for CPUBus {
             0x4000..=0x4013 => self.apu.borrow().read(address, Device::CPU),
             0x4014 => self.ppu.borrow().read(address, Device::CPU),
             0x4015 => self.apu.borrow().read(address, Device::CPU),
-            0x4016 => self.contoller.read(address, Device::CPU),
-            0x4017 => self.apu.borrow().read(address, Device::CPU),
+            0x4016 | 0x4017 => self.read_controller_port(address, false),
             0x4018..=0x401F => {
                 // unused CPU test mode registers
+                self.report_compat_warning(CompatWarningCategory::UnemulatedRegister, address, 0);
                 0
             }
-            0x4020..=0xFFFF => self.cartridge.borrow().read(address, Device::CPU),
+            0x4020..=0xFFFF => {
+                let device_result = if address < 0x6000 {
+                    self.expansion_device
+                        .borrow_mut()
+                        .as_mut()
+                        .and_then(|device| device.read(address))
+                } else {
+                    None
+                };
+
+                device_result.unwrap_or_else(|| self.cartridge.borrow().read(address, Device::CPU))
+            }
+        };
+
+        if !self.read_hooks.is_empty() {
+            Self::call_hooks(&self.read_hooks, address, data);
         }
+
+        data
     }
 
     fn write(&mut self, address: u16, data: u8) {
         match address {
             0x0000..=0x1FFF => self.ram[(address & 0x7FF) as usize] = data,
             0x2000..=0x3FFF => {
+                // PPU master/slave select (EXT pins) in `PPUCTRL`
+                if address & 0x7 == 0 && data & 0x40 != 0 {
+                    self.report_compat_warning(
+                        CompatWarningCategory::UnemulatedRegister,
+                        0x2000,
+                        data,
+                    );
+                }
+
                 self.ppu
                     .borrow_mut()
                     .write(0x2000 | (address & 0x7), data, Device::CPU)
@@ -136,32 +403,112 @@ impl CPUBusTrait for CPUBus {
             0x4000..=0x4013 => self.apu.borrow_mut().write(address, data, Device::CPU),
             0x4014 => self.ppu.borrow_mut().write(address, data, Device::CPU),
             0x4015 => self.apu.borrow_mut().write(address, data, Device::CPU),
-            0x4016 => self.contoller.write(address, data, Device::CPU),
+            0x4016 => {
+                // expansion port output bits (OUT1, OUT2), the VS. System
+                // uses OUT2 for bank switching
+                let unused_outputs = if self.vs_system.is_some() {
+                    0b010
+                } else {
+                    0b110
+                };
+                if data & unused_outputs != 0 {
+                    self.report_compat_warning(
+                        CompatWarningCategory::UnemulatedRegister,
+                        address,
+                        data,
+                    );
+                }
+
+                // both controllers share the strobe line
+                self.contoller.write(address, data, Device::CPU);
+                self.contoller_2.write(address, data, Device::CPU);
+                self.cartridge.borrow_mut().write_controller_port(data);
+            }
             0x4017 => self.apu.borrow_mut().write(address, data, Device::CPU),
             0x4018..=0x401F => {
                 // unused CPU test mode registers
+                self.report_compat_warning(
+                    CompatWarningCategory::UnemulatedRegister,
+                    address,
+                    data,
+                );
+            }
+            0x4020..=0xFFFF => {
+                let handled_by_device = address < 0x6000
+                    && self
+                        .expansion_device
+                        .get_mut()
+                        .as_mut()
+                        .map(|device| device.write(address, data))
+                        .unwrap_or(false);
+
+                if !handled_by_device {
+                    if address < 0x6000 && !self.cartridge.borrow().uses_expansion_area() {
+                        let category = match address {
+                            // FDS and MMC5 audio
+                            0x4040..=0x4097 | 0x5000..=0x5015 => {
+                                CompatWarningCategory::ExpansionAudioWrite
+                            }
+                            _ => CompatWarningCategory::UnsupportedMapperFeature,
+                        };
+
+                        self.report_compat_warning(category, address, data);
+                    }
+
+                    if let Some(mapper_write_log) = &mut self.mapper_write_log {
+                        mapper_write_log(address, data);
+                    }
+
+                    self.cartridge
+                        .borrow_mut()
+                        .write(address, data, Device::CPU)
+                }
             }
-            0x4020..=0xFFFF => self
-                .cartridge
-                .borrow_mut()
-                .write(address, data, Device::CPU),
+        }
+
+        if !self.write_hooks.is_empty() {
+            Self::call_hooks(&self.write_hooks, address, data);
         }
     }
 
+    /// the RESET button does not clear the RAM, some games check it to
+    /// detect a soft reset
     fn reset(&mut self) {
-        self.ram = [0; 0x800];
+        self.irq_source.set(None);
+    }
+}
+
+impl CPUBus {
+    /// read the bus part of a saved state without loading it, and return the
+    /// work RAM in it
+    fn ram_in_state<R: Read>(&self, reader: &mut R) -> Result<Vec<u8>, SaveError> {
+        let mut ram = vec![0; self.ram.len()];
+        reader.read_exact(&mut ram)?;
+        if self.vs_system.is_some() {
+            VsSystemInputs::new().load(reader)?;
+        }
+
+        Ok(ram)
     }
 }
 
 impl Savable for CPUBus {
     fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), SaveError> {
         writer.write_all(&self.ram)?;
+        // only present for VS. System cartridges, which is known from the
+        // cartridge when loading
+        if let Some(vs_system) = &self.vs_system {
+            vs_system.save(writer)?;
+        }
 
         Ok(())
     }
 
     fn load<R: Read>(&mut self, reader: &mut R) -> Result<(), SaveError> {
         reader.read_exact(&mut self.ram)?;
+        if let Some(vs_system) = &mut self.vs_system {
+            vs_system.load(reader)?;
+        }
 
         Ok(())
     }
@@ -169,10 +516,11 @@ impl Savable for CPUBus {
 
 impl PPUCPUConnection for CPUBus {
     fn is_nmi_pin_set(&self) -> bool {
-        self.ppu.borrow().is_nmi_pin_set()
+        self.external_nmi || self.ppu.borrow().is_nmi_pin_set()
     }
 
     fn clear_nmi_pin(&mut self) {
+        self.external_nmi = false;
         self.ppu.borrow_mut().clear_nmi_pin()
     }
 
@@ -206,18 +554,34 @@ impl APUCPUConnection for CPUBus {
 impl CPUIrqProvider for CPUBus {
     fn is_irq_change_requested(&self) -> bool {
         let result = self.apu.borrow().is_irq_change_requested()
-            || self.cartridge.borrow().is_irq_change_requested();
+            || self.apu_irq_gate_changed.get()
+            || self.cartridge.borrow().is_irq_change_requested()
+            || self.expansion_device_irq() != self.expansion_device_irq.get();
         self.irq_pin_change_requested.set(result);
         result
     }
 
     fn irq_pin_state(&self) -> bool {
         if self.irq_pin_change_requested.get() {
-            let mut result = self.apu.borrow().irq_pin_state();
-            if self.cartridge.borrow().is_irq_change_requested() {
-                result = result || self.cartridge.borrow().irq_pin_state();
-            }
-            result
+            let source = {
+                let apu = self.apu.borrow();
+                let cartridge = self.cartridge.borrow();
+
+                if self.apu_frame_irq_enabled && apu.frame_irq_pin_state() {
+                    Some(IrqSource::Apu)
+                } else if self.apu_dmc_irq_enabled && apu.dmc_irq_pin_state() {
+                    Some(IrqSource::Dmc)
+                } else if cartridge.is_irq_change_requested() && cartridge.irq_pin_state() {
+                    Some(IrqSource::Mapper)
+                } else if self.expansion_device_irq() {
+                    Some(IrqSource::Expansion)
+                } else {
+                    None
+                }
+            };
+            self.irq_source.set(source);
+
+            source.is_some()
         } else {
             false
         }
@@ -225,11 +589,58 @@ impl CPUIrqProvider for CPUBus {
 
     fn clear_irq_request_pin(&mut self) {
         *self.irq_pin_change_requested.get_mut() = false;
+        self.apu_irq_gate_changed.set(false);
+        self.expansion_device_irq.set(self.expansion_device_irq());
         self.cartridge.borrow_mut().clear_irq_request_pin();
         self.apu.borrow_mut().clear_irq_request_pin();
     }
 }
 
+/// number of CPU cycles per loop, one full frame
+pub(crate) const CPU_CYCLES_PER_FRAME: usize = 29780;
+
+/// the seed of the power-on RAM pattern if not changed with
+/// [`NES::set_random_seed`], fixed so the default runs are reproducible
+const DEFAULT_RANDOM_SEED: u64 = 0x504C_4153_5449_4321;
+
+/// What to do after reloading the ROM data with [`NES::reload_rom_data`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReloadMode {
+    /// only replace the PRG and CHR ROMs, the emulation continues from the
+    /// same state
+    RomOnly,
+    /// press the RESET button after replacing the ROMs
+    SoftReset,
+}
+
+/// The device which asserted the IRQ line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrqSource {
+    /// the APU frame counter
+    Apu,
+    /// the APU DMC channel, at the end of a sample
+    Dmc,
+    Mapper,
+    /// the device set by [`NES::set_expansion_device`]
+    Expansion,
+}
+
+/// The condition of [`NES::clock_until_opcode`] was not reached in time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeoutError {
+    /// the CPU cycles run before giving up, less than the timeout if the
+    /// CPU is jammed
+    pub cycles: u64,
+}
+
+impl Error for TimeoutError {}
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        write!(f, "timed out after {} CPU cycles", self.cycles)
+    }
+}
+
 pub struct NES<P: UiProvider + Send + 'static> {
     cartridge: Rc<RefCell<Cartridge>>,
     cpu: CPU6502<CPUBus>,
@@ -241,6 +652,40 @@ pub struct NES<P: UiProvider + Send + 'static> {
     ui: Option<P>, // just to hold the UI object (it will be taken in the main loop)
 
     paused: bool,
+    force_prg_ram: bool,
+
+    /// fill the work RAM with a pattern generated from `random_seed` on
+    /// power cycle instead of zeros
+    randomize_power_on_ram: bool,
+    random_seed: u64,
+
+    /// number of CPU cycles executed since the last reset
+    cpu_cycles: u64,
+    /// number of frames run by [`NES::clock_for_frame`] since the last reset
+    frame_count: u64,
+
+    // last state of the interrupt lines, used to detect when they are asserted
+    last_nmi_pin: bool,
+    last_apu_irq_pin: bool,
+    last_dmc_irq_pin: bool,
+    last_mapper_irq_pin: bool,
+    last_expansion_irq_pin: bool,
+
+    nmi_callback: Option<Box<dyn Fn(u64)>>,
+    irq_callback: Option<Box<dyn Fn(IrqSource, u64)>>,
+    scanline_callback: Option<Box<dyn Fn(i32)>>,
+
+    compat_warnings_enabled: bool,
+    chr_switch_logging: bool,
+
+    stats: FrameStatsCollector,
+
+    region: TvRegion,
+    timing_mode: TimingMode,
+
+    audio_resampler: AudioResampler,
+    /// copy of the pending audio samples returned by [`NES::audio_buffer_ref`]
+    peeked_audio_buffer: Vec<f32>,
 }
 
 impl<P: UiProvider + Send + 'static> NES<P> {
@@ -250,12 +695,44 @@ impl<P: UiProvider + Send + 'static> NES<P> {
         Ok(Self::create_nes(cartridge, ui))
     }
 
+    /// load the ROM `entry` from the zip archive at `filename`, if `entry` is
+    /// `None`, the archive must have only one `.nes` file, otherwise
+    /// [`CartridgeError::ZipMultipleRoms`] is returned with their names
+    #[cfg(feature = "zip")]
+    pub fn new_from_zip(
+        filename: &str,
+        entry: Option<&str>,
+        ui: P,
+    ) -> Result<Self, CartridgeError> {
+        let cartridge = Cartridge::from_zip(filename, entry)?;
+
+        Ok(Self::create_nes(cartridge, ui))
+    }
+
     pub fn new_without_file(ui: P) -> Self {
         let cartridge = Cartridge::new_without_file();
 
         Self::create_nes(cartridge, ui)
     }
 
+    /// create a console with an NROM cartridge made from the raw `prg` bytes
+    /// (at most 32KB), placed at `0x8000`, for testing CPU code without a
+    /// `.nes` file, the cartridge also has 8KB of PRG RAM at `0x6000` and 8KB
+    /// of CHR RAM
+    ///
+    /// If `prg` does not reach the vectors at the end, the RESET vector
+    /// points to `0x8000`, and NMI and IRQ to an `RTI` at `0xFFF9`. The
+    /// console is reset, so the next [`NES::clock`] runs the code at the
+    /// RESET vector.
+    pub fn with_test_bus(prg: &[u8], ui: P) -> Self {
+        let cartridge = Cartridge::from_prg(prg);
+
+        let mut nes = Self::create_nes(cartridge, ui);
+        nes.reset();
+
+        nes
+    }
+
     fn create_nes(cartridge: Cartridge, ui: P) -> Self {
         let cartridge = Rc::new(RefCell::new(cartridge));
         let ppubus = PPUBus::new(cartridge.clone());
@@ -272,13 +749,19 @@ impl<P: UiProvider + Send + 'static> NES<P> {
         let ctrl = Controller::new();
         let ctrl_state = ctrl.get_primary_controller_state();
 
-        let cpubus = CPUBus::new(cartridge.clone(), ppu.clone(), apu.clone(), ctrl);
+        let cpubus = CPUBus::new(
+            cartridge.clone(),
+            ppu.clone(),
+            apu.clone(),
+            ctrl,
+            Controller::new(),
+        );
 
         let cpu = CPU6502::new(cpubus);
 
         let paused = cartridge.borrow().is_empty();
 
-        Self {
+        let mut nes = Self {
             cartridge,
             cpu,
             ppu,
@@ -288,140 +771,1466 @@ impl<P: UiProvider + Send + 'static> NES<P> {
             ui: Some(ui),
 
             paused,
+            force_prg_ram: false,
+
+            randomize_power_on_ram: false,
+            random_seed: DEFAULT_RANDOM_SEED,
+
+            cpu_cycles: 0,
+            frame_count: 0,
+
+            last_nmi_pin: false,
+            last_apu_irq_pin: false,
+            last_dmc_irq_pin: false,
+            last_mapper_irq_pin: false,
+            last_expansion_irq_pin: false,
+
+            nmi_callback: None,
+            irq_callback: None,
+            scanline_callback: None,
+
+            compat_warnings_enabled: false,
+            chr_switch_logging: false,
+
+            stats: FrameStatsCollector::new(),
+
+            region: TvRegion::Ntsc,
+            timing_mode: TimingMode::Approximate,
+
+            audio_resampler: AudioResampler::new(),
+            peeked_audio_buffer: Vec::new(),
+        };
+        nes.setup_console_type();
+
+        nes
+    }
+
+    /// enable the VS. System inputs and palette if the cartridge is made
+    /// for it, or disable them otherwise
+    fn setup_console_type(&mut self) {
+        let vs_ppu_type = self.cartridge.borrow().vs_ppu_type();
+        let was_vs_system = self.cpu.bus_mut().vs_system_mut().is_some();
+
+        match vs_ppu_type {
+            Some(ppu_type) => self
+                .ppu
+                .borrow_mut()
+                .set_palette(Some(generate_vs_palette(ppu_type))),
+            None if was_vs_system => self.ppu.borrow_mut().set_palette(None),
+            None => {}
+        }
+        self.cpu.bus_mut().set_vs_system(vs_ppu_type.is_some());
+    }
+
+    /// replace the cartridge with the ROM in `filename` and power cycle the
+    /// console, the old cartridge is dropped (writing its battery save file),
+    /// on error the current cartridge is kept
+    pub fn load_rom(&mut self, filename: &str) -> Result<(), CartridgeError> {
+        let cartridge = Cartridge::from_file(filename)?;
+        self.load_cartridge(cartridge);
+
+        Ok(())
+    }
+
+    /// swap in `cartridge` in place of the current one, the buses keep
+    /// references to the same cartridge cell, so they are not recreated,
+    /// the power cycle clears the RAM and rebuilds the PPU bus
+    pub(crate) fn load_cartridge(&mut self, mut cartridge: Cartridge) {
+        cartridge.set_force_prg_ram(self.force_prg_ram);
+        cartridge.set_chr_switch_logging(self.chr_switch_logging);
+        self.cartridge.replace(cartridge);
+        self.setup_console_type();
+        self.power_cycle();
+    }
+
+    /// reload the PRG and CHR ROMs from `path` while keeping the RAMs (work
+    /// RAM, PRG RAM and CHR RAM), the mapper registers and the CPU state,
+    /// useful to see the changes of a rebuilt homebrew ROM in place.
+    /// The file must have the same mapper and PRG/CHR sizes as the loaded
+    /// cartridge, otherwise [`CartridgeError::ReloadMismatch`] is returned
+    pub fn reload_rom_data<T: AsRef<Path>>(
+        &mut self,
+        path: T,
+        mode: ReloadMode,
+    ) -> Result<(), CartridgeError> {
+        let file = fs::File::open(path)?;
+        self.cartridge.borrow_mut().reload_rom_data(file)?;
+
+        if mode == ReloadMode::SoftReset {
+            self.reset();
         }
+
+        Ok(())
     }
 
+    /// press the RESET button, the work RAM and the cartridge RAM are not
+    /// cleared, use [`NES::power_cycle`] for that, the PPU keeps its VRAM,
+    /// palette and OAM
     pub fn reset(&mut self) {
         self.cpu.reset();
         self.cpu.reset_bus();
 
+        self.ppu.borrow_mut().reset();
+
+        // the mixing, the audio buffer limit and the silence threshold are
+        // settings of the frontend, not of the console
+        let old_apu = self.apu.replace(APU2A03::new());
+        self.apu.borrow_mut().set_region(self.region);
+        self.apu.borrow_mut().keep_frontend_state(&old_apu);
+
+        self.paused = self.cartridge.borrow().is_empty();
+
+        self.cpu_cycles = 0;
+        self.frame_count = 0;
+        self.last_nmi_pin = false;
+        self.last_apu_irq_pin = false;
+        self.last_dmc_irq_pin = false;
+        self.last_mapper_irq_pin = false;
+        self.last_expansion_irq_pin = false;
+    }
+
+    /// turn the console off and on, unlike [`NES::reset`] this also returns
+    /// the cartridge to its power-on state, clears the RAM that is not
+    /// battery backed, puts the PPU in its power-up state, and starts the
+    /// PPU warm-up period where writes to `$2000`, `$2001`, `$2005` and
+    /// `$2006` are ignored (see [`NES::set_ppu_warmup_enabled`])
+    pub fn power_cycle(&mut self) {
+        self.cartridge.borrow_mut().power_cycle();
+        if self.randomize_power_on_ram {
+            self.cpu.bus_mut().randomize_ram(self.random_seed);
+        } else {
+            self.cpu.bus_mut().clear_ram();
+        }
+        self.reset();
+
         let ppubus = PPUBus::new(self.cartridge.clone());
+        self.ppu.borrow_mut().power_on(ppubus);
+    }
 
-        self.ppu.borrow_mut().reset(ppubus);
+    /// fill the work RAM with a random pattern on [`NES::power_cycle`]
+    /// instead of zeros, like the real hardware, to find games that depend
+    /// on uninitialized memory, disabled by default
+    pub fn set_randomize_power_on_ram(&mut self, enabled: bool) {
+        self.randomize_power_on_ram = enabled;
+    }
 
-        self.apu.replace(APU2A03::new());
+    /// set the seed of the random power-on RAM pattern (see
+    /// [`NES::set_randomize_power_on_ram`]), used on the next
+    /// [`NES::power_cycle`], the same seed always generates the same
+    /// pattern, so runs with the same ROM and seed are identical, the default
+    /// seed is a fixed value
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.random_seed = seed;
+    }
 
-        self.paused = self.cartridge.borrow().is_empty();
+    /// enable or disable the PPU warm-up period after [`NES::power_cycle`]
+    /// (enabled by default), disabling it also ends the current one
+    pub fn set_ppu_warmup_enabled(&mut self, enabled: bool) {
+        self.ppu.borrow_mut().set_warmup_enabled(enabled);
     }
 
-    fn get_base_save_state_folder(&self) -> Option<PathBuf> {
-        if let Some(proj_dirs) = ProjectDirs::from("Amjad50", "Plastic", "Plastic") {
-            let base_saved_states_dir = proj_dirs.data_local_dir().join("saved_states");
-            // Linux:   /home/../.local/share/plastic/saved_states
-            // Windows: C:\Users\..\AppData\Local\Plastic\Plastic\data\saved_states
-            // macOS:   /Users/../Library/Application Support/Amjad50.Plastic.Plastic/saved_states
+    /// the CPU cycles remaining in the PPU warm-up period after
+    /// [`NES::power_cycle`], `0` if the PPU accepts all writes
+    pub fn warmup_cycles_remaining(&self) -> u32 {
+        self.ppu.borrow().warmup_cycles_remaining()
+    }
 
-            fs::create_dir_all(&base_saved_states_dir).ok()?;
+    /// set a function to be called when the PPU asserts the NMI line (start of
+    /// VBlank), the argument is the CPU cycle count
+    pub fn set_nmi_callback<F: Fn(u64) + 'static>(&mut self, f: F) {
+        self.nmi_callback = Some(Box::new(f));
+    }
 
-            Some(base_saved_states_dir)
-        } else {
-            None
-        }
+    /// set a function to be called when any device asserts the IRQ line,
+    /// the arguments are the source of the IRQ and the CPU cycle count
+    pub fn set_irq_callback<F: Fn(IrqSource, u64) + 'static>(&mut self, f: F) {
+        self.irq_callback = Some(Box::new(f));
     }
 
-    fn get_save_state_file_path(&self, slot: u8) -> Option<Box<Path>> {
-        if self.cartridge.borrow().is_empty() {
-            return None;
-        }
+    /// set a function to be called at dot 0 of every scanline, the argument is
+    /// the scanline number, `-1` for the pre-render scanline and `241-260` for
+    /// VBlank
+    pub fn set_scanline_callback<F: Fn(i32) + 'static>(&mut self, f: F) {
+        self.scanline_callback = Some(Box::new(f));
+    }
 
-        let cartridge_path = self.cartridge.borrow().cartridge_path().to_path_buf();
+    /// add a function to be called after every CPU write to `address`
+    pub fn set_cpu_write_hook(&mut self, address: u16, f: CpuAccessHook) {
+        self.cpu
+            .bus_mut()
+            .write_hooks
+            .entry(address)
+            .or_default()
+            .push(f);
+    }
 
-        if let Some(base_saved_states_dir) = self.get_base_save_state_folder() {
-            Some(
-                base_saved_states_dir
-                    .join(format!(
-                        "{}_{}.pst",
-                        cartridge_path.file_stem().unwrap().to_string_lossy(),
-                        slot
-                    ))
-                    .into_boxed_path(),
-            )
-        } else {
-            None
-        }
+    /// add a function to be called after every CPU read from `address`
+    pub fn set_cpu_read_hook(&mut self, address: u16, f: CpuAccessHook) {
+        self.cpu
+            .bus_mut()
+            .read_hooks
+            .entry(address)
+            .or_default()
+            .push(f);
     }
 
-    fn get_present_save_states(&self) -> Option<Vec<u8>> {
-        if self.cartridge.borrow().is_empty() {
-            return None;
-        }
+    /// remove all write hooks of `address`
+    pub fn remove_cpu_write_hook(&mut self, address: u16) {
+        self.cpu.bus_mut().write_hooks.remove(&address);
+    }
 
-        let cartridge_path = self.cartridge.borrow().cartridge_path().to_path_buf();
+    /// remove all read hooks of `address`
+    pub fn remove_cpu_read_hook(&mut self, address: u16) {
+        self.cpu.bus_mut().read_hooks.remove(&address);
+    }
 
-        if let Some(base_saved_states_dir) = self.get_base_save_state_folder() {
-            let saved_states_files_regex = Regex::new(&format!(
-                r"{}_(\d*).pst",
-                regex::escape(&cartridge_path.file_stem().unwrap().to_string_lossy()),
-            ))
-            .ok()?;
+    /// replace the state of all keys of the controller of `player` (`0` or
+    /// `1`)
+    pub fn set_controller(&mut self, player: u8, state: StandardNESControllerState) {
+        self.cpu.bus().controller(player).set_state(state);
+    }
 
-            Some(
-                fs::read_dir(base_saved_states_dir)
-                    .ok()?
-                    .filter_map(|path| {
-                        if path.as_ref().ok()?.file_type().ok()?.is_file() {
-                            Some(path.ok()?.path())
-                        } else {
-                            None
-                        }
-                    })
-                    .filter_map(|path| {
-                        Some(
-                            saved_states_files_regex
-                                .captures(path.file_name()?.to_str()?)?
-                                .get(1)?
-                                .as_str()
-                                .parse::<u8>()
-                                .ok()?,
-                        )
-                    })
-                    .collect::<Vec<u8>>(),
-            )
+    /// is the IRQ line asserted as seen by the CPU, it is cleared when the
+    /// CPU starts executing the interrupt
+    pub fn irq_line_asserted(&self) -> bool {
+        self.cpu.irq_pin_status()
+    }
+
+    /// the device asserting the IRQ line, `None` if it is not asserted, if
+    /// more than one device is asserting it, the first in the order of
+    /// [`IrqSource`] is returned
+    pub fn irq_source(&self) -> Option<IrqSource> {
+        if self.irq_line_asserted() {
+            self.cpu.bus().irq_source()
         } else {
             None
         }
     }
 
-    pub fn save_state(&self, slot: u8) -> Result<(), SaveError> {
-        if let Some(path) = self.get_save_state_file_path(slot) {
-            let mut file = File::create(path)?;
+    /// the opcode, mnemonic and address of the last instruction fetched by
+    /// the CPU, useful to catch unofficial opcodes executed by a runaway `PC`
+    pub fn last_instruction(&self) -> Option<InstructionInfo> {
+        self.cpu.last_instruction()
+    }
 
-            self.cartridge.borrow().save(&mut file)?;
-            self.cpu.save(&mut file)?;
-            self.ppu.borrow().save(&mut file)?;
-            self.apu.borrow().save(&mut file)?;
+    /// the number of CPU cycles since the last reset
+    pub fn cpu_cycle_count(&self) -> u64 {
+        self.cpu_cycles
+    }
 
-            Ok(())
-        } else {
-            Err(SaveError::Others)
-        }
+    /// the number of CPU instructions executed since the last reset, or the
+    /// last call to [`NES::reset_executed_count`], with
+    /// [`NES::cpu_cycle_count`] it gives the average cycles per instruction
+    pub fn cpu_executed_count(&self) -> u64 {
+        self.cpu.executed_count()
     }
 
-    pub fn load_state(&mut self, slot: u8) -> Result<(), SaveError> {
-        if let Some(path) = self.get_save_state_file_path(slot) {
-            if path.exists() {
-                let mut file = File::open(path)?;
+    /// clear the count of [`NES::cpu_executed_count`], for profiling parts
+    /// of a game
+    pub fn reset_executed_count(&mut self) {
+        self.cpu.reset_executed_count();
+    }
 
-                self.cartridge.borrow_mut().load(&mut file)?;
-                self.cpu.load(&mut file)?;
-                self.ppu.borrow_mut().load(&mut file)?;
-                self.apu.borrow_mut().load(&mut file)?;
+    /// the CPU executed a `KIL` (`JAM`) opcode and is halted, only a reset
+    /// or a power cycle recovers it, the frame functions return immediately
+    /// while jammed
+    pub fn is_jammed(&self) -> bool {
+        self.cpu.is_jammed()
+    }
 
-                let mut rest = Vec::new();
-                file.read_to_end(&mut rest)?;
+    /// assert the NMI line as if the PPU did, the CPU runs the NMI handler
+    /// after the current instruction, even if NMIs are disabled in
+    /// `PPUCTRL`, for test ROMs that are driven by external NMIs
+    #[cfg(feature = "debugger")]
+    pub fn generate_nmi(&mut self) {
+        self.cpu.bus_mut().generate_nmi();
+    }
 
-                if !rest.is_empty() {
-                    return Err(SaveError::Others);
-                }
+    /// the internal latches of the PPU (`v`, `t`, fine X, ...)
+    #[cfg(feature = "debugger")]
+    pub fn ppu_internal_state(&self) -> PpuInternalState {
+        self.ppu.borrow().internal_state()
+    }
 
-                if !self.paused {
-                    self.apu.borrow().play();
-                }
+    /// replace the internal latches of the PPU, out of range values are masked
+    #[cfg(feature = "debugger")]
+    pub fn set_ppu_internal_state(&mut self, state: PpuInternalState) {
+        self.ppu.borrow_mut().set_internal_state(state);
+    }
 
-                Ok(())
-            } else {
-                Err(SaveError::IoError(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
+    /// the current keys state of the controller of `player` (`0` or `1`)
+    pub fn controller(&self, player: u8) -> StandardNESControllerState {
+        self.cpu.bus().controller(player).state()
+    }
+
+    /// press or release a single key of the controller of `player`
+    pub fn set_controller_key(&mut self, player: u8, key: StandardNESKey, pressed: bool) {
+        let mut state = self.controller(player);
+        state.set_key(key, pressed);
+        self.set_controller(player, state);
+    }
+
+    /// run [`NES::clock_for_frame`] for every step in `script` with its
+    /// buttons held on the first controller, all the buttons are released at
+    /// the end
+    pub fn run_input_script(&mut self, script: &[InputStep]) -> InputScriptSummary {
+        let mut frames = 0;
+
+        for step in script {
+            self.set_controller(0, step.hold);
+
+            for _ in 0..step.frames {
+                self.clock_for_frame();
+            }
+            frames += step.frames;
+        }
+
+        self.set_controller(0, StandardNESControllerState::empty());
+
+        // the debug overlay is not included, so the hash is stable
+        let frame_hash = match self.ppu.borrow().plain_image() {
+            Some(image) => frame_hash(&image),
+            None => frame_hash(&self.image.lock().unwrap()),
+        };
+
+        InputScriptSummary { frames, frame_hash }
+    }
+
+    /// allow pressing `Left+Right` or `Up+Down` together, which is not
+    /// possible on real hardware and can crash some games, default is `true`
+    pub fn set_allow_opposing_directions(&mut self, allow: bool) {
+        let bus = self.cpu.bus_mut();
+        bus.contoller.set_allow_opposing_directions(allow);
+        bus.contoller_2.set_allow_opposing_directions(allow);
+    }
+
+    /// the current PPU scanline, `-1` is the pre-render scanline
+    pub fn ppu_scanline(&self) -> i32 {
+        self.ppu.borrow().scanline()
+    }
+
+    /// returns `true` if the PPU completed a frame (wrapped from the
+    /// pre-render scanline to scanline 0) since the last call that returned
+    /// `true` or the last [`NES::clock_for_frame`], used with
+    /// [`NES::clock`] to know when to refresh the screen
+    pub fn is_frame_complete(&mut self) -> bool {
+        self.ppu.borrow_mut().take_frame_complete()
+    }
+
+    /// the current PPU dot (cycle) in the scanline
+    pub fn ppu_dot(&self) -> u16 {
+        self.ppu.borrow().cycle()
+    }
+
+    /// the CPU memory from `start` to `end` (both inclusive), read without
+    /// any side effects (the registers are not cleared or shifted, and the
+    /// read hooks are not called), empty if `end` is before `start`
+    pub fn dump_memory(&self, start: u16, end: u16) -> Vec<u8> {
+        let bus = self.cpu.bus();
+        (start..=end).map(|address| bus.peek(address)).collect()
+    }
+
+    /// the bytes on the CPU stack (`$0100 + SP + 1` to `$01FF`) in push
+    /// order, so the first byte is the oldest at `$01FF` and the last is the
+    /// top of the stack, read without any side effects like
+    /// [`NES::dump_memory`]. Empty if `SP` is `0xFF`
+    pub fn dump_stack(&self) -> Vec<u8> {
+        let sp = self.cpu.stack_pointer();
+        let bus = self.cpu.bus();
+
+        (0x0100 + sp as u16 + 1..=0x01FF)
+            .rev()
+            .map(|address| bus.peek(address))
+            .collect()
+    }
+
+    /// the content of `PPUSTATUS` register, reading it from here does not
+    /// have any side effects
+    pub fn ppustatus(&self) -> u8 {
+        self.ppu.borrow().status()
+    }
+
+    /// the sprite 0 hit flag (bit 6 of `PPUSTATUS`), without the side
+    /// effects of reading `$2002`, so the vblank flag and the write toggle
+    /// are not cleared
+    pub fn sprite_zero_hit(&self) -> bool {
+        self.ppu.borrow().sprite_0_hit()
+    }
+
+    /// the sprite overflow flag (bit 5 of `PPUSTATUS`), without the side
+    /// effects of reading `$2002`
+    pub fn sprite_overflow(&self) -> bool {
+        self.ppu.borrow().sprite_overflow()
+    }
+
+    /// the base nametable (0-3), bits 0-1 of `$2000`
+    pub fn ppu_base_nametable(&self) -> u8 {
+        self.ppu.borrow().base_nametable()
+    }
+
+    /// the current X scroll position (0-511) from the PPU internal `v`
+    /// register (nametable X, coarse X and fine X), this is the real scroll
+    /// position which is updated during rendering
+    pub fn ppu_scroll_x(&self) -> u16 {
+        self.ppu.borrow().scroll_x()
+    }
+
+    /// the current Y scroll position (0-479) from the PPU internal `v`
+    /// register (nametable Y, coarse Y and fine Y), this is the real scroll
+    /// position which is updated during rendering
+    pub fn ppu_scroll_y(&self) -> u16 {
+        self.ppu.borrow().scroll_y()
+    }
+
+    /// the registers of the cartridge mapper formatted in one line, useful
+    /// for logging bank switching writes, `None` if there is no cartridge
+    pub fn mapper_debug_state(&self) -> Option<String> {
+        self.cartridge.borrow().mapper_debug_state()
+    }
+
+    /// the header information and CRCs of the loaded ROM, for a ROM info
+    /// dialog, `None` if there is no ROM loaded, same as
+    /// [`rom_info`](crate::rom_info) for the file
+    pub fn rom_info(&self) -> Option<RomInfo> {
+        self.cartridge.borrow().rom_info()
+    }
+
+    /// the console the loaded ROM is made for, from the header, frontends
+    /// can warn that VS System and PlayChoice-10 ROMs are not fully supported
+    pub fn console_type(&self) -> ConsoleType {
+        self.cartridge.borrow().console_type()
+    }
+
+    /// the PPU of the VS. System board, its palette is used instead of the
+    /// NES palette, `None` for other consoles
+    pub fn vs_ppu_type(&self) -> Option<VsPpuType> {
+        self.cartridge.borrow().vs_ppu_type()
+    }
+
+    /// drop a coin in `slot` (`0` or `1`) of the VS. System cabinet, other
+    /// slots are ignored, does nothing for other consoles
+    pub fn insert_coin(&mut self, slot: u8) {
+        if let Some(vs_system) = self.cpu.bus_mut().vs_system_mut() {
+            vs_system.insert_coin(slot);
+        }
+    }
+
+    /// set the 8 DIP switches of the VS. System board, DIP switch 1 is bit
+    /// 0, the meaning of each switch depends on the game (difficulty, lives,
+    /// coins per credit), does nothing for other consoles
+    pub fn set_dip_switches(&mut self, dip_switches: u8) {
+        if let Some(vs_system) = self.cpu.bus_mut().vs_system_mut() {
+            vs_system.set_dip_switches(dip_switches);
+        }
+    }
+
+    /// press or release the service button of the VS. System cabinet, it
+    /// adds a credit in most games, does nothing for other consoles
+    pub fn set_vs_service_button(&mut self, pressed: bool) {
+        if let Some(vs_system) = self.cpu.bus_mut().vs_system_mut() {
+            vs_system.set_service_button(pressed);
+        }
+    }
+
+    /// swap the controller ports of the VS. System, for games that read the
+    /// first player from `$4017`, this is not in the header, so it must be
+    /// set from a database, does nothing for other consoles
+    pub fn set_vs_swap_controllers(&mut self, swap: bool) {
+        if let Some(vs_system) = self.cpu.bus_mut().vs_system_mut() {
+            vs_system.set_swap_controllers(swap);
+        }
+    }
+
+    /// the scroll state decoded from the PPU internal `v`, `t` and `x`
+    /// registers, useful to check the result of `$2005` and `$2006` writes,
+    /// reading it does not change the write toggle
+    pub fn ppu_scroll(&self) -> PpuScroll {
+        self.ppu.borrow().scroll()
+    }
+
+    /// the OAM indices (0-63) of the sprites in range of the visible
+    /// `scanline` (0-239) in the last rendered frame, more than 8 entries
+    /// means the scanline triggered the sprite overflow
+    pub fn oam_search_result(&self, scanline: i32) -> Vec<u8> {
+        self.ppu.borrow().oam_search_result(scanline)
+    }
+
+    /// force PPU reads of the pattern table `half` (0 for `$0000-$0FFF` and 1
+    /// for `$1000-$1FFF`) to use the 4KB CHR `bank` regardless of the mapper
+    /// state, used by CHR viewers to show all the banks
+    pub fn set_chr_bank_override(&mut self, bank: u8, half: u8) {
+        self.ppu
+            .borrow_mut()
+            .ppu_bus_mut()
+            .set_chr_bank_override(half, Some(bank));
+    }
+
+    /// restore the mapper banking for the pattern table `half`, see
+    /// [`NES::set_chr_bank_override`]
+    pub fn clear_chr_bank_override(&mut self, half: u8) {
+        self.ppu
+            .borrow_mut()
+            .ppu_bus_mut()
+            .set_chr_bank_override(half, None);
+    }
+
+    /// read the pattern tables from `address` into `out` through the PPU bus,
+    /// so the current CHR banks apply, addresses wrap at `$2000`
+    pub fn read_chr(&self, address: u16, out: &mut [u8]) {
+        self.with_mapper_state_restored(|| {
+            let ppu = self.ppu.borrow();
+            let bus = ppu.ppu_bus();
+            for (i, byte) in out.iter_mut().enumerate() {
+                let chr_address = address.wrapping_add(i as u16) & 0x1FFF;
+                *byte = bus.read(chr_address, Device::PPU);
+            }
+        });
+    }
+
+    /// run `f` and restore the mapper state after it, the viewers read the
+    /// pattern and name tables through the mapper, which can change its
+    /// state (MMC2 latches or MMC3 IRQ counter)
+    fn with_mapper_state_restored<T>(&self, f: impl FnOnce() -> T) -> T {
+        let mapper_state = self.cartridge.borrow().mapper_state();
+        let result = f();
+        self.cartridge
+            .borrow_mut()
+            .restore_mapper_state(&mapper_state);
+
+        result
+    }
+
+    /// write `data` to the pattern tables from `address` through the PPU bus,
+    /// so the current CHR banks apply and CHR RAM games show the change in
+    /// the next rendered tiles, addresses wrap at `$2000`
+    ///
+    /// returns an error on the first byte mapped to CHR ROM, the bytes
+    /// before it are written
+    pub fn write_chr(&mut self, address: u16, data: &[u8]) -> Result<(), ChrWriteError> {
+        if self.cartridge.borrow().is_empty() {
+            return Err(ChrWriteError::NoCartridge);
+        }
+
+        let mut ppu = self.ppu.borrow_mut();
+        let bus = ppu.ppu_bus_mut();
+        for (i, &byte) in data.iter().enumerate() {
+            let chr_address = address.wrapping_add(i as u16) & 0x1FFF;
+            if !bus.write_chr(chr_address, byte) {
+                return Err(ChrWriteError::ReadOnly {
+                    address: chr_address,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the 8x8 tile at `address` in the pattern tables as rows of color
+    /// indices (0-3), see [`NES::read_chr`]
+    pub fn decode_tile(&self, address: u16) -> [[u8; 8]; 8] {
+        let mut data = [0; 16];
+        self.read_chr(address, &mut data);
+
+        chr_edit::decode_tile(&data)
+    }
+
+    /// write the 8x8 tile `pixels` (color indices 0-3, as rows) at `address`
+    /// in the pattern tables, see [`NES::write_chr`]
+    pub fn encode_tile(
+        &mut self,
+        address: u16,
+        pixels: &[[u8; 8]; 8],
+    ) -> Result<(), ChrWriteError> {
+        self.write_chr(address, &chr_edit::encode_tile(pixels))
+    }
+
+    /// the sprite in OAM `oam_slot` (0-63) rendered with the current pattern
+    /// table, CHR banks and its palette, as a flat RGBA buffer of `8x8` or
+    /// `8x16` pixels depending on the sprite size, transparent pixels are
+    /// `[0, 0, 0, 0]`
+    pub fn dump_sprite_pixels(&self, oam_slot: u8) -> Vec<u8> {
+        self.with_mapper_state_restored(|| self.ppu.borrow().sprite_pixels(oam_slot))
+    }
+
+    /// render the nametable `index` (0-3) with the current CHR banks,
+    /// attributes and palettes into `buffer` as RGB without allocating,
+    /// `buffer` must be at least `256 * 240 * 3` bytes
+    pub fn render_nametable_to_buffer(&self, index: u8, buffer: &mut [u8]) {
+        self.with_mapper_state_restored(|| self.ppu.borrow().render_nametable(index, buffer));
+    }
+
+    /// like [`NES::render_nametable_to_buffer`] but returns a new buffer
+    pub fn dump_nametable(&self, index: u8) -> Vec<u8> {
+        let mut buffer = vec![0; TV_WIDTH * TV_HEIGHT * 3];
+        self.render_nametable_to_buffer(index, &mut buffer);
+
+        buffer
+    }
+
+    /// RGBA overlay of the frame size (`256 * 240 * 4` bytes) marking the
+    /// background tiles on screen whose tile index matches `predicate` with a
+    /// semi-transparent color, the rest is `[0, 0, 0, 0]`, useful to see the
+    /// tiles a game uses for collision, the tiles are placed with the scroll
+    /// of the top of the frame, so split screens are not followed
+    pub fn debug_highlight_tiles(&self, predicate: impl Fn(u8) -> bool) -> Vec<u8> {
+        self.with_mapper_state_restored(|| self.ppu.borrow().highlight_tiles(predicate))
+    }
+
+    /// the state of the DMC channel sample playback
+    pub fn dmc_status(&self) -> DmcStatus {
+        self.apu.borrow().dmc_status()
+    }
+
+    /// debug override to stop delivering the DMC and APU frame counter IRQs
+    /// to the CPU, both enabled by default, this is on top of the normal
+    /// register behavior (`$4010` and `$4017`), the flags in `$4015` and the
+    /// IRQ callback still see the disabled IRQs
+    pub fn set_apu_irq_enabled(&mut self, dmc: bool, frame: bool) {
+        self.cpu.bus_mut().set_apu_irq_enabled(dmc, frame);
+    }
+
+    /// connect a device to the CPU bus in `0x4020-0x5FFF`, it is checked
+    /// before the cartridge for reads and writes, and is clocked every CPU
+    /// cycle
+    pub fn set_expansion_device(&mut self, device: Box<dyn ExpansionDevice>) {
+        self.cpu.bus_mut().set_expansion_device(Some(device));
+    }
+
+    /// disconnect the expansion device and return it
+    pub fn take_expansion_device(&mut self) -> Option<Box<dyn ExpansionDevice>> {
+        let bus = self.cpu.bus_mut();
+        let device = bus.expansion_device.get_mut().take();
+        bus.set_expansion_device(None);
+
+        device
+    }
+
+    /// set a function to be called on every CPU write that reaches the
+    /// cartridge (`0x4020-0xFFFF`), the arguments are the address and value
+    pub fn set_mapper_write_log(&mut self, f: Box<dyn FnMut(u16, u8)>) {
+        self.cpu.bus_mut().set_mapper_write_log(Some(f));
+    }
+
+    pub fn clear_mapper_write_log(&mut self) {
+        self.cpu.bus_mut().set_mapper_write_log(None);
+    }
+
+    /// only draw every `(frame_skip + 1)`th frame into the screen buffer, to
+    /// save the pixel compositing cost on slow hosts, the skipped frames are
+    /// still emulated fully (and the audio is not affected), `0` draws every
+    /// frame
+    pub fn set_frame_skip(&mut self, frame_skip: u8) {
+        self.ppu.borrow_mut().set_frame_skip(frame_skip);
+    }
+
+    /// set how much of the PPU is emulated, [`VideoEmulation::TimingOnly`]
+    /// is for audio-only use (like recording the music of a game), it keeps
+    /// the exact timing, the `$2002` flags, the NMI and the mapper accesses
+    /// (like the MMC3 scanline IRQ), but does not produce pixels, so the
+    /// screen buffer keeps the last frame drawn before the switch (or blank)
+    ///
+    /// Sprite 0 hit is still computed from the pattern data, and `$2007`
+    /// accesses are not affected, so games run the same and the audio is
+    /// identical to [`VideoEmulation::Full`], only the displayed output and
+    /// the overlays of [`NES::set_debug_overlay`] are missing. The default
+    /// is [`VideoEmulation::Full`], and the mode is not part of the save
+    /// state
+    pub fn set_video_emulation(&mut self, mode: VideoEmulation) {
+        self.ppu.borrow_mut().set_video_emulation(mode);
+    }
+
+    /// disable rendering the background regardless of the `$2001` writes of
+    /// the game, useful for capturing the sprites layer alone, the setting is
+    /// kept across frames and resets until enabled again
+    pub fn set_render_background(&mut self, enabled: bool) {
+        self.ppu.borrow_mut().set_render_background(enabled);
+    }
+
+    /// disable rendering the sprites regardless of the `$2001` writes of the
+    /// game, useful for capturing the background layer alone, the setting is
+    /// kept across frames and resets until enabled again
+    pub fn set_render_sprites(&mut self, enabled: bool) {
+        self.ppu.borrow_mut().set_render_sprites(enabled);
+    }
+
+    /// draw diagnostic coloring on top of the displayed frame (attribute
+    /// areas, CHR banks or sprite outlines), the emulation, the save states
+    /// and the frame hash of [`NES::run_input_script`] are not affected
+    pub fn set_debug_overlay(&mut self, overlay: DebugOverlay) {
+        self.ppu.borrow_mut().set_debug_overlay(overlay);
+    }
+
+    /// set the TV region of the console, the APU DMC rates and the real time
+    /// duration of frames ([`NES::frame_duration_nanos`] and the pacing of
+    /// [`NES::run`]) depend on it, the emulated CPU and PPU timing (cycles
+    /// per frame and scanlines) is still NTSC
+    pub fn set_region(&mut self, region: TvRegion) {
+        self.region = region;
+        self.apu.borrow_mut().set_region(region);
+    }
+
+    pub fn region(&self) -> TvRegion {
+        self.region
+    }
+
+    /// switch between the default approximate timing and the cycle accurate
+    /// one, which models:
+    /// - the halt and odd/even alignment cycles of OAM DMA (513 or 514 cycles)
+    /// - DMC DMA stalls of 4 cycles, or 2 when it happens during OAM DMA
+    /// - the decay of the PPU I/O latch returned when reading write-only
+    ///   registers (and the low bits of `$2002`)
+    /// - sampling the IRQ line at the end of the instruction, and polling
+    ///   before the interrupt flag change of `CLI`, `SEI` and `PLP`
+    pub fn enable_timing_mode(&mut self, timing_mode: TimingMode) {
+        self.timing_mode = timing_mode;
+        self.cpu.set_timing_mode(timing_mode);
+        self.ppu
+            .borrow_mut()
+            .set_open_bus_decay(timing_mode == TimingMode::CycleAccurate);
+    }
+
+    pub fn timing_mode(&self) -> TimingMode {
+        self.timing_mode
+    }
+
+    /// take the audio samples generated since the last call, the samples are
+    /// stereo interleaved (L, R, L, R, ...) at
+    /// [`SAMPLE_RATE`](crate::nes_audio::SAMPLE_RATE)
+    pub fn take_audio_buffer(&mut self) -> Vec<f32> {
+        self.apu.borrow_mut().take_audio_buffer()
+    }
+
+    /// the samples that [`NES::take_audio_buffer`] would return, without
+    /// removing them, so checking them followed by a take returns the same
+    /// samples, they are copied into a buffer kept by the console since the
+    /// APU is shared with the CPU bus
+    pub fn audio_buffer_ref(&mut self) -> &[f32] {
+        self.peeked_audio_buffer.clear();
+        self.peeked_audio_buffer
+            .extend_from_slice(self.apu.borrow().audio_buffer());
+
+        &self.peeked_audio_buffer
+    }
+
+    /// same as [`NES::audio_buffer_ref`]
+    #[deprecated(note = "use `NES::audio_buffer_ref` instead")]
+    pub fn peek_audio_buffer(&mut self) -> &[f32] {
+        self.audio_buffer_ref()
+    }
+
+    /// the number of stereo sample pairs waiting to be taken by
+    /// [`NES::take_audio_buffer`], can be used to know if the audio output is
+    /// falling behind
+    pub fn audio_latency(&self) -> usize {
+        self.apu.borrow().audio_buffer().len() / 2
+    }
+
+    /// limit the samples kept for [`NES::take_audio_buffer`] (and the other
+    /// pull functions) to `ms` milliseconds, default is 300ms. If they are not
+    /// taken fast enough, the oldest samples are dropped and counted in
+    /// [`NES::audio_overrun_count`]. The audio played by the emulator in
+    /// [`NES::run`] is not affected. The limit and the count are kept on
+    /// [`NES::reset`], [`NES::power_cycle`] and [`NES::load_rom`]
+    pub fn set_max_audio_buffer_ms(&mut self, ms: u32) {
+        self.apu.borrow_mut().set_max_audio_buffer_ms(ms);
+    }
+
+    /// number of samples (per channel) dropped from the audio buffer because
+    /// it was full, see [`NES::set_max_audio_buffer_ms`]
+    pub fn audio_overrun_count(&self) -> u64 {
+        self.apu.borrow().dropped_audio_samples()
+    }
+
+    /// same as [`NES::take_audio_buffer`], but downmixed to mono (the average
+    /// of each left and right pair)
+    pub fn take_audio_mono(&mut self) -> Vec<f32> {
+        self.apu
+            .borrow_mut()
+            .take_audio_buffer()
+            .chunks_exact(2)
+            .map(|pair| (pair[0] + pair[1]) / 2.)
+            .collect()
+    }
+
+    /// same as [`NES::take_audio_buffer`], but the samples are linearly
+    /// interpolated to `target_hz`, consecutive calls produce a continuous
+    /// signal, so they should all use the same `target_hz`
+    pub fn take_audio_buffer_resampled(&mut self, target_hz: u32) -> Vec<f32> {
+        let samples = self.apu.borrow_mut().take_audio_buffer();
+
+        self.audio_resampler
+            .resample(&samples, SAMPLE_RATE, target_hz)
+    }
+
+    /// the number of CPU cycles between two audio samples, around
+    /// `CPU_FREQ / SAMPLE_RATE`, the samples are generated on the first
+    /// cycle where the fractional counter reaches it, so an external
+    /// scheduler can use [`NES::audio_samples_for_cycles`] to know exactly
+    /// how many samples the next cycles produce
+    ///
+    /// in [`NES::run`], the rate is adjusted slightly to follow the audio
+    /// device, otherwise it is constant
+    pub fn cpu_cycles_per_audio_sample(&self) -> f64 {
+        self.apu.borrow().cycles_per_sample()
+    }
+
+    /// the number of audio samples (per channel) that running the next
+    /// `cycles` CPU cycles (with [`NES::clock_cycles`] or any other clock
+    /// function) generates, taking into account the part of the current
+    /// sample already elapsed
+    pub fn audio_samples_for_cycles(&self, cycles: u64) -> u64 {
+        self.apu.borrow().samples_for_cycles(cycles)
+    }
+
+    /// the peak and RMS of the audio output (per channel) over the last
+    /// frame completed by the PPU, it is measured when the samples are
+    /// generated, so it does not depend on how (or if) the audio is consumed
+    pub fn audio_levels(&self) -> AudioLevels {
+        self.apu.borrow().audio_levels()
+    }
+
+    /// number of consecutive frames where the peak of the audio output was
+    /// below the silence threshold, see [`NES::set_silence_threshold`]
+    pub fn silent_frames(&self) -> u32 {
+        self.apu.borrow().silent_frames()
+    }
+
+    /// set the peak level (default `0.001`) below which a frame is counted
+    /// as silent in [`NES::silent_frames`], the samples are in `0.0-1.0`
+    /// and the threshold is kept on [`NES::reset`], [`NES::power_cycle`] and
+    /// [`NES::load_rom`]
+    pub fn set_silence_threshold(&mut self, threshold: f32) {
+        self.apu.borrow_mut().set_silence_threshold(threshold);
+    }
+
+    /// set the stereo position of `channel` in the audio output, from `-1.0`
+    /// (full left) to `1.0` (full right), default is `0.0` (center). The
+    /// settings are kept on [`NES::reset`] and when loading a state
+    ///
+    /// The pan and [`NES::set_channel_volume`] are applied to the channels
+    /// before the nonlinear mixer of the NES, which is then run for each
+    /// side separately, so a channel alone on one side is slightly louder
+    /// relative to the others than when it is centered. With the defaults,
+    /// both sides are exactly the mono output of the NES
+    pub fn set_channel_pan(&mut self, channel: APUChannelKind, pan: f32) {
+        self.apu.borrow_mut().set_channel_pan(channel, pan);
+    }
+
+    /// set the gain of `channel` in the audio output, default is `1.0`, `0.0`
+    /// mutes the channel, see [`NES::set_channel_pan`]
+    pub fn set_channel_volume(&mut self, channel: APUChannelKind, gain: f32) {
+        self.apu.borrow_mut().set_channel_volume(channel, gain);
+    }
+
+    /// the duration of one frame in real time for the current region, this
+    /// is the time to wait between frames when running at normal speed, the
+    /// same period [`FrameLimiter`] paces the frames at (around 60.0988 Hz
+    /// for NTSC and 50.0070 Hz for PAL and Dendy)
+    pub fn frame_duration_nanos(&self) -> u64 {
+        FrameLimiter::region_frame_period(self.region).as_nanos() as u64
+    }
+
+    /// enable or disable collecting warnings about accesses to features
+    /// that are not emulated, disabled by default
+    pub fn set_compat_warnings(&mut self, enabled: bool) {
+        self.compat_warnings_enabled = enabled;
+        self.cpu
+            .bus()
+            .compat_warnings
+            .borrow_mut()
+            .set_enabled(enabled);
+    }
+
+    /// take all the collected compatibility warnings since the last call
+    pub fn take_compat_warnings(&mut self) -> Vec<CompatWarning> {
+        self.cpu.bus().compat_warnings.borrow_mut().take()
+    }
+
+    /// enable or disable logging the CHR bank switches done by writes to the
+    /// mapper registers, to check that the banks are switched at the right
+    /// time relative to the PPU scanlines, disabled by default, as the banks
+    /// are compared before and after every write to the cartridge
+    pub fn enable_chr_switch_logging(&mut self, enabled: bool) {
+        self.chr_switch_logging = enabled;
+        self.cartridge.borrow_mut().set_chr_switch_logging(enabled);
+    }
+
+    /// take the CHR bank switches logged since the last call, in order, one
+    /// event for every 1KB slot that changed
+    pub fn take_chr_switch_events(&mut self) -> Vec<ChrSwitchEvent> {
+        self.cartridge.borrow_mut().take_chr_switch_events()
+    }
+
+    /// run one CPU cycle (and 3 PPU cycles)
+    pub fn clock(&mut self) {
+        self.run_cpu_cycle();
+    }
+
+    /// run exactly `cycles` CPU cycles (the APU is clocked once per CPU
+    /// cycle), and return the number of audio samples (per channel)
+    /// generated, which is the same as [`NES::audio_samples_for_cycles`]
+    /// called before
+    pub fn clock_cycles(&mut self, cycles: u64) -> u64 {
+        let start_samples = self.apu.borrow().generated_audio_samples();
+
+        for _ in 0..cycles {
+            self.run_cpu_cycle();
+        }
+
+        self.apu.borrow().generated_audio_samples() - start_samples
+    }
+
+    /// run the emulation for one full frame, stops early if the CPU is
+    /// jammed (see [`NES::is_jammed`])
+    pub fn clock_for_frame(&mut self) -> FrameSummary {
+        let start = self.begin_frame_stats();
+
+        for _ in 0..CPU_CYCLES_PER_FRAME {
+            if self.cpu.is_jammed() {
+                break;
+            }
+            self.run_cpu_cycle();
+        }
+
+        self.end_frame_stats(start)
+    }
+
+    /// run the emulation from the current PPU position until the end of the
+    /// visible frame (dot 0 of scanline 240), and return the CPU cycles run,
+    /// useful after stepping with [`NES::clock`], if the PPU is already on
+    /// scanline 240 it runs until the end of the next frame
+    ///
+    /// the PPU runs 3 dots per CPU cycle, so it stops on one of the first 3
+    /// dots of scanline 240
+    pub fn clock_until_frame_complete(&mut self) -> u64 {
+        let start_cycles = self.cpu_cycles;
+        let mut last_scanline = self.ppu_scanline();
+
+        loop {
+            self.run_cpu_cycle();
+
+            let scanline = self.ppu_scanline();
+            if scanline == 240 && last_scanline != 240 {
+                break;
+            }
+            last_scanline = scanline;
+        }
+
+        self.cpu_cycles - start_cycles
+    }
+
+    /// run the emulation until the CPU is about to fetch an instruction with
+    /// the `opcode` byte (for example `0x00` for `BRK`), so the PC points to
+    /// it, and return the CPU cycles run, at least one cycle is run, so
+    /// calling it again finds the next one
+    ///
+    /// returns [`TimeoutError`] if it is not reached after `timeout_cycles`
+    /// cycles, or the CPU is jammed. The opcode is checked between
+    /// instructions, so an interrupt that is pending at that point still
+    /// runs before it.
+    pub fn clock_until_opcode(
+        &mut self,
+        opcode: u8,
+        timeout_cycles: u64,
+    ) -> Result<u64, TimeoutError> {
+        let start_cycles = self.cpu_cycles;
+
+        while self.cpu_cycles - start_cycles < timeout_cycles && !self.cpu.is_jammed() {
+            self.run_cpu_cycle();
+
+            if let Some(pc) = self.cpu.next_instruction_pc() {
+                if self.cpu.bus().peek(pc) == opcode {
+                    return Ok(self.cpu_cycles - start_cycles);
+                }
+            }
+        }
+
+        Err(TimeoutError {
+            cycles: self.cpu_cycles - start_cycles,
+        })
+    }
+
+    /// wait for the next frame deadline of `limiter`, then run the emulation
+    /// for one full frame, for frontends without their own clock
+    pub fn clock_for_frame_paced(&mut self, limiter: &mut FrameLimiter) -> FrameSummary {
+        limiter.wait_for_next_frame();
+        self.clock_for_frame()
+    }
+
+    /// same as [`NES::clock_for_frame`], but returns the addresses of all the
+    /// instructions executed in the frame in order
+    pub fn clock_for_frame_traced(&mut self) -> Vec<u16> {
+        // most instructions take 2-4 cycles, so this is enough to not
+        // reallocate in most frames
+        let mut trace = Vec::with_capacity(CPU_CYCLES_PER_FRAME / 2);
+
+        let start = self.begin_frame_stats();
+
+        for _ in 0..CPU_CYCLES_PER_FRAME {
+            if self.cpu.is_jammed() {
+                break;
+            }
+            self.run_cpu_cycle();
+
+            if let Some(pc) = self.cpu.fetched_instruction_pc() {
+                trace.push(pc);
+            }
+        }
+
+        self.end_frame_stats(start);
+
+        trace
+    }
+
+    /// enable or disable collecting [`FrameStats`] in
+    /// [`NES::clock_for_frame`], disabled by default, as measuring the time
+    /// of every part slows down the emulation
+    pub fn set_stats_enabled(&mut self, enabled: bool) {
+        self.stats.set_enabled(enabled);
+    }
+
+    /// the stats of the last frame run by [`NES::clock_for_frame`], all zeros
+    /// if the stats are disabled
+    pub fn frame_stats(&self) -> FrameStats {
+        self.stats.last()
+    }
+
+    /// returns the CPU cycles and generated audio samples at the start of
+    /// the frame, to be passed to [`NES::end_frame_stats`]
+    fn begin_frame_stats(&mut self) -> (u64, u64) {
+        let generated_samples = self.apu.borrow().generated_audio_samples();
+        self.stats.begin_frame(generated_samples);
+
+        (self.cpu_cycles, generated_samples)
+    }
+
+    fn end_frame_stats(&mut self, (start_cycles, start_samples): (u64, u64)) -> FrameSummary {
+        let generated_samples = self.apu.borrow().generated_audio_samples();
+        self.stats.end_frame(generated_samples);
+
+        // the frame was consumed by the caller, so the next
+        // `is_frame_complete` is for the next one
+        self.ppu.borrow_mut().take_frame_complete();
+
+        self.frame_count += 1;
+
+        FrameSummary {
+            cycles_elapsed: self.cpu_cycles.saturating_sub(start_cycles),
+            audio_samples_produced: (generated_samples - start_samples) as usize,
+            total_frames: self.frame_count,
+        }
+    }
+
+    /// the number of frames run by [`NES::clock_for_frame`] (and its
+    /// variants) since the last reset
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// is the last frame drawn by the PPU different from the one before it,
+    /// checked while the pixels are written, so frontends encoding video can
+    /// skip duplicate frames without comparing the screen buffers.
+    ///
+    /// The first frame, the first frame after loading a state and after
+    /// changing the video filter, gamma or debug overlay are always
+    /// reported as changed, and frames that are not drawn (frame skip) are
+    /// not
+    pub fn frame_changed(&self) -> bool {
+        self.ppu.borrow().frame_changed()
+    }
+
+    /// incremented for every frame where [`NES::frame_changed`] is `true`,
+    /// never reset
+    pub fn frame_generation(&self) -> u64 {
+        self.ppu.borrow().frame_generation()
+    }
+
+    /// the single step used by all the clocking functions, so they produce
+    /// the same results, the order of the components in one CPU cycle is:
+    /// 1. APU
+    /// 2. CPU (including DMA)
+    /// 3. mapper and expansion device CPU cycle counters
+    /// 4. PPU for 3 dots (scanline callback after each dot)
+    /// 5. interrupt lines callbacks
+    ///
+    /// changing this order can shift the results of timing sensitive tests
+    fn run_cpu_cycle(&mut self) {
+        if self.compat_warnings_enabled {
+            self.cpu
+                .bus()
+                .compat_warnings
+                .borrow_mut()
+                .set_cpu_cycle(self.cpu_cycles);
+        }
+        if self.chr_switch_logging {
+            self.cartridge
+                .borrow_mut()
+                .set_chr_switch_cycle(self.cpu_cycles);
+        }
+
+        self.stats.start_lap();
+
+        self.apu.borrow_mut().clock();
+        self.stats.lap_apu();
+
+        let cpu_state = self.cpu.run_next();
+        self.cartridge.borrow_mut().cpu_clock();
+        self.cpu.bus_mut().clock_expansion_device(self.cpu_cycles);
+        self.stats.lap_cpu();
+
+        let completed_frames = self.ppu.borrow().completed_frames();
+        self.ppu.borrow_mut().clock_warmup();
+        if let Some(scanline_callback) = &self.scanline_callback {
+            for _ in 0..3 {
+                self.ppu.borrow_mut().clock();
+
+                let ppu = self.ppu.borrow();
+                if ppu.cycle() == 0 {
+                    scanline_callback(ppu.scanline());
+                }
+            }
+        } else {
+            let mut ppu = self.ppu.borrow_mut();
+            ppu.clock();
+            ppu.clock();
+            ppu.clock();
+        }
+        self.stats.lap_ppu();
+
+        // the audio levels are measured over the same frames as the picture
+        if self.ppu.borrow().completed_frames() != completed_frames {
+            self.apu.borrow_mut().end_audio_levels_frame();
+        }
+
+        self.stats.count_cpu_cycle(
+            self.cpu.fetched_instruction_pc().is_some(),
+            cpu_state == CPURunState::DmaTransfere,
+        );
+
+        self.cpu_cycles += 1;
+
+        self.check_interrupt_lines();
+    }
+
+    /// call the interrupt callbacks if any of the interrupt lines went high
+    fn check_interrupt_lines(&mut self) {
+        let nmi_pin = self.ppu.borrow().is_nmi_pin_set();
+        if nmi_pin && !self.last_nmi_pin {
+            self.stats.count_nmi();
+
+            if let Some(callback) = &self.nmi_callback {
+                callback(self.cpu_cycles);
+            }
+        }
+        self.last_nmi_pin = nmi_pin;
+
+        let (apu_irq_pin, dmc_irq_pin) = {
+            let apu = self.apu.borrow();
+            (apu.frame_irq_pin_state(), apu.dmc_irq_pin_state())
+        };
+        let mapper_irq_pin = {
+            let cartridge = self.cartridge.borrow();
+            cartridge.is_irq_change_requested() && cartridge.irq_pin_state()
+        };
+
+        let expansion_irq_pin = self.cpu.bus().expansion_device_irq();
+
+        let irq_edges = [
+            (IrqSource::Apu, apu_irq_pin && !self.last_apu_irq_pin),
+            (IrqSource::Dmc, dmc_irq_pin && !self.last_dmc_irq_pin),
+            (
+                IrqSource::Mapper,
+                mapper_irq_pin && !self.last_mapper_irq_pin,
+            ),
+            (
+                IrqSource::Expansion,
+                expansion_irq_pin && !self.last_expansion_irq_pin,
+            ),
+        ];
+
+        for (source, asserted) in irq_edges {
+            if asserted {
+                self.stats.count_irq();
+
+                if let Some(callback) = &self.irq_callback {
+                    callback(source, self.cpu_cycles);
+                }
+            }
+        }
+        self.last_apu_irq_pin = apu_irq_pin;
+        self.last_dmc_irq_pin = dmc_irq_pin;
+        self.last_mapper_irq_pin = mapper_irq_pin;
+        self.last_expansion_irq_pin = expansion_irq_pin;
+    }
+
+    /// the current content of the screen in the format of the UI provider
+    #[cfg(test)]
+    pub(crate) fn image(&self) -> Vec<u8> {
+        self.image.lock().unwrap().clone()
+    }
+
+    /// provide 8K of PRG RAM in `0x6000-0x7FFF` even if the cartridge does not
+    /// have one, this is not accurate, but some games need it to not get
+    /// stuck, default is `false`
+    pub fn force_prg_ram(&mut self, force: bool) {
+        self.force_prg_ram = force;
+        self.cartridge.borrow_mut().set_force_prg_ram(force);
+    }
+
+    /// set the post-processing filter applied on every frame after it is
+    /// completed by the PPU
+    pub fn set_video_filter(&mut self, filter: VideoFilter) {
+        self.ppu.borrow_mut().set_video_filter(filter);
+    }
+
+    /// apply a gamma curve on the final output colors (after the palette,
+    /// emphasis and video filter), values above `1.0` brighten the image,
+    /// the default `1.0` does not change the colors
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.ppu.borrow_mut().set_gamma(gamma);
+    }
+
+    /// generate a new palette from the NTSC decoding `params` and use it
+    /// instead of the default palette
+    pub fn set_palette_params(&mut self, params: PaletteParams) {
+        self.ppu
+            .borrow_mut()
+            .set_palette(Some(generate_palette(params)));
+    }
+
+    fn get_base_save_state_folder(&self) -> Option<PathBuf> {
+        if let Some(proj_dirs) = ProjectDirs::from("Amjad50", "Plastic", "Plastic") {
+            let base_saved_states_dir = proj_dirs.data_local_dir().join("saved_states");
+            // Linux:   /home/../.local/share/plastic/saved_states
+            // Windows: C:\Users\..\AppData\Local\Plastic\Plastic\data\saved_states
+            // macOS:   /Users/../Library/Application Support/Amjad50.Plastic.Plastic/saved_states
+
+            fs::create_dir_all(&base_saved_states_dir).ok()?;
+
+            Some(base_saved_states_dir)
+        } else {
+            None
+        }
+    }
+
+    /// the file name of the save state `slot` of the loaded cartridge,
+    /// `None` if there is no cartridge
+    pub fn save_state_file_name(&self, slot: u8) -> Option<String> {
+        if self.cartridge.borrow().is_empty() {
+            return None;
+        }
+
+        let cartridge = self.cartridge.borrow();
+        let stem = cartridge.cartridge_path().file_stem()?.to_string_lossy();
+
+        Some(format!("{}_{}.pst", stem, slot))
+    }
+
+    fn get_save_state_file_path(&self, slot: u8) -> Option<Box<Path>> {
+        let file_name = self.save_state_file_name(slot)?;
+
+        self.get_base_save_state_folder()
+            .map(|base_saved_states_dir| base_saved_states_dir.join(file_name).into_boxed_path())
+    }
+
+    fn get_present_save_states(&self) -> Option<Vec<u8>> {
+        if self.cartridge.borrow().is_empty() {
+            return None;
+        }
+
+        let cartridge_path = self.cartridge.borrow().cartridge_path().to_path_buf();
+
+        if let Some(base_saved_states_dir) = self.get_base_save_state_folder() {
+            let saved_states_files_regex = Regex::new(&format!(
+                r"^{}_(\d*)\.pst$",
+                regex::escape(&cartridge_path.file_stem().unwrap().to_string_lossy()),
+            ))
+            .ok()?;
+
+            Some(
+                fs::read_dir(base_saved_states_dir)
+                    .ok()?
+                    .filter_map(|path| {
+                        if path.as_ref().ok()?.file_type().ok()?.is_file() {
+                            Some(path.ok()?.path())
+                        } else {
+                            None
+                        }
+                    })
+                    .filter_map(|path| {
+                        Some(
+                            saved_states_files_regex
+                                .captures(path.file_name()?.to_str()?)?
+                                .get(1)?
+                                .as_str()
+                                .parse::<u8>()
+                                .ok()?,
+                        )
+                    })
+                    .collect::<Vec<u8>>(),
+            )
+        } else {
+            None
+        }
+    }
+
+    fn save_state_to<W: std::io::Write>(&self, writer: &mut W) -> Result<(), SaveError> {
+        self.cartridge.borrow().save(writer)?;
+        self.cpu.save(writer)?;
+        self.ppu.borrow().save(writer)?;
+        self.apu.borrow().save(writer)?;
+        self.cpu.bus().contoller.save(writer)?;
+        self.cpu.bus().contoller_2.save(writer)?;
+
+        // the expansion device is only saved if it supports it
+        let device_state = self
+            .cpu
+            .bus()
+            .expansion_device
+            .borrow()
+            .as_ref()
+            .and_then(|device| device.save_state());
+        if let Some(device_state) = device_state {
+            writer.write_all(&(device_state.len() as u32).to_le_bytes())?;
+            writer.write_all(&device_state)?;
+        }
+
+        Ok(())
+    }
+
+    /// the state of the expansion device at the end of `state`, after the
+    /// components, which are read into scratch ones, `None` if it was not
+    /// saved, the block is checked before loading anything into the console
+    fn expansion_device_state<'a>(&self, state: &'a [u8]) -> Result<Option<&'a [u8]>, SaveError> {
+        let reader = &mut &state[..];
+
+        self.read_state_snapshot(reader)?;
+        APU2A03::new().load(reader)?;
+        Controller::new().load(reader)?;
+        Controller::new().load(reader)?;
+
+        let rest = *reader;
+        if rest.is_empty() {
+            return Ok(None);
+        }
+
+        if self.cpu.bus().expansion_device.borrow().is_none() || rest.len() < 4 {
+            return Err(SaveError::CorruptedData);
+        }
+
+        let length = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        if rest.len() - 4 != length as usize {
+            return Err(SaveError::CorruptedData);
+        }
+
+        Ok(Some(&rest[4..]))
+    }
+
+    fn load_state_from(&mut self, state: &[u8]) -> Result<(), SaveError> {
+        let device_state = self.expansion_device_state(state)?;
+
+        let reader = &mut &state[..];
+        self.cartridge.borrow_mut().load(reader)?;
+        self.cpu.load(reader)?;
+        self.ppu.borrow_mut().load(reader)?;
+        self.apu.borrow_mut().load(reader)?;
+        self.apu.borrow_mut().set_region(self.region);
+        self.cpu.bus_mut().contoller.load(reader)?;
+        self.cpu.bus_mut().contoller_2.load(reader)?;
+
+        if let Some(device_state) = device_state {
+            if let Some(device) = self.cpu.bus().expansion_device.borrow_mut().as_mut() {
+                device.load_state(device_state);
+            }
+        }
+
+        if !self.paused {
+            self.apu.borrow().play();
+        }
+
+        Ok(())
+    }
+
+    /// save the state of the emulator into a buffer (uncompressed), this is
+    /// the same data saved by [`NES::save_state_uncompressed`]
+    pub fn save_state_bytes(&self) -> Result<Vec<u8>, SaveError> {
+        let mut data = Vec::new();
+        self.save_state_to(&mut data)?;
+
+        Ok(data)
+    }
+
+    /// load a state saved by [`NES::save_state_bytes`], compressed states are
+    /// detected and decompressed, truncated states are
+    /// [`SaveError::CorruptedData`]
+    pub fn load_state_bytes(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        let state = save_state::decompress_state(data)?;
+
+        // reading from memory only fails if the data ends early
+        self.load_state_from(&state).map_err(|err| match err {
+            SaveError::IoError(_) => SaveError::CorruptedData,
+            err => err,
+        })
+    }
+
+    fn save_state_file(&self, slot: u8, compressed: bool) -> Result<(), SaveError> {
+        if let Some(path) = self.get_save_state_file_path(slot) {
+            let state = self.save_state_bytes()?;
+
+            let data = if compressed {
+                save_state::compress_state(&state)
+            } else {
+                state
+            };
+
+            atomic_file::write_atomic(&path, &data)?;
+
+            Ok(())
+        } else {
+            Err(SaveError::Others)
+        }
+    }
+
+    /// save the state into `slot` file compressed
+    pub fn save_state(&self, slot: u8) -> Result<(), SaveError> {
+        self.save_state_file(slot, true)
+    }
+
+    /// save the state into `slot` file without compression
+    pub fn save_state_uncompressed(&self, slot: u8) -> Result<(), SaveError> {
+        self.save_state_file(slot, false)
+    }
+
+    /// save the state compressed into the file of `slot` in `dir`, the file is
+    /// replaced atomically, so the old state is kept if saving fails midway,
+    /// returns the path of the file
+    pub fn save_state_to_slot_file(&self, dir: &Path, slot: u8) -> Result<PathBuf, SaveError> {
+        let path = dir.join(self.save_state_file_name(slot).ok_or(SaveError::Others)?);
+        let state = save_state::compress_state(&self.save_state_bytes()?);
+
+        atomic_file::write_atomic(&path, &state)?;
+
+        Ok(path)
+    }
+
+    /// load the state saved by [`NES::save_state_to_slot_file`] from the file
+    /// of `slot` in `dir`, file errors are [`SaveError::IoError`]
+    pub fn load_state_from_slot_file(&mut self, dir: &Path, slot: u8) -> Result<(), SaveError> {
+        let path = dir.join(self.save_state_file_name(slot).ok_or(SaveError::Others)?);
+        let data = fs::read(path)?;
+
+        self.load_state_bytes(&data)
+    }
+
+    pub fn load_state(&mut self, slot: u8) -> Result<(), SaveError> {
+        if let Some(path) = self.get_save_state_file_path(slot) {
+            if path.exists() {
+                let data = fs::read(path)?;
+
+                self.load_state_bytes(&data)
+            } else {
+                Err(SaveError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
                     "save file not found",
                 )))
             }
@@ -430,11 +2239,71 @@ impl<P: UiProvider + Send + 'static> NES<P> {
         }
     }
 
+    /// compute the changes between the current state and `previous` (saved by
+    /// [`NES::save_state_bytes`]), this is much smaller than a full state
+    pub fn state_delta(&self, previous: &[u8]) -> Result<Vec<u8>, SaveError> {
+        let current = self.save_state_bytes()?;
+
+        Ok(save_state::compute_delta(previous, &current))
+    }
+
+    /// load the state resulting from applying `delta` (from
+    /// [`NES::state_delta`]) to `base`, malformed deltas return
+    /// [`SaveError::CorruptedData`]
+    pub fn apply_delta(&mut self, base: &[u8], delta: &[u8]) -> Result<(), SaveError> {
+        let state = save_state::apply_delta(base, delta)?;
+
+        self.load_state_bytes(&state)
+    }
+
+    /// read the parts of `state` (saved by [`NES::save_state_bytes`]) that
+    /// are compared by [`NES::diff_states`] into scratch components, the
+    /// console is not changed
+    fn state_snapshot(&self, state: &[u8]) -> Result<StateSnapshot, SaveError> {
+        let state = save_state::decompress_state(state)?;
+
+        self.read_state_snapshot(&mut state.as_ref())
+    }
+
+    /// read the components of a state up to the PPU into scratch ones, see
+    /// [`NES::state_snapshot`]
+    fn read_state_snapshot<R: Read>(&self, reader: &mut R) -> Result<StateSnapshot, SaveError> {
+        let mapper_state = self.cartridge.borrow().mapper_state_in_state(reader)?;
+        let cpu_registers = CPU6502::<CPUBus>::registers_in_state(reader)?.to_vec();
+        let ram = self.cpu.bus().ram_in_state(reader)?;
+
+        let tv = TV::new(P::get_tv_color_converter());
+        let mut ppu = PPU2C02::new(PPUBus::new(self.cartridge.clone()), tv);
+        ppu.load(reader)?;
+
+        Ok(StateSnapshot {
+            cpu_registers,
+            ram,
+            ppu_registers: ppu.registers().to_vec(),
+            mapper_state,
+        })
+    }
+
+    /// compare two states saved by [`NES::save_state_bytes`] for the current
+    /// game, the states are read into scratch components, so the console is
+    /// not changed
+    pub fn diff_states(&self, state_a: &[u8], state_b: &[u8]) -> Result<StateDiff, SaveError> {
+        let snapshot = |state| {
+            // reading from memory only fails if the data ends early
+            self.state_snapshot(state).map_err(|err| match err {
+                SaveError::IoError(_) => SaveError::CorruptedData,
+                err => err,
+            })
+        };
+
+        Ok(snapshot(state_a)?.diff(&snapshot(state_b)?))
+    }
+
     /// calculate a new view based on the window size
     pub fn run(&mut self) {
         let image = self.image.clone();
         let ctrl_state = self.ctrl_state.clone();
-        let mut frame_limiter = FrameLimiter::new(60);
+        let mut frame_limiter = FrameLimiter::new(self.region);
 
         let (ui_to_nes_sender, ui_to_nes_receiver) = channel::<UiEvent>();
         let (nes_to_ui_sender, nes_to_ui_receiver) = channel::<BackendEvent>();
@@ -453,8 +2322,6 @@ impl<P: UiProvider + Send + 'static> NES<P> {
 
         self.cpu.reset();
 
-        const N: usize = 29780; // number of CPU cycles per loop, one full frame
-
         // just a way to duplicate code, its not meant to be efficient way to do it
         // I used this, since `self` cannot be referenced here and anywhere else at
         // the same time.
@@ -494,10 +2361,7 @@ impl<P: UiProvider + Send + 'static> NES<P> {
                     }
 
                     UiEvent::LoadRom(file_location) => {
-                        let cartridge = Cartridge::from_file(file_location);
-                        if let Ok(cartridge) = cartridge {
-                            self.cartridge.replace(cartridge);
-                            self.reset();
+                        if self.load_rom(&file_location).is_ok() {
                             handle_apu_after_reset!();
                         } else {
                             println!("This game is not supported yet");
@@ -539,24 +2403,13 @@ impl<P: UiProvider + Send + 'static> NES<P> {
 
             if self.paused {
                 std::thread::sleep(std::time::Duration::from_millis(50));
+                frame_limiter.reset();
                 continue;
             }
 
-            if frame_limiter.begin() {
-                for _ in 0..N {
-                    self.apu.borrow_mut().clock();
-
-                    self.cpu.run_next();
-                    {
-                        let mut ppu = self.ppu.borrow_mut();
-                        ppu.clock();
-                        ppu.clock();
-                        ppu.clock();
-                    }
-                }
-
-                frame_limiter.end();
-            }
+            self.clock_for_frame_paced(&mut frame_limiter);
+            // the audio is played by the APU directly
+            self.apu.borrow_mut().clear_audio_buffer();
         }
 
         ui_thread_handler.join().unwrap();
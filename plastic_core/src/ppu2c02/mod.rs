@@ -4,6 +4,11 @@ mod ppu2c02_registers;
 mod sprite;
 mod vram;
 
+mod tests;
+
 pub use palette::Palette;
+#[cfg(feature = "debugger")]
+pub use ppu2c02::PpuInternalState;
 pub use ppu2c02::PPU2C02;
+pub use ppu2c02::{DebugOverlay, PpuScroll, VideoEmulation};
 pub use vram::VRam;
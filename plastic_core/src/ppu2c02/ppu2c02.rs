@@ -5,12 +5,20 @@ use crate::common::{
     save_state::{Savable, SaveError},
     Bus, Device,
 };
-use crate::display::{Color, COLORS, TV};
+use crate::display::{Color, VideoFilter, COLORS, GENERATED_PALETTE_SIZE, TV, TV_HEIGHT, TV_WIDTH};
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 use std::cell::Cell;
 use std::cmp::min;
 
+/// number of CPU cycles after power-on where writes to `$2000`, `$2001`,
+/// `$2005` and `$2006` are ignored
+pub const PPU_WARMUP_CPU_CYCLES: u32 = 29658;
+
+/// number of frames (about 600ms) after which the I/O latch of the PPU decays
+/// to 0 if it was not refreshed
+pub const IO_LATCH_DECAY_FRAMES: u8 = 36;
+
 bitflags! {
     pub struct ControlReg: u8 {
         const BASE_NAMETABLE = 0b00000011;
@@ -106,6 +114,111 @@ bitflags! {
     }
 }
 
+/// The internal latches of the PPU which are not readable through the
+/// registers, used to import/export the exact state for comparing with other
+/// emulators
+#[cfg(feature = "debugger")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PpuInternalState {
+    /// the current VRAM address (15 bits)
+    pub v: u16,
+    /// the temporary VRAM address (15 bits)
+    pub t: u16,
+    /// fine X scroll (3 bits)
+    pub x: u8,
+    /// the write toggle of `$2005` and `$2006`
+    pub w: bool,
+    pub oam_addr: u8,
+    /// the buffer of `$2007` reads
+    pub read_buffer: u8,
+}
+
+/// The scroll position decoded from the internal `v` and `x` registers, see
+/// [`NES::ppu_scroll`](crate::nes::NES::ppu_scroll)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PpuScroll {
+    /// the current VRAM address (15 bits)
+    pub v: u16,
+    /// the temporary VRAM address (15 bits), where `$2005` and `$2006`
+    /// writes go before being copied to `v`
+    pub t: u16,
+    /// coarse X scroll (0-31), in tiles
+    pub coarse_x: u8,
+    /// coarse Y scroll (0-31), in tiles
+    pub coarse_y: u8,
+    /// fine X scroll (0-7), in pixels
+    pub fine_x: u8,
+    /// fine Y scroll (0-7), in pixels
+    pub fine_y: u8,
+    /// the nametable select bits (0-3)
+    pub nametable: u8,
+}
+
+/// Diagnostic coloring drawn on top of the frame, see
+/// [`NES::set_debug_overlay`](crate::nes::NES::set_debug_overlay)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugOverlay {
+    #[default]
+    None,
+    /// tint the background pixels by their 16x16 attribute area (the
+    /// quadrant of the attribute byte)
+    AttributeGrid,
+    /// tint the background pixels by the 1KB CHR bank their tile pattern is
+    /// mapped from
+    BankColor,
+    /// draw a 1 pixel outline around every rendered sprite
+    SpriteBoxes,
+}
+
+/// How much of the PPU is emulated, see
+/// [`NES::set_video_emulation`](crate::nes::NES::set_video_emulation)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VideoEmulation {
+    /// emulate the timing and produce the pixels of every frame
+    #[default]
+    Full,
+    /// emulate the timing, the status flags (including sprite 0 hit), the
+    /// NMI and the mapper accesses, but do not produce any pixels
+    TimingOnly,
+}
+
+/// colors used by the tints of [`DebugOverlay`]
+const OVERLAY_TINTS: [Color; 8] = [
+    Color { r: 255, g: 0, b: 0 },
+    Color { r: 0, g: 255, b: 0 },
+    Color { r: 0, g: 0, b: 255 },
+    Color {
+        r: 255,
+        g: 255,
+        b: 0,
+    },
+    Color {
+        r: 255,
+        g: 0,
+        b: 255,
+    },
+    Color {
+        r: 0,
+        g: 255,
+        b: 255,
+    },
+    Color {
+        r: 255,
+        g: 128,
+        b: 0,
+    },
+    Color {
+        r: 128,
+        g: 0,
+        b: 255,
+    },
+];
+const OVERLAY_SPRITE_BOX: Color = Color {
+    r: 255,
+    g: 0,
+    b: 255,
+};
+
 pub struct PPU2C02<T: Bus + Savable> {
     // memory mapped registers
     reg_control: ControlReg,
@@ -126,6 +239,14 @@ pub struct PPU2C02<T: Bus + Savable> {
 
     ppu_data_read_buffer: Cell<u8>,
 
+    /// bit 12 of the last pattern table address put on the bus, the mapper
+    /// is signaled on its rising edges, not part of the save state
+    address_bus_a12: Cell<bool>,
+    /// set while the debug views read the memory, as these reads are not
+    /// done by the PPU hardware, they must not change `address_bus_a12` or
+    /// clock the mapper
+    debug_reading: Cell<bool>,
+
     fine_x_scroll: u8,
 
     w_toggle: Cell<bool>, // this is used for registers that require 2 writes
@@ -139,19 +260,67 @@ pub struct PPU2C02<T: Bus + Savable> {
     bus: T,
     tv: TV,
 
+    /// palette used instead of [`COLORS`] if set, it contains all emphasis
+    /// combinations, indexed by `emphasis << 6 | color`
+    palette: Option<Box<[Color; GENERATED_PALETTE_SIZE]>>,
+
     primary_oam: [Sprite; 64],
     secondary_oam: [Sprite; 8],
     rendering_oam: [Sprite; 8],
 
     rendering_oam_counter: u8,
+    /// the row of each sprite in `rendering_oam` on the current scanline
+    rendering_oam_rows: [u8; 8],
 
     sprite_0_present: bool,
     next_scanline_sprite_0_present: bool,
 
+    /// for each visible scanline, bit `n` is set if the sprite `n` in OAM is
+    /// in range of it, even if it was dropped because of the 8 sprites limit
+    oam_search_results: [u64; 240],
+    /// `oam_search_results` of the last rendered frame
+    last_oam_search_results: [u64; 240],
+
     is_dma_request: bool,
     dma_request_address: u8,
 
     is_odd_frame: bool,
+    /// set when the scanline wraps from 261 to 0, cleared by
+    /// [`PPU2C02::take_frame_complete`]
+    frame_complete: bool,
+    /// incremented with `frame_complete`, but never cleared, so the emulator
+    /// can check for the end of a frame without taking the flag
+    completed_frames: u64,
+
+    /// CPU cycles remaining until the PPU accepts writes to `$2000`,
+    /// `$2001`, `$2005` and `$2006`, only set on power-on, not part of the
+    /// save state
+    ppu_warmup_cycles_remaining: u32,
+    /// if `false`, [`PPU2C02::power_on`] does not start the warm-up period
+    warmup_enabled: bool,
+
+    /// `$2001` bits forced off by [`PPU2C02::set_render_background`] and
+    /// [`PPU2C02::set_render_sprites`], not part of the save state
+    mask_disabled_bits: u8,
+
+    /// not part of the save state
+    debug_overlay: DebugOverlay,
+    /// the attribute area (0-3) and the CHR bank of the two background tiles
+    /// in the shift registers, the first is the older tile, only updated
+    /// when `debug_overlay` is enabled
+    bg_tiles_overlay_info: [(u8, Option<u16>); 2],
+
+    /// number of frames to skip compositing after every rendered frame
+    frame_skip: u8,
+    /// number of frames since the last composited frame
+    skipped_frames: u8,
+    /// not part of the save state
+    video_emulation: VideoEmulation,
+
+    open_bus_decay: bool,
+    io_latch: Cell<u8>,
+    /// number of frames since the last refresh of `io_latch`
+    io_latch_age: Cell<u8>,
 }
 
 impl<T> PPU2C02<T>
@@ -174,6 +343,9 @@ where
 
             ppu_data_read_buffer: Cell::new(0),
 
+            address_bus_a12: Cell::new(false),
+            debug_reading: Cell::new(false),
+
             fine_x_scroll: 0,
 
             w_toggle: Cell::new(false),
@@ -187,24 +359,46 @@ where
             bus,
             tv,
 
+            palette: None,
+
             primary_oam: [Sprite::empty(); 64],
             secondary_oam: [Sprite::empty(); 8],
             rendering_oam: [Sprite::empty(); 8],
 
             rendering_oam_counter: 0,
+            rendering_oam_rows: [0; 8],
 
             sprite_0_present: false,
             next_scanline_sprite_0_present: false,
 
+            oam_search_results: [0; 240],
+            last_oam_search_results: [0; 240],
+
             is_dma_request: false,
             dma_request_address: 0,
 
             is_odd_frame: false,
+            frame_complete: false,
+            completed_frames: 0,
+
+            ppu_warmup_cycles_remaining: 0,
+            warmup_enabled: true,
+
+            mask_disabled_bits: 0,
+            debug_overlay: DebugOverlay::None,
+            bg_tiles_overlay_info: [(0, None); 2],
+            frame_skip: 0,
+            skipped_frames: 0,
+            video_emulation: VideoEmulation::Full,
+
+            open_bus_decay: false,
+            io_latch: Cell::new(0),
+            io_latch_age: Cell::new(0),
         }
     }
 
     pub(crate) fn read_register(&self, register: Register) -> u8 {
-        match register {
+        let result = match register {
             Register::Status => {
                 // reset w_mode
                 self.w_toggle.set(false);
@@ -258,15 +452,64 @@ where
             }
             _ => {
                 // unreadable
+                if self.open_bus_decay {
+                    return self.io_latch.get();
+                }
                 0
             }
+        };
+
+        if !self.open_bus_decay {
+            return result;
+        }
+
+        // reading refreshes the I/O latch, but `$2002` only drives the top
+        // 3 bits and the rest come from the latch
+        let result = if matches!(register, Register::Status) {
+            result & 0xE0 | self.io_latch.get() & 0x1F
+        } else {
+            result
+        };
+        self.refresh_io_latch(result);
+
+        result
+    }
+
+    /// the value a CPU read of `register` would return, without changing
+    /// any state (the `w` toggle, the read buffer, `v`, ...)
+    pub(crate) fn peek_register(&self, register: Register) -> u8 {
+        let latch = if self.open_bus_decay {
+            self.io_latch.get()
+        } else {
+            0
+        };
+
+        match register {
+            Register::Status => self.reg_status.get().bits & 0xE0 | latch & 0x1F,
+            Register::OmaData => self.read_sprite_byte(self.reg_oam_addr.get()),
+            Register::PPUData => {
+                let address = self.vram_address_cur.get();
+                if address <= 0x3EFF {
+                    self.ppu_data_read_buffer.get()
+                } else {
+                    // palette reads are not buffered, and have no side effects
+                    self.read_bus(address)
+                }
+            }
+            _ => latch,
         }
     }
 
     pub(crate) fn write_register(&mut self, register: Register, data: u8) {
+        if self.open_bus_decay {
+            self.refresh_io_latch(data);
+        }
+
         match register {
-            // After power/reset, writes to this register are ignored for about 30,000 cycles
-            // TODO: not sure, if I should account for that
+            // After power-on, writes to these registers are ignored for about
+            // 30,000 cycles (not after reset)
+            Register::Control | Register::Mask | Register::Scroll | Register::PPUAddress
+                if self.ppu_warmup_cycles_remaining > 0 => {}
             Register::Control => {
                 self.reg_control.bits = data;
 
@@ -301,7 +544,7 @@ where
             Register::OmaAddress => self.reg_oam_addr.set(data),
             Register::OmaData => {
                 self.write_sprite_byte(self.reg_oam_addr.get(), data);
-                if self.scanline > 240 || !self.reg_mask.rendering_enabled() {
+                if self.scanline > 240 || !self.mask().rendering_enabled() {
                     *self.reg_oam_addr.get_mut() = self.reg_oam_addr.get().wrapping_add(1);
                 }
             }
@@ -360,19 +603,460 @@ where
         };
     }
 
+    pub fn set_video_filter(&mut self, filter: VideoFilter) {
+        self.tv.set_video_filter(filter);
+    }
+
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.tv.set_gamma(gamma);
+    }
+
+    /// only composite the pixels of every `(frame_skip + 1)`th frame, the
+    /// other frames are still run for the timing and the status flags (like
+    /// sprite 0 hit), but the screen buffer is not updated
+    pub fn set_frame_skip(&mut self, frame_skip: u8) {
+        self.frame_skip = frame_skip;
+        // the rest of the current frame is skipped, and the next is drawn
+        self.skipped_frames = frame_skip;
+    }
+
+    /// in [`VideoEmulation::TimingOnly`] every frame is skipped like
+    /// [`PPU2C02::set_frame_skip`], so the screen buffer keeps the last
+    /// composited frame
+    pub fn set_video_emulation(&mut self, mode: VideoEmulation) {
+        self.video_emulation = mode;
+    }
+
+    /// when `false`, the background is not rendered even if the game enables
+    /// it in `$2001`, as if the bit is cleared on every write
+    pub fn set_render_background(&mut self, enabled: bool) {
+        self.set_mask_bit_enabled(MaskReg::SHOW_BACKGROUND, enabled);
+    }
+
+    /// when `false`, the sprites are not rendered even if the game enables
+    /// them in `$2001`, as if the bit is cleared on every write
+    pub fn set_render_sprites(&mut self, enabled: bool) {
+        self.set_mask_bit_enabled(MaskReg::SHOW_SPRITES, enabled);
+    }
+
+    fn set_mask_bit_enabled(&mut self, bit: MaskReg, enabled: bool) {
+        if enabled {
+            self.mask_disabled_bits &= !bit.bits();
+        } else {
+            self.mask_disabled_bits |= bit.bits();
+        }
+    }
+
+    /// draw `overlay` on top of the displayed frame, the frame without the
+    /// overlay is still available in [`PPU2C02::plain_image`]
+    pub fn set_debug_overlay(&mut self, overlay: DebugOverlay) {
+        self.debug_overlay = overlay;
+        self.tv.set_overlay_enabled(overlay != DebugOverlay::None);
+    }
+
+    /// the last frame without the debug overlay, `None` if there is no
+    /// overlay
+    pub fn plain_image(&self) -> Option<Vec<u8>> {
+        self.tv.plain_image().map(|image| image.to_vec())
+    }
+
+    pub fn frame_changed(&self) -> bool {
+        self.tv.frame_changed()
+    }
+
+    pub fn frame_generation(&self) -> u64 {
+        self.tv.frame_generation()
+    }
+
+    /// the `$2001` value written by the CPU with the overridden bits cleared
+    fn mask(&self) -> MaskReg {
+        MaskReg::from_bits_truncate(self.reg_mask.bits() & !self.mask_disabled_bits)
+    }
+
+    fn is_frame_skipped(&self) -> bool {
+        self.skipped_frames != 0 || self.video_emulation == VideoEmulation::TimingOnly
+    }
+
+    /// emulate the I/O latch of the PPU, that is returned when reading
+    /// write-only registers, and decays to 0 if not refreshed for
+    /// [`IO_LATCH_DECAY_FRAMES`] frames, if disabled these reads return 0
+    pub fn set_open_bus_decay(&mut self, enabled: bool) {
+        self.open_bus_decay = enabled;
+        self.refresh_io_latch(0);
+    }
+
+    fn refresh_io_latch(&self, data: u8) {
+        self.io_latch.set(data);
+        self.io_latch_age.set(0);
+    }
+
+    /// start the power-on period where writes to `$2000`, `$2001`, `$2005`
+    /// and `$2006` are ignored, see [`PPU_WARMUP_CPU_CYCLES`], does nothing
+    /// if the warm-up is disabled
+    pub fn start_warmup(&mut self) {
+        if self.warmup_enabled {
+            self.ppu_warmup_cycles_remaining = PPU_WARMUP_CPU_CYCLES;
+        }
+    }
+
+    /// enable or disable the power-on warm-up period (enabled by default),
+    /// disabling it also ends the current one
+    pub fn set_warmup_enabled(&mut self, enabled: bool) {
+        self.warmup_enabled = enabled;
+        if !enabled {
+            self.ppu_warmup_cycles_remaining = 0;
+        }
+    }
+
+    /// should be called on every CPU cycle
+    pub fn clock_warmup(&mut self) {
+        self.ppu_warmup_cycles_remaining = self.ppu_warmup_cycles_remaining.saturating_sub(1);
+    }
+
+    pub fn warmup_cycles_remaining(&self) -> u32 {
+        self.ppu_warmup_cycles_remaining
+    }
+
+    /// set the palette to use (including emphasis), `None` to use the
+    /// default [`COLORS`]
+    pub fn set_palette(&mut self, palette: Option<[Color; GENERATED_PALETTE_SIZE]>) {
+        self.palette = palette.map(Box::new);
+    }
+
+    /// the current scanline, where the pre-render scanline is `-1`
+    pub fn scanline(&self) -> i32 {
+        if self.scanline == 261 {
+            -1
+        } else {
+            self.scanline as i32
+        }
+    }
+
+    /// the current dot (cycle) in the scanline, (0-340)
+    pub fn cycle(&self) -> u16 {
+        self.cycle
+    }
+
+    /// the number of frames completed so far, see
+    /// [`PPU2C02::take_frame_complete`]
+    pub fn completed_frames(&self) -> u64 {
+        self.completed_frames
+    }
+
+    /// returns `true` if a frame was completed since the last call, and
+    /// clears the flag
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
+    /// the content of the status register, without the side effects of
+    /// reading it from the CPU
+    pub fn status(&self) -> u8 {
+        self.reg_status.get().bits()
+    }
+
+    /// the sprite 0 hit flag of the status register
+    pub fn sprite_0_hit(&self) -> bool {
+        self.reg_status.get().contains(StatusReg::SPRITE_0_HIT)
+    }
+
+    /// the sprite overflow flag of the status register
+    pub fn sprite_overflow(&self) -> bool {
+        self.reg_status.get().contains(StatusReg::SPRITE_OVERFLOW)
+    }
+
+    /// the base nametable selected by bits 0-1 of the control register
+    pub fn base_nametable(&self) -> u8 {
+        self.reg_control.nametable_selector()
+    }
+
+    /// the X scroll (0-511) from the internal `v` register, the nametable X
+    /// bit is included, so `256` and above means the right nametable
+    pub fn scroll_x(&self) -> u16 {
+        let nametable_x = (self.vram_address_cur.get() >> 10) & 1;
+
+        nametable_x * 256
+            + self.current_coarse_x_scroll() as u16 * 8
+            + self.current_fine_x_scroll() as u16
+    }
+
+    /// the Y scroll (0-479) from the internal `v` register, the nametable Y
+    /// bit is included, so `240` and above means the bottom nametable
+    pub fn scroll_y(&self) -> u16 {
+        let nametable_y = (self.vram_address_cur.get() >> 11) & 1;
+
+        nametable_y * 240
+            + self.current_coarse_y_scroll() as u16 * 8
+            + self.current_fine_y_scroll() as u16
+    }
+
+    /// decode the scroll position from the internal registers, this does not
+    /// change the write toggle
+    pub fn scroll(&self) -> PpuScroll {
+        PpuScroll {
+            v: self.vram_address_cur.get(),
+            t: self.vram_address_top_left,
+            coarse_x: self.current_coarse_x_scroll(),
+            coarse_y: self.current_coarse_y_scroll(),
+            fine_x: self.current_fine_x_scroll(),
+            fine_y: self.current_fine_y_scroll(),
+            nametable: ((self.vram_address_cur.get() >> 10) & 0b11) as u8,
+        }
+    }
+
+    /// the OAM indices of the sprites in range of `scanline` in the last
+    /// rendered frame, more than 8 means the sprite overflow was triggered
+    pub fn oam_search_result(&self, scanline: i32) -> Vec<u8> {
+        let result = if (0..240).contains(&scanline) {
+            self.last_oam_search_results[scanline as usize]
+        } else {
+            0
+        };
+
+        (0..64).filter(|i| result & (1 << i) != 0).collect()
+    }
+
+    /// the sprite in OAM `oam_slot` as RGBA pixels, `8x8` or `8x16` depending
+    /// on the sprite size, transparent pixels are `[0, 0, 0, 0]`
+    pub fn sprite_pixels(&self, oam_slot: u8) -> Vec<u8> {
+        self.debug_read(|| self.sprite_pixels_inner(oam_slot))
+    }
+
+    fn sprite_pixels_inner(&self, oam_slot: u8) -> Vec<u8> {
+        let sprite = self.primary_oam[(oam_slot & 0x3F) as usize];
+        let attribute = sprite.get_attribute();
+        let height = self.reg_control.sprite_height();
+
+        let mut pixels = Vec::with_capacity(8 * height as usize * 4);
+
+        for row in 0..height {
+            let fine_y = if attribute.is_flip_vertical() {
+                height - 1 - row
+            } else {
+                row
+            };
+
+            let [low, high] = self.fetch_pattern_sprite(sprite.get_tile(), fine_y);
+
+            for x in 0..8 {
+                let bit = if attribute.is_flip_horizontal() {
+                    x
+                } else {
+                    7 - x
+                };
+                let color_bits = (low >> bit) & 1 | ((high >> bit) & 1) << 1;
+
+                if color_bits == 0 {
+                    pixels.extend_from_slice(&[0, 0, 0, 0]);
+                } else {
+                    let color_index =
+                        self.read_bus(0x3F10 | (attribute.palette() << 2 | color_bits) as u16);
+                    let color = self.output_color(color_index);
+
+                    pixels.extend_from_slice(&color.to_rgba());
+                }
+            }
+        }
+
+        pixels
+    }
+
+    /// render the nametable `index` (0-3) with the current background pattern
+    /// table and palettes into `buffer` as RGB, `buffer` must be at least
+    /// `256 * 240 * 3` bytes
+    pub fn render_nametable(&self, index: u8, buffer: &mut [u8]) {
+        assert!(
+            buffer.len() >= TV_WIDTH * TV_HEIGHT * 3,
+            "the nametable buffer is too small"
+        );
+
+        self.debug_read(|| self.render_nametable_inner(index, buffer));
+    }
+
+    fn render_nametable_inner(&self, index: u8, buffer: &mut [u8]) {
+        let nametable = 0x2000 | (index as u16 & 3) << 10;
+        let pattern_table = self.reg_control.background_pattern_address();
+
+        for tile_y in 0..30 {
+            for tile_x in 0..32 {
+                let tile = self.read_bus(nametable | tile_y << 5 | tile_x);
+                let attribute = self.read_bus(nametable | 0x3C0 | (tile_y >> 2) << 3 | tile_x >> 2);
+                // each attribute byte holds the palettes of 4x4 tiles
+                let shift = (tile_y & 2) << 1 | (tile_x & 2);
+                let palette = (attribute >> shift) & 3;
+
+                for fine_y in 0..8 {
+                    let [low, high] = self.fetch_pattern(pattern_table, tile, fine_y);
+
+                    for fine_x in 0..8 {
+                        let bit = 7 - fine_x;
+                        let color_bits = (low >> bit) & 1 | ((high >> bit) & 1) << 1;
+                        let palette_address = if color_bits == 0 {
+                            0x3F00
+                        } else {
+                            0x3F00 | (palette << 2 | color_bits) as u16
+                        };
+                        let color = self.output_color(self.read_bus(palette_address));
+
+                        let x = tile_x as usize * 8 + fine_x as usize;
+                        let y = tile_y as usize * 8 + fine_y as usize;
+                        let index = (y * TV_WIDTH + x) * 3;
+                        buffer[index..index + 3].copy_from_slice(&[color.r, color.g, color.b]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// RGBA overlay of the frame size, where the background tiles with an
+    /// index matching `predicate` are filled with a semi-transparent color and
+    /// the rest is `[0, 0, 0, 0]`, the tiles are placed with the scroll of the
+    /// top left of the frame (`t` and fine X), so mid-frame scroll changes
+    /// are not followed
+    pub fn highlight_tiles(&self, predicate: impl Fn(u8) -> bool) -> Vec<u8> {
+        self.debug_read(|| self.highlight_tiles_inner(predicate))
+    }
+
+    fn highlight_tiles_inner(&self, predicate: impl Fn(u8) -> bool) -> Vec<u8> {
+        const HIGHLIGHT: [u8; 4] = [0xFF, 0x00, 0xFF, 0x80];
+
+        let mut overlay = vec![0; TV_WIDTH * TV_HEIGHT * 4];
+
+        let top_left = self.vram_address_top_left;
+        let start_coarse_x = top_left & 0b11111;
+        let fine_x = self.fine_x_scroll as i32;
+        let fine_y = ((top_left >> 12) & 0b111) as i32;
+
+        let mut coarse_y = (top_left >> 5) & 0b11111;
+        let mut nametable_y = top_left & 0x800;
+
+        // one more row and column for the partial tiles when fine scrolled
+        for row in 0..31 {
+            for column in 0..33 {
+                let coarse_x = start_coarse_x + column;
+                let nametable_x = (top_left ^ (coarse_x & 0x20) << 5) & 0x400;
+                let tile = self.read_bus(
+                    0x2000 | nametable_y | nametable_x | coarse_y << 5 | (coarse_x & 0b11111),
+                );
+
+                if !predicate(tile) {
+                    continue;
+                }
+
+                let left = column as i32 * 8 - fine_x;
+                let top = row * 8 - fine_y;
+                for y in top.max(0)..(top + 8).min(TV_HEIGHT as i32) {
+                    for x in left.max(0)..(left + 8).min(TV_WIDTH as i32) {
+                        let index = (y as usize * TV_WIDTH + x as usize) * 4;
+                        overlay[index..index + 4].copy_from_slice(&HIGHLIGHT);
+                    }
+                }
+            }
+
+            // same as the increment of the rendering, row 29 wraps to the
+            // next nametable, and the attribute rows wrap in the same one
+            match coarse_y {
+                29 => {
+                    coarse_y = 0;
+                    nametable_y ^= 0x800;
+                }
+                31 => coarse_y = 0,
+                _ => coarse_y += 1,
+            }
+        }
+
+        overlay
+    }
+
+    /// the values of the internal registers with their names, used for
+    /// debugging
+    pub fn registers(&self) -> [(&'static str, u16); 10] {
+        [
+            ("PPUCTRL", self.reg_control.bits() as u16),
+            ("PPUMASK", self.reg_mask.bits() as u16),
+            ("PPUSTATUS", self.reg_status.get().bits() as u16),
+            ("OAMADDR", self.reg_oam_addr.get() as u16),
+            ("v", self.vram_address_cur.get()),
+            ("t", self.vram_address_top_left),
+            ("x", self.fine_x_scroll as u16),
+            ("w", self.w_toggle.get() as u16),
+            ("scanline", self.scanline),
+            ("cycle", self.cycle),
+        ]
+    }
+
+    #[cfg(feature = "debugger")]
+    pub fn internal_state(&self) -> PpuInternalState {
+        PpuInternalState {
+            v: self.vram_address_cur.get(),
+            t: self.vram_address_top_left,
+            x: self.fine_x_scroll,
+            w: self.w_toggle.get(),
+            oam_addr: self.reg_oam_addr.get(),
+            read_buffer: self.ppu_data_read_buffer.get(),
+        }
+    }
+
+    /// out of range values of `v`, `t` and `x` are masked
+    #[cfg(feature = "debugger")]
+    pub fn set_internal_state(&mut self, state: PpuInternalState) {
+        self.vram_address_cur.set(state.v & 0x7FFF);
+        self.vram_address_top_left = state.t & 0x7FFF;
+        self.fine_x_scroll = state.x & 0b111;
+        self.w_toggle.set(state.w);
+        self.reg_oam_addr.set(state.oam_addr);
+        self.ppu_data_read_buffer.set(state.read_buffer);
+    }
+
     /// expose the bus for reading only
     pub fn ppu_bus(&self) -> &T {
         &self.bus
     }
 
+    pub fn ppu_bus_mut(&mut self) -> &mut T {
+        &mut self.bus
+    }
+
+    /// run `f` with the bus reads done for the debug views, which do not
+    /// track A12
+    fn debug_read<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.debug_reading.set(true);
+        let result = f();
+        self.debug_reading.set(false);
+
+        result
+    }
+
     fn read_bus(&self, address: u16) -> u8 {
+        self.update_address_bus_a12(address);
         self.bus.read(address, Device::PPU)
     }
 
     fn write_bus(&mut self, address: u16, data: u8) {
+        self.update_address_bus_a12(address);
         self.bus.write(address, data, Device::PPU);
     }
 
+    /// track bit 12 of the addresses put on the bus, and signal the mapper
+    /// when it goes from 0 to 1
+    ///
+    /// only the pattern table addresses (`0x0000-0x1FFF`) are tracked, the
+    /// MMC3 ignores A12 going low for the short nametable and attribute
+    /// fetches between the pattern fetches, so this approximates its filter
+    /// without timing how long A12 stays low
+    fn update_address_bus_a12(&self, address: u16) {
+        if address > 0x1FFF || self.debug_reading.get() {
+            return;
+        }
+
+        let a12 = address & 0x1000 != 0;
+
+        if a12 && !self.address_bus_a12.get() {
+            self.bus.ppu_a12_rising_edge();
+        }
+        self.address_bus_a12.set(a12);
+    }
+
     fn read_sprite_byte(&self, address: u8) -> u8 {
         let sprite_location = address >> 2;
         self.primary_oam[sprite_location as usize].read_offset(address & 0b11)
@@ -520,7 +1204,7 @@ where
 
     fn increment_vram_readwrite(&self) {
         // only increment if its valid, and increment by the correct ammount
-        if self.scanline > 240 || !self.reg_mask.rendering_enabled() {
+        if self.scanline > 240 || !self.mask().rendering_enabled() {
             self.vram_address_cur
                 .set(self.vram_address_cur.get() + self.reg_control.vram_increment());
 
@@ -611,6 +1295,21 @@ where
         // bit-1 is for (top, bottom), bit-0 is for (left, right)
         let palette = (attribute_byte >> (attribute_location * 2)) & 0b11;
 
+        if self.debug_overlay != DebugOverlay::None {
+            let chr_bank = if self.debug_overlay == DebugOverlay::BankColor {
+                let pattern_table = self.reg_control.background_pattern_address();
+                self.bus
+                    .chr_bank(pattern_table | (nametable_tile as u16) << 4)
+            } else {
+                None
+            };
+
+            self.bg_tiles_overlay_info = [
+                self.bg_tiles_overlay_info[1],
+                (attribute_location, chr_bank),
+            ];
+        }
+
         // update th shift registers
         for i in 0..=1 {
             // clear the bottom value
@@ -682,31 +1381,42 @@ where
         self.read_bus(0x2000 | self.current_nametable() << 10 | 0xF << 6 | y << 3 | x)
     }
 
-    fn reload_sprite_shift_registers(&mut self) {
-        // move sprite_0_present
-        self.sprite_0_present = self.next_scanline_sprite_0_present;
-        // reset for scanline after next
-        self.next_scanline_sprite_0_present = false;
+    /// fetch the pattern of the sprite in `slot` of `secondary_oam` for the
+    /// next scanline, the 8 slots are fetched in dots 257-320, one every 8
+    /// dots, even the empty ones (0xFF), as the mapper may count the fetches
+    fn fetch_sprite_slot(&mut self, slot: usize) {
+        if slot == 0 {
+            // move sprite_0_present
+            self.sprite_0_present = self.next_scanline_sprite_0_present;
+            // reset for scanline after next
+            self.next_scanline_sprite_0_present = false;
+        }
 
         let next_y = self.get_next_scroll_y_render();
 
         let sprite_height = self.reg_control.sprite_height();
 
-        // loop through all secondary_oam, even the empty ones (0xFF)
-        // a write to the cartridge MUST be done here even if no sprites
-        // are drawn
-        for i in 0..8 as usize {
-            let mut sprite = self.secondary_oam[i];
-            let mut fine_y = next_y.wrapping_sub(sprite.get_y());
+        let mut sprite = self.secondary_oam[slot];
+        let mut fine_y = next_y.wrapping_sub(sprite.get_y());
+        self.rendering_oam_rows[slot] = fine_y;
 
-            // handle flipping vertically
-            if sprite.get_attribute().is_flip_vertical() {
-                fine_y = (sprite_height - 1).wrapping_sub(fine_y);
-            }
+        // handle flipping vertically
+        if sprite.get_attribute().is_flip_vertical() {
+            fine_y = (sprite_height - 1).wrapping_sub(fine_y);
+        }
 
-            sprite.set_pattern(self.fetch_pattern_sprite(sprite.get_tile(), fine_y));
+        sprite.set_pattern(self.fetch_pattern_sprite(sprite.get_tile(), fine_y));
 
-            self.rendering_oam[i] = sprite;
+        self.rendering_oam[slot] = sprite;
+    }
+
+    /// the sprite slot whose pattern is fetched in the current dot, the
+    /// pattern is read in the 5th dot of the 8 dots of every slot, after the
+    /// two garbage nametable reads
+    fn sprite_fetch_slot(&self) -> Option<usize> {
+        match self.cycle {
+            257..=320 if (self.cycle - 257) % 8 == 4 => Some((self.cycle - 257) as usize / 8),
+            _ => None,
         }
     }
 
@@ -731,8 +1441,8 @@ where
     }
 
     fn get_background_pixel(&self) -> u8 {
-        if !self.reg_mask.background_enabled()
-            || (self.cycle < 8 && self.reg_mask.background_left_clipping_enabled())
+        if !self.mask().background_enabled()
+            || (self.cycle < 8 && self.mask().background_left_clipping_enabled())
         {
             return 0;
         }
@@ -764,8 +1474,8 @@ where
     }
 
     fn get_sprites_first_non_transparent_pixel(&mut self) -> (u8, bool, bool) {
-        if !self.reg_mask.sprites_enabled()
-            || (self.cycle < 8 && self.reg_mask.sprites_left_clipping_enabled())
+        if !self.mask().sprites_enabled()
+            || (self.cycle < 8 && self.mask().sprites_left_clipping_enabled())
             || self.cycle == 255
         {
             return (0, false, false);
@@ -835,9 +1545,9 @@ where
     }
 
     fn emphasis_color(&self, color: Color) -> Color {
-        let is_red_emph = self.reg_mask.intersects(MaskReg::EMPHASIZE_RED);
-        let is_green_emph = self.reg_mask.intersects(MaskReg::EMPHASIZE_GREEN);
-        let is_blue_emph = self.reg_mask.intersects(MaskReg::EMPHASIZE_BLUE);
+        let is_red_emph = self.mask().intersects(MaskReg::EMPHASIZE_RED);
+        let is_green_emph = self.mask().intersects(MaskReg::EMPHASIZE_GREEN);
+        let is_blue_emph = self.mask().intersects(MaskReg::EMPHASIZE_BLUE);
 
         let mut red = 1.;
         let mut green = 1.;
@@ -866,21 +1576,97 @@ where
         }
     }
 
-    fn render_pixel(&mut self) {
+    /// the output color of the palette entry `color`, using the current
+    /// grayscale and emphasis settings
+    fn output_color(&self, color: u8) -> Color {
         // fix overflowing colors
-        let mut color = self.generate_pixel() & 0x3F;
+        let mut color = color & 0x3F;
 
-        if self.reg_mask.is_grayscale() {
+        if self.mask().is_grayscale() {
             // select from the gray column (0x00, 0x10, 0x20, 0x30)
             color &= 0x30;
         }
 
+        if let Some(palette) = &self.palette {
+            let emphasis = (self.reg_mask.bits() >> 5) as usize;
+
+            palette[emphasis << 6 | color as usize]
+        } else {
+            self.emphasis_color(COLORS[color as usize])
+        }
+    }
+
+    fn render_pixel(&mut self) {
+        let color_index = self.generate_pixel();
+        if self.is_frame_skipped() {
+            return;
+        }
+
+        let color = self.output_color(color_index);
+
         // render the color
-        self.tv.set_pixel(
-            self.cycle as u32,
-            self.scanline as u32,
-            &self.emphasis_color(COLORS[color as usize]),
-        );
+        self.tv
+            .set_pixel(self.cycle as u32, self.scanline as u32, &color);
+
+        if self.debug_overlay != DebugOverlay::None {
+            let color = self.overlay_color(color);
+            self.tv
+                .set_overlay_pixel(self.cycle as u32, self.scanline as u32, &color);
+        }
+    }
+
+    /// the color of the current pixel with the debug overlay applied on it
+    fn overlay_color(&self, color: Color) -> Color {
+        let blend = |tint: Color| Color {
+            r: ((color.r as u16 + tint.r as u16) / 2) as u8,
+            g: ((color.g as u16 + tint.g as u16) / 2) as u8,
+            b: ((color.b as u16 + tint.b as u16) / 2) as u8,
+        };
+
+        match self.debug_overlay {
+            DebugOverlay::None => color,
+            DebugOverlay::AttributeGrid | DebugOverlay::BankColor => {
+                if !self.mask().background_enabled() {
+                    return color;
+                }
+
+                // the pixel is from the older tile until the shift registers
+                // are shifted past it, they are reloaded every 8 cycles
+                let shifts = self.current_fine_x_scroll() as u16 + self.cycle % 8;
+                let (attribute_location, chr_bank) =
+                    self.bg_tiles_overlay_info[(shifts >= 8) as usize];
+
+                if self.debug_overlay == DebugOverlay::AttributeGrid {
+                    blend(OVERLAY_TINTS[attribute_location as usize])
+                } else if let Some(chr_bank) = chr_bank {
+                    blend(OVERLAY_TINTS[chr_bank as usize % OVERLAY_TINTS.len()])
+                } else {
+                    color
+                }
+            }
+            DebugOverlay::SpriteBoxes => {
+                if !self.mask().sprites_enabled() {
+                    return color;
+                }
+
+                let last_row = self.reg_control.sprite_height() - 1;
+                let is_outline = self
+                    .rendering_oam
+                    .iter()
+                    .zip(self.rendering_oam_rows.iter())
+                    .take(self.rendering_oam_counter as usize)
+                    .any(|(sprite, &row)| {
+                        let x = self.cycle.wrapping_sub(sprite.get_x() as u16);
+                        x < 8 && (x == 0 || x == 7 || row == 0 || row == last_row)
+                    });
+
+                if is_outline {
+                    OVERLAY_SPRITE_BOX
+                } else {
+                    color
+                }
+            }
+        }
     }
 
     // run one cycle, this should be fed from Master clock
@@ -894,16 +1680,22 @@ where
                 self.reg_status.get_mut().remove(StatusReg::SPRITE_0_HIT)
             }
             (261, 2) => {
+                // clear v-blank, `$2002` reads see it cleared one clock after
+                // the NMI can no longer be triggered, source: tests
+                // (`03-vbl_clear_time` and `07-nmi_on_timing`)
+                self.reg_status.get_mut().remove(StatusReg::VERTICAL_BLANK);
+
                 // reset nmi_occured_in_this_frame
                 self.nmi_occured_in_this_frame.set(false);
             }
             (261, 1) => {
                 // clear sprite overflow
                 self.reg_status.get_mut().remove(StatusReg::SPRITE_OVERFLOW);
-                // clear v-blank
-                self.reg_status.get_mut().remove(StatusReg::VERTICAL_BLANK);
+                // the v-blank period is over for the NMI, enabling it in
+                // `$2000` now does not trigger one
+                self.nmi_occured_in_this_frame.set(true);
 
-                if self.reg_mask.rendering_enabled() {
+                if self.mask().rendering_enabled() {
                     self.restore_rendering_scroll_x();
                     self.restore_rendering_scroll_y();
 
@@ -924,20 +1716,28 @@ where
                     }
                 }
             }
-            (261, 257) => {
-                // reload all of them in one go
-                self.reload_sprite_shift_registers();
+            (261, 257..=320) if self.mask().rendering_enabled() => {
+                if let Some(slot) = self.sprite_fetch_slot() {
+                    self.fetch_sprite_slot(slot);
+                }
             }
             (0..=239, _) => {
                 // render only if allowed
-                if self.reg_mask.rendering_enabled() {
+                if self.mask().rendering_enabled() {
                     self.run_render_cycle();
                 }
             }
             (240, 1) => {
                 // post-render
                 // idle
-                self.tv.signal_end_of_frame();
+                if self.is_frame_skipped() {
+                    self.tv.signal_skipped_frame();
+                } else {
+                    self.tv.signal_end_of_frame();
+                }
+
+                self.last_oam_search_results = self.oam_search_results;
+                self.oam_search_results = [0; 240];
             }
             (241, 1) => {
                 // set v-blank
@@ -957,7 +1757,7 @@ where
             || (self.scanline == 261
                 && self.cycle == 340
                 && self.is_odd_frame
-                && self.reg_mask.rendering_enabled())
+                && self.mask().rendering_enabled())
         {
             self.scanline += 1;
             self.cycle = 0;
@@ -966,6 +1766,23 @@ where
             if self.scanline > 261 {
                 self.scanline = 0;
                 self.is_odd_frame = !self.is_odd_frame;
+                self.frame_complete = true;
+                self.completed_frames += 1;
+
+                self.skipped_frames = if self.skipped_frames >= self.frame_skip {
+                    0
+                } else {
+                    self.skipped_frames + 1
+                };
+
+                if self.open_bus_decay {
+                    let age = self.io_latch_age.get() + 1;
+                    if age >= IO_LATCH_DECAY_FRAMES {
+                        self.refresh_io_latch(0);
+                    } else {
+                        self.io_latch_age.set(age);
+                    }
+                }
             }
         }
     }
@@ -1004,15 +1821,19 @@ where
                     if diff >= 0 && diff < height {
                         // in range
 
+                        // stored by the scanline doing the evaluation (same as
+                        // the overflow flag), the sprite is rendered on the next one
+                        self.oam_search_results[self.scanline as usize] |= 1 << i;
+
                         // sprite 0
                         if i == 0 {
                             self.next_scanline_sprite_0_present = true;
                         }
 
                         if counter > 7 {
-                            // overflow
+                            // overflow, continue only to fill `oam_search_results`
                             self.reg_status.get_mut().insert(StatusReg::SPRITE_OVERFLOW);
-                            break;
+                            continue;
                         }
 
                         self.secondary_oam[counter] = *sprite;
@@ -1029,9 +1850,10 @@ where
                 // to fix nametable wrapping around
                 self.restore_nametable_horizontal();
             }
-            258 => {
-                // reload them all in one go
-                self.reload_sprite_shift_registers();
+            258..=320 => {
+                if let Some(slot) = self.sprite_fetch_slot() {
+                    self.fetch_sprite_slot(slot);
+                }
             }
             321 => {
                 // load next 2 bytes
@@ -1058,17 +1880,34 @@ where
         }
     }
 
-    pub fn reset(&mut self, bus: T) {
+    /// the state after turning the console on, `bus` should be in its
+    /// power-on state as well, this starts the warm-up period if enabled
+    pub fn power_on(&mut self, bus: T) {
+        self.reset();
+
+        // the vblank and sprite overflow flags are often set at power-on
+        self.reg_status = Cell::new(StatusReg::VERTICAL_BLANK | StatusReg::SPRITE_OVERFLOW);
+        self.reg_oam_addr = Cell::new(0);
+        self.vram_address_cur = Cell::new(0);
+        self.primary_oam = [Sprite::empty(); 64];
+
+        self.bus = bus;
+
+        self.ppu_warmup_cycles_remaining = 0;
+        self.start_warmup();
+    }
+
+    /// the state after pressing the RESET button, `$2003`, `$2006` (the
+    /// current VRAM address), OAM, the status flags and the bus (VRAM and
+    /// palette) keep their values
+    pub fn reset(&mut self) {
         // just as if calling the constructor but without TV, just reset it
         self.reg_control = ControlReg::empty();
         self.reg_mask = MaskReg::empty();
-        self.reg_status = Cell::new(StatusReg::empty());
-        self.reg_oam_addr = Cell::new(0);
 
         self.scanline = 0; // start from -1 scanline
         self.cycle = 0;
 
-        self.vram_address_cur = Cell::new(0);
         self.vram_address_top_left = 0;
 
         self.ppu_data_read_buffer = Cell::new(0);
@@ -1083,9 +1922,6 @@ where
         self.nmi_pin_status = Cell::new(false);
         self.nmi_occured_in_this_frame = Cell::new(false);
 
-        self.bus = bus;
-
-        self.primary_oam = [Sprite::empty(); 64];
         self.secondary_oam = [Sprite::empty(); 8];
         self.rendering_oam = [Sprite::empty(); 8];
 
@@ -1094,10 +1930,16 @@ where
         self.sprite_0_present = false;
         self.next_scanline_sprite_0_present = false;
 
+        self.oam_search_results = [0; 240];
+        self.last_oam_search_results = [0; 240];
+
         self.is_dma_request = false;
         self.dma_request_address = 0;
 
         self.is_odd_frame = false;
+        self.frame_complete = false;
+
+        self.skipped_frames = self.frame_skip;
 
         self.tv.reset();
     }
@@ -1129,6 +1971,7 @@ where
         self.is_dma_request = state.is_dma_request;
         self.dma_request_address = state.dma_request_address;
         self.is_odd_frame = state.is_odd_frame;
+        self.ppu_warmup_cycles_remaining = 0;
     }
 }
 
@@ -1269,6 +2112,7 @@ impl<T: Bus + Savable> Savable for PPU2C02<T> {
             })?;
 
         self.load_serialized_state(state);
+        self.tv.force_frame_changed();
 
         Ok(())
     }
@@ -16,6 +16,21 @@ memory_mapped_registers! {
     }
 }
 
+impl<T> PPU2C02<T>
+where
+    T: Bus + Savable,
+{
+    /// the value a CPU read from `address` would return, without the side
+    /// effects of reading the register
+    pub(crate) fn peek(&self, address: u16) -> u8 {
+        if let Ok(register) = address.try_into() {
+            self.peek_register(register)
+        } else {
+            unreachable!("Bus address mapping should be handled correctly (PPU Memory I/O)");
+        }
+    }
+}
+
 impl<T> Bus for PPU2C02<T>
 where
     T: Bus + Savable,
@@ -59,6 +59,10 @@ impl Sprite {
         }
     }
 
+    pub fn get_x(&self) -> u8 {
+        self.x
+    }
+
     pub fn get_y(&self) -> u8 {
         self.y
     }
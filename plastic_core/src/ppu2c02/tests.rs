@@ -0,0 +1,600 @@
+#[cfg(test)]
+mod vram_tests {
+    use super::super::VRam;
+    use crate::common::{Bus, Device, MirroringMode, MirroringProvider, NametableTarget};
+    use std::{cell::RefCell, rc::Rc};
+
+    /// vertical mirroring, with nametable 3 mapped to cartridge RAM and
+    /// nametable 2 forced to the first VRAM page
+    struct MockNametableCartridge {
+        ram: [u8; 0x400],
+    }
+
+    impl MirroringProvider for MockNametableCartridge {
+        fn mirroring_mode(&self) -> MirroringMode {
+            MirroringMode::Vertical
+        }
+
+        fn map_nametable(&self, address: u16) -> NametableTarget {
+            match address & 0xC00 {
+                0x800 => NametableTarget::InternalVramPage(0),
+                0xC00 => NametableTarget::CartridgeMemory(address as usize & 0x3FF),
+                _ => NametableTarget::Default,
+            }
+        }
+
+        fn read_nametable_memory(&self, offset: usize) -> u8 {
+            self.ram[offset]
+        }
+
+        fn write_nametable_memory(&mut self, offset: usize, data: u8) {
+            self.ram[offset] = data;
+        }
+    }
+
+    #[test]
+    fn cartridge_nametable_mapping() {
+        let cartridge = Rc::new(RefCell::new(MockNametableCartridge { ram: [0; 0x400] }));
+        let mut vram = VRam::new(cartridge.clone());
+
+        vram.write(0x2C05, 0x12, Device::PPU);
+        assert_eq!(cartridge.borrow().ram[5], 0x12);
+        assert_eq!(vram.read(0x2C05, Device::PPU), 0x12);
+        // the console VRAM is not touched
+        assert_eq!(vram.read(0x2405, Device::PPU), 0);
+
+        // nametable 2 is mapped to VRAM page 0 instead of page 0 by
+        // vertical mirroring (same page, but through a different path)
+        vram.write(0x2010, 0x34, Device::PPU);
+        assert_eq!(vram.read(0x2810, Device::PPU), 0x34);
+
+        // default path uses the mirroring mode
+        vram.write(0x2420, 0x56, Device::PPU);
+        assert_eq!(vram.read(0x2420, Device::PPU), 0x56);
+        assert_eq!(vram.read(0x2020, Device::PPU), 0);
+        assert_eq!(cartridge.borrow().ram[0x20], 0);
+    }
+}
+
+#[cfg(test)]
+mod ppu_tests {
+    #[cfg(feature = "debugger")]
+    use super::super::ppu2c02::PpuInternalState;
+    use super::super::{
+        ppu2c02::{IO_LATCH_DECAY_FRAMES, PPU_WARMUP_CPU_CYCLES},
+        ppu2c02_registers::Register,
+        PPU2C02,
+    };
+    use crate::common::{
+        save_state::{Savable, SaveError},
+        Bus, Device,
+    };
+    use crate::display::{COLORS, TV};
+    use std::cell::Cell;
+
+    /// flat PPU memory filled with a pattern, so that every tile and
+    /// scroll position looks different
+    struct PatternBus {
+        memory: Vec<u8>,
+        a12_rising_edges: Cell<u32>,
+    }
+
+    impl PatternBus {
+        fn new() -> Self {
+            Self {
+                memory: (0..0x4000u32)
+                    .map(|i| (i.wrapping_mul(37) ^ (i >> 5)) as u8)
+                    .collect(),
+                a12_rising_edges: Cell::new(0),
+            }
+        }
+    }
+
+    impl Bus for PatternBus {
+        fn read(&self, address: u16, _: Device) -> u8 {
+            self.memory[address as usize & 0x3FFF]
+        }
+
+        fn write(&mut self, address: u16, data: u8, _: Device) {
+            self.memory[address as usize & 0x3FFF] = data;
+        }
+
+        fn ppu_a12_rising_edge(&self) {
+            self.a12_rising_edges.set(self.a12_rising_edges.get() + 1);
+        }
+    }
+
+    impl Savable for PatternBus {
+        fn save<W: std::io::Write>(&self, _: &mut W) -> Result<(), SaveError> {
+            unreachable!()
+        }
+
+        fn load<R: std::io::Read>(&mut self, _: &mut R) -> Result<(), SaveError> {
+            unreachable!()
+        }
+    }
+
+    /// create a PPU with rendering enabled, and run `setup` before the
+    /// first frame, returns the image of the second frame
+    #[cfg(feature = "debugger")]
+    fn render<F: FnOnce(&mut PPU2C02<PatternBus>)>(setup: F) -> Vec<u8> {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let image = tv.get_image_clone();
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        ppu.write_register(Register::Mask, 0x1E);
+        setup(&mut ppu);
+
+        for _ in 0..341 * 262 * 2 {
+            ppu.clock();
+        }
+
+        let image = image.lock().unwrap().clone();
+        image
+    }
+
+    #[test]
+    #[cfg(feature = "debugger")]
+    fn internal_state_matches_register_writes() {
+        let expected = PpuInternalState {
+            v: 0,
+            // fine Y 6, nametable 1, coarse Y 11, coarse X 5
+            t: 6 << 12 | 1 << 10 | 11 << 5 | 5,
+            x: 5,
+            w: false,
+            oam_addr: 0,
+            read_buffer: 0,
+        };
+
+        let from_registers = render(|ppu| {
+            ppu.write_register(Register::Control, 0x01);
+            ppu.write_register(Register::Scroll, 0x2D);
+            ppu.write_register(Register::Scroll, 0x5E);
+
+            assert_eq!(ppu.internal_state(), expected);
+        });
+
+        let from_state = render(|ppu| {
+            ppu.write_register(Register::Control, 0x01);
+            ppu.set_internal_state(expected);
+        });
+
+        let unscrolled = render(|_| {});
+
+        assert!(from_registers == from_state);
+        assert!(from_registers != unscrolled);
+    }
+
+    #[test]
+    #[cfg(feature = "debugger")]
+    fn internal_state_masked() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        ppu.set_internal_state(PpuInternalState {
+            v: 0xFFFF,
+            t: 0xFFFF,
+            x: 0xFF,
+            w: true,
+            oam_addr: 0x12,
+            read_buffer: 0x34,
+        });
+
+        assert_eq!(
+            ppu.internal_state(),
+            PpuInternalState {
+                v: 0x7FFF,
+                t: 0x7FFF,
+                x: 7,
+                w: true,
+                oam_addr: 0x12,
+                read_buffer: 0x34,
+            }
+        );
+    }
+
+    #[test]
+    fn oam_search_result_includes_overflow() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        // sprites 0-9 on Y 50, sprite 20 on Y 100, and the rest hidden
+        ppu.write_register(Register::OmaAddress, 0);
+        for i in 0..64 {
+            let y = match i {
+                0..=9 => 50,
+                20 => 100,
+                _ => 0xF0,
+            };
+
+            for &byte in &[y, 0, 0, i * 4] {
+                ppu.write_register(Register::OmaData, byte);
+            }
+        }
+        // 8x16 sprites
+        ppu.write_register(Register::Control, 0x20);
+        ppu.write_register(Register::Mask, 0x1E);
+
+        for _ in 0..341 * 262 * 2 {
+            ppu.clock();
+        }
+
+        let overflowed = (0..10).collect::<Vec<u8>>();
+        assert_eq!(ppu.oam_search_result(50), overflowed);
+        assert_eq!(ppu.oam_search_result(65), overflowed);
+        assert!(ppu.oam_search_result(49).is_empty());
+        assert!(ppu.oam_search_result(66).is_empty());
+        assert_eq!(ppu.oam_search_result(100), vec![20]);
+        assert_eq!(ppu.oam_search_result(115), vec![20]);
+
+        assert!(ppu.oam_search_result(-1).is_empty());
+        assert!(ppu.oam_search_result(240).is_empty());
+    }
+
+    #[test]
+    fn warmup_ignores_register_writes() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        ppu.start_warmup();
+        ppu.write_register(Register::Control, 0x03);
+        ppu.write_register(Register::Mask, 0x1E);
+        ppu.write_register(Register::Scroll, 0x07);
+        ppu.write_register(Register::PPUAddress, 0x21);
+        // other registers are not affected
+        ppu.write_register(Register::OmaAddress, 0x40);
+
+        let register = |ppu: &PPU2C02<PatternBus>, name| {
+            ppu.registers()
+                .iter()
+                .find(|(register, _)| *register == name)
+                .unwrap()
+                .1
+        };
+
+        assert_eq!(register(&ppu, "PPUCTRL"), 0);
+        assert_eq!(register(&ppu, "PPUMASK"), 0);
+        assert_eq!(register(&ppu, "x"), 0);
+        assert_eq!(register(&ppu, "t"), 0);
+        assert_eq!(register(&ppu, "w"), 0);
+        assert_eq!(register(&ppu, "OAMADDR"), 0x40);
+
+        for _ in 0..PPU_WARMUP_CPU_CYCLES - 1 {
+            ppu.clock_warmup();
+        }
+        assert_eq!(ppu.warmup_cycles_remaining(), 1);
+        ppu.write_register(Register::Control, 0x03);
+        assert_eq!(register(&ppu, "PPUCTRL"), 0);
+
+        ppu.clock_warmup();
+        assert_eq!(ppu.warmup_cycles_remaining(), 0);
+        ppu.write_register(Register::Control, 0x03);
+        ppu.write_register(Register::Mask, 0x1E);
+        assert_eq!(register(&ppu, "PPUCTRL"), 3);
+        assert_eq!(register(&ppu, "PPUMASK"), 0x1E);
+
+        ppu.start_warmup();
+        ppu.set_warmup_enabled(false);
+        assert_eq!(ppu.warmup_cycles_remaining(), 0);
+        ppu.start_warmup();
+        assert_eq!(ppu.warmup_cycles_remaining(), 0);
+    }
+
+    #[test]
+    fn open_bus_decay() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        ppu.write_register(Register::Scroll, 0xA5);
+        assert_eq!(ppu.read_register(Register::Control), 0);
+
+        ppu.set_open_bus_decay(true);
+        ppu.write_register(Register::Scroll, 0xA5);
+        assert_eq!(ppu.read_register(Register::Control), 0xA5);
+        // only the low bits of the status come from the latch, and reading
+        // it refreshes the latch
+        assert_eq!(ppu.read_register(Register::Status) & 0x1F, 0x05);
+        assert_eq!(ppu.read_register(Register::Mask) & 0x1F, 0x05);
+
+        for _ in 0..IO_LATCH_DECAY_FRAMES as u32 * 341 * 262 {
+            ppu.clock();
+        }
+        assert_eq!(ppu.read_register(Register::Mask), 0);
+    }
+
+    #[test]
+    fn frame_skip() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let image = tv.get_image_clone();
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        ppu.write_register(Register::Mask, 0x1E);
+        ppu.set_frame_skip(1);
+
+        let mut frames = Vec::new();
+        for frame in 0..4u8 {
+            // fill the palette with one color, so the whole frame has it
+            ppu.write_register(Register::PPUAddress, 0x3F);
+            ppu.write_register(Register::PPUAddress, 0x00);
+            for _ in 0..32 {
+                ppu.write_register(Register::PPUData, 0x11 + frame);
+            }
+
+            while ppu.scanline() == 241 {
+                ppu.clock();
+            }
+            while ppu.scanline() != 241 {
+                ppu.clock();
+            }
+
+            frames.push(image.lock().unwrap()[0..4].to_vec());
+        }
+
+        let color = |color: u8| COLORS[color as usize].to_rgba().to_vec();
+        // every other frame is drawn
+        assert_eq!(
+            frames,
+            vec![color(0x11), color(0x11), color(0x13), color(0x13)]
+        );
+    }
+
+    #[test]
+    fn render_background_override() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let image = tv.get_image_clone();
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        ppu.write_register(Register::Mask, 0x1E);
+
+        // the number of different colors in line 100 of the next frame, far
+        // from the sprites (all at the top left)
+        let line_colors = |ppu: &mut PPU2C02<PatternBus>| {
+            while ppu.scanline() == 241 {
+                ppu.clock();
+            }
+            while ppu.scanline() != 241 {
+                ppu.clock();
+            }
+
+            let image = image.lock().unwrap();
+            let mut colors = image[100 * 256 * 4..101 * 256 * 4]
+                .chunks(4)
+                .collect::<Vec<_>>();
+            colors.sort();
+            colors.dedup();
+            colors.len()
+        };
+
+        assert!(line_colors(&mut ppu) > 1);
+
+        // only the backdrop color
+        ppu.set_render_background(false);
+        assert_eq!(line_colors(&mut ppu), 1);
+
+        // the game cannot enable it
+        ppu.write_register(Register::Mask, 0x1E);
+        assert_eq!(line_colors(&mut ppu), 1);
+
+        ppu.set_render_background(true);
+        assert!(line_colors(&mut ppu) > 1);
+    }
+
+    #[test]
+    fn scroll_from_internal_v_register() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        ppu.write_register(Register::Mask, 0x1E);
+        ppu.write_register(Register::Control, 0x03);
+        ppu.write_register(Register::Scroll, 0x2D);
+        ppu.write_register(Register::Scroll, 0x5E);
+
+        // only `t` and fine X are updated by the writes
+        assert_eq!(ppu.base_nametable(), 3);
+        assert_eq!((ppu.scroll_x(), ppu.scroll_y()), (5, 0));
+
+        // `t` is copied into `v` in the pre-render scanline, and then the
+        // first two tiles of scanline 0 are fetched, which increments coarse
+        // X, the PPU starts at the end of the pre-render scanline, so skip
+        // to the next one
+        while ppu.scanline() != 1 {
+            ppu.clock();
+        }
+        while ppu.scanline() != 0 {
+            ppu.clock();
+        }
+
+        assert_eq!(ppu.scroll_x(), 256 + 0x2D + 2 * 8);
+        assert_eq!(ppu.scroll_y(), 240 + 0x5E);
+    }
+
+    #[test]
+    fn scroll_decoded_without_changing_toggle() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        ppu.write_register(Register::Scroll, 0x2D);
+        // reading the scroll between the two writes should not reset `w`
+        ppu.scroll();
+        ppu.write_register(Register::Scroll, 0x5E);
+
+        let scroll = ppu.scroll();
+        assert_eq!(scroll.t, 0x6165);
+        assert_eq!(scroll.v, 0);
+        assert_eq!(scroll.fine_x, 5);
+
+        ppu.write_register(Register::PPUAddress, 0x23);
+        ppu.scroll();
+        ppu.write_register(Register::PPUAddress, 0xC5);
+
+        let scroll = ppu.scroll();
+        assert_eq!(scroll.v, 0x23C5);
+        assert_eq!(scroll.t, 0x23C5);
+        assert_eq!(
+            (
+                scroll.coarse_x,
+                scroll.coarse_y,
+                scroll.fine_y,
+                scroll.nametable
+            ),
+            (5, 30, 2, 0)
+        );
+        assert_eq!(scroll.fine_x, 5);
+    }
+
+    #[test]
+    fn render_nametable_tiles_and_attributes() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        // nametable 1, tile (3, 5) is $20 with palette 2, its second row:
+        // pixel 0 is color 3, pixel 7 is color 2 and the rest are color 0
+        let writes = [
+            (0x24A3, 0x20),
+            (0x27C8, 0b0000_1000),
+            (0x0201, 0x80),
+            (0x0209, 0x81),
+            (0x3F00, 0x0F),
+            (0x3F0A, 0x16),
+            (0x3F0B, 0x2A),
+        ];
+        for &(address, data) in &writes {
+            ppu.ppu_bus_mut().write(address, data, Device::PPU);
+        }
+
+        let mut buffer = vec![0; 256 * 240 * 3];
+        ppu.render_nametable(1, &mut buffer);
+
+        let pixel = |x: usize, y: usize| buffer[(y * 256 + x) * 3..][..3].to_vec();
+        let rgb = |color: u8| COLORS[color as usize].to_rgba()[..3].to_vec();
+        assert_eq!(pixel(24, 41), rgb(0x2A));
+        assert_eq!(pixel(25, 41), rgb(0x0F));
+        assert_eq!(pixel(31, 41), rgb(0x16));
+    }
+
+    #[test]
+    fn sprite_pixels_palette_and_flip() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+
+        // tile $10 first row: pixel 0 is color 3, pixel 7 is color 1
+        let writes = [
+            (0x0100, 0x81),
+            (0x0108, 0x80),
+            (0x3F15, 0x16),
+            (0x3F17, 0x2A),
+        ];
+        for &(address, data) in &writes {
+            ppu.write_register(Register::PPUAddress, (address >> 8) as u8);
+            ppu.write_register(Register::PPUAddress, address as u8);
+            ppu.write_register(Register::PPUData, data);
+        }
+        // clear the rest of the tile
+        ppu.write_register(Register::PPUAddress, 0x01);
+        ppu.write_register(Register::PPUAddress, 0x01);
+        for _ in 0..7 {
+            ppu.write_register(Register::PPUData, 0);
+        }
+        ppu.write_register(Register::PPUAddress, 0x01);
+        ppu.write_register(Register::PPUAddress, 0x09);
+        for _ in 0..7 {
+            ppu.write_register(Register::PPUData, 0);
+        }
+
+        // slot 3, tile $10, palette 1
+        ppu.write_register(Register::OmaAddress, 3 * 4);
+        for &data in &[20, 0x10, 0x01, 30] {
+            ppu.write_register(Register::OmaData, data);
+        }
+
+        let color_1 = COLORS[0x16].to_rgba();
+        let color_3 = COLORS[0x2A].to_rgba();
+
+        let pixels = ppu.sprite_pixels(3);
+        assert_eq!(pixels.len(), 8 * 8 * 4);
+        assert_eq!(pixels[0..4], color_3);
+        assert_eq!(pixels[4..8], [0, 0, 0, 0]);
+        assert_eq!(pixels[7 * 4..8 * 4], color_1);
+        assert!(pixels[8 * 4..].iter().all(|&byte| byte == 0));
+
+        // flipped in both directions
+        ppu.write_register(Register::OmaAddress, 3 * 4 + 2);
+        ppu.write_register(Register::OmaData, 0xC1);
+
+        let pixels = ppu.sprite_pixels(3);
+        let last_row = 7 * 8 * 4;
+        assert_eq!(pixels[last_row..last_row + 4], color_1);
+        assert_eq!(pixels[last_row + 7 * 4..], color_3);
+        assert!(pixels[..last_row].iter().all(|&byte| byte == 0));
+
+        // 8x16 sprites, the tile is `$10` in the first pattern table, and
+        // the bottom half is `$11`
+        ppu.write_register(Register::Control, 0x20);
+        assert_eq!(ppu.sprite_pixels(3).len(), 8 * 16 * 4);
+    }
+
+    #[test]
+    fn a12_rising_edges() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+        let edges = |ppu: &PPU2C02<PatternBus>| ppu.ppu_bus().a12_rising_edges.get();
+
+        // background at `$0000` and sprites at `$1000`, A12 rises on the
+        // first sprite pattern fetch of every rendered scanline, in dot 261
+        ppu.write_register(Register::Control, 0x08);
+        ppu.write_register(Register::Mask, 0x18);
+        // from the end of the pre-render scanline
+        for _ in 0..262 {
+            ppu.clock();
+        }
+        assert_eq!((ppu.scanline(), ppu.cycle()), (0, 261));
+        assert_eq!(edges(&ppu), 0);
+        ppu.clock();
+        assert_eq!(edges(&ppu), 1);
+
+        // the rest of the visible scanlines and the pre-render scanline
+        while ppu.scanline() != -1 {
+            ppu.clock();
+        }
+        while ppu.scanline() != 0 || ppu.cycle() != 262 {
+            ppu.clock();
+        }
+        assert_eq!(edges(&ppu), 242);
+
+        // both at `$0000`, there are no edges
+        ppu.write_register(Register::Control, 0x00);
+        for _ in 0..341 * 262 {
+            ppu.clock();
+        }
+        assert_eq!(edges(&ppu), 242);
+
+        // with rendering disabled, A12 follows the addresses written to
+        // `$2006`, the nametables do not change it
+        ppu.write_register(Register::Mask, 0x00);
+        for address in [0x1000u16, 0x2000, 0x1FFF, 0x0000, 0x1000] {
+            ppu.write_register(Register::PPUAddress, (address >> 8) as u8);
+            ppu.write_register(Register::PPUAddress, address as u8);
+        }
+        assert_eq!(edges(&ppu), 244);
+    }
+
+    #[test]
+    fn debug_views_do_not_clock_a12() {
+        let tv = TV::new(|color| [color.r, color.g, color.b, 0xFF]);
+        let mut ppu = PPU2C02::new(PatternBus::new(), tv);
+        let edges = |ppu: &PPU2C02<PatternBus>| ppu.ppu_bus().a12_rising_edges.get();
+
+        // both pattern tables at `$1000`
+        ppu.write_register(Register::Control, 0x18);
+        ppu.sprite_pixels(0);
+        let mut buffer = vec![0; 256 * 240 * 3];
+        ppu.render_nametable(0, &mut buffer);
+        ppu.highlight_tiles(|_| true);
+        assert_eq!(edges(&ppu), 0);
+
+        // A12 is still low, so the next pattern address raises it
+        ppu.write_register(Register::PPUAddress, 0x10);
+        ppu.write_register(Register::PPUAddress, 0x00);
+        assert_eq!(edges(&ppu), 1);
+    }
+}
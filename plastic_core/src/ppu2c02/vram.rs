@@ -1,6 +1,6 @@
 use crate::common::{
     save_state::{Savable, SaveError},
-    Bus, Device, MirroringMode, MirroringProvider,
+    Bus, Device, MirroringMode, MirroringProvider, NametableTarget,
 };
 use std::{cell::RefCell, rc::Rc};
 
@@ -33,7 +33,11 @@ impl VRam {
             }
         } as usize;
 
-        let start_address = block_num << 10;
+        Self::page_address(block_num as u8, address)
+    }
+
+    fn page_address(page: u8, address: u16) -> usize {
+        let start_address = (page as usize & 1) << 10;
 
         start_address + (address as usize & 0x3FF)
     }
@@ -43,16 +47,39 @@ impl Bus for VRam {
     fn read(&self, address: u16, device: Device) -> u8 {
         assert!(device == Device::PPU);
 
-        let address = self.map_address(address);
+        // the cartridge gets the first chance to handle the access
+        let target = self.mirroring_provider.borrow().map_nametable(address);
 
-        self.vram_data[address as usize]
+        match target {
+            NametableTarget::Default => self.vram_data[self.map_address(address)],
+            NametableTarget::InternalVramPage(page) => {
+                self.vram_data[Self::page_address(page, address)]
+            }
+            NametableTarget::CartridgeMemory(offset) => self
+                .mirroring_provider
+                .borrow()
+                .read_nametable_memory(offset),
+        }
     }
     fn write(&mut self, address: u16, data: u8, device: Device) {
         assert!(device == Device::PPU);
 
-        let address = self.map_address(address);
+        // the cartridge gets the first chance to handle the access
+        let target = self.mirroring_provider.borrow().map_nametable(address);
 
-        self.vram_data[address as usize] = data;
+        match target {
+            NametableTarget::Default => {
+                let address = self.map_address(address);
+                self.vram_data[address] = data;
+            }
+            NametableTarget::InternalVramPage(page) => {
+                self.vram_data[Self::page_address(page, address)] = data;
+            }
+            NametableTarget::CartridgeMemory(offset) => self
+                .mirroring_provider
+                .borrow_mut()
+                .write_nametable_memory(offset, data),
+        }
     }
 }
 
@@ -0,0 +1,123 @@
+use std::fmt::{Display, Formatter, Result as fmtResult};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub name: &'static str,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// A changed byte, `address` is the CPU address for RAM, and the offset in
+/// the state for the mapper
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub address: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// The differences between two save states
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub cpu_registers: Vec<RegisterChange>,
+    pub ram: Vec<MemoryChange>,
+    pub ppu_registers: Vec<RegisterChange>,
+    pub mapper_state: Vec<MemoryChange>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.cpu_registers.is_empty()
+            && self.ram.is_empty()
+            && self.ppu_registers.is_empty()
+            && self.mapper_state.is_empty()
+    }
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        if self.is_empty() {
+            return writeln!(f, "no differences");
+        }
+
+        let sections = [
+            ("CPU registers", &self.cpu_registers),
+            ("PPU registers", &self.ppu_registers),
+        ];
+        for (title, changes) in sections.iter() {
+            if !changes.is_empty() {
+                writeln!(f, "{}:", title)?;
+                for change in changes.iter() {
+                    writeln!(
+                        f,
+                        "  {:<10} {:04X} -> {:04X}",
+                        change.name, change.old, change.new
+                    )?;
+                }
+            }
+        }
+
+        let sections = [("RAM", &self.ram), ("Mapper state", &self.mapper_state)];
+        for (title, changes) in sections.iter() {
+            if !changes.is_empty() {
+                writeln!(f, "{}:", title)?;
+                for change in changes.iter() {
+                    writeln!(
+                        f,
+                        "  ${:04X}      {:02X} -> {:02X}",
+                        change.address, change.old, change.new
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The parts of the emulator state that are compared by [`StateDiff`]
+pub(crate) struct StateSnapshot {
+    pub cpu_registers: Vec<(&'static str, u16)>,
+    pub ram: Vec<u8>,
+    pub ppu_registers: Vec<(&'static str, u16)>,
+    pub mapper_state: Vec<u8>,
+}
+
+impl StateSnapshot {
+    fn diff_registers(
+        old: &[(&'static str, u16)],
+        new: &[(&'static str, u16)],
+    ) -> Vec<RegisterChange> {
+        old.iter()
+            .zip(new.iter())
+            .filter(|(old, new)| old.1 != new.1)
+            .map(|(old, new)| RegisterChange {
+                name: old.0,
+                old: old.1,
+                new: new.1,
+            })
+            .collect()
+    }
+
+    fn diff_memory(old: &[u8], new: &[u8]) -> Vec<MemoryChange> {
+        old.iter()
+            .zip(new.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(address, (old, new))| MemoryChange {
+                address: address as u16,
+                old: *old,
+                new: *new,
+            })
+            .collect()
+    }
+
+    pub fn diff(&self, new: &StateSnapshot) -> StateDiff {
+        StateDiff {
+            cpu_registers: Self::diff_registers(&self.cpu_registers, &new.cpu_registers),
+            ram: Self::diff_memory(&self.ram, &new.ram),
+            ppu_registers: Self::diff_registers(&self.ppu_registers, &new.ppu_registers),
+            mapper_state: Self::diff_memory(&self.mapper_state, &new.mapper_state),
+        }
+    }
+}
@@ -37,6 +37,7 @@ fn run_blargg_test_6000_80(filename: &str) -> Result<(), TestError> {
     let result_memory_address = 0x6000;
 
     let mut nes = NesTester::new(filename)?;
+    nes.force_prg_ram();
     nes.reset_cpu();
 
     // first loop until an infnite loop (this infinite loop might be the
@@ -0,0 +1,198 @@
+use super::{write_rom, NoUi, TempDir};
+use crate::nes::{IrqSource, NES};
+use std::{cell::RefCell, rc::Rc};
+
+/// run `cycles` CPU cycles, and check that every call of the callback
+/// (pushing to `calls`) happens in the cycle it reports
+fn clock_checking_cycles<T>(nes: &mut NES<NoUi>, calls: &RefCell<Vec<(T, u64)>>, cycles: u64) {
+    for _ in 0..cycles {
+        let count = calls.borrow().len();
+        nes.clock();
+
+        let calls = calls.borrow();
+        assert!(calls.len() <= count + 1);
+        if let Some((_, cycle)) = calls.get(count) {
+            assert_eq!(*cycle, nes.cpu_cycle_count());
+        }
+    }
+}
+
+#[test]
+fn nmi_callback_once_per_vblank() {
+    let program = [
+        0x78, // SEI
+        // wait for 2 vblanks
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        0xA9, 0x80, 0x8D, 0x00, 0x20, // LDA #$80, STA $2000 (enable NMI)
+        // loop, reading $2002 does not assert NMI again
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x4C, 0x10, 0x80, // JMP loop
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let calls_callback = calls.clone();
+    nes.set_nmi_callback(move |cycle| calls_callback.borrow_mut().push(((), cycle)));
+
+    // NMI is not enabled yet
+    for _ in 0..2 {
+        nes.clock_for_frame();
+    }
+    assert!(calls.borrow().is_empty());
+
+    let mut last_cycle = None;
+    for _ in 0..5 {
+        let count = calls.borrow().len();
+        clock_checking_cycles(&mut nes, &calls, 29781);
+        assert_eq!(calls.borrow().len(), count + 1);
+
+        // at the start of vblank
+        let cycle = calls.borrow()[count].1;
+        assert!(nes.cpu_cycle_count() - cycle < 29781);
+        if let Some(last_cycle) = last_cycle {
+            assert!((29780..=29781).contains(&(cycle - last_cycle)));
+        }
+        last_cycle = Some(cycle);
+    }
+}
+
+#[test]
+fn irq_callback_apu_and_dmc() {
+    let program = [
+        0x78, // SEI
+        0xA9, 0x00, 0x8D, 0x17, 0x40, // LDA #$00, STA $4017 (frame IRQ)
+        0xA9, 0x8F, 0x8D, 0x10, 0x40, // LDA #$8F, STA $4010 (DMC IRQ)
+        0xA9, 0x00, 0x8D, 0x12, 0x40, // LDA #$00, STA $4012
+        0x8D, 0x13, 0x40, // STA $4013 (1 byte sample)
+        0xA9, 0x10, 0x8D, 0x15, 0x40, // LDA #$10, STA $4015
+        0x4C, 0x18, 0x80, // JMP loop
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let calls_callback = calls.clone();
+    nes.set_irq_callback(move |source, cycle| calls_callback.borrow_mut().push((source, cycle)));
+
+    // the flags are not acknowledged, so the lines stay asserted
+    clock_checking_cycles(&mut nes, &calls, 29781 * 3);
+
+    let calls = calls.borrow();
+    assert_eq!(
+        calls.iter().map(|(source, _)| *source).collect::<Vec<_>>(),
+        [IrqSource::Dmc, IrqSource::Apu]
+    );
+    // the DMC sample (8 bits at rate 15) ends long before the frame IRQ
+    assert!(calls[0].1 < 1000);
+    // the frame IRQ is 29829 cycles after `$4017` is written
+    assert!((29829..29829 + 20).contains(&calls[1].1));
+}
+
+/// MMC3 (mapper 4) with 32KB PRG and 8KB CHR ROM, that enables rendering
+/// and the scanline IRQ with a latch of 16
+fn mmc3_irq_rom() -> Vec<u8> {
+    let program = [
+        0x78, // SEI
+        0xA9, 0x40, 0x8D, 0x17, 0x40, // LDA #$40, STA $4017 (no frame IRQ)
+        // wait for vblank
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        0xA9, 0x08, 0x8D, 0x00, 0x20, // LDA #$08, STA $2000 (sprites at $1000)
+        0xA9, 0x18, 0x8D, 0x01, 0x20, // LDA #$18, STA $2001
+        0xA9, 0x10, 0x8D, 0x00, 0xC0, // LDA #$10, STA $C000 (latch)
+        0x8D, 0x01, 0xC0, // STA $C001 (reload)
+        0x8D, 0x01, 0xE0, // STA $E001 (enable)
+        0x4C, 0x20, 0xE0, // JMP loop
+    ];
+
+    let mut rom = vec![
+        b'N', b'E', b'S', 0x1A, 2, 1, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    // the program is in the last bank, fixed at `$E000`
+    let mut prg = vec![0; 0x8000];
+    prg[0x6000..0x6000 + program.len()].copy_from_slice(&program);
+    // NMI, RESET and IRQ vectors
+    prg[0x7FFA..].copy_from_slice(&[0x20, 0xE0, 0x00, 0xE0, 0x20, 0xE0]);
+    rom.extend_from_slice(&prg);
+    rom.extend_from_slice(&[0; 0x2000]);
+
+    rom
+}
+
+#[test]
+fn irq_callback_mapper() {
+    let dir = TempDir::new("irq_callback");
+    let mut nes = NES::new(&write_rom(&dir, &mmc3_irq_rom()), NoUi).unwrap();
+    nes.reset();
+
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let calls_callback = calls.clone();
+    nes.set_irq_callback(move |source, cycle| calls_callback.borrow_mut().push((source, cycle)));
+
+    let mut scanlines = Vec::new();
+    for _ in 0..29781 * 3 {
+        let count = calls.borrow().len();
+        clock_checking_cycles(&mut nes, &calls, 1);
+        if calls.borrow().len() > count {
+            scanlines.push(nes.ppu_scanline());
+        }
+    }
+
+    assert!(calls
+        .borrow()
+        .iter()
+        .all(|(source, _)| *source == IrqSource::Mapper));
+    // the counter is reloaded at the pre-render scanline, then it takes 16
+    // scanlines to reach zero, and 17 after that (the reload from zero is
+    // one of them), the mapper asserts the IRQ every time it reaches zero
+    assert_eq!(scanlines[..3], [15, 32, 49]);
+}
+
+#[test]
+fn scanline_callback_once_per_scanline() {
+    let program = [
+        0x78, // SEI
+        // wait for vblank
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        // enable rendering, so odd frames are one dot shorter
+        0xA9, 0x18, 0x8D, 0x01, 0x20, // LDA #$18, STA $2001
+        0x4C, 0x0B, 0x80, // JMP loop
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+    nes.clock_for_frame();
+
+    let scanlines = Rc::new(RefCell::new(Vec::new()));
+    let scanlines_callback = scanlines.clone();
+    nes.set_scanline_callback(move |scanline| scanlines_callback.borrow_mut().push(scanline));
+
+    for _ in 0..29781 * 4 {
+        let count = scanlines.borrow().len();
+        nes.clock();
+
+        // called in the same scanline
+        let scanlines = scanlines.borrow();
+        assert!(scanlines.len() <= count + 1);
+        if let Some(scanline) = scanlines.get(count) {
+            assert_eq!(*scanline, nes.ppu_scanline());
+        }
+    }
+
+    let scanlines = scanlines.borrow();
+    let expected = (-1..=260).collect::<Vec<_>>();
+
+    // the first frame is partial, it ends with the last scanlines
+    let start = scanlines.iter().position(|&s| s == -1).unwrap();
+    assert_eq!(scanlines[..start], expected[262 - start..]);
+
+    let frames = scanlines[start..].chunks_exact(262);
+    let rest = frames.remainder();
+    assert_eq!(*rest, expected[..rest.len()]);
+
+    assert!(frames.len() >= 3);
+    for frame in frames {
+        assert_eq!(frame, expected);
+    }
+}
@@ -0,0 +1,123 @@
+use super::{write_rom, NoUi, TempDir};
+use crate::nes::{ChrWriteError, NES};
+
+/// NROM with CHR RAM if `chr_rom` is `false`, that sets the background
+/// palette to black, white, red and green, and puts tile 1 at the second
+/// row and column of the first nametable (pixels `8-15`), with no scrolling
+fn tile_rom(chr_rom: bool) -> Vec<u8> {
+    let program = [
+        // wait for vblank
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F, STA $2006
+        0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00, STA $2006
+        0xA9, 0x0F, 0x8D, 0x07, 0x20, // LDA #$0F, STA $2007
+        0xA9, 0x30, 0x8D, 0x07, 0x20, // LDA #$30, STA $2007
+        0xA9, 0x16, 0x8D, 0x07, 0x20, // LDA #$16, STA $2007
+        0xA9, 0x2A, 0x8D, 0x07, 0x20, // LDA #$2A, STA $2007
+        0xA9, 0x20, 0x8D, 0x06, 0x20, // LDA #$20, STA $2006
+        0xA9, 0x21, 0x8D, 0x06, 0x20, // LDA #$21, STA $2006
+        0xA9, 0x01, 0x8D, 0x07, 0x20, // LDA #$01, STA $2007
+        // reset the scroll
+        0xA9, 0x00, 0x8D, 0x00, 0x20, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20, // ..
+        0xA9, 0x0A, 0x8D, 0x01, 0x20, // LDA #$0A, STA $2001
+        0x4C, 0x42, 0x80, // JMP loop
+    ];
+
+    let chr_banks = chr_rom as u8;
+    let mut rom = vec![
+        b'N', b'E', b'S', 0x1A, 1, chr_banks, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(&program);
+    // NMI, RESET and IRQ vectors
+    prg[0x3FFA..].copy_from_slice(&[0x42, 0x80, 0x00, 0x80, 0x42, 0x80]);
+    rom.extend_from_slice(&prg);
+    if chr_rom {
+        rom.extend_from_slice(&[0; 0x2000]);
+    }
+
+    rom
+}
+
+fn load_tile_rom(chr_rom: bool) -> NES<NoUi> {
+    // the ROM is only read while loading
+    let dir = TempDir::new("chr_edit");
+
+    NES::new(&write_rom(&dir, &tile_rom(chr_rom)), NoUi).unwrap()
+}
+
+/// a diagonal pattern using all the 4 colors
+fn test_tile() -> [[u8; 8]; 8] {
+    let mut pixels = [[0; 8]; 8];
+    for (y, row) in pixels.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = ((x / 2 + y) % 4) as u8;
+        }
+    }
+
+    pixels
+}
+
+#[test]
+fn encode_tile_on_chr_ram() {
+    let mut nes = load_tile_rom(false);
+    nes.reset();
+
+    for _ in 0..2 {
+        nes.clock_for_frame();
+    }
+
+    let tile = test_tile();
+    nes.encode_tile(0x0010, &tile).unwrap();
+    assert_eq!(nes.decode_tile(0x0010), tile);
+    // tile 0 is not changed
+    assert_eq!(nes.decode_tile(0x0000), [[0; 8]; 8]);
+
+    let mut data = [0; 2];
+    nes.read_chr(0x0010, &mut data);
+    // first row, low and high bit planes of `0 0 1 1 2 2 3 3`
+    nes.read_chr(0x0018, &mut data[1..]);
+    assert_eq!(data, [0b0011_0011, 0b0000_1111]);
+
+    for _ in 0..2 {
+        nes.clock_for_frame();
+    }
+
+    let image = nes.image();
+    // RGBA pixel at `x`, `y`
+    let pixel = |x: usize, y: usize| image[(y * 256 + x) * 4..][..4].to_vec();
+
+    let mut colors = vec![None; 4];
+    // background tile 0
+    colors[0] = Some(pixel(0, 0));
+    for (y, row) in tile.iter().enumerate() {
+        for (x, &index) in row.iter().enumerate() {
+            let color = pixel(8 + x, 8 + y);
+            let expected = colors[index as usize].get_or_insert_with(|| color.clone());
+            assert_eq!(&color, expected, "pixel {}, {}", x, y);
+        }
+    }
+    // all the 4 colors are different
+    for i in 0..4 {
+        for j in i + 1..4 {
+            assert_ne!(colors[i], colors[j]);
+        }
+    }
+}
+
+#[test]
+fn write_chr_rom_fails() {
+    let mut nes = load_tile_rom(true);
+    nes.reset();
+
+    assert!(matches!(
+        nes.write_chr(0x1234, &[1, 2, 3]),
+        Err(ChrWriteError::ReadOnly { address: 0x1234 })
+    ));
+    assert!(matches!(
+        nes.encode_tile(0x0010, &test_tile()),
+        Err(ChrWriteError::ReadOnly { address: 0x0010 })
+    ));
+    assert_eq!(nes.decode_tile(0x0010), [[0; 8]; 8]);
+}
@@ -0,0 +1,702 @@
+use super::NoUi;
+use crate::apu2a03::APU2A03;
+use crate::cartridge::Cartridge;
+use crate::common::{
+    expansion_device::ExpansionDevice, interconnection::CPUIrqProvider, save_state::SaveError,
+};
+use crate::controller::{Controller, StandardNESControllerState};
+use crate::cpu6502::{CPUBusTrait, CPU6502};
+use crate::display::TV;
+use crate::nes::{CPUBus, IrqSource, PPUBus, TimeoutError, NES};
+use crate::ppu2c02::PPU2C02;
+use std::{cell::RefCell, rc::Rc};
+
+/// a latch at `$5000`, the low 3 bytes of the cycle counter at
+/// `$5001-$5003`, and IRQ asserted when the latch is `0xFF`
+struct ToyDevice {
+    latch: u8,
+    cycles: u64,
+}
+
+impl ExpansionDevice for ToyDevice {
+    fn read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x5000 => Some(self.latch),
+            0x5001..=0x5003 => Some((self.cycles >> ((address - 0x5001) * 8)) as u8),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) -> bool {
+        if address == 0x5000 {
+            self.latch = data;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn clock(&mut self, _cpu_cycles: u64) {
+        self.cycles += 1;
+    }
+
+    fn irq(&self) -> bool {
+        self.latch == 0xFF
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(vec![self.latch])
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        self.latch = data[0];
+    }
+}
+
+fn create_bus() -> CPUBus {
+    create_bus_with_cartridge(Cartridge::new_without_file())
+}
+
+fn create_bus_with_cartridge(cartridge: Cartridge) -> CPUBus {
+    let cartridge = Rc::new(RefCell::new(cartridge));
+    let ppu = PPU2C02::new(PPUBus::new(cartridge.clone()), TV::new(|_| [0; 4]));
+
+    CPUBus::new(
+        cartridge,
+        Rc::new(RefCell::new(ppu)),
+        Rc::new(RefCell::new(APU2A03::new())),
+        Controller::new(),
+        Controller::new(),
+    )
+}
+
+#[test]
+fn expansion_device_read_write() {
+    let mut bus = create_bus();
+
+    // empty cartridge
+    assert_eq!(bus.read(0x5000), 0xEA);
+
+    bus.set_expansion_device(Some(Box::new(ToyDevice {
+        latch: 0,
+        cycles: 0,
+    })));
+
+    bus.write(0x5000, 0x42);
+    assert_eq!(bus.read(0x5000), 0x42);
+
+    for _ in 0..0x10203 {
+        bus.clock_expansion_device(0);
+    }
+    assert_eq!(bus.read(0x5001), 0x03);
+    assert_eq!(bus.read(0x5002), 0x02);
+    assert_eq!(bus.read(0x5003), 0x01);
+
+    // not handled by the device, goes to the cartridge
+    assert_eq!(bus.read(0x5004), 0xEA);
+    // outside the expansion area
+    assert_eq!(bus.read(0x8000), 0xEA);
+}
+
+#[test]
+fn expansion_device_irq() {
+    let mut bus = create_bus();
+    bus.set_expansion_device(Some(Box::new(ToyDevice {
+        latch: 0,
+        cycles: 0,
+    })));
+
+    assert!(!bus.is_irq_change_requested());
+
+    bus.write(0x5000, 0xFF);
+    assert!(bus.is_irq_change_requested());
+    assert!(bus.irq_pin_state());
+    assert_eq!(bus.irq_source(), Some(IrqSource::Expansion));
+    bus.clear_irq_request_pin();
+    assert!(!bus.is_irq_change_requested());
+
+    bus.write(0x5000, 0);
+    assert!(bus.is_irq_change_requested());
+    assert!(!bus.irq_pin_state());
+    assert_eq!(bus.irq_source(), None);
+    bus.clear_irq_request_pin();
+    assert!(!bus.is_irq_change_requested());
+}
+
+#[test]
+fn mapper_write_log() {
+    let mut bus = create_bus();
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let log_clone = log.clone();
+    bus.set_mapper_write_log(Some(Box::new(move |address, data| {
+        log_clone.borrow_mut().push((address, data))
+    })));
+
+    bus.write(0x0000, 1);
+    bus.write(0x2000, 2);
+    bus.write(0x4020, 3);
+    bus.write(0x8000, 4);
+    bus.write(0xFFFF, 5);
+
+    assert_eq!(*log.borrow(), vec![(0x4020, 3), (0x8000, 4), (0xFFFF, 5)]);
+}
+
+#[test]
+fn mapper31_4kb_prg_banks() {
+    use std::{io::Cursor, path::Path};
+
+    // 256KB PRG, CHR RAM, and the number of each 4KB bank at its start
+    let mut rom = vec![
+        b'N', b'E', b'S', 0x1A, 16, 0, 0xF0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    for bank in 0..64 {
+        let mut data = vec![0; 0x1000];
+        data[0] = bank;
+        rom.extend_from_slice(&data);
+    }
+
+    let cartridge = Cartridge::from_reader(Path::new("mapper31.nes"), Cursor::new(rom)).unwrap();
+    let mut bus = create_bus_with_cartridge(cartridge);
+
+    // only the last bank is mapped at power-on
+    assert_eq!(bus.read(0xF000), 63);
+
+    for register in 0..8 {
+        bus.write(0x5FF8 + register, 10 + register as u8 * 7);
+    }
+    for register in 0..8 {
+        assert_eq!(
+            bus.read(0x8000 + register * 0x1000),
+            10 + register as u8 * 7
+        );
+    }
+
+    // the registers are mirrored in `$5000-$5FFF`
+    bus.write(0x5001, 33);
+    assert_eq!(bus.read(0x9000), 33);
+}
+
+/// run an OAM DMA from `page` with `setup` called on the bus before it,
+/// `controller` is the state of the first controller
+fn run_oam_dma<F: FnOnce(&mut CPUBus)>(
+    page: u8,
+    controller: StandardNESControllerState,
+    setup: F,
+) -> (CPU6502<CPUBus>, Rc<RefCell<PPU2C02<PPUBus>>>) {
+    let cartridge = Rc::new(RefCell::new(Cartridge::new_without_file()));
+    let ppu = Rc::new(RefCell::new(PPU2C02::new(
+        PPUBus::new(cartridge.clone()),
+        TV::new(|_| [0; 4]),
+    )));
+    let contoller = Controller::new();
+    contoller.set_state(controller);
+
+    let mut cpu = CPU6502::new(CPUBus::new(
+        cartridge,
+        ppu.clone(),
+        Rc::new(RefCell::new(APU2A03::new())),
+        contoller,
+        Controller::new(),
+    ));
+    // the empty cartridge returns `NOP`s, so the reset vector is `$EAEA`
+    cpu.reset();
+
+    setup(cpu.bus_mut());
+    cpu.bus_mut().write(0x4014, page);
+
+    // 513/514 cycles for the DMA, and a bit more for the `NOP`s
+    for _ in 0..600 {
+        cpu.run_next();
+    }
+
+    (cpu, ppu)
+}
+
+fn ppu_register(ppu: &PPU2C02<PPUBus>, name: &str) -> u16 {
+    ppu.registers()
+        .iter()
+        .find(|(register, _)| *register == name)
+        .unwrap()
+        .1
+}
+
+#[test]
+fn oam_dma_from_ppu_registers_page() {
+    let (_, ppu) = run_oam_dma(0x20, StandardNESControllerState::empty(), |bus| {
+        bus.write(0x2006, 0x21);
+        bus.write(0x2006, 0x00);
+        // first write of `$2005`, it should be reset by reading `$2002`
+        bus.write(0x2005, 0x00);
+    });
+
+    // `$2007` is mirrored 32 times in the page, each read increments `v`
+    assert_eq!(ppu_register(&ppu.borrow(), "v"), 0x2100 + 32);
+    assert_eq!(ppu_register(&ppu.borrow(), "w"), 0);
+}
+
+#[test]
+fn oam_dma_from_apu_registers_page() {
+    let controller = StandardNESControllerState::A | StandardNESControllerState::SELECT;
+    let (mut cpu, _) = run_oam_dma(0x40, controller, |bus| {
+        bus.write(0x4016, 1);
+        bus.write(0x4016, 0);
+    });
+    let bus = cpu.bus_mut();
+
+    // `$4016` was read once during the DMA (A)
+    bus.write(0x2003, 0x16);
+    assert_eq!(bus.read(0x2004), 1);
+
+    // B, then SELECT
+    assert_eq!(bus.read(0x4016), 0);
+    assert_eq!(bus.read(0x4016), 1);
+}
+
+#[test]
+fn ppu_chr_bank_override() {
+    use crate::common::{Bus, Device};
+    use std::{io::Cursor, path::Path};
+
+    // CNROM with 32KB CHR, and the number of each 4KB bank at its start
+    let mut rom = vec![
+        b'N', b'E', b'S', 0x1A, 1, 4, 0x30, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    rom.extend_from_slice(&[0; 0x4000]);
+    for bank in 0..8 {
+        let mut data = vec![0; 0x1000];
+        data[0] = bank;
+        rom.extend_from_slice(&data);
+    }
+
+    let cartridge = Cartridge::from_reader(Path::new("cnrom.nes"), Cursor::new(rom)).unwrap();
+    let mut ppubus = PPUBus::new(Rc::new(RefCell::new(cartridge)));
+
+    assert_eq!(ppubus.read(0x0000, Device::PPU), 0);
+    assert_eq!(ppubus.read(0x1000, Device::PPU), 1);
+
+    ppubus.set_chr_bank_override(0, Some(5));
+    ppubus.set_chr_bank_override(1, Some(10));
+    assert_eq!(ppubus.read(0x0000, Device::PPU), 5);
+    // banks past the end are mirrored
+    assert_eq!(ppubus.read(0x1000, Device::PPU), 2);
+
+    ppubus.set_chr_bank_override(0, None);
+    assert_eq!(ppubus.read(0x0000, Device::PPU), 0);
+    assert_eq!(ppubus.read(0x1000, Device::PPU), 2);
+}
+
+#[test]
+fn reset_preserves_ram() {
+    let mut bus = create_bus();
+
+    bus.write(0x0123, 0x5A);
+    // mirrored
+    assert_eq!(bus.read(0x0923), 0x5A);
+
+    bus.reset();
+    assert_eq!(bus.read(0x0123), 0x5A);
+
+    bus.clear_ram();
+    assert_eq!(bus.read(0x0123), 0);
+}
+
+#[test]
+fn peek_has_no_side_effects() {
+    let cartridge = Rc::new(RefCell::new(Cartridge::new_without_file()));
+    let ppu = PPU2C02::new(PPUBus::new(cartridge.clone()), TV::new(|_| [0; 4]));
+    let contoller = Controller::new();
+    contoller.set_state(StandardNESControllerState::A);
+    let mut bus = CPUBus::new(
+        cartridge,
+        Rc::new(RefCell::new(ppu)),
+        Rc::new(RefCell::new(APU2A03::new())),
+        contoller,
+        Controller::new(),
+    );
+
+    bus.write(0x0010, 0x42);
+    assert_eq!(bus.peek(0x0810), 0x42);
+
+    // the `w` toggle is not reset by peeking `$2002`
+    bus.write(0x2006, 0x20);
+    bus.peek(0x2002);
+    bus.write(0x2006, 0x00);
+    bus.write(0x2007, 0x55);
+
+    // fill the read buffer
+    bus.write(0x2006, 0x20);
+    bus.write(0x2006, 0x00);
+    bus.read(0x2007);
+    // the buffer is not refilled, and `v` is not incremented
+    assert_eq!(bus.peek(0x2007), 0x55);
+    assert_eq!(bus.peek(0x2007), 0x55);
+    assert_eq!(bus.read(0x2007), 0x55);
+
+    // the controller is not shifted
+    bus.write(0x4016, 1);
+    bus.write(0x4016, 0);
+    assert_eq!(bus.peek(0x4016), 1);
+    assert_eq!(bus.peek(0x4016), 1);
+    assert_eq!(bus.read(0x4016), 1);
+    assert_eq!(bus.read(0x4016), 0);
+
+    assert_eq!(bus.peek(0x4015), bus.read(0x4015));
+    assert_eq!(bus.peek(0x8000), 0xEA);
+}
+
+#[test]
+fn dump_memory_inclusive_range() {
+    let mut nes = NES::new(
+        "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+        NoUi,
+    )
+    .unwrap();
+    nes.reset();
+    nes.clock_for_frame();
+
+    let ram = nes.dump_memory(0x0000, 0x07FF);
+    assert_eq!(ram.len(), 0x800);
+    // mirrored
+    assert_eq!(nes.dump_memory(0x0800, 0x0FFF), ram);
+
+    assert_eq!(nes.dump_memory(0xFFFC, 0xFFFF).len(), 4);
+    assert_eq!(nes.dump_memory(0x10, 0x10).len(), 1);
+    assert!(nes.dump_memory(0x11, 0x10).is_empty());
+}
+
+#[test]
+fn dump_stack() {
+    let program = [
+        0xA2, 0xFF, // LDX #$FF
+        0x9A, // TXS
+        0xEA, // NOP
+        0xA9, 0x11, 0x48, // LDA #$11, PHA
+        0xA9, 0x22, 0x48, // LDA #$22, PHA
+        0x20, 0x0E, 0x80, // JSR $800E
+        0xEA, // NOP
+        0xA2, 0x00, // LDX #0
+        0x9A, // TXS
+        0xEA, // NOP
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+
+    assert_eq!(nes.clock_until_opcode(0xEA, 1000), Ok(7 + 2 + 2));
+    assert!(nes.dump_stack().is_empty());
+
+    assert_eq!(nes.clock_until_opcode(0xA2, 1000).map(|_| ()), Ok(()));
+    // the return address of `JSR` is pushed high byte first, and is the
+    // last byte of the `JSR`
+    assert_eq!(nes.dump_stack(), [0x11, 0x22, 0x80, 0x0C]);
+
+    nes.clock_until_opcode(0xEA, 1000).unwrap();
+    let stack = nes.dump_stack();
+    assert_eq!(stack.len(), 255);
+    assert_eq!(stack[..4], [0x11, 0x22, 0x80, 0x0C]);
+    assert_eq!(stack, {
+        let mut memory = nes.dump_memory(0x0101, 0x01FF);
+        memory.reverse();
+        memory
+    });
+}
+
+#[test]
+fn apu_irq_gate() {
+    let cartridge = Rc::new(RefCell::new(Cartridge::new_without_file()));
+    let ppu = PPU2C02::new(PPUBus::new(cartridge.clone()), TV::new(|_| [0; 4]));
+    let apu = Rc::new(RefCell::new(APU2A03::new()));
+    let mut bus = CPUBus::new(
+        cartridge,
+        Rc::new(RefCell::new(ppu)),
+        apu.clone(),
+        Controller::new(),
+        Controller::new(),
+    );
+
+    bus.set_apu_irq_enabled(true, false);
+
+    // the frame counter starts in 4-step mode with IRQ enabled
+    for _ in 0..30000 {
+        apu.borrow_mut().clock();
+    }
+    assert!(apu.borrow().frame_irq_pin_state());
+
+    assert!(bus.is_irq_change_requested());
+    assert!(!bus.irq_pin_state());
+    assert_eq!(bus.irq_source(), None);
+    bus.clear_irq_request_pin();
+    assert!(!bus.is_irq_change_requested());
+
+    // the CPU sees the IRQ as soon as it is enabled again
+    bus.set_apu_irq_enabled(true, true);
+    assert!(bus.is_irq_change_requested());
+    assert!(bus.irq_pin_state());
+    assert_eq!(bus.irq_source(), Some(IrqSource::Apu));
+}
+
+#[cfg(feature = "debugger")]
+#[test]
+fn generate_nmi() {
+    use crate::common::interconnection::PPUCPUConnection;
+
+    let cartridge = Rc::new(RefCell::new(Cartridge::new_without_file()));
+    let ppu = PPU2C02::new(PPUBus::new(cartridge.clone()), TV::new(|_| [0; 4]));
+    let mut cpu = CPU6502::new(CPUBus::new(
+        cartridge,
+        Rc::new(RefCell::new(ppu)),
+        Rc::new(RefCell::new(APU2A03::new())),
+        Controller::new(),
+        Controller::new(),
+    ));
+    // the empty cartridge returns `NOP`s, so all the vectors are `$EAEA`
+    cpu.reset();
+    for _ in 0..20 {
+        cpu.run_next();
+    }
+    assert_eq!(cpu.bus().peek(0x01FD), 0);
+
+    cpu.bus_mut().generate_nmi();
+    assert!(cpu.bus().is_nmi_pin_set());
+    for _ in 0..20 {
+        cpu.run_next();
+    }
+
+    // the return address is pushed, and the line is released
+    assert_eq!(cpu.bus().peek(0x01FD), 0xEA);
+    assert!(!cpu.bus().is_nmi_pin_set());
+}
+
+#[test]
+fn with_test_bus() {
+    let program = [
+        0xA9, 0x42, // LDA #$42
+        0x8D, 0x00, 0x60, // STA $6000
+        0x00, 0x00, // BRK, returns from the default `RTI`
+        0xA9, 0x07, // LDA #$07
+        0x85, 0x01, // STA $01
+        0x4C, 0x0B, 0x80, // JMP loop
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+    for _ in 0..100 {
+        nes.clock();
+    }
+    assert_eq!(nes.dump_memory(0x6000, 0x6000), [0x42]);
+    assert_eq!(nes.dump_memory(0x0001, 0x0001), [0x07]);
+    // 16KB is mirrored
+    assert_eq!(nes.dump_memory(0xC000, 0xC001), [0xA9, 0x42]);
+
+    // the vectors of `prg` are used if present
+    let mut prg = vec![0; 0x8000];
+    prg[0x100..0x105].copy_from_slice(&[0xA9, 0x33, 0x85, 0x02, 0xDB]); // LDA, STA $02, STP
+    prg[0x7FFC..0x7FFE].copy_from_slice(&[0x00, 0x81]);
+    let mut nes = NES::with_test_bus(&prg, NoUi);
+    for _ in 0..20 {
+        nes.clock();
+    }
+    assert_eq!(nes.dump_memory(0x0002, 0x0002), [0x33]);
+}
+
+#[test]
+fn clock_until_opcode() {
+    let program = [
+        0xA2, 0x03, // LDX #$03
+        0xCA, // loop: DEX
+        0xD0, 0xFD, // BNE loop
+        0x20, 0x0A, 0x80, // JSR sub
+        0x00, 0x00, // BRK
+        0x60, // sub: RTS
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+
+    // the PC points to the opcode, it is fetched on the next cycle
+    let cycles = nes.clock_until_opcode(0x20, 1000).unwrap();
+    // 7 reset cycles, LDX, 3 DEX and 3 BNE (the last not taken)
+    assert_eq!(cycles, 7 + 2 + 3 * 2 + 2 * 3 + 2);
+    nes.clock();
+    let instruction = nes.last_instruction().unwrap();
+    assert_eq!((instruction.pc, instruction.opcode), (0x8005, 0x20));
+
+    assert_eq!(nes.clock_until_opcode(0x60, 1000), Ok(5));
+    nes.clock();
+    assert_eq!(nes.last_instruction().unwrap().pc, 0x800A);
+
+    nes.clock_until_opcode(0x00, 1000).unwrap();
+    nes.clock();
+    assert_eq!(nes.last_instruction().unwrap().pc, 0x8008);
+
+    // there is no `LDA #`, the rest of the PRG is `BRK`s
+    assert_eq!(
+        nes.clock_until_opcode(0xA9, 500),
+        Err(TimeoutError { cycles: 500 })
+    );
+}
+
+#[test]
+fn clock_cycles_audio_samples() {
+    let program = [0x4C, 0x00, 0x80]; // JMP $8000
+    let mut nes = NES::with_test_bus(&program, NoUi);
+    nes.take_audio_buffer();
+
+    let mut total = 0;
+    for cycles in [1000, 123, 29781, 1] {
+        let expected = nes.audio_samples_for_cycles(cycles);
+        assert_eq!(nes.clock_cycles(cycles), expected);
+        total += expected;
+    }
+
+    assert_eq!(nes.take_audio_buffer().len() as u64, total * 2);
+    let rate = nes.cpu_cycles_per_audio_sample();
+    assert!((total as f64 - (1000 + 123 + 29781 + 1) as f64 / rate).abs() <= 1.);
+}
+
+#[test]
+fn audio_buffer_limit_kept_on_reset() {
+    let program = [0x4C, 0x00, 0x80]; // JMP $8000
+    let mut nes = NES::with_test_bus(&program, NoUi);
+    // 10ms, around 441 stereo samples at 44100Hz
+    nes.set_max_audio_buffer_ms(10);
+    nes.clock_for_frame();
+    let overrun = nes.audio_overrun_count();
+    assert!(overrun > 0);
+
+    nes.reset();
+    assert_eq!(nes.audio_overrun_count(), overrun);
+    for _ in 0..3 {
+        nes.clock_for_frame();
+    }
+    assert!(nes.audio_latency() <= 441);
+    assert!(nes.audio_overrun_count() > overrun);
+
+    let overrun = nes.audio_overrun_count();
+    nes.power_cycle();
+    nes.clock_for_frame();
+    assert!(nes.audio_latency() <= 441);
+    assert!(nes.audio_overrun_count() > overrun);
+}
+
+#[test]
+fn silence_threshold_kept_on_reset() {
+    let program = [0x4C, 0x00, 0x80]; // JMP $8000
+    let mut nes = NES::with_test_bus(&program, NoUi);
+    // no channel is playing, so the frames are silent with the default
+    nes.clock_for_frame();
+    nes.clock_for_frame();
+    assert!(nes.silent_frames() > 0);
+
+    // nothing is below `0.0`
+    nes.set_silence_threshold(0.);
+    for reload in [NES::reset, NES::power_cycle] {
+        reload(&mut nes);
+        nes.clock_for_frame();
+        nes.clock_for_frame();
+        assert_eq!(nes.silent_frames(), 0);
+    }
+}
+
+#[test]
+fn audio_levels_follow_ppu_frames() {
+    let program = [0x4C, 0x00, 0x80]; // JMP $8000
+    let mut nes = NES::with_test_bus(&program, NoUi);
+
+    // stepping with uneven amounts, every completed frame is metered
+    let mut frames = 0;
+    for cycles in [1, 100, 29780, 5000, 40000, 7, 29780] {
+        for _ in 0..cycles {
+            nes.clock();
+            if nes.is_frame_complete() {
+                frames += 1;
+            }
+        }
+
+        assert_eq!(nes.silent_frames(), frames);
+    }
+    assert!(frames > 0);
+}
+
+#[test]
+fn cpu_access_hooks() {
+    let program = [
+        0xA9, 0x42, // LDA #$42
+        0x8D, 0x00, 0x03, // STA $0300
+        0xAD, 0x00, 0x03, // LDA $0300
+        0x8D, 0x01, 0x03, // STA $0301
+        0x4C, 0x00, 0x80, // JMP $8000
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    for name in ["write 1", "write 2"] {
+        let log = log.clone();
+        nes.set_cpu_write_hook(
+            0x0300,
+            Rc::new(move |address, data| log.borrow_mut().push((name, address, data))),
+        );
+    }
+    let read_log = log.clone();
+    nes.set_cpu_read_hook(
+        0x0300,
+        Rc::new(move |address, data| read_log.borrow_mut().push(("read", address, data))),
+    );
+
+    // reset, and one iteration (2 + 4 + 4 + 4 + 3 cycles)
+    nes.clock_cycles(7 + 17);
+    // both write hooks are called in order, and not for the read, or for
+    // other addresses
+    assert_eq!(
+        *log.borrow(),
+        [
+            ("write 1", 0x0300, 0x42),
+            ("write 2", 0x0300, 0x42),
+            ("read", 0x0300, 0x42)
+        ]
+    );
+
+    nes.remove_cpu_write_hook(0x0300);
+    log.borrow_mut().clear();
+    nes.clock_cycles(17);
+    assert_eq!(*log.borrow(), [("read", 0x0300, 0x42)]);
+
+    nes.remove_cpu_read_hook(0x0300);
+    log.borrow_mut().clear();
+    nes.clock_cycles(17);
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn expansion_device_state_checked_before_loading() {
+    let program = [
+        0xE6, 0x10, // INC $10
+        0x4C, 0x00, 0x80, // JMP $8000
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+    nes.set_expansion_device(Box::new(ToyDevice {
+        latch: 0x42,
+        cycles: 0,
+    }));
+    let state = nes.save_state_bytes().unwrap();
+
+    nes.clock_for_frame();
+    let ram = nes.dump_memory(0x10, 0x10);
+    let mut device = nes.take_expansion_device().unwrap();
+    device.write(0x5000, 0);
+
+    // there is no device to load the state into
+    assert!(matches!(
+        nes.load_state_bytes(&state),
+        Err(SaveError::CorruptedData)
+    ));
+    assert_eq!(nes.dump_memory(0x10, 0x10), ram);
+
+    // the length of the device state does not match
+    nes.set_expansion_device(device);
+    assert!(matches!(
+        nes.load_state_bytes(&state[..state.len() - 1]),
+        Err(SaveError::CorruptedData)
+    ));
+    assert_eq!(nes.dump_memory(0x10, 0x10), ram);
+
+    nes.load_state_bytes(&state).unwrap();
+    assert_ne!(nes.dump_memory(0x10, 0x10), ram);
+    let mut device = nes.take_expansion_device().unwrap();
+    assert_eq!(device.read(0x5000), Some(0x42));
+}
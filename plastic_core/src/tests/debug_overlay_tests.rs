@@ -0,0 +1,141 @@
+use super::{write_rom, NoUi, TempDir};
+use crate::controller::StandardNESControllerState;
+use crate::input_script::InputStep;
+use crate::nes::NES;
+use crate::nes_debug::DebugOverlay;
+
+/// NROM that fills the background with tile 0 (all pixels color 1) with
+/// white color, and no scrolling
+fn solid_background_rom() -> Vec<u8> {
+    let program = [
+        // wait for vblank
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F, STA $2006
+        0xA9, 0x01, 0x8D, 0x06, 0x20, // LDA #$01, STA $2006
+        0xA9, 0x30, 0x8D, 0x07, 0x20, // LDA #$30, STA $2007
+        // reset the scroll
+        0xA9, 0x00, 0x8D, 0x00, 0x20, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20, // ..
+        0xA9, 0x0A, 0x8D, 0x01, 0x20, // LDA #$0A, STA $2001
+        0x4C, 0x25, 0x80, // JMP loop
+    ];
+
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(&program);
+    // NMI, RESET and IRQ vectors
+    prg[0x3FFA..].copy_from_slice(&[0x25, 0x80, 0x00, 0x80, 0x25, 0x80]);
+    rom.extend_from_slice(&prg);
+    let mut chr = vec![0; 0x2000];
+    chr[..8].fill(0xFF);
+    rom.extend_from_slice(&chr);
+
+    rom
+}
+
+/// run the ROM for some frames with `overlay`, and return the frame hash and
+/// the displayed image
+fn run_with_overlay(overlay: DebugOverlay) -> (u64, Vec<u8>) {
+    let dir = TempDir::new("debug_overlay");
+    let mut nes = NES::new(&write_rom(&dir, &solid_background_rom()), NoUi).unwrap();
+
+    nes.reset();
+    nes.set_debug_overlay(overlay);
+
+    let summary = nes.run_input_script(&[InputStep {
+        hold: StandardNESControllerState::empty(),
+        frames: 3,
+    }]);
+
+    (summary.frame_hash, nes.image())
+}
+
+#[test]
+fn attribute_grid_overlay() {
+    let (plain_hash, plain_image) = run_with_overlay(DebugOverlay::None);
+    let (overlay_hash, overlay_image) = run_with_overlay(DebugOverlay::AttributeGrid);
+
+    assert_eq!(plain_hash, overlay_hash);
+
+    // RGBA pixel at `x`, `y`
+    let pixel = |image: &[u8], x: usize, y: usize| image[(y * 256 + x) * 4..][..4].to_vec();
+
+    let white = pixel(&plain_image, 0, 0);
+    for (x, y) in [(15, 100), (16, 100), (100, 15), (100, 16)] {
+        assert_eq!(pixel(&plain_image, x, y), white);
+        assert_ne!(pixel(&overlay_image, x, y), white);
+    }
+    // the two sides of the attribute area boundaries are tinted differently
+    assert_ne!(
+        pixel(&overlay_image, 15, 100),
+        pixel(&overlay_image, 16, 100)
+    );
+    assert_ne!(
+        pixel(&overlay_image, 100, 15),
+        pixel(&overlay_image, 100, 16)
+    );
+    assert_eq!(
+        pixel(&overlay_image, 16, 100),
+        pixel(&overlay_image, 31, 100)
+    );
+    assert_eq!(
+        pixel(&overlay_image, 100, 16),
+        pixel(&overlay_image, 100, 31)
+    );
+}
+
+#[test]
+fn highlight_tiles_follows_scroll() {
+    let program = [
+        // wait for vblank
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        // tile 5 at column 1, row 1 of the first nametable
+        0xA9, 0x20, 0x8D, 0x06, 0x20, // LDA #$20, STA $2006
+        0xA9, 0x21, 0x8D, 0x06, 0x20, // LDA #$21, STA $2006
+        0xA9, 0x05, 0x8D, 0x07, 0x20, // LDA #$05, STA $2007
+        // scroll 4 pixels to the right
+        0xA9, 0x00, 0x8D, 0x00, 0x20, // LDA #$00, STA $2000
+        0xA9, 0x04, 0x8D, 0x05, 0x20, // LDA #$04, STA $2005
+        0xA9, 0x00, 0x8D, 0x05, 0x20, // LDA #$00, STA $2005
+        0x4C, 0x23, 0x80, // JMP loop
+    ];
+
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(&program);
+    // NMI, RESET and IRQ vectors
+    prg[0x3FFA..].copy_from_slice(&[0x23, 0x80, 0x00, 0x80, 0x23, 0x80]);
+    rom.extend_from_slice(&prg);
+    rom.extend_from_slice(&[0; 0x2000]);
+
+    let dir = TempDir::new("highlight_tiles");
+    let mut nes = NES::new(&write_rom(&dir, &rom), NoUi).unwrap();
+
+    nes.reset();
+    nes.run_input_script(&[InputStep {
+        hold: StandardNESControllerState::empty(),
+        frames: 3,
+    }]);
+    let image_before = nes.image();
+
+    let overlay = nes.debug_highlight_tiles(|tile| tile == 5);
+    assert_eq!(overlay.len(), 256 * 240 * 4);
+
+    let alpha = |x: usize, y: usize| overlay[(y * 256 + x) * 4 + 3];
+    for (x, y) in [(4, 8), (11, 8), (4, 15), (11, 15)] {
+        assert_ne!(alpha(x, y), 0, "({}, {}) is not highlighted", x, y);
+    }
+    for (x, y) in [(3, 8), (12, 8), (4, 7), (4, 16), (0, 0), (255, 239)] {
+        assert_eq!(alpha(x, y), 0, "({}, {}) is highlighted", x, y);
+    }
+    let highlighted = overlay.chunks(4).filter(|pixel| pixel[3] != 0).count();
+    assert_eq!(highlighted, 8 * 8);
+
+    // all the visible tiles are matched, including the partial ones
+    let overlay = nes.debug_highlight_tiles(|_| true);
+    assert!(overlay.chunks(4).all(|pixel| pixel[3] != 0));
+
+    // the emulation is not affected
+    assert_eq!(nes.image(), image_before);
+}
@@ -0,0 +1,70 @@
+use super::NoUi;
+use crate::controller::{StandardNESControllerState, StandardNESKey};
+use crate::nes::{CPU_CYCLES_PER_FRAME, NES};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+const FRAMES: usize = 2000;
+
+/// the same input for every run, changes every few frames to go through
+/// menus and test selections
+fn scripted_input(frame: usize) -> StandardNESControllerState {
+    let mut state = StandardNESControllerState::empty();
+
+    state.set_key(StandardNESKey::Start, frame % 120 < 5);
+    state.set_key(StandardNESKey::A, frame % 30 < 2);
+    state.set_key(StandardNESKey::Right, (frame / 200) % 2 == 1);
+
+    state
+}
+
+fn frame_hash(nes: &mut NES<NoUi>) -> (u64, u64) {
+    let mut image_hasher = DefaultHasher::new();
+    nes.image().hash(&mut image_hasher);
+
+    let mut audio_hasher = DefaultHasher::new();
+    for sample in nes.take_audio_buffer() {
+        sample.to_bits().hash(&mut audio_hasher);
+    }
+
+    (image_hasher.finish(), audio_hasher.finish())
+}
+
+/// run `filename` with `clock` and `clock_for_frame` and make sure that they
+/// produce the same frames and audio
+fn run_clock_equivalence(filename: &str) {
+    let mut by_cycle = NES::new(filename, NoUi).unwrap();
+    let mut by_frame = NES::new(filename, NoUi).unwrap();
+    by_cycle.reset();
+    by_frame.reset();
+
+    for frame in 0..FRAMES {
+        let input = scripted_input(frame);
+        by_cycle.set_controller(0, input);
+        by_frame.set_controller(0, input);
+
+        for _ in 0..CPU_CYCLES_PER_FRAME {
+            by_cycle.clock();
+        }
+        by_frame.clock_for_frame();
+
+        assert_eq!(
+            frame_hash(&mut by_cycle),
+            frame_hash(&mut by_frame),
+            "`clock` and `clock_for_frame` diverged at frame {}",
+            frame
+        );
+    }
+}
+
+#[test]
+fn clock_equivalence_ppu() {
+    run_clock_equivalence("../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes");
+}
+
+#[test]
+fn clock_equivalence_apu() {
+    run_clock_equivalence("../test_roms/blargg_apu_2005.07.30/04.clock_jitter.nes");
+}
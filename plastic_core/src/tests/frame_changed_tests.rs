@@ -0,0 +1,91 @@
+use super::NoUi;
+use crate::controller::StandardNESControllerState;
+use crate::nes::NES;
+
+/// "title screen" program with a transparent background, it fades in the
+/// backdrop color over 5 frames, then waits for START and changes the color
+fn title_screen_program() -> Vec<u8> {
+    let program = [
+        0xA2, 0x00, // LDX #0
+        // fade:
+        0xBD, 0x50, 0x80, // LDA table,X
+        0x20, 0x30, 0x80, // JSR set_color
+        0xE8, // INX
+        0xE0, 0x05, // CPX #5
+        0xD0, 0xF5, // BNE fade
+        // poll: read A, B, SELECT and START
+        0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #1, STA $4016
+        0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #0, STA $4016
+        0xAD, 0x16, 0x40, 0xAD, 0x16, 0x40, // LDA $4016, LDA $4016
+        0xAD, 0x16, 0x40, 0xAD, 0x16, 0x40, // LDA $4016, LDA $4016
+        0x29, 0x01, // AND #1
+        0xF0, 0xE6, // BEQ poll
+        0xA9, 0x16, // LDA #$16
+        0x20, 0x30, 0x80, // JSR set_color
+        0x4C, 0x2C, 0x80, // JMP *
+        0xEA, // NOP
+        // set_color: wait for vblank and write A to the backdrop color
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL set_color
+        0xA0, 0x3F, 0x8C, 0x06, 0x20, // LDY #$3F, STY $2006
+        0xA0, 0x00, 0x8C, 0x06, 0x20, // LDY #0, STY $2006
+        0x8D, 0x07, 0x20, // STA $2007
+        // reset the VRAM address (the scroll) and enable the background
+        0x8C, 0x06, 0x20, 0x8C, 0x06, 0x20, // STY $2006, STY $2006
+        0xA0, 0x0A, 0x8C, 0x01, 0x20, // LDY #$0A, STY $2001
+        0x60, // RTS
+    ];
+
+    let mut prg = vec![0; 0x50];
+    prg[..program.len()].copy_from_slice(&program);
+    // fade-in colors
+    prg.extend_from_slice(&[0x0F, 0x00, 0x10, 0x20, 0x30]);
+
+    prg
+}
+
+#[test]
+fn frame_changed_on_static_screen() {
+    let mut nes = NES::with_test_bus(&title_screen_program(), NoUi);
+
+    // the first frame is always changed
+    nes.clock_for_frame();
+    assert!(nes.frame_changed());
+
+    // the fade-in
+    let mut changed_frames = 0;
+    for _ in 0..10 {
+        nes.clock_for_frame();
+        changed_frames += nes.frame_changed() as u32;
+    }
+    assert!(changed_frames >= 3);
+
+    // static title screen
+    let generation = nes.frame_generation();
+    for _ in 0..10 {
+        nes.clock_for_frame();
+        assert!(!nes.frame_changed());
+    }
+    assert_eq!(nes.frame_generation(), generation);
+
+    let state = nes.save_state_bytes().unwrap();
+
+    nes.set_controller(0, StandardNESControllerState::START);
+    let mut changed_frames = 0;
+    for _ in 0..3 {
+        nes.clock_for_frame();
+        changed_frames += nes.frame_changed() as u32;
+    }
+    assert_eq!(changed_frames, 1);
+    assert_eq!(nes.frame_generation(), generation + 1);
+    nes.clock_for_frame();
+    assert!(!nes.frame_changed());
+
+    // loading a state always changes the frame
+    nes.set_controller(0, StandardNESControllerState::empty());
+    nes.load_state_bytes(&state).unwrap();
+    nes.clock_for_frame();
+    assert!(nes.frame_changed());
+    nes.clock_for_frame();
+    assert!(!nes.frame_changed());
+}
@@ -0,0 +1,77 @@
+use super::NoUi;
+use crate::nes::{FrameLimiter, TvRegion, NES};
+use std::time::Instant;
+
+#[test]
+fn frame_limiter_periods() {
+    let ntsc = FrameLimiter::new(TvRegion::Ntsc);
+    assert!((ntsc.target_fps() - 60.0988).abs() < 0.0001);
+
+    let pal = FrameLimiter::new(TvRegion::Pal);
+    assert!((pal.target_fps() - 50.0070).abs() < 0.0001);
+    assert_eq!(
+        FrameLimiter::new(TvRegion::Dendy).frame_period(),
+        pal.frame_period()
+    );
+}
+
+#[test]
+fn frame_duration_matches_frame_limiter() {
+    let mut nes = NES::with_test_bus(&[], NoUi);
+
+    for region in [TvRegion::Ntsc, TvRegion::Pal, TvRegion::Dendy] {
+        nes.set_region(region);
+        assert_eq!(
+            nes.frame_duration_nanos(),
+            FrameLimiter::new(region).frame_period().as_nanos() as u64
+        );
+    }
+    assert_eq!(nes.frame_duration_nanos(), 19_997_209);
+}
+
+#[test]
+fn frame_limiter_missed_frames() {
+    let mut limiter = FrameLimiter::new(TvRegion::Ntsc);
+    let period = limiter.frame_period();
+
+    let start = Instant::now();
+    for _ in 0..4 {
+        limiter.wait_for_next_frame();
+    }
+    // the first call does not wait
+    assert!(start.elapsed() >= period * 3);
+    assert_eq!(limiter.missed_frames(), 0);
+
+    std::thread::sleep(period * 3 + period / 2);
+    limiter.wait_for_next_frame();
+    assert!(limiter.missed_frames() >= 2);
+
+    // after a reset, the time since the last frame is not counted
+    let missed_frames = limiter.missed_frames();
+    limiter.reset();
+    std::thread::sleep(period * 3);
+    limiter.wait_for_next_frame();
+    assert_eq!(limiter.missed_frames(), missed_frames);
+}
+
+#[cfg(feature = "slow-tests")]
+#[test]
+fn frame_limiter_average_rate() {
+    let mut limiter = FrameLimiter::new(TvRegion::Ntsc);
+
+    limiter.wait_for_next_frame();
+    let start = Instant::now();
+    let mut frames = 0;
+    while start.elapsed() < std::time::Duration::from_secs(10) {
+        limiter.wait_for_next_frame();
+        frames += 1;
+    }
+    let fps = frames as f64 / start.elapsed().as_secs_f64();
+
+    assert!(
+        (fps - limiter.target_fps()).abs() < 0.01,
+        "measured {} fps, expected {}",
+        fps,
+        limiter.target_fps()
+    );
+}
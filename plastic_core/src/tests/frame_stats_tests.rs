@@ -0,0 +1,114 @@
+use super::NoUi;
+use crate::apu2a03::SAMPLE_RATE;
+use crate::nes::{CPU_CYCLES_PER_FRAME, NES};
+use crate::nes_debug::FrameStats;
+
+#[test]
+fn frame_stats_counts() {
+    let mut nes = NES::new(
+        "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+        NoUi,
+    )
+    .unwrap();
+    nes.reset();
+
+    nes.set_stats_enabled(true);
+    for _ in 0..10 {
+        nes.clock_for_frame();
+    }
+
+    let stats = nes.frame_stats();
+    assert_eq!(stats.cpu_cycles as usize, CPU_CYCLES_PER_FRAME);
+    // between 2 and 7 cycles per instruction
+    assert!(stats.cpu_instructions as usize > CPU_CYCLES_PER_FRAME / 7);
+    assert!((stats.cpu_instructions as usize) < CPU_CYCLES_PER_FRAME / 2);
+    // ~60 FPS
+    let samples_per_frame = SAMPLE_RATE / 60;
+    assert!(stats.audio_samples_generated.abs_diff(samples_per_frame) < samples_per_frame / 20);
+    assert!(stats.nmi_count <= 1);
+
+    nes.set_stats_enabled(false);
+    nes.clock_for_frame();
+    assert_eq!(nes.frame_stats(), FrameStats::default());
+}
+
+#[test]
+fn clock_for_frame_summary() {
+    let mut nes = NES::new(
+        "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+        NoUi,
+    )
+    .unwrap();
+    nes.reset();
+
+    let mut audio_samples = 0;
+    for frame in 1..=10 {
+        let summary = nes.clock_for_frame();
+        assert_eq!(summary.cycles_elapsed as usize, CPU_CYCLES_PER_FRAME);
+        assert_eq!(summary.total_frames, frame);
+        audio_samples += summary.audio_samples_produced;
+    }
+    assert_eq!(nes.frame_count(), 10);
+    // stereo samples
+    assert_eq!(nes.audio_buffer_ref().len(), audio_samples * 2);
+    // not taken by the peek
+    let peeked = nes.audio_buffer_ref().to_vec();
+    assert_eq!(nes.take_audio_buffer(), peeked);
+    assert!(nes.audio_buffer_ref().is_empty());
+
+    nes.reset();
+    assert_eq!(nes.frame_count(), 0);
+}
+
+#[test]
+fn clock_until_frame_complete_from_mid_frame() {
+    let mut nes = NES::new(
+        "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+        NoUi,
+    )
+    .unwrap();
+    nes.reset();
+
+    // align to the end of a frame, then step into the middle of the next one
+    nes.clock_until_frame_complete();
+    for _ in 0..10000 {
+        nes.clock();
+    }
+    let cycles = nes.clock_until_frame_complete();
+    assert_eq!(nes.ppu_scanline(), 240);
+    assert!(nes.ppu_dot() < 3);
+    assert!((cycles as usize).abs_diff(CPU_CYCLES_PER_FRAME - 10000) <= 1);
+
+    // already on scanline 240, so it runs a full frame
+    let cycles = nes.clock_until_frame_complete();
+    assert_eq!(nes.ppu_scanline(), 240);
+    assert!((cycles as usize).abs_diff(CPU_CYCLES_PER_FRAME) <= 1);
+}
+
+#[test]
+fn is_frame_complete_when_stepping() {
+    let mut nes = NES::new(
+        "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+        NoUi,
+    )
+    .unwrap();
+    nes.reset();
+
+    // align to the start of a frame
+    while !nes.is_frame_complete() {
+        nes.clock();
+    }
+    assert_eq!(nes.ppu_scanline(), 0);
+    assert!(!nes.is_frame_complete());
+
+    let mut cycles = 0;
+    while !nes.is_frame_complete() {
+        nes.clock();
+        cycles += 1;
+    }
+    assert!((cycles as usize).abs_diff(CPU_CYCLES_PER_FRAME) <= 1);
+
+    // a frame run by `clock_for_frame` is not reported
+    nes.clock_for_frame();
+    assert!(!nes.is_frame_complete());
+}
@@ -0,0 +1,93 @@
+use super::{write_rom, NoUi, TempDir};
+use crate::controller::StandardNESControllerState;
+use crate::display::COLORS;
+use crate::input_script::{frame_hash, InputScript, InputScriptError, InputStep};
+use crate::nes::NES;
+
+#[test]
+fn input_script_parse() {
+    let script = InputScript::parse("A:2  .:58\nstart+Right:1").unwrap();
+
+    assert_eq!(
+        script.steps(),
+        &[
+            InputStep {
+                hold: StandardNESControllerState::A,
+                frames: 2,
+            },
+            InputStep {
+                hold: StandardNESControllerState::empty(),
+                frames: 58,
+            },
+            InputStep {
+                hold: StandardNESControllerState::START | StandardNESControllerState::RIGHT,
+                frames: 1,
+            },
+        ]
+    );
+
+    assert!(matches!(
+        InputScript::parse("A:2 START"),
+        Err(InputScriptError::InvalidToken { index: 1, .. })
+    ));
+    assert!(matches!(
+        InputScript::parse(".:1 A+X:2"),
+        Err(InputScriptError::UnknownButton { index: 1, ref button, .. }) if button == "X"
+    ));
+    assert!(matches!(
+        InputScript::parse("A:-1"),
+        Err(InputScriptError::InvalidFrames { index: 0, .. })
+    ));
+}
+
+/// NROM with CHR RAM that shows an empty background, and changes the
+/// backdrop color to `$16` when START is pressed
+fn start_menu_rom() -> Vec<u8> {
+    let program = [
+        0x78, // SEI
+        0xA9, 0x08, 0x8D, 0x01, 0x20, // LDA #$08, STA $2001
+        // loop: strobe the controller and read the first 4 buttons
+        0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #$01, STA $4016
+        0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #$00, STA $4016
+        0xAD, 0x16, 0x40, // LDA $4016 (A)
+        0xAD, 0x16, 0x40, // LDA $4016 (B)
+        0xAD, 0x16, 0x40, // LDA $4016 (SELECT)
+        0xAD, 0x16, 0x40, // LDA $4016 (START)
+        0x29, 0x01, // AND #$01
+        0xF0, 0xE6, // BEQ loop
+        // wait for vblank
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F, STA $2006
+        0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00, STA $2006
+        0xA9, 0x16, 0x8D, 0x07, 0x20, // LDA #$16, STA $2007
+        0xA9, 0x00, 0x8D, 0x06, 0x20, 0x8D, 0x06, 0x20, // $2006 = $0000
+        0x4C, 0x3C, 0x80, // JMP $803C
+    ];
+
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(&program);
+    // NMI, RESET and IRQ vectors
+    prg[0x3FFA..].copy_from_slice(&[0x3C, 0x80, 0x00, 0x80, 0x3C, 0x80]);
+    rom.extend_from_slice(&prg);
+
+    rom
+}
+
+#[test]
+fn input_script_start_menu() {
+    let dir = TempDir::new("input_script");
+    let mut nes = NES::new(&write_rom(&dir, &start_menu_rom()), NoUi).unwrap();
+    nes.reset();
+
+    let before_start = nes.run_input_script(InputScript::parse(".:10").unwrap().steps());
+    let summary = nes.run_input_script(InputScript::parse("START:2 .:10").unwrap().steps());
+
+    let backdrop = COLORS[0x16].to_rgba();
+    let expected = backdrop.repeat(256 * 240);
+
+    assert_eq!(summary.frames, 12);
+    assert_eq!(summary.frame_hash, frame_hash(&expected));
+    assert_ne!(before_start.frame_hash, summary.frame_hash);
+}
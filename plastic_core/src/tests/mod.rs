@@ -6,19 +6,63 @@ use crate::common::{
     save_state::{Savable, SaveError},
     Bus, Device,
 };
+use crate::controller::StandardNESControllerState;
 use crate::cpu6502::{CPUBusTrait, CPURunState, CPU6502};
-use crate::display::{COLORS, TV};
+use crate::display::{Color, COLORS, TV};
 use crate::ppu2c02::{Palette, VRam, PPU2C02};
+use crate::{BackendEvent, UiEvent, UiProvider};
 use std::{
     cell::{Cell, RefCell},
     convert::From,
     error::Error,
     fmt::{Debug, Display, Formatter, Result as fmtResult},
+    fs::File,
+    path::{Path, PathBuf},
     rc::Rc,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
 };
 
 mod blargg_tests;
+mod callback_tests;
+mod chr_edit_tests;
+mod cpu_bus_tests;
+mod debug_overlay_tests;
+#[cfg(feature = "slow-tests")]
+mod determinism_tests;
+mod frame_changed_tests;
+mod frame_limiter_tests;
+mod frame_stats_tests;
+mod input_script_tests;
+mod power_cycle_tests;
+mod ppu_status_tests;
+mod reload_rom_tests;
+mod slot_file_tests;
+mod state_diff_tests;
+mod video_emulation_tests;
+mod vs_system_tests;
+
+/// UI provider for running [`NES`](crate::nes::NES) in tests without a UI
+struct NoUi;
+
+impl UiProvider for NoUi {
+    fn get_tv_color_converter() -> fn(&Color) -> [u8; 4] {
+        |color| [color.r, color.g, color.b, 0xFF]
+    }
+
+    fn run_ui_loop(
+        &mut self,
+        _ui_to_nes_sender: Sender<UiEvent>,
+        _nes_to_ui_receiver: Receiver<BackendEvent>,
+        _image: Arc<Mutex<Vec<u8>>>,
+        _ctrl_state: Arc<Mutex<StandardNESControllerState>>,
+    ) {
+        unreachable!()
+    }
+}
 
 // FIXME: used constants hosted in TV
 const TV_WIDTH: u32 = 256;
@@ -93,6 +137,10 @@ impl Bus for PPUBus {
             0x4000..=0xFFFF => self.write(address & 0x3FFF, data, device),
         }
     }
+
+    fn ppu_a12_rising_edge(&self) {
+        self.cartridge.borrow_mut().notify_ppu_a12_rising_edge();
+    }
 }
 
 impl Savable for PPUBus {
@@ -256,16 +304,74 @@ impl CPUIrqProvider for CPUBus {
     }
 }
 
+/// a new directory in the system temporary directory, unique for every
+/// call even for tests running in parallel, it is removed with its content
+/// on drop
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "plastic_{}_{}_{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// write the `.nes` file `rom` in `dir`, and return its path
+fn write_rom(dir: &TempDir, rom: &[u8]) -> String {
+    let path = dir.path().join("test.nes");
+    std::fs::write(&path, rom).unwrap();
+
+    path.to_str().unwrap().to_owned()
+}
+
 pub struct NesTester {
+    cartridge: Rc<RefCell<Cartridge>>,
     cpu: CPU6502<CPUBus>,
     ppu: Rc<RefCell<PPU2C02<PPUBus>>>,
     tv_image: Arc<Mutex<Vec<u8>>>,
     apu: Rc<RefCell<APU2A03>>,
+    /// the battery save is written here when the cartridge is dropped, so it
+    /// must be dropped after all the other fields
+    _sram_dir: TempDir,
 }
 
 impl NesTester {
     pub fn new(filename: &str) -> Result<Self, CartridgeError> {
-        let cartridge = Rc::new(RefCell::new(Cartridge::from_file(filename)?));
+        // the cartridge is loaded as if it is in a temporary directory, so
+        // the battery save is not written next to the ROM, the one next to
+        // the ROM is copied to be loaded
+        let sram_dir = TempDir::new("tester");
+        let rom_path = Path::new(filename);
+        let sram_path = sram_dir
+            .path()
+            .join(rom_path.file_name().unwrap_or_default());
+        if let Ok(sram) = std::fs::read(rom_path.with_extension("nes.sav")) {
+            std::fs::write(sram_path.with_extension("nes.sav"), sram).unwrap();
+        }
+
+        let cartridge = Cartridge::from_reader(&sram_path, File::open(rom_path)?)?;
+        let cartridge = Rc::new(RefCell::new(cartridge));
 
         let ppubus = PPUBus::new(cartridge.clone());
 
@@ -276,18 +382,26 @@ impl NesTester {
 
         let apu = Rc::new(RefCell::new(APU2A03::new()));
 
-        let cpubus = CPUBus::new(cartridge, ppu.clone(), apu.clone());
+        let cpubus = CPUBus::new(cartridge.clone(), ppu.clone(), apu.clone());
 
         let cpu = CPU6502::new(cpubus);
 
         Ok(Self {
+            cartridge,
             cpu,
             ppu,
             tv_image,
             apu,
+            _sram_dir: sram_dir,
         })
     }
 
+    /// provide 8K of RAM in `0x6000-0x7FFF` even if the cartridge has none,
+    /// test ROMs write their results there
+    pub fn force_prg_ram(&mut self) {
+        self.cartridge.borrow_mut().set_force_prg_ram(true);
+    }
+
     pub fn reset_cpu(&mut self) {
         self.cpu.reset();
     }
@@ -304,6 +418,7 @@ impl NesTester {
         self.apu.borrow_mut().clock();
 
         let return_value = self.cpu.run_next();
+        self.cartridge.borrow_mut().cpu_clock();
 
         {
             let mut ppu = self.ppu.borrow_mut();
@@ -0,0 +1,120 @@
+use super::{write_rom, NoUi, TempDir};
+use crate::display::COLORS;
+use crate::nes::NES;
+
+/// NROM that writes `$2001` to enable rendering as the first instruction and
+/// loops forever, tile 0 is filled with color 1
+fn early_mask_write_rom() -> Vec<u8> {
+    let program = [
+        0xA9, 0x1E, 0x8D, 0x01, 0x20, // LDA #$1E, STA $2001
+        0x4C, 0x05, 0x80, // JMP loop
+    ];
+
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(&program);
+    // NMI, RESET and IRQ vectors
+    prg[0x3FFA..].copy_from_slice(&[0x05, 0x80, 0x00, 0x80, 0x05, 0x80]);
+    rom.extend_from_slice(&prg);
+    let mut chr = vec![0; 0x2000];
+    chr[..8].fill(0xFF);
+    rom.extend_from_slice(&chr);
+
+    rom
+}
+
+#[test]
+fn power_cycle_starts_ppu_warmup() {
+    let mut nes = NES::new(
+        "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+        NoUi,
+    )
+    .unwrap();
+
+    nes.reset();
+    assert_eq!(nes.warmup_cycles_remaining(), 0);
+
+    nes.power_cycle();
+    assert_eq!(nes.warmup_cycles_remaining(), 29658);
+
+    for _ in 0..1000 {
+        nes.clock();
+    }
+    assert_eq!(nes.warmup_cycles_remaining(), 29658 - 1000);
+
+    nes.clock_for_frame();
+    assert_eq!(nes.warmup_cycles_remaining(), 0);
+
+    // only on power-on
+    nes.reset();
+    assert_eq!(nes.warmup_cycles_remaining(), 0);
+}
+
+#[test]
+fn ppu_warmup_ignores_early_mask_write() {
+    let dir = TempDir::new("ppu_warmup");
+    let mut nes = NES::new(&write_rom(&dir, &early_mask_write_rom()), NoUi).unwrap();
+
+    // color 1 of the power-up palette
+    let tile_color = COLORS[0x01].to_rgba();
+    let pixel = |image: &[u8]| image[(100 * 256 + 100) * 4..][..4].to_vec();
+
+    // the write is ignored, so rendering is never enabled
+    nes.power_cycle();
+    for _ in 0..2 {
+        nes.clock_for_frame();
+    }
+    assert_ne!(pixel(&nes.image()), tile_color);
+
+    nes.set_ppu_warmup_enabled(false);
+    nes.power_cycle();
+    assert_eq!(nes.warmup_cycles_remaining(), 0);
+    for _ in 0..2 {
+        nes.clock_for_frame();
+    }
+    assert_eq!(pixel(&nes.image()), tile_color);
+}
+
+#[test]
+fn random_seed_reproducible_power_on_ram() {
+    let power_on = |seed: Option<u64>| {
+        let mut nes = NES::new(
+            "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+            NoUi,
+        )
+        .unwrap();
+        nes.set_randomize_power_on_ram(true);
+        if let Some(seed) = seed {
+            nes.set_random_seed(seed);
+        }
+        nes.power_cycle();
+
+        let ram = nes.dump_memory(0x0000, 0x07FF);
+        for _ in 0..5 {
+            nes.clock_for_frame();
+        }
+
+        (ram, nes.image())
+    };
+
+    let (ram, image) = power_on(Some(42));
+    assert!(ram.iter().any(|&byte| byte != 0));
+    assert_eq!(power_on(Some(42)), (ram.clone(), image));
+    assert_ne!(power_on(Some(43)).0, ram);
+
+    // the default seed is fixed
+    assert_eq!(power_on(None).0, power_on(None).0);
+}
+
+#[test]
+fn power_on_ram_cleared_by_default() {
+    let mut nes = NES::new(
+        "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+        NoUi,
+    )
+    .unwrap();
+    nes.set_random_seed(42);
+    nes.power_cycle();
+
+    assert_eq!(nes.dump_memory(0x0000, 0x07FF), vec![0; 0x800]);
+}
@@ -0,0 +1,82 @@
+use super::NoUi;
+use crate::nes::NES;
+
+/// 9 sprites on the same scanlines (`0x21-0x28`) over an opaque background,
+/// so both sprite 0 hit and sprite overflow are set in every frame
+fn sprite_flags_program() -> Vec<u8> {
+    vec![
+        0x78, // SEI
+        // wait for 2 vblanks
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        // tile 0 is opaque
+        0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00, STA $2006
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0xFF, // LDA #$FF
+        0xA2, 0x08, // LDX #$08
+        0x8D, 0x07, 0x20, // STA $2007
+        0xCA, // DEX
+        0xD0, 0xFA, // BNE -6
+        // fill the OAM page with `0xFF`, sprites are off screen
+        0xA2, 0x00, // LDX #$00
+        0x9D, 0x00, 0x02, // STA $0200,X
+        0xE8, // INX
+        0xD0, 0xFA, // BNE -6
+        // 9 sprites of tile `0xFF` at X=0x20, Y=0x20
+        0xA9, 0x20, // LDA #$20
+        0xA2, 0x00, // LDX #$00
+        0x9D, 0x00, 0x02, // STA $0200,X
+        0x9D, 0x03, 0x02, // STA $0203,X
+        0xE8, 0xE8, 0xE8, 0xE8, // INX * 4
+        0xE0, 0x24, // CPX #$24
+        0xD0, 0xF2, // BNE -14
+        // tile `0xFF` is opaque
+        0xA9, 0x0F, 0x8D, 0x06, 0x20, // LDA #$0F, STA $2006
+        0xA9, 0xF0, 0x8D, 0x06, 0x20, // LDA #$F0, STA $2006
+        0xA9, 0xFF, // LDA #$FF
+        0xA2, 0x08, // LDX #$08
+        0x8D, 0x07, 0x20, // STA $2007
+        0xCA, // DEX
+        0xD0, 0xFA, // BNE -6
+        // OAM DMA from page 2
+        0xA9, 0x00, 0x8D, 0x03, 0x20, // LDA #$00, STA $2003
+        0xA9, 0x02, 0x8D, 0x14, 0x40, // LDA #$02, STA $4014
+        // reset the scroll and enable rendering
+        0xA9, 0x00, 0x8D, 0x00, 0x20, // LDA #$00, STA $2000
+        0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20, // STA $2005, STA $2005
+        0xA9, 0x1E, 0x8D, 0x01, 0x20, // LDA #$1E, STA $2001
+        0x4C, 0x65, 0x80, // JMP loop
+    ]
+}
+
+fn clock_until_scanline(nes: &mut NES<NoUi>, scanline: i32) {
+    while nes.ppu_scanline() != scanline {
+        nes.clock();
+    }
+}
+
+#[test]
+fn sprite_flags_without_side_effects() {
+    let mut nes = NES::with_test_bus(&sprite_flags_program(), NoUi);
+    for _ in 0..5 {
+        nes.clock_for_frame();
+    }
+
+    // cleared on the pre-render scanline
+    clock_until_scanline(&mut nes, 10);
+    assert!(!nes.sprite_zero_hit());
+    assert!(!nes.sprite_overflow());
+
+    clock_until_scanline(&mut nes, 100);
+    assert!(nes.sprite_zero_hit());
+    assert!(nes.sprite_overflow());
+
+    // reading the flags does not clear the vblank flag
+    clock_until_scanline(&mut nes, 245);
+    assert!(nes.ppustatus() & 0x80 != 0);
+    assert!(nes.sprite_zero_hit());
+    assert!(nes.sprite_overflow());
+    assert_eq!(nes.ppustatus() & 0xE0, 0xE0);
+}
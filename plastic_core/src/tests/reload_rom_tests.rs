@@ -0,0 +1,129 @@
+use super::{write_rom, NoUi, TempDir};
+use crate::cartridge::CartridgeError;
+use crate::display::COLORS;
+use crate::nes::{ReloadMode, NES};
+use std::{cell::Cell, rc::Rc};
+
+/// offset of the CHR ROM in the file
+const CHR_OFFSET: usize = 16 + 0x4000;
+
+/// NROM with 8KB PRG RAM (NES 2.0 header), that writes `$A5` to `$6000`
+/// then shows tile 0 in all the background with black and white colors, and
+/// keeps reading `$6000`
+fn background_rom() -> Vec<u8> {
+    let program = [
+        0x78, // SEI
+        0xA9, 0xA5, 0x8D, 0x00, 0x60, // LDA #$A5, STA $6000
+        // wait for vblank
+        0x2C, 0x02, 0x20, // BIT $2002
+        0x10, 0xFB, // BPL -5
+        0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F, STA $2006
+        0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00, STA $2006
+        0xA9, 0x0F, 0x8D, 0x07, 0x20, // LDA #$0F, STA $2007
+        0xA9, 0x30, 0x8D, 0x07, 0x20, // LDA #$30, STA $2007
+        // reset the scroll
+        0xA9, 0x00, 0x8D, 0x00, 0x20, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20, 0xA9, 0x0A, 0x8D, 0x01,
+        0x20, // LDA #$0A, STA $2001
+        // loop
+        0xAD, 0x00, 0x60, // LDA $6000
+        0x4C, 0x2F, 0x80, // JMP loop
+    ];
+
+    let mut rom = vec![
+        b'N', b'E', b'S', 0x1A, 1, 1, 0, 0x08, 0, 0, 0x07, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(&program);
+    // NMI, RESET and IRQ vectors
+    prg[0x3FFA..].copy_from_slice(&[0x2F, 0x80, 0x00, 0x80, 0x2F, 0x80]);
+    rom.extend_from_slice(&prg);
+    rom.extend_from_slice(&[0; 0x2000]);
+
+    rom
+}
+
+#[test]
+fn reload_rom_data_keeps_ram() {
+    let dir = TempDir::new("reload_rom");
+    let mut rom = background_rom();
+    let path = write_rom(&dir, &rom);
+
+    let mut nes = NES::new(&path, NoUi).unwrap();
+    nes.reset();
+
+    let marker = Rc::new(Cell::new(0));
+    let marker_hook = marker.clone();
+    nes.set_cpu_read_hook(0x6000, Rc::new(move |_, data| marker_hook.set(data)));
+
+    let black = COLORS[0x0F].to_rgba();
+    let white = COLORS[0x30].to_rgba();
+    // RGBA pixel at `x`, `y`
+    let pixel = |image: &[u8], x: usize, y: usize| image[(y * 256 + x) * 4..][..4].to_vec();
+
+    for _ in 0..3 {
+        nes.clock_for_frame();
+    }
+    assert_eq!(pixel(&nes.image(), 100, 8), black);
+    assert_eq!(marker.get(), 0xA5);
+
+    // the first row of tile 0 is color 1
+    rom[CHR_OFFSET] = 0xFF;
+    std::fs::write(&path, &rom).unwrap();
+    nes.reload_rom_data(&path, ReloadMode::RomOnly).unwrap();
+
+    // the program only writes `$6000` on reset, so the marker is kept
+    marker.set(0);
+    for _ in 0..2 {
+        nes.clock_for_frame();
+    }
+    let image = nes.image();
+    assert_eq!(pixel(&image, 100, 8), white);
+    assert_eq!(pixel(&image, 100, 9), black);
+    assert_eq!(marker.get(), 0xA5);
+
+    // different PRG size
+    let mut other_rom = background_rom();
+    other_rom[4] = 2;
+    other_rom.splice(16..16, vec![0; 0x4000]);
+    std::fs::write(&path, &other_rom).unwrap();
+    assert!(matches!(
+        nes.reload_rom_data(&path, ReloadMode::RomOnly),
+        Err(CartridgeError::ReloadMismatch)
+    ));
+}
+
+#[test]
+fn load_rom_replaces_cartridge() {
+    let dir = TempDir::new("load_rom");
+    let path = write_rom(&dir, &background_rom());
+    let other_path = dir.path().join("other.nes");
+    // writes `$5A` to `$6000` instead
+    let mut other_rom = background_rom();
+    other_rom[16 + 2] = 0x5A;
+    std::fs::write(&other_path, &other_rom).unwrap();
+
+    let mut nes = NES::new(&path, NoUi).unwrap();
+    nes.reset();
+
+    let marker = Rc::new(Cell::new(0));
+    let marker_hook = marker.clone();
+    nes.set_cpu_read_hook(0x6000, Rc::new(move |_, data| marker_hook.set(data)));
+
+    for _ in 0..2 {
+        nes.clock_for_frame();
+    }
+    assert_eq!(marker.get(), 0xA5);
+
+    nes.load_rom(other_path.to_str().unwrap()).unwrap();
+    assert!(nes.warmup_cycles_remaining() > 0);
+    for _ in 0..2 {
+        nes.clock_for_frame();
+    }
+    assert_eq!(marker.get(), 0x5A);
+
+    // the current cartridge is kept on error
+    assert!(nes.load_rom("missing.nes").is_err());
+    marker.set(0);
+    nes.clock_for_frame();
+    assert_eq!(marker.get(), 0x5A);
+}
@@ -0,0 +1,90 @@
+use super::{NoUi, TempDir};
+use crate::common::save_state::SaveError;
+use crate::nes::NES;
+
+#[test]
+fn slot_file_round_trip() {
+    let temp_dir = TempDir::new("slot_files");
+    let dir = temp_dir.path();
+
+    let mut nes = NES::new(
+        "../test_roms/ppu_sprite_overflow/ppu_sprite_overflow.nes",
+        NoUi,
+    )
+    .unwrap();
+    nes.reset();
+
+    assert_eq!(
+        nes.save_state_file_name(3).as_deref(),
+        Some("ppu_sprite_overflow_3.pst")
+    );
+
+    for _ in 0..5 {
+        nes.clock_for_frame();
+    }
+    let path = nes.save_state_to_slot_file(dir, 3).unwrap();
+    assert_eq!(path, dir.join("ppu_sprite_overflow_3.pst"));
+    let state = nes.save_state_bytes().unwrap();
+
+    for _ in 0..5 {
+        nes.clock_for_frame();
+    }
+    assert_ne!(nes.save_state_bytes().unwrap(), state);
+
+    nes.load_state_from_slot_file(dir, 3).unwrap();
+    assert_eq!(nes.save_state_bytes().unwrap(), state);
+
+    // missing files are IO errors, and broken files are not
+    assert!(matches!(
+        nes.load_state_from_slot_file(dir, 4),
+        Err(SaveError::IoError(_))
+    ));
+    std::fs::write(&path, [1, 2, 3]).unwrap();
+    assert!(matches!(
+        nes.load_state_from_slot_file(dir, 3),
+        Err(SaveError::CorruptedData)
+    ));
+}
+
+#[test]
+fn random_states_do_not_panic() {
+    fn next_random(state: &mut u32) -> u32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state
+    }
+
+    let mut nes = NES::new(
+        "../test_roms/holy-mapperel-bin-0.02/testroms/M4_P128K_CR8K.nes",
+        NoUi,
+    )
+    .unwrap();
+    nes.reset();
+    nes.clock_for_frame();
+    let state = nes.save_state_bytes().unwrap();
+    let mut random = 0x1234_5678;
+
+    for _ in 0..200 {
+        // random bytes, and valid states with random bytes changed
+        let mut data = if next_random(&mut random) & 1 == 0 {
+            let len = next_random(&mut random) as usize % (state.len() * 2);
+            (0..len).map(|_| next_random(&mut random) as u8).collect()
+        } else {
+            state.clone()
+        };
+
+        for _ in 0..1 + next_random(&mut random) % 8 {
+            if !data.is_empty() {
+                let index = next_random(&mut random) as usize % data.len();
+                data[index] = next_random(&mut random) as u8;
+            }
+        }
+
+        let _ = nes.load_state_bytes(&data);
+    }
+
+    // the emulator is usable again after loading a valid state
+    nes.load_state_bytes(&state).unwrap();
+    assert_eq!(nes.save_state_bytes().unwrap(), state);
+}
@@ -0,0 +1,90 @@
+use super::NoUi;
+use crate::nes::NES;
+use crate::state_diff::{MemoryChange, RegisterChange, StateSnapshot};
+
+fn snapshot() -> StateSnapshot {
+    StateSnapshot {
+        cpu_registers: vec![("PC", 0xC000), ("A", 0x00)],
+        ram: vec![0; 0x800],
+        ppu_registers: vec![("PPUCTRL", 0x80)],
+        mapper_state: vec![0, 1, 2],
+    }
+}
+
+#[test]
+fn state_diff_changes() {
+    let old = snapshot();
+    let mut new = snapshot();
+
+    assert!(old.diff(&new).is_empty());
+
+    new.cpu_registers[1].1 = 0x12;
+    new.ram[0x300] = 0x55;
+    new.mapper_state[2] = 7;
+
+    let diff = old.diff(&new);
+
+    assert_eq!(
+        diff.cpu_registers,
+        vec![RegisterChange {
+            name: "A",
+            old: 0,
+            new: 0x12
+        }]
+    );
+    assert_eq!(
+        diff.ram,
+        vec![MemoryChange {
+            address: 0x300,
+            old: 0,
+            new: 0x55
+        }]
+    );
+    assert!(diff.ppu_registers.is_empty());
+    assert_eq!(
+        diff.mapper_state,
+        vec![MemoryChange {
+            address: 2,
+            old: 2,
+            new: 7
+        }]
+    );
+
+    let text = diff.to_string();
+    assert!(text.contains("CPU registers:"));
+    assert!(text.contains("A          0000 -> 0012"));
+    assert!(text.contains("$0300      00 -> 55"));
+    assert!(!text.contains("PPU registers:"));
+}
+
+#[test]
+fn diff_states_keeps_the_console() {
+    let program = [
+        0xE6, 0x10, // INC $10
+        0x4C, 0x00, 0x80, // JMP $8000
+    ];
+    let mut nes = NES::with_test_bus(&program, NoUi);
+
+    let state_a = nes.save_state_bytes().unwrap();
+    while nes.dump_memory(0x10, 0x10) == [0] {
+        nes.clock();
+    }
+    let state_b = nes.save_state_bytes().unwrap();
+    nes.clock_for_frame();
+    let current = nes.save_state_bytes().unwrap();
+
+    let diff = nes.diff_states(&state_a, &state_b).unwrap();
+    assert_eq!(
+        diff.ram,
+        vec![MemoryChange {
+            address: 0x10,
+            old: 0,
+            new: 1
+        }]
+    );
+    assert!(diff.cpu_registers.iter().any(|change| change.name == "PC"));
+    assert!(nes.diff_states(&state_b, &state_b).unwrap().is_empty());
+    assert_eq!(nes.save_state_bytes().unwrap(), current);
+
+    assert!(nes.diff_states(&state_a, &state_b[..100]).is_err());
+}
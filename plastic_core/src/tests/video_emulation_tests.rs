@@ -0,0 +1,83 @@
+use super::NoUi;
+use crate::nes::{VideoEmulation, NES};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+const FRAMES: usize = 600;
+
+/// run `filename` for [`FRAMES`] frames with `mode` and return the hash of
+/// the audio and the CPU RAM, and the final image
+fn run_with_video_emulation(filename: &str, mode: VideoEmulation) -> (u64, u64, Vec<u8>) {
+    let mut nes = NES::new(filename, NoUi).unwrap();
+    nes.reset();
+    nes.set_video_emulation(mode);
+
+    let mut audio_hasher = DefaultHasher::new();
+    for _ in 0..FRAMES {
+        nes.clock_for_frame();
+        for sample in nes.take_audio_buffer() {
+            sample.to_bits().hash(&mut audio_hasher);
+        }
+    }
+
+    let mut ram_hasher = DefaultHasher::new();
+    nes.dump_memory(0x0000, 0x07FF).hash(&mut ram_hasher);
+
+    (audio_hasher.finish(), ram_hasher.finish(), nes.image())
+}
+
+fn assert_same_timing(filename: &str) {
+    let (full_audio, full_ram, full_image) =
+        run_with_video_emulation(filename, VideoEmulation::Full);
+    let (timing_audio, timing_ram, timing_image) =
+        run_with_video_emulation(filename, VideoEmulation::TimingOnly);
+
+    assert_eq!(full_audio, timing_audio, "the audio is different");
+    assert_eq!(full_ram, timing_ram, "the RAM is different");
+    // no frame is drawn
+    assert_ne!(full_image, timing_image);
+    assert!(timing_image.iter().all(|&byte| byte == timing_image[0]));
+}
+
+#[test]
+fn timing_only_same_audio() {
+    assert_same_timing("../test_roms/blargg_apu_2005.07.30/04.clock_jitter.nes");
+}
+
+#[test]
+fn timing_only_keeps_sprite_0_hit() {
+    assert_same_timing("../test_roms/sprite_hit_tests/01.basics.nes");
+}
+
+#[test]
+fn timing_only_keeps_last_frame() {
+    let filename = "../test_roms/sprite_hit_tests/01.basics.nes";
+    let mut nes = NES::new(filename, NoUi).unwrap();
+    nes.reset();
+
+    for _ in 0..60 {
+        nes.clock_for_frame();
+    }
+    let last_frame = nes.image();
+
+    nes.set_video_emulation(VideoEmulation::TimingOnly);
+    for _ in 0..60 {
+        nes.clock_for_frame();
+    }
+    assert_eq!(nes.image(), last_frame);
+
+    // going back to full draws the same frames as if it was never changed
+    nes.set_video_emulation(VideoEmulation::Full);
+    for _ in 0..60 {
+        nes.clock_for_frame();
+    }
+
+    let mut full_nes = NES::new(filename, NoUi).unwrap();
+    full_nes.reset();
+    for _ in 0..180 {
+        full_nes.clock_for_frame();
+    }
+    assert_eq!(nes.image(), full_nes.image());
+}
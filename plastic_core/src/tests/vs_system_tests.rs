@@ -0,0 +1,119 @@
+use super::{write_rom, NoUi, TempDir};
+use crate::nes::{VsPpuType, NES};
+
+/// mapper 99 with 32KB PRG and 16KB CHR (filled with the bank number),
+/// selects the second CHR bank and writes `0x42` to `$6000`, or NROM if
+/// `vs_system` is `false`
+fn vs_rom(vs_system: bool) -> Vec<u8> {
+    let program = [
+        0xA9, 0x04, 0x8D, 0x16, 0x40, // LDA #$04, STA $4016
+        0xA9, 0x42, 0x8D, 0x00, 0x60, // LDA #$42, STA $6000
+        0x4C, 0x0A, 0x80, // JMP loop
+    ];
+
+    let (flags_6, flags_7) = if vs_system { (0x30, 0x61) } else { (0, 0) };
+    let mut rom = vec![
+        b'N', b'E', b'S', 0x1A, 2, 2, flags_6, flags_7, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = vec![0; 0x8000];
+    prg[..program.len()].copy_from_slice(&program);
+    // NMI, RESET and IRQ vectors
+    prg[0x7FFA..].copy_from_slice(&[0x0A, 0x80, 0x00, 0x80, 0x0A, 0x80]);
+    rom.extend_from_slice(&prg);
+    rom.extend_from_slice(&[0; 0x2000]);
+    rom.extend_from_slice(&[1; 0x2000]);
+
+    rom
+}
+
+fn load_vs_rom(vs_system: bool) -> NES<NoUi> {
+    // the ROM is only read while loading
+    let dir = TempDir::new("vs_system");
+
+    NES::new(&write_rom(&dir, &vs_rom(vs_system)), NoUi).unwrap()
+}
+
+#[test]
+fn dip_switches_and_service_button() {
+    let mut nes = load_vs_rom(true);
+    assert_eq!(nes.vs_ppu_type(), Some(VsPpuType::Rp2c03));
+    assert_eq!(nes.dump_memory(0x4016, 0x4017), [0, 0]);
+
+    nes.set_dip_switches(0b1010_0110);
+    // DIP switches 1-2 in bits 3-4 of `$4016`, and 3-8 in bits 2-7 of `$4017`
+    assert_eq!(nes.dump_memory(0x4016, 0x4017), [0b0001_0000, 0b1010_0100]);
+
+    nes.set_vs_service_button(true);
+    assert_eq!(nes.dump_memory(0x4016, 0x4016), [0b0001_0100]);
+}
+
+#[test]
+fn coin_is_held_for_a_few_frames() {
+    let mut nes = load_vs_rom(true);
+
+    nes.insert_coin(0);
+    assert_eq!(nes.dump_memory(0x4016, 0x4016), [0b0010_0000]);
+    nes.insert_coin(1);
+    assert_eq!(nes.dump_memory(0x4016, 0x4016), [0b0110_0000]);
+    // there are only 2 coin slots
+    nes.insert_coin(2);
+    assert_eq!(nes.dump_memory(0x4016, 0x4016), [0b0110_0000]);
+
+    nes.clock_for_frame();
+    assert_eq!(nes.dump_memory(0x4016, 0x4016), [0b0110_0000]);
+
+    for _ in 0..3 {
+        nes.clock_for_frame();
+    }
+    assert_eq!(nes.dump_memory(0x4016, 0x4016), [0]);
+}
+
+#[test]
+fn coins_and_dip_switches_in_save_state() {
+    let mut nes = load_vs_rom(true);
+    nes.set_dip_switches(0b1010_0110);
+    nes.insert_coin(1);
+    let state = nes.save_state_bytes().unwrap();
+
+    nes.set_dip_switches(0);
+    for _ in 0..4 {
+        nes.clock_for_frame();
+    }
+    assert_eq!(nes.dump_memory(0x4016, 0x4017), [0, 0]);
+
+    nes.load_state_bytes(&state).unwrap();
+    assert_eq!(nes.dump_memory(0x4016, 0x4017), [0b0101_0000, 0b1010_0100]);
+
+    // the coin timer continues from the saved state
+    for _ in 0..4 {
+        nes.clock_for_frame();
+    }
+    assert_eq!(nes.dump_memory(0x4016, 0x4017), [0b0001_0000, 0b1010_0100]);
+}
+
+#[test]
+fn controller_port_selects_chr_bank() {
+    let mut nes = load_vs_rom(true);
+    nes.reset();
+    let mut chr = [0xFF; 1];
+
+    nes.read_chr(0x0000, &mut chr);
+    assert_eq!(chr, [0]);
+
+    nes.clock_for_frame();
+    nes.read_chr(0x0000, &mut chr);
+    assert_eq!(chr, [1]);
+
+    // the 2KB work RAM is mirrored in `0x6000-0x7FFF`
+    assert_eq!(nes.dump_memory(0x7800, 0x7800), [0x42]);
+}
+
+#[test]
+fn vs_inputs_ignored_for_nes_roms() {
+    let mut nes = load_vs_rom(false);
+    assert_eq!(nes.vs_ppu_type(), None);
+
+    nes.insert_coin(0);
+    nes.set_dip_switches(0xFF);
+    assert_eq!(nes.dump_memory(0x4016, 0x4017), [0, 0]);
+}
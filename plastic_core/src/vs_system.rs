@@ -0,0 +1,105 @@
+use crate::common::save_state::{Savable, SaveError};
+use crate::nes::CPU_CYCLES_PER_FRAME;
+
+/// how long the coin switch is held after [`NES::insert_coin`], games
+/// check it once per frame, so it must be held for a few frames
+///
+/// [`NES::insert_coin`]: crate::nes::NES::insert_coin
+const COIN_PULSE_CYCLES: u32 = CPU_CYCLES_PER_FRAME as u32 * 3;
+
+/// The inputs of the VS. System cabinet, they are read in the upper bits of
+/// `$4016` and `$4017` together with the controllers
+pub(crate) struct VsSystemInputs {
+    /// DIP switch 1 in bit 0 to DIP switch 8 in bit 7
+    dip_switches: u8,
+    service_button: bool,
+    /// remaining CPU cycles of the coin switch of each slot
+    coin_cycles: [u32; 2],
+    /// read the first controller from `$4017` and the second from `$4016`,
+    /// some games are wired like this
+    swap_controllers: bool,
+}
+
+impl VsSystemInputs {
+    pub fn new() -> Self {
+        Self {
+            dip_switches: 0,
+            service_button: false,
+            coin_cycles: [0; 2],
+            swap_controllers: false,
+        }
+    }
+
+    pub fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.dip_switches = dip_switches;
+    }
+
+    pub fn set_service_button(&mut self, pressed: bool) {
+        self.service_button = pressed;
+    }
+
+    pub fn set_swap_controllers(&mut self, swap: bool) {
+        self.swap_controllers = swap;
+    }
+
+    pub fn swap_controllers(&self) -> bool {
+        self.swap_controllers
+    }
+
+    /// hold the coin switch of `slot` (`0` or `1`) for a few frames, other
+    /// slots are ignored
+    pub fn insert_coin(&mut self, slot: u8) {
+        if let Some(cycles) = self.coin_cycles.get_mut(slot as usize) {
+            *cycles = COIN_PULSE_CYCLES;
+        }
+    }
+
+    /// should be called on every CPU cycle
+    pub fn clock(&mut self) {
+        for cycles in &mut self.coin_cycles {
+            *cycles = cycles.saturating_sub(1);
+        }
+    }
+
+    /// the bits of `$4016` that are not from the controller, `PCCDDS00`,
+    /// Coins, DIP switches 2 and 1, and the Service button, the primary CPU
+    /// bit (`P`) is always `0`
+    pub fn bits_4016(&self) -> u8 {
+        ((self.coin_cycles[1] != 0) as u8) << 6
+            | ((self.coin_cycles[0] != 0) as u8) << 5
+            | (self.dip_switches & 0b11) << 3
+            | (self.service_button as u8) << 2
+    }
+
+    /// the bits of `$4017` that are not from the controller, `DDDDDD00`, DIP
+    /// switches 8 to 3
+    pub fn bits_4017(&self) -> u8 {
+        self.dip_switches & 0xFC
+    }
+}
+
+impl Savable for VsSystemInputs {
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), SaveError> {
+        writer.write_all(&[self.dip_switches, self.service_button as u8])?;
+        for cycles in &self.coin_cycles {
+            writer.write_all(&cycles.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn load<R: std::io::Read>(&mut self, reader: &mut R) -> Result<(), SaveError> {
+        let mut data = [0; 2];
+        reader.read_exact(&mut data)?;
+        self.dip_switches = data[0];
+        self.service_button = data[1] != 0;
+
+        for cycles in &mut self.coin_cycles {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes)?;
+            *cycles = u32::from_le_bytes(bytes);
+        }
+
+        Ok(())
+    }
+}